@@ -0,0 +1,437 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use proc_macro2::{Group, Ident, Punct, Spacing, Span, TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::ext::IdentExt;
+use syn::{Item, ItemMod, UseTree, Visibility};
+
+use crate::encoding;
+
+// Where the code of a crate comes from: the files about to be written, before the ones on disk.
+// A crate written somewhere else, such as by --package, reads the files it doesn't write from
+// the directory of the original root
+pub struct Sources<'a> {
+    pub generated: &'a HashMap<PathBuf, String>,
+    pub written_to: Option<(&'a Path, &'a Path)>,
+}
+
+impl Sources<'_> {
+    fn exists(&self, path: &Path) -> bool {
+        self.generated.contains_key(path) || self.on_disk(path).is_file()
+    }
+
+    fn read(&self, path: &Path) -> Result<String, String> {
+        match self.generated.get(path) {
+            Some(code) => Ok(code.clone()),
+            None => encoding::read_source(&self.on_disk(path), true).map(|(content, _)| content),
+        }
+    }
+
+    fn on_disk(&self, path: &Path) -> PathBuf {
+        match self.written_to.and_then(|(new_dir, original_dir)| Some(original_dir.join(path.strip_prefix(new_dir).ok()?))) {
+            Some(original) if !path.is_file() => original,
+            _ => path.to_path_buf(),
+        }
+    }
+}
+
+// A module of the crate, with whether it is declared `pub`
+struct Module {
+    items: Vec<Item>,
+    public: bool,
+}
+
+// Function to find the file of `mod name;` among the sources, following its `#[path]` if any
+fn module_file(item_mod: &ItemMod, module_dir: &Path, sources: &Sources) -> Option<PathBuf> {
+    let name = item_mod.ident.unraw().to_string();
+    let path_attr = item_mod.attrs.iter().find(|attr| attr.path().is_ident("path"));
+    if let Some(syn::Meta::NameValue(syn::MetaNameValue {
+        value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(path), .. }),
+        ..
+    })) = path_attr.map(|attr| &attr.meta)
+    {
+        return Some(module_dir.join(path.value())).filter(|path| sources.exists(path));
+    }
+    [module_dir.join(format!("{}.rs", name)), module_dir.join(&name).join("mod.rs")]
+        .into_iter()
+        .find(|path| sources.exists(path))
+}
+
+fn collect_modules(
+    items: Vec<Item>,
+    module_path: Vec<String>,
+    public: bool,
+    module_dir: &Path,
+    sources: &Sources,
+    modules: &mut HashMap<Vec<String>, Module>,
+) -> Result<(), String> {
+    for item in &items {
+        let Item::Mod(item_mod) = item else {
+            continue;
+        };
+        let mut child_path = module_path.clone();
+        child_path.push(item_mod.ident.to_string());
+        let child_dir = module_dir.join(item_mod.ident.unraw().to_string());
+        let child_public = matches!(item_mod.vis, Visibility::Public(_));
+        let child_items = match &item_mod.content {
+            Some((_, child_items)) => child_items.clone(),
+            None => match module_file(item_mod, module_dir, sources) {
+                Some(file) => {
+                    let content = sources.read(&file)?;
+                    syn::parse_file(&content).map_err(|e| format!("Failed to parse {:?}: {}", file, e))?.items
+                }
+                None => continue,
+            },
+        };
+        collect_modules(child_items, child_path, child_public, &child_dir, sources, modules)?;
+    }
+    modules.insert(module_path, Module { items, public });
+    Ok(())
+}
+
+// The names a crate makes public, by the path code outside of it names them with, such as
+// `crate::net::connect`, mapped to what they are. Functions are described by their signature,
+// other items by their whole code, and impls of the types by their headers and public methods
+pub struct PublicApi {
+    pub names: BTreeMap<String, String>,
+    pub impls: BTreeSet<String>,
+}
+
+struct Crate {
+    modules: HashMap<Vec<String>, Module>,
+}
+
+impl Crate {
+    // Function to resolve a path written in `module_path` to the module of the crate it names
+    fn resolve_module(&self, module_path: &[String], path: &[String]) -> Option<Vec<String>> {
+        let (first, rest) = path.split_first()?;
+        let resolved = match first.as_str() {
+            "crate" => rest.to_vec(),
+            "self" => [module_path, rest].concat(),
+            "super" => {
+                let supers = path.iter().take_while(|segment| *segment == "super").count();
+                let base = module_path.get(..module_path.len().checked_sub(supers)?)?;
+                [base, &path[supers..]].concat()
+            }
+            _ => [module_path, path].concat(),
+        };
+        self.modules.contains_key(&resolved).then_some(resolved)
+    }
+
+    // Function to describe what `name` is in the module at `module_path`
+    fn describe(&self, module_path: &[String], name: &str, seen: &mut BTreeSet<Vec<String>>) -> Option<String> {
+        let module = self.modules.get(module_path)?;
+        for item in &module.items {
+            if item_name(item).as_deref() == Some(name) {
+                return Some(describe_item(item, module_path));
+            }
+        }
+        self.exports(module_path, seen).remove(name)
+    }
+
+    // Function to list the names the module at `module_path` makes public, with what they are
+    fn exports(&self, module_path: &[String], seen: &mut BTreeSet<Vec<String>>) -> BTreeMap<String, String> {
+        let mut exports = BTreeMap::new();
+        let Some(module) = self.modules.get(module_path) else {
+            return exports;
+        };
+        if !seen.insert(module_path.to_vec()) {
+            return exports;
+        }
+        // Names declared or imported by name shadow the ones glob imports bring in
+        let mut globbed = BTreeMap::new();
+        for item in &module.items {
+            match item {
+                Item::Use(item_use) if matches!(item_use.vis, Visibility::Public(_)) => {
+                    let mut leaves = Vec::new();
+                    use_leaves(&item_use.tree, &mut Vec::new(), &mut leaves);
+                    for (path, name) in leaves {
+                        let written = path.join("::");
+                        match name {
+                            Some(name) => {
+                                let (target, last) = path.split_at(path.len() - 1);
+                                let description = self
+                                    .resolve_module(module_path, target)
+                                    .and_then(|target| self.describe(&target, &last[0], seen))
+                                    .unwrap_or_else(|| format!("use {}", written));
+                                exports.insert(name, description);
+                            }
+                            None => match self.resolve_module(module_path, &path) {
+                                Some(target) => {
+                                    for (name, description) in self.exports(&target, seen) {
+                                        // Two glob imports bringing different items under one name make it ambiguous
+                                        globbed
+                                            .entry(name)
+                                            .and_modify(|seen: &mut Option<String>| {
+                                                if seen.as_ref() != Some(&description) {
+                                                    *seen = None;
+                                                }
+                                            })
+                                            .or_insert(Some(description));
+                                    }
+                                }
+                                None => {
+                                    exports.insert(format!("*{}", written), format!("use {}::*", written));
+                                }
+                            },
+                        }
+                    }
+                }
+                _ if matches!(item_visibility(item), Some(Visibility::Public(_))) => {
+                    if let Some(name) = item_name(item) {
+                        exports.insert(name, describe_item(item, module_path));
+                    }
+                }
+                _ => {}
+            }
+        }
+        seen.remove(module_path);
+        for (name, description) in globbed {
+            let Some(description) = description else {
+                continue;
+            };
+            exports.entry(name).or_insert(description);
+        }
+        exports
+    }
+}
+
+// Function to list what a `use` tree imports: the full path of each name with the name it is
+// imported as, or None for a glob
+fn use_leaves(tree: &UseTree, prefix: &mut Vec<String>, leaves: &mut Vec<(Vec<String>, Option<String>)>) {
+    match tree {
+        UseTree::Path(use_path) => {
+            prefix.push(use_path.ident.to_string());
+            use_leaves(&use_path.tree, prefix, leaves);
+            prefix.pop();
+        }
+        UseTree::Name(use_name) if use_name.ident == "self" => {
+            if let Some(last) = prefix.last().cloned() {
+                leaves.push((prefix.clone(), Some(last)));
+            }
+        }
+        UseTree::Name(use_name) => leaves.push(([prefix.as_slice(), &[use_name.ident.to_string()]].concat(), Some(use_name.ident.to_string()))),
+        UseTree::Rename(use_rename) if use_rename.rename == "_" => {}
+        UseTree::Rename(use_rename) => {
+            let path = if use_rename.ident == "self" { prefix.clone() } else { [prefix.as_slice(), &[use_rename.ident.to_string()]].concat() };
+            leaves.push((path, Some(use_rename.rename.to_string())));
+        }
+        UseTree::Glob(_) => leaves.push((prefix.clone(), None)),
+        UseTree::Group(group) => group.items.iter().for_each(|tree| use_leaves(tree, prefix, leaves)),
+    }
+}
+
+fn item_name(item: &Item) -> Option<String> {
+    let ident = match item {
+        Item::Fn(item) => &item.sig.ident,
+        Item::Struct(item) => &item.ident,
+        Item::Enum(item) => &item.ident,
+        Item::Union(item) => &item.ident,
+        Item::Trait(item) => &item.ident,
+        Item::TraitAlias(item) => &item.ident,
+        Item::Type(item) => &item.ident,
+        Item::Const(item) => &item.ident,
+        Item::Static(item) => &item.ident,
+        Item::Mod(item) => &item.ident,
+        _ => return None,
+    };
+    Some(ident.to_string())
+}
+
+fn item_visibility(item: &Item) -> Option<&Visibility> {
+    match item {
+        Item::Fn(item) => Some(&item.vis),
+        Item::Struct(item) => Some(&item.vis),
+        Item::Enum(item) => Some(&item.vis),
+        Item::Union(item) => Some(&item.vis),
+        Item::Trait(item) => Some(&item.vis),
+        Item::TraitAlias(item) => Some(&item.vis),
+        Item::Type(item) => Some(&item.vis),
+        Item::Const(item) => Some(&item.vis),
+        Item::Static(item) => Some(&item.vis),
+        Item::Mod(item) => Some(&item.vis),
+        _ => None,
+    }
+}
+
+fn describe_item(item: &Item, module_path: &[String]) -> String {
+    match item {
+        Item::Fn(item_fn) => absolute(item_fn.sig.to_token_stream(), module_path).to_string(),
+        Item::Mod(item_mod) => format!("mod {}", item_mod.ident),
+        item => absolute(item.to_token_stream(), module_path).to_string(),
+    }
+}
+
+// Function to write the `self::` and `super::` paths in `tokens` from the crate root, so the
+// same type is described the same way from whichever module names it
+fn absolute(tokens: TokenStream, module_path: &[String]) -> TokenStream {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let is_path_sep = |at: usize| {
+        matches!((tokens.get(at), tokens.get(at + 1)), (Some(TokenTree::Punct(first)), Some(TokenTree::Punct(second)))
+            if first.as_char() == ':' && first.spacing() == Spacing::Joint && second.as_char() == ':')
+    };
+    let mut written = Vec::new();
+    let mut at = 0;
+    while at < tokens.len() {
+        let relative = matches!(&tokens[at], TokenTree::Ident(ident) if ident == "self" || ident == "super");
+        if relative && is_path_sep(at + 1) && !(at >= 2 && is_path_sep(at - 2)) {
+            let mut base = module_path.to_vec();
+            if matches!(&tokens[at], TokenTree::Ident(ident) if ident == "self") {
+                at += 3;
+            } else {
+                while matches!(tokens.get(at), Some(TokenTree::Ident(ident)) if ident == "super") && is_path_sep(at + 1) {
+                    base.pop();
+                    at += 3;
+                }
+            }
+            let span = Span::call_site();
+            for segment in std::iter::once("crate").chain(base.iter().map(String::as_str)) {
+                written.push(TokenTree::Ident(Ident::new(segment, span)));
+                written.push(TokenTree::Punct(Punct::new(':', Spacing::Joint)));
+                written.push(TokenTree::Punct(Punct::new(':', Spacing::Alone)));
+            }
+            continue;
+        }
+        written.push(match &tokens[at] {
+            TokenTree::Group(group) => {
+                let mut inner = Group::new(group.delimiter(), absolute(group.stream(), module_path));
+                inner.set_span(group.span());
+                TokenTree::Group(inner)
+            }
+            token => token.clone(),
+        });
+        at += 1;
+    }
+    written.into_iter().collect()
+}
+
+// Function to find the public API of the crate whose root at `root` holds `root_code`
+pub fn public_api(root: &Path, root_code: &str, sources: &Sources) -> Result<PublicApi, String> {
+    let syntax_tree = syn::parse_file(root_code).map_err(|e| format!("Failed to parse {:?}: {}", root, e))?;
+    let mut modules = HashMap::new();
+    collect_modules(syntax_tree.items, Vec::new(), true, root.parent().unwrap_or(Path::new(".")), sources, &mut modules)?;
+    let krate = Crate { modules };
+
+    let mut api = PublicApi {
+        names: BTreeMap::new(),
+        impls: BTreeSet::new(),
+    };
+    let mut pending = vec![Vec::new()];
+    while let Some(module_path) = pending.pop() {
+        for (name, description) in krate.exports(&module_path, &mut BTreeSet::new()) {
+            let child = [module_path.as_slice(), std::slice::from_ref(&name)].concat();
+            if krate.modules.get(&child).is_some_and(|module| module.public) {
+                pending.push(child);
+            }
+            let path = std::iter::once("crate").chain(module_path.iter().map(String::as_str)).chain([name.as_str()]);
+            api.names.insert(path.collect::<Vec<_>>().join("::"), description);
+        }
+    }
+
+    // Exported macros and impls belong to the crate wherever they are written
+    for (module_path, module) in &krate.modules {
+        for item in &module.items {
+            match item {
+                Item::Macro(item_macro) if item_macro.attrs.iter().any(|attr| attr.path().is_ident("macro_export")) => {
+                    if let Some(ident) = &item_macro.ident {
+                        api.names.insert(format!("crate::{}", ident), format!("macro_rules! {}", ident));
+                    }
+                }
+                Item::Impl(item_impl) => {
+                    let header = match &item_impl.trait_ {
+                        Some((bang, trait_path, _)) => format!(
+                            "impl {} {}{} for {}",
+                            item_impl.generics.to_token_stream(),
+                            bang.map_or("", |_| "!"),
+                            absolute(trait_path.to_token_stream(), module_path),
+                            absolute(item_impl.self_ty.to_token_stream(), module_path)
+                        ),
+                        None => format!("impl {} {}", item_impl.generics.to_token_stream(), absolute(item_impl.self_ty.to_token_stream(), module_path)),
+                    };
+                    api.impls.insert(header.clone());
+                    for impl_item in &item_impl.items {
+                        if let syn::ImplItem::Fn(method) = impl_item {
+                            if item_impl.trait_.is_none() && !matches!(method.vis, Visibility::Public(_)) {
+                                continue;
+                            }
+                            api.impls.insert(format!("{} {{ {} }}", header, absolute(method.sig.to_token_stream(), module_path)));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    Ok(api)
+}
+
+// Function to list how the API changed, as lines starting with `-` for what is gone or changed
+// and `+` for what is new or changed
+pub fn diff(before: &PublicApi, after: &PublicApi) -> Vec<String> {
+    let mut changes = Vec::new();
+    for (name, description) in &before.names {
+        if after.names.get(name) != Some(description) {
+            changes.push(format!("- {}: {}", name, description));
+        }
+    }
+    for (name, description) in &after.names {
+        if before.names.get(name) != Some(description) {
+            changes.push(format!("+ {}: {}", name, description));
+        }
+    }
+    changes.extend(before.impls.difference(&after.impls).map(|header| format!("- {}", header)));
+    changes.extend(after.impls.difference(&before.impls).map(|header| format!("+ {}", header)));
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_of(root_code: &str, files: &[(&str, &str)]) -> PublicApi {
+        let generated: HashMap<PathBuf, String> = files.iter().map(|(path, code)| (Path::new("/pkg/src").join(path), code.to_string())).collect();
+        public_api(Path::new("/pkg/src/lib.rs"), root_code, &Sources { generated: &generated, written_to: None }).unwrap()
+    }
+
+    #[test]
+    fn the_api_is_what_code_outside_the_crate_can_name() {
+        let api = api_of(
+            "pub mod net;\nmod util;\npub use util::*;\npub struct Config;\nimpl Config { pub fn new() -> Self { Config } fn hidden(&self) {} }\n",
+            &[("net.rs", "pub fn connect(port: u16) -> bool { port > 0 }\nfn private() {}\n"), ("util.rs", "pub fn parse(text: &str) -> u8 { 0 }\n")],
+        );
+        let names: Vec<&str> = api.names.keys().map(String::as_str).collect();
+        assert_eq!(names, ["crate::Config", "crate::net", "crate::net::connect", "crate::parse"]);
+        assert_eq!(api.names["crate::parse"], "fn parse (text : & str) -> u8");
+        assert!(api.impls.iter().any(|header| header.ends_with("{ fn new () -> Self }")) && !api.impls.iter().any(|header| header.contains("hidden")));
+    }
+
+    #[test]
+    fn moves_behind_a_reexport_keep_the_api() {
+        let before = api_of("pub fn parse(text: &str) -> u8 { 0 }\npub fn connect() {}\n", &[]);
+        let kept = api_of("mod util_mod;\npub use util_mod::*;\npub fn connect() {}\n", &[("util_mod.rs", "pub fn parse(text: &str) -> u8 { 0 }\n")]);
+        assert!(diff(&before, &kept).is_empty());
+        let hidden = api_of("mod util_mod;\npub fn connect() {}\n", &[("util_mod.rs", "pub fn parse(text: &str) -> u8 { 0 }\n")]);
+        assert_eq!(diff(&before, &hidden), ["- crate::parse: fn parse (text : & str) -> u8"]);
+    }
+
+    #[test]
+    fn names_two_globs_bring_in_are_ambiguous() {
+        let before = api_of("mod net;\npub use net::*;\npub fn parse() {}\n", &[("net.rs", "pub fn parse(port: u16) {}\n")]);
+        let moved = api_of(
+            "mod net;\nmod util_mod;\npub use net::*;\npub use util_mod::*;\n",
+            &[("net.rs", "pub fn parse(port: u16) {}\n"), ("util_mod.rs", "pub fn parse() {}\n")],
+        );
+        assert_eq!(diff(&before, &moved), ["- crate::parse: fn parse ()"]);
+    }
+
+    #[test]
+    fn relative_paths_name_the_same_type_from_any_module() {
+        let before = api_of("pub type Counts = u8;\npub fn parse(text: &str) -> self::Counts { 0 }\n", &[]);
+        let moved = api_of(
+            "mod util_mod;\npub use util_mod::*;\npub type Counts = u8;\n",
+            &[("util_mod.rs", "pub fn parse(text: &str) -> super::Counts { 0 }\n")],
+        );
+        assert_eq!(before.names["crate::parse"], "fn parse (text : & str) -> crate :: Counts");
+        assert!(diff(&before, &moved).is_empty());
+    }
+}
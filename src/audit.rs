@@ -0,0 +1,178 @@
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+use quote::ToTokens;
+use syn::{Item, UseTree};
+
+use crate::{collect_idents, encoding, format_glue, has_glob, import_paths, module_names_at, replace_region, tree, REEXPORTS_REGION};
+
+// A generated re-export and the names it brings into the root that some other code still uses
+pub struct ReexportUse {
+    pub statement: String,
+    pub module: String,
+    pub visibility: String,
+    pub used: BTreeSet<String>,
+    // What the statement re-exports, or None if the module's names couldn't all be read
+    pub reexported: Option<BTreeSet<String>>,
+    // Public re-exports of a library are its API, which code outside of the crate may use
+    pub public_api: bool,
+}
+
+impl ReexportUse {
+    // Function to write the statement re-exporting only the names in use, or None to drop it
+    fn tightened(&self) -> Option<String> {
+        if self.used.is_empty() {
+            return None;
+        }
+        let vis = &self.visibility;
+        let names: Vec<&str> = self.used.iter().map(String::as_str).collect();
+        let statement = if names.len() == 1 {
+            format!("{} use {}::{};", vis, self.module, names[0])
+        } else {
+            format!("{} use {}::{{{}}};", vis, self.module, names.join(", "))
+        };
+        Some(format_glue(statement.trim_start()))
+    }
+
+    fn is_loose(&self) -> bool {
+        !self.public_api && self.reexported.as_ref().is_some_and(|reexported| reexported.len() > self.used.len())
+    }
+}
+
+// Function to find the re-exports of the generated modules that the crate at `crate_path` no longer
+// needs all of, and with `fix` to remove or narrow them in the root's re-export region
+pub fn audit_reexports(crate_path: &Path, fix: bool) -> Result<Vec<ReexportUse>, String> {
+    let root = tree::resolve_crate_root(crate_path).ok_or_else(|| format!("Found no crate root at {:?}", crate_path))?;
+    let (source, style) = encoding::read_source(&root, false)?;
+    let syntax_tree = syn::parse_file(&source).map_err(|e| format!("Failed to parse {:?}: {}", root, e))?;
+    let region = region_body(&source, REEXPORTS_REGION).ok_or_else(|| format!("{:?} has no region of generated re-exports", root))?;
+    let statements = syn::parse_file(region).map_err(|e| format!("Failed to parse the re-exports of {:?}: {}", root, e))?;
+    let root_dir = root.parent().unwrap_or(Path::new("."));
+    let is_library = !syntax_tree.items.iter().any(|item| matches!(item, Item::Fn(func) if func.sig.ident == "main"));
+
+    // Names mentioned by the root outside of the region and by every other file, per top-level
+    // module, so that a module's own mentions don't count as uses of its re-export
+    let root_rest = replace_region(&source, REEXPORTS_REGION, "").unwrap_or_default();
+    let mut mentions: Vec<(Option<String>, HashSet<String>)> = Vec::new();
+    for module_file in tree::crate_module_files(&root) {
+        let content = if module_file.module_path.is_empty() {
+            root_rest.clone()
+        } else {
+            encoding::read_source(&module_file.path, true)?.0
+        };
+        let mut idents = HashSet::new();
+        collect_idents(content.parse().unwrap_or_default(), &mut idents);
+        mentions.push((module_file.module_path.first().cloned(), idents));
+    }
+
+    let mut audit = Vec::new();
+    let mut tightened = Vec::new();
+    for item in &statements.items {
+        let Item::Use(item_use) = item else {
+            continue;
+        };
+        let statement = format_glue(&item_use.to_token_stream().to_string());
+        let module = match &item_use.tree {
+            UseTree::Path(use_path) => use_path.ident.to_string(),
+            _ => {
+                tightened.push(statement);
+                continue;
+            }
+        };
+        let reexported: Option<BTreeSet<String>> = if has_glob(&item_use.tree) {
+            module_names_at(&syntax_tree.items, std::slice::from_ref(&module), root_dir).map(|names| names.into_iter().collect())
+        } else {
+            Some(import_paths(item_use).into_iter().map(|(name, _)| name).collect())
+        };
+        let used = match &reexported {
+            Some(reexported) => reexported
+                .iter()
+                .filter(|name| mentions.iter().any(|(owner, idents)| owner.as_ref() != Some(&module) && idents.contains(*name)))
+                .cloned()
+                .collect(),
+            None => BTreeSet::new(),
+        };
+        let reexport = ReexportUse {
+            statement: statement.clone(),
+            module,
+            visibility: item_use.vis.to_token_stream().to_string(),
+            used,
+            reexported,
+            public_api: is_library && matches!(item_use.vis, syn::Visibility::Public(_)),
+        };
+        if reexport.is_loose() {
+            tightened.extend(reexport.tightened());
+        } else {
+            tightened.push(statement);
+        }
+        audit.push(reexport);
+    }
+
+    if fix && audit.iter().any(ReexportUse::is_loose) {
+        let updated = replace_region(&source, REEXPORTS_REGION, &tightened.join("\n")).expect("The re-export region disappeared");
+        std::fs::write(&root, style.restore(&updated, true)).map_err(|e| format!("Failed to write {:?}: {}", root, e))?;
+    }
+    Ok(audit)
+}
+
+// Function to find the body of a named region
+pub fn region_body<'a>(source: &'a str, name: &str) -> Option<&'a str> {
+    let start_marker = format!("// <refactor:{}>", name);
+    let end_marker = format!("// </refactor:{}>", name);
+    let start = source.find(&start_marker)? + start_marker.len();
+    let end = start + source[start..].find(&end_marker)?;
+    Some(&source[start..end])
+}
+
+// Function to print what the audit found, and what was done about it with `fixed`
+pub fn print_audit(audit: &[ReexportUse], fixed: bool) {
+    let mut loose = 0;
+    for reexport in audit {
+        if reexport.public_api {
+            println!("`{}`: kept, it is part of the library's API", reexport.statement);
+        } else if reexport.reexported.is_none() {
+            println!("`{}`: kept, the names of `{}` can't all be read", reexport.statement, reexport.module);
+        } else if reexport.used.is_empty() {
+            println!("`{}`: nothing else uses it, remove it", reexport.statement);
+        } else if let Some(tightened) = reexport.tightened().filter(|_| reexport.is_loose()) {
+            println!("`{}`: only part of it is used, narrow it to `{}`", reexport.statement, tightened);
+        }
+        loose += usize::from(reexport.is_loose());
+    }
+    match (loose, fixed) {
+        (0, _) => println!("Every generated re-export is still used."),
+        (1, true) => println!("Tightened 1 re-export."),
+        (_, true) => println!("Tightened {} re-exports.", loose),
+        (1, false) => println!("Run again with --fix to tighten it."),
+        (_, false) => println!("Run again with --fix to tighten {} re-exports.", loose),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reexports_are_narrowed_to_the_names_in_use() {
+        let mut reexport = ReexportUse {
+            statement: "pub(crate) use net_mod::*;".to_string(),
+            module: "net_mod".to_string(),
+            visibility: "pub(crate)".to_string(),
+            used: BTreeSet::from(["connect".to_string(), "send".to_string()]),
+            reexported: Some(BTreeSet::from(["connect".to_string(), "receive".to_string(), "send".to_string()])),
+            public_api: false,
+        };
+        assert!(reexport.is_loose());
+        assert_eq!(reexport.tightened().as_deref(), Some("pub(crate) use net_mod::{connect, send};"));
+        reexport.public_api = true;
+        assert!(!reexport.is_loose());
+        reexport.used.clear();
+        assert_eq!(reexport.tightened(), None);
+    }
+
+    #[test]
+    fn regions_are_found_by_their_markers() {
+        let source = "mod a;\n// <refactor:reexports>\nuse a::*;\n// </refactor:reexports>\n";
+        assert_eq!(region_body(source, REEXPORTS_REGION), Some("\nuse a::*;\n"));
+        assert_eq!(region_body(source, "mods"), None);
+    }
+}
@@ -0,0 +1,155 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::wildcard_match;
+
+// Function to check whether an input is a glob pattern rather than a path
+pub fn is_glob(input: &str) -> bool {
+    input.contains(['*', '?'])
+}
+
+// Function to find the entries of a directory matching one component of a pattern. Hidden
+// entries only match a component that starts with a dot, as in a shell
+fn matching_entries(dir: &Path, component: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+        .filter(|name| wildcard_match(component, name))
+        .map(|name| dir.join(name))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// Function to expand the components of a pattern below `dir`, where `**` stands for any number of
+// directories and `*` and `?` match within one file name
+fn expand(dir: &Path, components: &[&str], found: &mut Vec<PathBuf>) {
+    let Some((component, rest)) = components.split_first() else {
+        if dir.is_file() {
+            found.push(dir.to_path_buf());
+        }
+        return;
+    };
+    match *component {
+        "**" => {
+            expand(dir, rest, found);
+            for subdir in matching_entries(dir, "*").into_iter().filter(|path| path.is_dir()) {
+                expand(&subdir, components, found);
+            }
+        }
+        component if is_glob(component) => {
+            for path in matching_entries(dir, component) {
+                expand(&path, rest, found);
+            }
+        }
+        component => expand(&dir.join(component), rest, found),
+    }
+}
+
+// Function to turn the inputs of a run into the files it refactors, in the order given, with the
+// files a pattern matches sorted and each file taken once
+pub fn input_files(inputs: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if !is_glob(input) {
+            files.push(PathBuf::from(input));
+            continue;
+        }
+        let mut found = Vec::new();
+        let root = if input.starts_with('/') { Path::new("/") } else { Path::new("") };
+        let components: Vec<&str> = input.split('/').filter(|component| !component.is_empty() && *component != ".").collect();
+        expand(root, &components, &mut found);
+        if found.is_empty() {
+            return Err(format!("no file matches `{}`", input));
+        }
+        found.sort();
+        files.extend(found);
+    }
+    let mut seen = std::collections::HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    Ok(files)
+}
+
+// Function to find two files that would write their roots and plans to the same directory, which
+// only files refactored in place don't. Crates are split in place anyway
+pub fn shared_output_dir(files: &[PathBuf]) -> Option<(&PathBuf, &PathBuf)> {
+    let files: Vec<&PathBuf> = files.iter().filter(|file| file.is_file()).collect();
+    files.iter().enumerate().find_map(|(index, file)| {
+        let dir = |path: &Path| fs::canonicalize(path).ok().and_then(|path| path.parent().map(Path::to_path_buf));
+        files[index + 1..].iter().find(|other| dir(other) == dir(file)).map(|other| (*file, *other))
+    })
+}
+
+// Function to refactor each input in a run of its own with the same flags, one after the other,
+// and sum up how each went. Returns the highest exit status among them, so that a single failure
+// fails the whole run, and with --check a single file that would be split
+pub fn run(inputs: &[PathBuf], flags: &[String], check: bool) -> Result<i32, String> {
+    let program = std::env::current_exe().map_err(|e| format!("Failed to find the refactor executable: {}", e))?;
+    let mut statuses = Vec::new();
+    for (index, input) in inputs.iter().enumerate() {
+        if index > 0 {
+            println!();
+        }
+        println!("==> {} <==", input.display());
+        let status = Command::new(&program)
+            .args(flags)
+            .arg(input)
+            .status()
+            .map_err(|e| format!("Failed to refactor {:?}: {}", input, e))?;
+        // A run ended by a signal has no code, and counts as failed
+        statuses.push(status.code().unwrap_or(1));
+    }
+
+    println!();
+    let count = if inputs.len() == 1 { "1 file".to_string() } else { format!("{} files", inputs.len()) };
+    println!("{} {}:", if check { "Checked" } else { "Refactored" }, count);
+    for (input, status) in inputs.iter().zip(&statuses) {
+        let outcome = match status {
+            0 => "ok".to_string(),
+            1 if check => "would be split".to_string(),
+            status => format!("failed with exit status {}", status),
+        };
+        println!("  {}: {}", input.display(), outcome);
+    }
+    Ok(statuses.into_iter().max().unwrap_or(0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_expand_to_the_files_they_match() {
+        let dir = std::env::temp_dir().join(format!("refactor-batch-{}", std::process::id()));
+        for file in ["a/main.rs", "a/b/lib.rs", "a/b/notes.txt", "a/.hidden/main.rs", "c/main.rs"] {
+            fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let base = dir.display().to_string();
+        let files = input_files(&[format!("{}/a/**/*.rs", base), format!("{}/c/main.rs", base), format!("{}/a/main.rs", base)]).unwrap();
+        assert_eq!(files, vec![dir.join("a/b/lib.rs"), dir.join("a/main.rs"), dir.join("c/main.rs")]);
+        assert_eq!(input_files(&[format!("{}/a/.*/ma?n.rs", base)]).unwrap(), vec![dir.join("a/.hidden/main.rs")]);
+        assert!(input_files(&[format!("{}/*/*.toml", base)]).unwrap_err().starts_with("no file matches"));
+        // Paths that aren't patterns are taken as they are, for the run to report if they are missing
+        assert_eq!(input_files(&["missing.rs".to_string()]).unwrap(), vec![PathBuf::from("missing.rs")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_of_one_directory_share_their_output() {
+        let dir = std::env::temp_dir().join(format!("refactor-batch-shared-{}", std::process::id()));
+        for file in ["a/main.rs", "a/other.rs", "b/main.rs"] {
+            fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            fs::write(dir.join(file), "").unwrap();
+        }
+        let (a, other, b) = (dir.join("a/main.rs"), dir.join("a/other.rs"), dir.join("b/main.rs"));
+        assert_eq!(shared_output_dir(&[a.clone(), b.clone()]), None);
+        assert_eq!(shared_output_dir(&[a.clone(), b, other.clone()]), Some((&a, &other)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
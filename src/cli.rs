@@ -0,0 +1,389 @@
+// A flag of the command line: what follows it, if anything, and what it does. A value of
+// alternatives separated by `|` lists everything the flag accepts, and `<n>` takes a number.
+// This is the one list of the flags: the arguments are parsed, checked, completed and read from
+// refactor.toml by it
+pub struct Flag {
+    pub name: &'static str,
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+    // Whether refactor.toml can set the flag, by its name without the dashes and with `_` for `-`
+    pub config: bool,
+}
+
+// A command, the arguments it takes after its options, and what it does
+pub struct Command {
+    pub name: &'static str,
+    pub args: &'static str,
+    pub help: &'static str,
+}
+
+const fn flag(name: &'static str, help: &'static str) -> Flag {
+    Flag { name, value: None, help, config: false }
+}
+
+const fn valued(name: &'static str, value: &'static str, help: &'static str) -> Flag {
+    Flag { name, value: Some(value), help, config: false }
+}
+
+impl Flag {
+    // Function to let refactor.toml set the flag as a project's default
+    const fn in_config(self) -> Flag {
+        Flag { config: true, ..self }
+    }
+}
+
+// The commands other than refactoring the inputs, which is what a run does without one
+pub const COMMANDS: &[Command] = &[
+    Command { name: "tree", args: "<path>", help: "Print the current and proposed module trees side by side" },
+    Command { name: "rename-mod", args: "<old> <new> [<path>]", help: "Rename a module across the crate" },
+    Command { name: "god-objects", args: "<path>", help: "Propose how to split the crate's god objects" },
+    Command { name: "audit-reexports", args: "<path>", help: "Report the generated re-exports nothing uses anymore" },
+    Command { name: "gate", args: "<path>", help: "Fail when a file or function of the crate is too long" },
+    Command { name: "graph", args: "<path>", help: "Write the graph of which modules use which" },
+    Command { name: "explain-error", args: "<path>", help: "Explain the errors of `cargo check` by the plan behind them" },
+    Command { name: "spans", args: "<input_file>", help: "Print where each planned function is and where it goes" },
+    Command { name: "preview", args: "<input_file>", help: "Write a page showing the input next to the split" },
+    Command { name: "recipes", args: "", help: "List the built-in recipes" },
+    Command { name: "plan", args: "<input_file>", help: "Write the plan of a refactoring without carrying it out" },
+    Command { name: "apply", args: "<plan_file>", help: "Carry out a plan on the input it was made for" },
+    Command { name: "revert", args: "[<path>]", help: "Undo what the last run that wrote files did" },
+    Command { name: "undo", args: "[<path>]", help: "The same as revert" },
+    Command { name: "watch", args: "<path>", help: "Show the plan again every time the input changes" },
+    Command { name: "completions", args: "bash|zsh|fish", help: "Print the completion script of a shell" },
+    Command { name: "help", args: "", help: "Print this help" },
+];
+
+// The flags, in the sections the help lists them in
+pub const SECTIONS: &[(&str, &[Flag])] = &[
+    (
+        "Grouping",
+        &[
+            valued("--strategy", "<kind=policy,...>", "How each kind of item is placed: fn=keywords|calls, type=stay|clusters, impl=with-type|stay, generic=group|support|stay, macro=pinned").in_config(),
+            valued("--recipe", "<name>", "Set the options up for a common shape of file; `refactor recipes` lists them").in_config(),
+            valued("--like", "<crate>", "Imitate the module layout of another crate").in_config(),
+            flag("--inline-single-use", "Move functions only called from one other group next to their caller").in_config(),
+            flag("--promote-inline-mods", "Write each inline module into its own file").in_config(),
+            valued("--asm", "keep|group|free", "Keep items with assembly where they are, group them into an asm module, or place them freely").in_config(),
+            valued("--exclude", "<name|glob>,...", "Keep these functions where they are, by name or path").in_config(),
+            valued("--seed", "<n>", "Break the ties between equally good placements").in_config(),
+            valued("--plan", "<plan_file>", "Place the functions as an edited plan says"),
+            flag("--interactive", "Ask for each proposed module whether to write, skip or rename it"),
+        ],
+    ),
+    (
+        "Naming",
+        &[
+            valued("--min-module-items", "<n>", "Merge groups of fewer functions into the misc module").in_config(),
+            valued("--min-inline-fns", "<n>", "Keep inline modules with fewer functions as they are (default 4)").in_config(),
+            valued("--misc-module", "<name>", "Name the module small groups are merged into, or `self` to keep them in place").in_config(),
+            valued("--module-prefix", "<prefix>", "Put this in front of every generated module name instead of `_mod` after it").in_config(),
+        ],
+    ),
+    (
+        "Sizes",
+        &[
+            valued("--min-lines", "<n>", "Split the files of a crate longer than this").in_config(),
+            valued("--max-file-lines", "<n>", "The same as --min-lines, and the longest file a gate allows").in_config(),
+            valued("--min-fns", "<n>", "Only split the files of a crate with at least this many functions").in_config(),
+            valued("--max-fn-lines", "<n>", "The longest function a gate allows").in_config(),
+        ],
+    ),
+    (
+        "Output",
+        &[
+            valued("--emit", "files|memory|git|stdout|json", "Write the files next to the input, nowhere, into a commit, or onto stdout").in_config(),
+            valued("--output-dir", "<dir>", "Write the modules and root into this directory").in_config(),
+            valued("--package", "<dir>", "Write the result as a new package with a Cargo.toml"),
+            flag("--in-place", "Write the root over the input, which `refactor revert` puts back"),
+            flag("--smoke-tests", "Add a test naming an item through every generated module").in_config(),
+            valued("--verify", "warnings", "Roll back when `cargo check` reports new warnings").in_config(),
+            flag("--allow-api-changes", "Let the refactoring of a library change its public API"),
+            valued("--stdin-name", "<name>", "Name the input read from `-`"),
+            flag("--lossy", "Read input that isn't valid UTF-8, replacing the invalid bytes"),
+            flag("--wait", "Wait for another run writing to the package instead of failing"),
+            flag("--open", "Open the preview in the browser"),
+            valued("--output", "<file>", "Write the graph to this file instead of stdout"),
+            flag("--fix", "Remove what an audit found instead of only reporting it"),
+        ],
+    ),
+    (
+        "Formatting",
+        &[
+            valued("--import-style", "original|grouped|per-line|merged", "Lay out the imports of generated modules").in_config(),
+            flag("--explicit-imports", "Spell out the glob imports of generated modules").in_config(),
+            flag("--no-format", "Write generated code without formatting it").in_config(),
+            valued("--edition", "2015|2018|2021|2024", "Format as this edition instead of the manifest's").in_config(),
+            valued("--rustfmt-config", "<file>", "Format with this rustfmt.toml instead of the nearest one").in_config(),
+        ],
+    ),
+    (
+        "Reporting",
+        &[
+            flag("--dry-run", "Print what would be written instead of writing it"),
+            flag("--diff", "Print what would be written as a unified diff"),
+            flag("--check", "Write nothing and exit with 1 when the input would be split"),
+            valued("--format", "<format>", "Report as text or json, or write a graph as json, graphml or structurizr"),
+            flag("--teach", "Explain each decision with the Rust concept behind it"),
+            valued("--stats", "<file>", "Write the lines, functions and imports of each module to this file, as JSON when it ends in .json").in_config(),
+            flag("-v", "Log what each phase does; -vv also logs why each function went where it did"),
+            flag("-vv", "Log at debug level"),
+            valued("--log-level", "error|warn|info|debug|trace", "Log this much on stderr").in_config(),
+        ],
+    ),
+    (
+        "Configuration",
+        &[
+            valued("--config", "<file>", "Read the defaults from this file instead of the nearest refactor.toml"),
+            flag("--no-config", "Read no config file"),
+            flag("--help", "Print this help"),
+            flag("-h", "Print this help"),
+        ],
+    ),
+];
+
+// Function to find a flag by its name
+pub fn find(name: &str) -> Option<&'static Flag> {
+    SECTIONS.iter().flat_map(|(_, flags)| flags.iter()).find(|flag| flag.name == name)
+}
+
+// Function to tell how many edits turn one name into another, to suggest the flag a typo meant
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substituted = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substituted.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+// Function to name an unknown flag, along with the one it most likely meant
+pub fn unknown_flag(arg: &str) -> String {
+    let closest = SECTIONS
+        .iter()
+        .flat_map(|(_, flags)| flags.iter())
+        .map(|flag| (edit_distance(arg, flag.name), flag.name))
+        .min()
+        .filter(|(distance, _)| *distance <= 3);
+    match closest {
+        Some((_, name)) => format!("unknown flag `{}`, did you mean `{}`?", arg, name),
+        None => format!("unknown flag `{}`", arg),
+    }
+}
+
+// Function to check that a value is one a flag takes: a number for `<n>`, and one of the
+// alternatives for a flag that lists them
+pub fn check_value(flag: &Flag, value: &str) -> Result<(), String> {
+    let placeholder = flag.value.unwrap_or_default();
+    if placeholder == "<n>" && value.parse::<u64>().is_err() {
+        return Err(format!("`{}` takes a number, not `{}`", flag.name, value));
+    }
+    if !placeholder.starts_with('<') && !placeholder.split('|').any(|choice| choice == value) {
+        let choices: Vec<&str> = placeholder.split('|').collect();
+        let listed = match choices.split_last() {
+            Some((last, [])) => last.to_string(),
+            Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+            None => String::new(),
+        };
+        return Err(format!("`{}` takes {}, not `{}`", flag.name, listed, value));
+    }
+    Ok(())
+}
+
+// Function to take the value following a flag that needs one
+pub fn flag_value<'a>(flag: &Flag, args: &mut impl Iterator<Item = &'a String>) -> Result<&'a String, String> {
+    let value = args.next().ok_or_else(|| format!("`{}` needs a value: {}", flag.name, flag.value.unwrap_or_default()))?;
+    check_value(flag, value)?;
+    Ok(value)
+}
+
+// Function to check the flags of a command line before they are parsed, so that a mistake is
+// named instead of answered with the usage: an unknown flag, with the one it most likely meant,
+// a flag missing its value, or a value the flag doesn't take
+pub fn check(args: &[String]) -> Result<(), String> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            continue;
+        }
+        let flag = find(arg).ok_or_else(|| unknown_flag(arg))?;
+        if flag.value.is_some() {
+            flag_value(flag, &mut args)?;
+        }
+    }
+    Ok(())
+}
+
+// Function to write the short usage printed when the arguments don't make a run
+pub fn usage() -> String {
+    let mut usage = String::from("Usage: refactor [options] <input_file>...|<glob>...|-\n");
+    for command in COMMANDS {
+        usage.push_str(format!("       refactor {} {}", command.name, command.args).trim_end());
+        usage.push('\n');
+    }
+    usage.push_str("Run `refactor --help` for the options.\n");
+    usage
+}
+
+// Function to write the full help, with every command and option and what it does
+pub fn help() -> String {
+    let mut help = String::from("Split a large Rust file or the large files of a crate into modules.\n\n");
+    help.push_str("Usage: refactor [options] <input_file>...|<glob>...|-\n       refactor <command> [options] <args>\n\nCommands:\n");
+    let width = COMMANDS.iter().map(|command| command.name.len() + command.args.len() + 1).max().unwrap_or(0);
+    for command in COMMANDS {
+        let synopsis = format!("{} {}", command.name, command.args);
+        help.push_str(&format!("  {:<width$}  {}\n", synopsis.trim_end(), command.help, width = width));
+    }
+    let synopsis = |flag: &Flag| flag.value.map_or_else(|| flag.name.to_string(), |value| format!("{} {}", flag.name, value));
+    let width = SECTIONS.iter().flat_map(|(_, flags)| flags.iter()).map(|flag| synopsis(flag).len()).max().unwrap_or(0);
+    for (section, flags) in SECTIONS {
+        help.push_str(&format!("\n{}:\n", section));
+        for flag in flags.iter() {
+            help.push_str(&format!("  {:<width$}  {}\n", synopsis(flag), flag.help, width = width));
+        }
+    }
+    help.push_str("\nA refactor.toml in the current directory or above sets defaults for the options, by their names with `_` for `-`.\n");
+    help
+}
+
+// Function to list the choices of a flag that takes one of a few values
+fn choices(flag: &Flag) -> Option<Vec<&'static str>> {
+    flag.value.filter(|value| !value.starts_with('<')).map(|value| value.split('|').collect())
+}
+
+// Function to check whether a flag takes a path, for the shell to complete file names after it
+fn takes_path(flag: &Flag) -> bool {
+    matches!(flag.value, Some("<file>" | "<dir>" | "<plan_file>" | "<crate>"))
+}
+
+// Function to write the script completing the commands, flags and their values in a shell
+pub fn completions(shell: &str) -> Result<String, String> {
+    let flags: Vec<&Flag> = SECTIONS.iter().flat_map(|(_, flags)| flags.iter()).collect();
+    let commands: Vec<&str> = COMMANDS.iter().map(|command| command.name).collect();
+    match shell {
+        "bash" => {
+            let mut script = String::from("_refactor() {\n    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n    case \"$prev\" in\n");
+            for flag in flags.iter().filter(|flag| flag.value.is_some()) {
+                let reply = match choices(flag) {
+                    Some(choices) => format!("COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))", choices.join(" ")),
+                    None if takes_path(flag) => "COMPREPLY=($(compgen -f -- \"$cur\"))".to_string(),
+                    None => "COMPREPLY=()".to_string(),
+                };
+                script.push_str(&format!("        {}) {}; return ;;\n", flag.name, reply));
+            }
+            script.push_str("    esac\n");
+            script.push_str(&format!(
+                "    if [[ \"$cur\" == -* ]]; then\n        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n    elif [[ $COMP_CWORD -eq 1 ]]; then\n        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\") $(compgen -f -- \"$cur\"))\n    else\n        COMPREPLY=($(compgen -f -- \"$cur\"))\n    fi\n}}\ncomplete -F _refactor refactor\n",
+                flags.iter().map(|flag| flag.name).collect::<Vec<_>>().join(" "),
+                commands.join(" ")
+            ));
+            Ok(script)
+        }
+        "zsh" => {
+            // Help texts go inside `[...]` within single quotes
+            let escape = |text: &str| text.replace('\'', "'\\''").replace('[', "\\[").replace(']', "\\]").replace(':', "\\:");
+            let mut script = String::from("#compdef refactor\n\n_refactor() {\n    _arguments \\\n");
+            for flag in &flags {
+                let value = match choices(flag) {
+                    Some(choices) => format!(":{}:({})", &flag.name[2.min(flag.name.len())..], choices.join(" ")),
+                    None if takes_path(flag) => ":path:_files".to_string(),
+                    None => flag.value.map_or_else(String::new, |value| format!(":{}: ", escape(value))),
+                };
+                script.push_str(&format!("        '{}[{}]{}' \\\n", flag.name, escape(flag.help), value));
+            }
+            let described: Vec<String> = COMMANDS.iter().map(|command| format!("{}\\:\"{}\"", command.name, escape(command.help).replace('"', "\\\""))).collect();
+            script.push_str(&format!("        '1: :(({}))' \\\n        '*:file:_files'\n}}\n\n_refactor \"$@\"\n", described.join(" ")));
+            Ok(script)
+        }
+        "fish" => {
+            let escape = |text: &str| text.replace('\\', "\\\\").replace('\'', "\\'");
+            let mut script = String::new();
+            for command in COMMANDS {
+                script.push_str(&format!("complete -c refactor -n '__fish_use_subcommand' -a {} -d '{}'\n", command.name, escape(command.help)));
+            }
+            for flag in &flags {
+                let name = match flag.name.strip_prefix("--") {
+                    Some(long) => format!("-l {}", long),
+                    None => format!("-o {}", &flag.name[1..]),
+                };
+                let value = match choices(flag) {
+                    Some(choices) => format!(" -x -a '{}'", choices.join(" ")),
+                    None if takes_path(flag) => " -r -F".to_string(),
+                    None if flag.value.is_some() => " -x".to_string(),
+                    None => String::new(),
+                };
+                script.push_str(&format!("complete -c refactor {}{} -d '{}'\n", name, value, escape(flag.help)));
+            }
+            Ok(script)
+        }
+        _ => Err(format!("there are no completions for `{}`; pick bash, zsh or fish", shell)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_listed_flag_is_parsed() {
+        for flag in SECTIONS.iter().flat_map(|(_, flags)| flags.iter()) {
+            let value = match (flag.name, flag.value) {
+                (_, None) => None,
+                ("--strategy", _) => Some("fn=calls"),
+                ("--recipe", _) => Some("cli-monolith"),
+                ("--format", _) => Some("json"),
+                (_, Some("<n>")) => Some("3"),
+                (_, Some(_)) => Some(choices(flag).map_or("value", |choices| choices[0])),
+            };
+            let args: Vec<String> = [Some(flag.name), value, Some("src/main.rs")].into_iter().flatten().map(String::from).collect();
+            assert!(check(&args).is_ok(), "{}", flag.name);
+            if let Err(e) = crate::parse_args(&args, &[]) {
+                panic!("{}: {}", flag.name, e);
+            }
+        }
+    }
+
+    #[test]
+    fn config_keys_are_the_names_of_the_flags() {
+        for flag in SECTIONS.iter().flat_map(|(_, flags)| flags.iter()).filter(|flag| flag.config) {
+            assert!(flag.name.starts_with("--") && !flag.name.contains('_'), "{}", flag.name);
+        }
+    }
+
+    #[test]
+    fn typos_are_answered_with_the_flag_they_meant() {
+        assert_eq!(edit_distance("--dry-rn", "--dry-run"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(unknown_flag("--dryrun"), "unknown flag `--dryrun`, did you mean `--dry-run`?");
+        assert_eq!(unknown_flag("--completely-different"), "unknown flag `--completely-different`");
+    }
+
+    #[test]
+    fn values_are_checked_against_what_the_flag_takes() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+        assert_eq!(check(&args(&["--seed", "many"])).unwrap_err(), "`--seed` takes a number, not `many`");
+        assert_eq!(check(&args(&["--asm", "drop"])).unwrap_err(), "`--asm` takes keep, group or free, not `drop`");
+        assert_eq!(check(&args(&["--verify", "tests"])).unwrap_err(), "`--verify` takes warnings, not `tests`");
+        assert_eq!(check(&args(&["--seed"])).unwrap_err(), "`--seed` needs a value: <n>");
+        assert!(check(&args(&["-", "--stdin-name", "src/lib.rs"])).is_ok());
+    }
+
+    #[test]
+    fn completions_offer_every_command_and_flag() {
+        for shell in ["bash", "zsh", "fish"] {
+            let script = completions(shell).unwrap();
+            for name in COMMANDS.iter().map(|command| command.name) {
+                assert!(script.contains(name), "{} lacks {}", shell, name);
+            }
+            for flag in SECTIONS.iter().flat_map(|(_, flags)| flags.iter()) {
+                assert!(script.contains(flag.name.trim_start_matches('-')), "{} lacks {}", shell, flag.name);
+            }
+        }
+        assert!(completions("tcsh").unwrap_err().contains("pick bash, zsh or fish"));
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cli;
+
+// The file holding a project's defaults, found in the current directory or the nearest one above
+pub const CONFIG_FILE: &str = "refactor.toml";
+
+// Function to find the config file for a run: the one named by --config, none with --no-config,
+// or else refactor.toml in the current directory or the nearest directory above it
+pub fn find(args: &[String]) -> Result<Option<PathBuf>, String> {
+    if args.iter().any(|arg| arg == "--no-config") {
+        return Ok(None);
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--config") {
+        let path = args.get(index + 1).ok_or("--config needs the path of a config file")?;
+        return Ok(Some(PathBuf::from(path)));
+    }
+    let current_dir = std::env::current_dir().map_err(|e| format!("Failed to find the current directory: {}", e))?;
+    Ok(current_dir.ancestors().map(|dir| dir.join(CONFIG_FILE)).find(|path| path.is_file()))
+}
+
+// Function to read a value of the config file: a string, an integer, a boolean or an array of
+// those on one line, which is as much TOML as the keys need
+fn parse_value(value: &str) -> Option<Vec<String>> {
+    if let Some(items) = value.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        let mut values = Vec::new();
+        for item in items.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            values.extend(parse_value(item)?);
+        }
+        return Some(values);
+    }
+    if let Some(text) = value.strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        return Some(vec![text.replace("\\\"", "\"").replace("\\\\", "\\")]);
+    }
+    if let Some(text) = value.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')) {
+        return Some(vec![text.to_string()]);
+    }
+    if value == "true" || value == "false" || value.parse::<i64>().is_ok() {
+        return Some(vec![value.to_string()]);
+    }
+    None
+}
+
+// Function to drop a comment from the end of a line, leaving `#` inside strings alone
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(open), _) if c == open => quote = None,
+            (None, '#') => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Function to turn a config file into the flags it stands for, in the order it sets them, so
+// that flags given on the command line after them override them
+pub fn flags(path: &Path) -> Result<Vec<String>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let mut flags = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        let at = || format!("{}:{}", path.display(), number + 1);
+        if line.is_empty() || (line.starts_with('[') && line.ends_with(']')) {
+            continue;
+        }
+        let (key, value) = line.split_once('=').ok_or_else(|| format!("{}: expected `key = value`", at()))?;
+        let (key, value) = (key.trim(), value.trim());
+        // Each key stands for the command-line flag of the same name. The tables keys are written
+        // in only group them, so `[naming] misc_module` and a top-level `misc_module` are the same key
+        let flag = cli::find(&format!("--{}", key.replace('_', "-")))
+            .filter(|flag| flag.config && !key.contains('-'))
+            .ok_or_else(|| format!("{}: unknown key `{}`", at(), key))?;
+        let values = parse_value(value).ok_or_else(|| format!("{}: can't read the value of `{}`", at(), key))?;
+        match flag.value {
+            None => match values.as_slice() {
+                [value] if value == "true" => flags.push(flag.name.to_string()),
+                [value] if value == "false" => {}
+                _ => return Err(format!("{}: `{}` is either true or false", at(), key)),
+            },
+            Some(_) => {
+                for value in values {
+                    cli::check_value(flag, &value).map_err(|e| format!("{}: {}", at(), e))?;
+                    flags.push(flag.name.to_string());
+                    flags.push(value);
+                }
+            }
+        }
+    }
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_flags(name: &str, text: &str) -> Result<Vec<String>, String> {
+        let path = std::env::temp_dir().join(format!("refactor-config-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        let flags = flags(&path).map_err(|e| e.replace(&path.display().to_string(), "refactor.toml"));
+        fs::remove_file(&path).unwrap();
+        flags
+    }
+
+    #[test]
+    fn values_are_strings_integers_booleans_or_arrays() {
+        assert_eq!(parse_value("\"say \\\"hi\\\"\""), Some(vec!["say \"hi\"".to_string()]));
+        assert_eq!(parse_value("'C:\\dir'"), Some(vec!["C:\\dir".to_string()]));
+        assert_eq!(parse_value("[\"a\", 'b', 3]"), Some(vec!["a".to_string(), "b".to_string(), "3".to_string()]));
+        assert_eq!(parse_value("true"), Some(vec!["true".to_string()]));
+        assert_eq!(parse_value("yes"), None);
+    }
+
+    #[test]
+    fn comments_end_outside_strings() {
+        assert_eq!(strip_comment("seed = 3 # fixed"), "seed = 3 ");
+        assert_eq!(strip_comment("misc_module = \"a#b\" # c"), "misc_module = \"a#b\" ");
+    }
+
+    #[test]
+    fn keys_become_the_flags_of_the_same_name() {
+        let text = "# Defaults of the project\nseed = 7\n\n[naming]\nmisc_module = \"common\"\nexclude = [\"main\", \"run\"]\nsmoke_tests = true\nno_format = false\n";
+        assert_eq!(
+            config_flags("keys", text).unwrap(),
+            vec!["--seed", "7", "--misc-module", "common", "--exclude", "main", "--exclude", "run", "--smoke-tests"]
+        );
+    }
+
+    #[test]
+    fn bad_lines_are_reported_where_they_are() {
+        assert_eq!(config_flags("unknown", "seed = 1\nmisc-module = \"a\"\n").unwrap_err(), "refactor.toml:2: unknown key `misc-module`");
+        assert_eq!(config_flags("dry", "dry_run = true\n").unwrap_err(), "refactor.toml:1: unknown key `dry_run`");
+        assert_eq!(config_flags("bool", "smoke_tests = 1\n").unwrap_err(), "refactor.toml:1: `smoke_tests` is either true or false");
+        assert_eq!(config_flags("missing", "seed\n").unwrap_err(), "refactor.toml:1: expected `key = value`");
+    }
+
+    #[test]
+    fn no_config_wins_over_config() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<String>>();
+        assert_eq!(find(&args(&["--config", "other.toml", "--no-config"])).unwrap(), None);
+        assert_eq!(find(&args(&["--config", "other.toml"])).unwrap(), Some(PathBuf::from("other.toml")));
+        assert!(find(&args(&["--config"])).is_err());
+    }
+}
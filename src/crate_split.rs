@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::tree::{self, ModuleFile};
+use crate::{
+    attrs_end, diff, encoding, format_glue, log, output, parsed_source, progress, refactor_reviewed, revert, stats, rewrite_doc_links, trim_blank_lines, verify, warn, Emit,
+    GeneratedFile, Options,
+};
+
+// A file of the crate that was split, with the files replacing it: the file
+// itself rewritten as the parent of its new modules, then those modules
+struct SplitFile {
+    path: PathBuf,
+    style: encoding::Style,
+    files: Vec<GeneratedFile>,
+    moves: HashMap<Vec<String>, Vec<String>>,
+}
+
+// Function to split one file of the crate into modules below it, or to find nothing to move
+fn split_file(module_file: &ModuleFile, options: &mut Options) -> Result<Option<SplitFile>, String> {
+    let path = &module_file.path;
+    let original = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let (content, style) = encoding::decode(&original, options.lossy).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    let syntax_tree = syn::parse_file(&content).map_err(|e| format!("Failed to parse {:?}: {}", path, e))?;
+    log::info("parse", format_args!("{} has {} items in {} lines", path.display(), syntax_tree.items.len(), content.lines().count()));
+    let source = parsed_source(&content);
+    let items_start = attrs_end(&syntax_tree.attrs).unwrap_or(0);
+    let module_path = &module_file.module_path;
+    let mut refactored = refactor_reviewed(source, &syntax_tree.items, items_start, &module_file.module_dir, module_path, options)?;
+    if refactored.files.is_empty() {
+        return Ok(None);
+    }
+    // Files of the crate itself are never replaced by generated ones
+    if let Some(file) = refactored.files.iter().find(|file| file.path.exists()) {
+        return Err(format!("{:?} already exists, so {:?} was left as it is", file.path, path));
+    }
+
+    // The file keeps its inner attributes and docs on top, then what stays in it, then the
+    // declarations of its new modules and the re-exports keeping their items at the old paths
+    let mut code = String::new();
+    let shebang = content[..content.len() - source.len()].trim();
+    let prelude = trim_blank_lines(&source[..items_start]);
+    for part in [shebang, prelude.as_str()] {
+        if !part.is_empty() {
+            code.push_str(part);
+            code.push_str("\n\n");
+        }
+    }
+    for item in refactored.imports.iter().chain(&refactored.other_items) {
+        code.push_str(item);
+        code.push_str("\n\n");
+    }
+    refactored.mod_declarations.sort();
+    refactored.use_statements.sort();
+    for glue in [&refactored.mod_declarations, &refactored.use_statements] {
+        if !glue.is_empty() {
+            code.push_str(&format_glue(&glue.join("\n")));
+            code.push_str("\n\n");
+        }
+    }
+    if let Some(main_function) = &refactored.main_function {
+        code.push_str(main_function);
+        code.push_str("\n\n");
+    }
+
+    let mut files = vec![GeneratedFile {
+        path: path.clone(),
+        code: format!("{}\n", rewrite_doc_links(&code, module_path, module_path, &refactored.moves).trim_end()),
+        module_path: module_path.clone(),
+        written_in: module_path.clone(),
+    }];
+    for mut file in refactored.files {
+        file.code = rewrite_doc_links(&file.code, &file.written_in, &file.module_path, &refactored.moves);
+        files.push(file);
+    }
+    Ok(Some(SplitFile {
+        path: path.clone(),
+        style,
+        files,
+        moves: refactored.moves,
+    }))
+}
+
+// Function to count the free functions of a file, which are what splitting it moves, including
+// those of its inline modules
+pub fn function_count(items: &[syn::Item]) -> usize {
+    items
+        .iter()
+        .map(|item| match item {
+            syn::Item::Fn(_) => 1,
+            syn::Item::Mod(syn::ItemMod { content: Some((_, items)), .. }) => function_count(items),
+            _ => 0,
+        })
+        .sum()
+}
+
+// Function to check whether a file is large enough to be split: longer than --min-lines, with at
+// least --min-fns functions. A file that doesn't parse is counted in, for splitting it to report why
+pub fn is_oversized(content: &str, options: &Options) -> bool {
+    content.lines().count() > options.max_file_lines
+        && (options.min_fns == 0 || syn::parse_file(content).map_or(usize::MAX, |file| function_count(&file.items)) >= options.min_fns)
+}
+
+// Function to split every file of the crate rooted at `root` that is longer than --min-lines and
+// has at least --min-fns functions, each rewritten in place as the parent of the modules taken
+// out of it, so that the results fit into the module tree the crate already has. Returns how many
+// files were split, or would be by a dry run
+pub fn refactor_crate(root: &std::path::Path, options: &mut Options, command: &str) -> Result<usize, String> {
+    // Step 1: Split the files that are too large, children before their parents
+    let mut splits = Vec::new();
+    let mut oversized = 0;
+    for module_file in tree::crate_module_files(root) {
+        let (content, _) = encoding::read_source(&module_file.path, true)?;
+        if !is_oversized(&content, options) {
+            continue;
+        }
+        oversized += 1;
+        progress::status(format_args!("Splitting {}", module_file.path.display()));
+        match split_file(&module_file, options) {
+            Ok(Some(split)) => splits.push(split),
+            Ok(None) => println!("{} has nothing to split out.", module_file.path.display()),
+            // An interactive run stops when its review does, and at a file it can't split, whose
+            // warning would scroll away under the questions about the next one
+            Err(e) if options.interactive => return Err(e),
+            Err(e) => warn(e),
+        }
+    }
+    let size = match options.min_fns {
+        0 => format!("longer than {} lines", options.max_file_lines),
+        min_fns => format!("longer than {} lines with {} or more functions", options.max_file_lines, min_fns),
+    };
+    if oversized == 0 {
+        println!("No file of the crate is {}.", size);
+        return Ok(0);
+    }
+
+    if let Some(stats_path) = &options.stats {
+        stats::write(std::path::Path::new(stats_path), splits.iter().flat_map(|split| &split.files))?;
+    }
+
+    // Step 2: Show what would be written, or check that it can be
+    if options.dry_run {
+        println!("Dry run, nothing was written.");
+        for split in &splits {
+            println!();
+            println!("{}", split.path.display());
+            for file in &split.files[1..] {
+                println!("  {} (crate::{})", file.path.display(), file.module_path.join("::"));
+            }
+        }
+        return Ok(splits.len());
+    }
+    if options.diff {
+        for split in &splits {
+            for file in &split.files {
+                let old = fs::read(&file.path).ok().map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+                let new = split.style.restore(&file.code, file.path == split.path);
+                let old_name = old.as_ref().map_or_else(|| "/dev/null".to_string(), |_| diff::file_label("a", &file.path));
+                print!("{}", diff::unified(old.as_deref().unwrap_or_default(), &new, &old_name, &diff::file_label("b", &file.path)));
+            }
+        }
+        return Ok(splits.len());
+    }
+    let package_root = options.verify.as_ref().map(|_| verify::find_package_root(root).ok_or("--verify needs the crate to be a cargo package")).transpose()?;
+    let warnings_before = package_root.as_ref().map(|package_root| verify::cargo_warnings(package_root)).transpose()?;
+
+    // What the files held is recorded before any of them is written, so that the run can be
+    // reverted until the next one writes
+    if options.emit == Emit::Files {
+        let package_dir = verify::find_package_root(root).unwrap_or_else(|| root.parent().unwrap_or(std::path::Path::new(".")).to_path_buf());
+        let backups: Vec<verify::Backup> = splits.iter().flat_map(|split| &split.files).map(|file| verify::backup(&file.path)).collect();
+        let moves: HashMap<Vec<String>, Vec<String>> = splits.iter().flat_map(|split| split.moves.clone()).collect();
+        revert::record(&package_dir, &backups, &moves, command).map_err(|e| format!("Nothing was written, as the run couldn't be made revertible: {}", e))?;
+    }
+
+    // Step 3: Write every split file over itself, and its new modules
+    let mut file_system = output::FileSystem::default();
+    let mut memory = output::Memory::default();
+    let mut stdout = output::Stdout::default();
+    let mut git_index;
+    let sink: &mut dyn output::OutputSink = match options.emit {
+        Emit::Files => &mut file_system,
+        Emit::Memory => &mut memory,
+        Emit::Stdout | Emit::Json => {
+            stdout.json = options.emit == Emit::Json;
+            &mut stdout
+        }
+        Emit::Git => {
+            git_index = output::GitIndex::new(root.parent().unwrap_or(std::path::Path::new(".")))?;
+            &mut git_index
+        }
+    };
+    for split in &splits {
+        for file in &split.files {
+            log::debug("write", format_args!("{}", file.path.display()));
+            sink.write(&file.path, split.style.restore(&file.code, file.path == split.path).as_bytes())?;
+        }
+    }
+    let summary = sink.finish()?;
+
+    // Step 4: Check the package as written, rolling everything back on failure
+    if let (Some(package_root), Some(warnings_before)) = (package_root, warnings_before) {
+        let failure = match verify::cargo_warnings(&package_root) {
+            Ok(warnings_after) => {
+                let new_warnings = verify::new_warnings(&warnings_before, &warnings_after);
+                (!new_warnings.is_empty()).then(|| format!("The refactoring introduced new warnings:\n{}", new_warnings.join("\n")))
+            }
+            Err(e) => Some(e),
+        };
+        if let Some(failure) = failure {
+            file_system.rollback();
+            return Err(format!("{}\nVerification failed, the generated files were rolled back.", failure));
+        }
+    }
+
+    // What is printed for another program is all that goes to stdout
+    if matches!(options.emit, Emit::Stdout | Emit::Json) {
+        print!("{}", summary);
+        return Ok(splits.len());
+    }
+    println!("Refactoring complete. Split {} of the {} files {}:", splits.len(), oversized, size);
+    for split in &splits {
+        let modules: Vec<String> = split.files[1..].iter().map(|file| file.path.display().to_string()).collect();
+        println!("  {} into {}", split.path.display(), modules.join(", "));
+    }
+    if !summary.is_empty() {
+        println!("{}", summary);
+    }
+    Ok(splits.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn functions_of_inline_modules_are_counted() {
+        let file = syn::parse_file("fn a() {}\nstruct S;\nimpl S { fn method(&self) {} }\nmod inner { fn b() {} mod deeper { fn c() {} } }\nmod outlined;\n").unwrap();
+        assert_eq!(function_count(&file.items), 3);
+    }
+
+    #[test]
+    fn files_are_oversized_past_both_limits() {
+        let content = "fn a() {}\n\nfn b() {}\n\nfn c() {}\n";
+        let mut options = Options { max_file_lines: 4, ..Options::default() };
+        assert!(is_oversized(content, &options));
+        options.min_fns = 3;
+        assert!(is_oversized(content, &options));
+        options.min_fns = 4;
+        assert!(!is_oversized(content, &options));
+        // A file that doesn't parse is split, to report why it can't be
+        assert!(is_oversized("fn a( {\n\n\n\n\n", &options));
+        options.max_file_lines = 5;
+        assert!(!is_oversized(content, &options));
+    }
+}
@@ -0,0 +1,163 @@
+use std::path::Path;
+
+// Lines of context around each change, as `diff -u` and git show them
+const CONTEXT_LINES: usize = 3;
+
+// One step of turning the old lines into the new ones
+#[derive(Clone, Copy, PartialEq)]
+enum Edit {
+    Keep(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+// Function to find the shortest edit script from `old` to `new` with Myers' algorithm. Only the
+// diagonals reachable at each step are kept, so memory grows with the square of the changes
+// rather than with the product of the file lengths
+fn edits(old: &[&str], new: &[&str]) -> Vec<Edit> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; 2 * max as usize + 3];
+    // The furthest x on each diagonal before each step, for diagonals -(d + 1)..=d + 1
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+    let at = |k: isize| (k + offset) as usize;
+
+    'search: for d in 0..=max {
+        trace.push(v[at(-d - 1)..=at(d + 1)].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[at(k - 1)] < v[at(k + 1)]) { v[at(k + 1)] } else { v[at(k - 1)] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[at(k)] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back from the end through the furthest points of each step
+    let mut script = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, snapshot) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let get = |k: isize| snapshot[(k + d + 1) as usize];
+        let k = x - y;
+        let previous_k = if k == -d || (k != d && get(k - 1) < get(k + 1)) { k + 1 } else { k - 1 };
+        let previous_x = get(previous_k);
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            script.push(Edit::Keep(x as usize - 1, y as usize - 1));
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            if x == previous_x {
+                script.push(Edit::Insert(y as usize - 1));
+            } else {
+                script.push(Edit::Delete(x as usize - 1));
+            }
+        }
+        x = previous_x;
+        y = previous_y;
+    }
+    script.reverse();
+    script
+}
+
+// Function to write a line of a hunk, marking a last line that has no newline as diff does
+fn push_line(out: &mut String, marker: char, line: &str) {
+    out.push(marker);
+    out.push_str(line);
+    if !line.ends_with('\n') {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+// Function to describe the lines a hunk covers, as `start,count` counted from 1, or from the
+// line before when the hunk has none of them
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+// Function to write the unified diff from `old` to `new`, or nothing when they are the same.
+// A missing side is named `/dev/null`, as for files being created
+pub fn unified(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let script = edits(&old_lines, &new_lines);
+    let changes: Vec<usize> = script.iter().enumerate().filter(|(_, edit)| !matches!(edit, Edit::Keep(..))).map(|(index, _)| index).collect();
+    if changes.is_empty() {
+        return String::new();
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    // Changes closer than twice the context share a hunk
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &change in &changes {
+        match hunks.last_mut() {
+            Some((_, end)) if change <= *end + 2 * CONTEXT_LINES => *end = change,
+            _ => hunks.push((change, change)),
+        }
+    }
+    for (first, last) in hunks {
+        let start = first.saturating_sub(CONTEXT_LINES);
+        let end = (last + CONTEXT_LINES + 1).min(script.len());
+        let edits = &script[start..end];
+        // Where the hunk starts in each file: at its first line there, or after the lines before it
+        let old_start = script[..start].iter().filter(|edit| !matches!(edit, Edit::Insert(_))).count();
+        let new_start = script[..start].iter().filter(|edit| !matches!(edit, Edit::Delete(_))).count();
+        let old_count = edits.iter().filter(|edit| !matches!(edit, Edit::Insert(_))).count();
+        let new_count = edits.iter().filter(|edit| !matches!(edit, Edit::Delete(_))).count();
+        out.push_str(&format!("@@ -{} +{} @@\n", hunk_range(old_start, old_count), hunk_range(new_start, new_count)));
+        for edit in edits {
+            match *edit {
+                Edit::Keep(index, _) => push_line(&mut out, ' ', old_lines[index]),
+                Edit::Delete(index) => push_line(&mut out, '-', old_lines[index]),
+                Edit::Insert(index) => push_line(&mut out, '+', new_lines[index]),
+            }
+        }
+    }
+    out
+}
+
+// Function to name a file of the diff below `a/` or `b/`, relative to the current directory as
+// `git apply` and `patch -p1` expect
+pub fn file_label(side: &str, path: &Path) -> String {
+    let current_dir = std::env::current_dir().unwrap_or_default();
+    match path.strip_prefix(&current_dir) {
+        Ok(relative) => format!("{}/{}", side, relative.display()),
+        Err(_) if path.is_relative() => format!("{}/{}", side, path.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_far_apart_get_hunks_of_their_own() {
+        let old: String = (1..=20).map(|line| format!("line {}\n", line)).collect();
+        let new = old.replace("line 2\n", "line two\n").replace("line 18\n", "");
+        let diff = unified(&old, &new, "a/src/main.rs", "b/src/main.rs");
+        let hunks: Vec<&str> = diff.lines().filter(|line| line.starts_with("@@")).collect();
+        assert_eq!(hunks, ["@@ -1,5 +1,5 @@", "@@ -15,6 +15,5 @@"]);
+        assert!(diff.starts_with("--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1,5 +1,5 @@\n line 1\n-line 2\n+line two\n line 3\n"), "{}", diff);
+        assert!(diff.contains("\n line 17\n-line 18\n line 19\n"), "{}", diff);
+        assert_eq!(unified(&old, &old, "a/src/main.rs", "b/src/main.rs"), "");
+    }
+
+    #[test]
+    fn created_files_are_diffed_against_nothing() {
+        let diff = unified("", "mod util_mod;\nfn main() {}", "/dev/null", "b/src/util_mod.rs");
+        assert_eq!(diff, "--- /dev/null\n+++ b/src/util_mod.rs\n@@ -0,0 +1,2 @@\n+mod util_mod;\n+fn main() {}\n\\ No newline at end of file\n");
+    }
+}
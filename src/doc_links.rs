@@ -0,0 +1,144 @@
+use std::ops::Range;
+
+// An intra-doc link found in a doc comment
+pub struct DocLink {
+    // Byte range of the link's path within the source, without backticks, `fn@` style
+    // disambiguators or `()`/`!` suffixes
+    pub range: Range<usize>,
+    pub path: String,
+    // Whether the path is the link text itself, as in [`Item`], rather than a `(target)`
+    pub shorthand: bool,
+}
+
+// Function to find the intra-doc links in the doc comment at `range` of `source`. Brackets in
+// fenced code blocks are code, such as the `[0]` of an example, so the lines between fences are
+// skipped; `in_code_block` carries whether a block is open across the lines of `///` comments
+pub fn find_doc_links(source: &str, range: Range<usize>, in_code_block: &mut bool) -> Vec<DocLink> {
+    let mut links = Vec::new();
+    let mut line_start = range.start;
+    for line in source[range.clone()].split_inclusive('\n') {
+        let line_range = line_start..line_start + line.len();
+        line_start = line_range.end;
+        let content = line.trim_start().trim_start_matches(['/', '!', '*', '#', '[']).trim_start();
+        let content = content.strip_prefix("doc").map_or(content, |rest| rest.trim_start_matches([' ', '=', '"']));
+        if content.starts_with("```") || content.starts_with("~~~") {
+            *in_code_block = !*in_code_block;
+        } else if !*in_code_block {
+            links.extend(find_line_links(source, line_range));
+        }
+    }
+    links
+}
+
+fn find_line_links(source: &str, range: Range<usize>) -> Vec<DocLink> {
+    let text = &source[range.clone()];
+    let mut links = Vec::new();
+    let mut search_from = 0;
+
+    while let Some(open) = text[search_from..].find('[') {
+        let text_start = search_from + open + 1;
+        let Some(close) = text[text_start..].find(']') else {
+            break;
+        };
+        let text_end = text_start + close;
+        search_from = text_end + 1;
+
+        // `[text](target)` links carry their path inside the parentheses
+        if text[search_from..].starts_with('(') {
+            let target_start = search_from + 1;
+            if let Some(target_close) = text[target_start..].find(')') {
+                let target_end = target_start + target_close;
+                if let Some(link) = parse_link_path(text, target_start..target_end, false) {
+                    links.push(offset_link(link, range.start));
+                }
+                search_from = target_end + 1;
+            }
+            continue;
+        }
+
+        if let Some(link) = parse_link_path(text, text_start..text_end, true) {
+            links.push(offset_link(link, range.start));
+        }
+    }
+
+    links
+}
+
+fn offset_link(link: DocLink, offset: usize) -> DocLink {
+    DocLink {
+        range: link.range.start + offset..link.range.end + offset,
+        path: link.path,
+        shorthand: link.shorthand,
+    }
+}
+
+// Function to extract the item path from the text of a link, if it looks like one
+fn parse_link_path(text: &str, range: Range<usize>, shorthand: bool) -> Option<DocLink> {
+    let link = &text[range.clone()];
+
+    // Strip the backticks, disambiguators like `fn@` and call or macro suffixes
+    let mut start = link.len() - link.trim_start_matches('`').len();
+    let mut path = link.trim_matches('`');
+    if let Some(at) = path.find('@') {
+        start += at + 1;
+        path = &path[at + 1..];
+    }
+    let path = path.trim_end_matches("()").trim_end_matches('!');
+
+    let is_path = path.split("::").all(|segment| {
+        segment.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+            && segment.chars().all(|c| c.is_alphanumeric() || c == '_')
+    });
+    if !is_path {
+        return None;
+    }
+
+    let path_start = range.start + start;
+    Some(DocLink {
+        range: path_start..path_start + path.len(),
+        path: path.to_string(),
+        shorthand,
+    })
+}
+
+// Function to resolve the path of a link written in `module_path` to an absolute path,
+// returning None for paths that leave the crate. Plain relative paths are assumed to name
+// items of the module itself
+pub fn absolute_link_path(path: &str, module_path: &[String]) -> Option<Vec<String>> {
+    let segments: Vec<&str> = path.split("::").collect();
+    let (mut absolute, mut consumed) = match segments[0] {
+        "crate" => (Vec::new(), 1),
+        "self" => (module_path.to_vec(), 1),
+        _ => (module_path.to_vec(), 0),
+    };
+    while segments.get(consumed) == Some(&"super") {
+        absolute.pop()?;
+        consumed += 1;
+    }
+    absolute.extend(segments[consumed..].iter().map(|segment| segment.to_string()));
+    Some(absolute)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_are_found_outside_code_blocks() {
+        let source = "/// Reads with [`fn@crate::io::read()`], see [the docs](super::Config) and [`parse!`].\n/// ```\n/// let first = words[0];\n/// ```\n/// Not a link: [1, 2]\n";
+        let mut in_code_block = false;
+        let links = find_doc_links(source, 0..source.len(), &mut in_code_block);
+        let found: Vec<(&str, &str, bool)> = links.iter().map(|link| (link.path.as_str(), &source[link.range.clone()], link.shorthand)).collect();
+        assert_eq!(found, [("crate::io::read", "crate::io::read", true), ("super::Config", "super::Config", false), ("parse", "parse", true)]);
+        assert!(!in_code_block);
+    }
+
+    #[test]
+    fn link_paths_are_resolved_against_their_module() {
+        let module_path = ["net".to_string(), "tcp".to_string()];
+        assert_eq!(absolute_link_path("crate::io::read", &module_path).unwrap(), ["io", "read"]);
+        assert_eq!(absolute_link_path("super::Config", &module_path).unwrap(), ["net", "Config"]);
+        assert_eq!(absolute_link_path("Stream", &module_path).unwrap(), ["net", "tcp", "Stream"]);
+        assert!(absolute_link_path("super::super::super::Outside", &module_path).is_none());
+    }
+}
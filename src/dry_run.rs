@@ -0,0 +1,115 @@
+use quote::ToTokens;
+use syn::spanned::Spanned;
+use syn::Item;
+
+use crate::tree::{self, ModuleTree};
+use crate::GeneratedFile;
+
+// Function to find the `use` statements of a file as written, and the names of its other items
+// by kind, such as functions and types
+pub fn contents(code: &str) -> (Vec<String>, Vec<String>, Vec<String>) {
+    let Ok(file) = syn::parse_file(code) else {
+        return (Vec::new(), Vec::new(), Vec::new());
+    };
+    let (mut imports, mut functions, mut others) = (Vec::new(), Vec::new(), Vec::new());
+    for item in &file.items {
+        match item {
+            Item::Use(_) => imports.push(code[item.span().byte_range()].to_string()),
+            Item::Fn(func) => functions.push(func.sig.ident.to_string()),
+            Item::Struct(item) => others.push(item.ident.to_string()),
+            Item::Enum(item) => others.push(item.ident.to_string()),
+            Item::Trait(item) => others.push(item.ident.to_string()),
+            Item::Type(item) => others.push(item.ident.to_string()),
+            Item::Const(item) => others.push(item.ident.to_string()),
+            Item::Static(item) => others.push(item.ident.to_string()),
+            Item::Mod(item) => others.push(format!("mod {}", item.ident)),
+            Item::Impl(item) => others.push(format!("impl {}", item.self_ty.to_token_stream().to_string().replace(' ', ""))),
+            Item::ForeignMod(_) => others.push("extern block".to_string()),
+            _ => {}
+        }
+    }
+    (imports, functions, others)
+}
+
+// Function to compare imports by their tokens, so that formatting alone doesn't count as a change,
+// down to the trailing comma rustfmt puts in a list it spreads over lines
+pub fn normalized(import: &str) -> String {
+    syn::parse_str::<Item>(import).map_or_else(|_| import.to_string(), |item| item.to_token_stream().to_string().replace(", }", "}"))
+}
+
+// Function to print what a refactoring would write without writing it: the module trees, the
+// items and imports of each generated module, and how the root's imports change
+pub fn print_summary(files: &[GeneratedFile], root_path: &std::path::Path, original: &str, current: &ModuleTree, proposed: &ModuleTree) {
+    println!("Dry run, nothing was written.");
+    println!();
+    tree::print_side_by_side(current, proposed);
+
+    for file in files.iter().filter(|file| !file.module_path.is_empty()) {
+        let (imports, functions, others) = contents(&file.code);
+        println!();
+        println!("{} (crate::{})", file.path.display(), file.module_path.join("::"));
+        if !functions.is_empty() {
+            println!("  functions: {}", functions.join(", "));
+        }
+        if !others.is_empty() {
+            println!("  items: {}", others.join(", "));
+        }
+        if !imports.is_empty() {
+            println!("  imports:");
+            for import in imports {
+                println!("    {}", import.replace('\n', "\n    "));
+            }
+        }
+    }
+
+    if let Some(root) = files.iter().find(|file| file.path == root_path) {
+        let (before, _, _) = contents(original);
+        let (after, _, _) = contents(&root.code);
+        let before_normalized: Vec<String> = before.iter().map(|import| normalized(import)).collect();
+        let after_normalized: Vec<String> = after.iter().map(|import| normalized(import)).collect();
+        println!();
+        println!("{} (the new root)", root.path.display());
+        for import in before.iter().filter(|import| !after_normalized.contains(&normalized(import))) {
+            println!("  drops {}", import.replace('\n', " "));
+        }
+        for import in after.iter().filter(|import| !before_normalized.contains(&normalized(import))) {
+            println!("  adds {}", import.replace('\n', " "));
+        }
+    }
+
+    let others: Vec<String> = files
+        .iter()
+        .filter(|file| file.module_path.is_empty() && file.path != root_path)
+        .map(|file| file.path.display().to_string())
+        .collect();
+    if !others.is_empty() {
+        println!();
+        println!("Also writes {}.", others.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contents_sort_the_items_of_a_file_by_kind() {
+        let code = "use std::fmt;\nuse std::{\n    fs,\n    io,\n};\n\nstruct Config;\nenum Mode { Fast }\nimpl fmt::Debug for Config {\n    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { Ok(()) }\n}\nmod util;\nfn main() {}\nfn parse() {}\n";
+        let (imports, functions, others) = contents(code);
+        assert_eq!(imports, vec!["use std::fmt;", "use std::{\n    fs,\n    io,\n};"]);
+        assert_eq!(functions, vec!["main", "parse"]);
+        assert_eq!(others, vec!["Config", "Mode", "impl Config", "mod util"]);
+    }
+
+    #[test]
+    fn files_that_dont_parse_have_no_contents() {
+        assert_eq!(contents("fn main( {"), (Vec::new(), Vec::new(), Vec::new()));
+    }
+
+    #[test]
+    fn imports_written_differently_compare_the_same() {
+        assert_eq!(normalized("use std::{\n    fs,\n    io,\n};"), normalized("use std::{fs, io};"));
+        assert_ne!(normalized("use std::{fs, io};"), normalized("use std::{io, fs};"));
+        assert_eq!(normalized("not rust"), "not rust");
+    }
+}
@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::Path;
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+
+// How a source file was encoded, to write the generated files back the same way
+#[derive(Clone, Copy, Default)]
+pub struct Style {
+    bom: bool,
+    crlf: bool,
+}
+
+impl Style {
+    // Function to put the newlines (and the byte order mark of the root) back as they were read
+    pub fn restore(self, code: &str, is_root: bool) -> String {
+        let code = if self.crlf { code.replace('\n', "\r\n") } else { code.to_string() };
+        if self.bom && is_root {
+            format!("\u{feff}{}", code)
+        } else {
+            code
+        }
+    }
+}
+
+// Function to read a source file as text with `\n` newlines and no byte order mark, replacing
+// invalid UTF-8 when `lossy` is set instead of failing
+pub fn read_source(path: &Path, lossy: bool) -> Result<(String, Style), String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+    decode(&bytes, lossy).map_err(|e| format!("Failed to read {:?}: {}", path, e))
+}
+
+// Function to decode the bytes of a source file, see `read_source`
+pub fn decode(bytes: &[u8], lossy: bool) -> Result<(String, Style), String> {
+    if bytes.starts_with(b"\xff\xfe") || bytes.starts_with(b"\xfe\xff") {
+        return Err("the file is UTF-16 encoded, but Rust sources must be UTF-8".to_string());
+    }
+    let bom = bytes.starts_with(UTF8_BOM);
+    let bytes = bytes.strip_prefix(UTF8_BOM).unwrap_or(bytes);
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text.to_string(),
+        Err(e) if lossy => {
+            eprintln!("Warning: invalid UTF-8 at byte {} and after was replaced with U+FFFD", e.valid_up_to());
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+        Err(e) => return Err(format!("invalid UTF-8 at byte {} (pass --lossy to replace it)", e.valid_up_to())),
+    };
+
+    // The style is the one most lines end with, so a stray line ending doesn't flip the whole file
+    let crlf_count = text.matches("\r\n").count();
+    let crlf = crlf_count > text.matches('\n').count() - crlf_count;
+    Ok((text.replace("\r\n", "\n"), Style { bom, crlf }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources_are_written_back_as_they_were_read() {
+        let (text, style) = decode(b"\xef\xbb\xbffn main() {}\r\nfn other() {}\r\n", false).unwrap();
+        assert_eq!(text, "fn main() {}\nfn other() {}\n");
+        assert_eq!(style.restore("mod util_mod;\n", true), "\u{feff}mod util_mod;\r\n");
+        // Only the root keeps the byte order mark
+        assert_eq!(style.restore("fn helper() {}\n", false), "fn helper() {}\r\n");
+
+        // A stray `\r\n` doesn't make a file of `\n` lines a CRLF one
+        let (_, style) = decode(b"fn a() {}\nfn b() {}\r\nfn c() {}\n", false).unwrap();
+        assert_eq!(style.restore("fn a() {}\n", true), "fn a() {}\n");
+    }
+
+    #[test]
+    fn sources_that_are_not_utf8_are_refused_unless_lossy() {
+        assert!(decode(b"\xff\xfef\x00n\x00", true).err().unwrap().contains("UTF-16"));
+        assert_eq!(decode(b"// caf\xe9\n", false).err().unwrap(), "invalid UTF-8 at byte 6 (pass --lossy to replace it)");
+        assert_eq!(decode(b"// caf\xe9\n", true).unwrap().0, "// caf\u{fffd}\n");
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use proc_macro2::LineColumn;
+use syn::ext::IdentExt;
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+use syn::{ImplItemFn, ItemFn, UseTree};
+
+use crate::{audit, encoding, plan, tree, verify, MODS_REGION};
+
+// A compiler error after an apply, with the plan decision that likely caused it and what would fix it
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "schema", rename = "refactor/error-explanation/v1"))]
+pub struct Explanation {
+    // Where the error is, as cargo reported it
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub code: Option<String>,
+    pub message: String,
+    // What went wrong, such as "missing import for `Duration`"
+    pub cause: String,
+    // The decision behind it, such as "signature analysis gap for fn `wait_for`", when one is known
+    pub decision: Option<String>,
+    pub remediation: Vec<String>,
+}
+
+// The kinds of errors decisions of the tool are known to cause
+#[derive(PartialEq)]
+enum Kind {
+    // A name the moved code uses isn't imported where it went
+    MissingName,
+    // A method is called without its trait in scope
+    MissingMethod,
+    // An item the moved code uses isn't visible from where it went
+    Private,
+    // An import no longer leads to its item
+    BadImport,
+    // A name is brought into scope twice, such as by a glob and an import
+    Duplicate,
+    Other,
+}
+
+fn kind(code: Option<&str>, message: &str) -> Kind {
+    match code {
+        Some("E0405" | "E0412" | "E0422" | "E0423" | "E0425" | "E0433" | "E0531" | "E0532" | "E0574") => Kind::MissingName,
+        Some("E0599") => Kind::MissingMethod,
+        Some("E0603" | "E0616" | "E0624") => Kind::Private,
+        Some("E0432") => Kind::BadImport,
+        Some("E0252" | "E0254" | "E0255" | "E0428" | "E0659") => Kind::Duplicate,
+        // Macros that aren't in scope are reported without a code
+        None if message.starts_with("cannot find macro") => Kind::MissingName,
+        _ => Kind::Other,
+    }
+}
+
+// Where in a function the error is, which tells which part of the analysis missed it
+enum Site {
+    Signature,
+    Macro,
+    Body,
+}
+
+// A function of the erroring file, with the lines it spans and the macros in it
+struct Function {
+    name: String,
+    start: LineColumn,
+    end: LineColumn,
+    signature_end: LineColumn,
+    macros: Vec<(LineColumn, LineColumn)>,
+    // Only free functions are placed by the plan
+    free: bool,
+}
+
+#[derive(Default)]
+struct FunctionVisitor {
+    self_type: Option<String>,
+    functions: Vec<Function>,
+}
+
+impl FunctionVisitor {
+    fn note(&mut self, name: &syn::Ident, sig: &syn::Signature, block: &syn::Block, free: bool) {
+        let name = match &self.self_type {
+            Some(self_type) => format!("{}::{}", self_type, name),
+            None => name.to_string(),
+        };
+        let mut macros = MacroVisitor::default();
+        macros.visit_block(block);
+        self.functions.push(Function {
+            name,
+            start: sig.span().start(),
+            end: block.span().end(),
+            signature_end: sig.span().end(),
+            macros: macros.spans,
+            free,
+        });
+    }
+}
+
+impl Visit<'_> for FunctionVisitor {
+    fn visit_item_fn(&mut self, node: &ItemFn) {
+        self.note(&node.sig.ident, &node.sig, &node.block, self.self_type.is_none());
+        syn::visit::visit_item_fn(self, node);
+    }
+
+    fn visit_item_impl(&mut self, node: &syn::ItemImpl) {
+        let self_type = quote::ToTokens::to_token_stream(&node.self_ty).to_string().replace(' ', "");
+        let outer = self.self_type.replace(self_type);
+        syn::visit::visit_item_impl(self, node);
+        self.self_type = outer;
+    }
+
+    fn visit_impl_item_fn(&mut self, node: &ImplItemFn) {
+        self.note(&node.sig.ident, &node.sig, &node.block, false);
+        syn::visit::visit_impl_item_fn(self, node);
+    }
+}
+
+#[derive(Default)]
+struct MacroVisitor {
+    spans: Vec<(LineColumn, LineColumn)>,
+}
+
+impl Visit<'_> for MacroVisitor {
+    fn visit_macro(&mut self, node: &syn::Macro) {
+        self.spans.push((node.span().start(), node.delimiter.span().close().end()));
+    }
+}
+
+fn position(line_column: LineColumn) -> (usize, usize) {
+    (line_column.line, line_column.column)
+}
+
+// Function to split a line of cargo's short message format into its location, code and message
+fn parse_error(line: &str) -> Option<(PathBuf, usize, usize, Option<String>, String)> {
+    let (location, rest) = line.split_once(": error")?;
+    let mut parts = location.rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line_number = parts.next()?.parse().ok()?;
+    let path = PathBuf::from(parts.next()?);
+    let (code, message) = match rest.strip_prefix('[') {
+        Some(rest) => {
+            let (code, message) = rest.split_once("]: ")?;
+            (Some(code.to_string()), message)
+        }
+        None => (None, rest.trim_start_matches(": ")),
+    };
+    Some((path, line_number, column, code, message.to_string()))
+}
+
+// Function to find the first name quoted in a message, such as `Duration` in "use of undeclared
+// type `Duration`"
+fn quoted_name(message: &str) -> Option<&str> {
+    let (_, rest) = message.split_once('`')?;
+    rest.split_once('`').map(|(name, _)| name)
+}
+
+// Function to collect the names the crate's files import, with the path of each, so that a
+// missing import can be copied from a module that has it
+fn import_paths(root: &Path) -> HashMap<String, String> {
+    fn collect(tree: &UseTree, prefix: &mut Vec<String>, paths: &mut HashMap<String, String>) {
+        match tree {
+            UseTree::Path(use_path) => {
+                prefix.push(use_path.ident.to_string());
+                collect(&use_path.tree, prefix, paths);
+                prefix.pop();
+            }
+            // Paths relative to the importing module would lead elsewhere from another one
+            _ if prefix.first().is_some_and(|first| first == "self" || first == "super") => {}
+            UseTree::Name(use_name) if use_name.ident != "self" => {
+                let path = [prefix.as_slice(), &[use_name.ident.to_string()]].concat().join("::");
+                paths.entry(use_name.ident.to_string()).or_insert(path);
+            }
+            UseTree::Name(_) => {
+                if let Some(last) = prefix.last() {
+                    paths.entry(last.clone()).or_insert_with(|| prefix.join("::"));
+                }
+            }
+            UseTree::Rename(use_rename) => {
+                let path = [prefix.as_slice(), &[use_rename.ident.to_string()]].concat().join("::");
+                paths.entry(use_rename.rename.to_string()).or_insert(format!("{} as {}", path, use_rename.rename));
+            }
+            UseTree::Glob(_) => {}
+            UseTree::Group(group) => group.items.iter().for_each(|tree| collect(tree, prefix, paths)),
+        }
+    }
+
+    let mut paths = HashMap::new();
+    for module_file in tree::crate_module_files(root) {
+        let Some(syntax_tree) = encoding::read_source(&module_file.path, true).ok().and_then(|(content, _)| syn::parse_file(&content).ok()) else {
+            continue;
+        };
+        for item in &syntax_tree.items {
+            if let syn::Item::Use(item_use) = item {
+                collect(&item_use.tree, &mut Vec::new(), &mut paths);
+            }
+        }
+    }
+    paths
+}
+
+// Function to find the files of the modules the tool generated, which the root declares in its
+// region of `mod` declarations
+fn generated_files(root: &Path) -> Vec<PathBuf> {
+    let root_dir = root.parent().unwrap_or(Path::new("."));
+    let source = encoding::read_source(root, true).map(|(source, _)| source).unwrap_or_default();
+    let Some(declarations) = audit::region_body(&source, MODS_REGION).and_then(|region| syn::parse_file(region).ok()) else {
+        return Vec::new();
+    };
+    declarations
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Mod(item_mod) => tree::declared_module_file(item_mod, &item_mod.ident.unraw().to_string(), root_dir),
+            _ => None,
+        })
+        .filter_map(|file| file.canonicalize().ok())
+        .collect()
+}
+
+// Function to find the file cargo reported an error in, whose path is relative to the workspace
+// root, which may be above the package
+fn locate(package_root: &Path, path: &Path) -> Option<PathBuf> {
+    package_root.ancestors().map(|dir| dir.join(path)).find(|candidate| candidate.is_file())
+}
+
+// Function to run `cargo check` on the package of the crate at `crate_path` and explain each of
+// its errors by the decision of the crate's plan that likely caused it
+pub fn explain_errors(crate_path: &Path) -> Result<Vec<Explanation>, String> {
+    let root = tree::resolve_crate_root(crate_path).ok_or_else(|| format!("Found no crate root at {:?}", crate_path))?;
+    let package_root = verify::find_package_root(&root).ok_or_else(|| format!("Found no Cargo.toml above {:?}", root))?;
+    let plan_path = package_root.join(plan::PLAN_FILE);
+    let decisions = plan::read_decisions(&plan_path);
+    let imports = import_paths(&root);
+    let generated = generated_files(&root);
+    let plan_name = plan_path.display().to_string();
+
+    let mut explanations = Vec::new();
+    for error in verify::cargo_errors(&package_root)? {
+        let Some((path, line, column, code, message)) = parse_error(&error) else {
+            continue;
+        };
+        let kind = kind(code.as_deref(), &message);
+        let name = quoted_name(&message).unwrap_or_default().to_string();
+        let file_name = path.file_name().map_or_else(|| path.display().to_string(), |name| name.to_string_lossy().into_owned());
+
+        // The innermost function around the error, and where in it the error is
+        let mut visitor = FunctionVisitor::default();
+        let file = locate(&package_root, &path);
+        let syntax_tree = file
+            .as_ref()
+            .and_then(|file| encoding::read_source(file, true).ok())
+            .and_then(|(content, _)| syn::parse_file(&content).ok());
+        if let Some(syntax_tree) = &syntax_tree {
+            visitor.visit_file(syntax_tree);
+        }
+        let at = (line, column.saturating_sub(1));
+        let function = visitor
+            .functions
+            .iter()
+            .filter(|function| position(function.start) <= at && at <= position(function.end))
+            .min_by_key(|function| function.end.line - function.start.line);
+        let site = function.map(|function| {
+            if at <= position(function.signature_end) {
+                Site::Signature
+            } else if function.macros.iter().any(|(start, end)| position(*start) <= at && at <= position(*end)) {
+                Site::Macro
+            } else {
+                Site::Body
+            }
+        });
+
+        // The plan entry of the function, which paths name by the module they were written in
+        let decision = function.filter(|function| function.free).and_then(|function| {
+            let mut entries: Vec<(&String, &(String, Option<String>))> = decisions
+                .iter()
+                .filter(|(item_path, _)| item_path.rsplit("::").next() == Some(function.name.as_str()))
+                .collect();
+            entries.sort();
+            let stem = file_name.trim_end_matches(".rs");
+            entries.iter().find(|(_, (group, _))| stem.starts_with(group.as_str())).or(entries.first()).copied()
+        });
+
+        let subject = match function {
+            Some(function) => format!("fn `{}`", function.name),
+            None => format!("the items of {}", file_name),
+        };
+        // Code the tool didn't move or generate is left out, its errors aren't the tool's
+        let is_generated = file.and_then(|file| file.canonicalize().ok()).is_some_and(|file| generated.contains(&file));
+        let gap = match (&kind, &site) {
+            _ if decision.is_none() && !is_generated => None,
+            (Kind::MissingName, Some(Site::Signature)) => Some("signature analysis gap"),
+            (Kind::MissingName, Some(Site::Macro)) => Some("macro analysis gap"),
+            (Kind::MissingName, _) => Some("body analysis gap"),
+            (Kind::MissingMethod, _) => Some("trait method analysis gap"),
+            (Kind::Private, _) => Some("visibility rewrite gap"),
+            (Kind::BadImport, _) => Some("import path rewrite gap"),
+            (Kind::Duplicate, _) => Some("import deduplication gap"),
+            (Kind::Other, _) => None,
+        };
+        let placed = decision.map(|(_, (group, rationale))| match rationale {
+            Some(rationale) => format!(", which the plan put in `{}` ({})", group, rationale),
+            None => format!(", which the plan put in `{}`", group),
+        });
+        let decision_text = gap.map(|gap| format!("{} for {}{}", gap, subject, placed.clone().unwrap_or_default()));
+
+        let cause = match kind {
+            Kind::MissingName => format!("missing import for `{}`", name),
+            Kind::MissingMethod => format!("missing trait import for the method `{}`", name),
+            Kind::Private => format!("`{}` isn't visible from {}", name, file_name),
+            Kind::BadImport => format!("the import of `{}` doesn't resolve", name),
+            Kind::Duplicate => format!("`{}` is brought into scope twice", name),
+            Kind::Other => message.clone(),
+        };
+
+        let mut remediation = Vec::new();
+        match kind {
+            Kind::MissingName => match imports.get(&name) {
+                Some(import) => remediation.push(format!("add `use {};` to {}", import, path.display())),
+                None => remediation.push(format!("import `{}` in {}", name, path.display())),
+            },
+            Kind::MissingMethod => {
+                let traits: Vec<String> = crate::KNOWN_TRAIT_METHODS
+                    .iter()
+                    .chain(crate::ALLOC_TRAIT_METHODS)
+                    .filter(|(_, methods)| methods.contains(&name.as_str()))
+                    .map(|(trait_name, _)| match imports.get(*trait_name) {
+                        Some(import) => format!("`use {};`", import),
+                        None => format!("the `{}` trait", trait_name),
+                    })
+                    .collect();
+                if traits.is_empty() {
+                    remediation.push(format!("import the trait providing `.{}()` in {}", name, path.display()));
+                } else {
+                    remediation.push(format!("add {} to {}", traits.join(" or "), path.display()));
+                }
+            }
+            Kind::Private => remediation.push(format!("make `{}` `pub(crate)` where it is declared", name)),
+            Kind::BadImport => remediation.push(format!("point the import at where the item lives now, which `refactor tree {}` shows", crate_path.display())),
+            Kind::Duplicate => remediation.push(format!("remove one of the imports of `{}` in {}", name, path.display())),
+            Kind::Other => remediation.push("no decision of the tool is known to cause this error, compare with the code before the apply".to_string()),
+        }
+        if let (Some((item_path, _)), true) = (decision, gap.is_some()) {
+            remediation.push(format!(
+                "or keep it where it was: set `{} = self` in {} and re-run with --plan {}",
+                item_path, plan_name, plan_name
+            ));
+        }
+
+        explanations.push(Explanation {
+            path,
+            line,
+            column,
+            code,
+            message,
+            cause,
+            decision: decision_text,
+            remediation,
+        });
+    }
+    Ok(explanations)
+}
+
+// Function to print the explanations of the errors, each followed by what would fix it
+pub fn print_explanations(explanations: &[Explanation]) {
+    for explanation in explanations {
+        let code = explanation.code.as_deref().unwrap_or("error");
+        let file_name = explanation.path.display();
+        let location = format!("{}:{}:{}", file_name, explanation.line, explanation.column);
+        match &explanation.decision {
+            Some(decision) => println!("{} in {}: {} — {}", code, location, explanation.cause, decision),
+            None => println!("{} in {}: {}", code, location, explanation.cause),
+        }
+        if explanation.cause != explanation.message {
+            println!("    compiler: {}", explanation.message);
+        }
+        for remediation in &explanation.remediation {
+            println!("    fix: {}", remediation);
+        }
+    }
+    let explained = explanations.iter().filter(|explanation| explanation.decision.is_some()).count();
+    match explanations.len() {
+        0 => println!("cargo check reports no errors."),
+        1 => println!("1 error, {} explained by decisions of the tool.", explained),
+        count => println!("{} errors, {} explained by decisions of the tool.", count, explained),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cargo_errors_are_split_into_location_code_and_message() {
+        let error = parse_error("src/net_mod.rs:12:5: error[E0433]: failed to resolve: use of undeclared type `Duration`").unwrap();
+        assert_eq!(error, (PathBuf::from("src/net_mod.rs"), 12, 5, Some("E0433".to_string()), "failed to resolve: use of undeclared type `Duration`".to_string()));
+        let (_, _, _, code, message) = parse_error("src/main.rs:3:1: error: cannot find macro `log` in this scope").unwrap();
+        assert_eq!((code, message.as_str()), (None, "cannot find macro `log` in this scope"));
+        assert!(parse_error("src/main.rs:3:1: warning: unused import: `std::fs`").is_none());
+        assert_eq!(quoted_name(&error.4), Some("Duration"));
+    }
+
+    #[test]
+    fn errors_are_sorted_by_the_decision_likely_causing_them() {
+        assert!(kind(Some("E0412"), "cannot find type `Duration` in this scope") == Kind::MissingName);
+        assert!(kind(None, "cannot find macro `log` in this scope") == Kind::MissingName);
+        assert!(kind(Some("E0599"), "no method named `read_to_string` found") == Kind::MissingMethod);
+        assert!(kind(Some("E0603"), "function `parse` is private") == Kind::Private);
+        assert!(kind(Some("E0432"), "unresolved import `crate::parse`") == Kind::BadImport);
+        assert!(kind(Some("E0252"), "the name `fs` is defined multiple times") == Kind::Duplicate);
+        assert!(kind(Some("E0308"), "mismatched types") == Kind::Other);
+    }
+}
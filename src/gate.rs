@@ -0,0 +1,176 @@
+use std::path::{Path, PathBuf};
+use quote::ToTokens;
+use syn::visit::Visit;
+use syn::{ImplItemFn, ItemFn, ItemImpl, TraitItemFn};
+
+use crate::{encoding, tree};
+
+// Thresholds used when the command line doesn't set them
+pub const DEFAULT_MAX_FILE_LINES: usize = 1500;
+pub const DEFAULT_MAX_FN_LINES: usize = 300;
+
+// A file or function longer than the gate allows, and what to do about it
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "schema", rename = "refactor/gate-violation/v1"))]
+pub struct Violation {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    // What is too long: the file itself, or the function with this path, such as `Parser::parse`
+    pub item: Option<String>,
+    pub lines: usize,
+    pub limit: usize,
+    pub suggestion: String,
+}
+
+// Collects the functions of a file with where they start and how many lines they span
+struct FunctionVisitor {
+    self_type: Option<String>,
+    functions: Vec<(String, proc_macro2::LineColumn, usize)>,
+}
+
+impl FunctionVisitor {
+    fn note(&mut self, name: &syn::Ident, start: proc_macro2::Span, end: proc_macro2::Span) {
+        let name = match &self.self_type {
+            Some(self_type) => format!("{}::{}", self_type, name),
+            None => name.to_string(),
+        };
+        self.functions.push((name, start.start(), end.end().line + 1 - start.start().line));
+    }
+}
+
+impl Visit<'_> for FunctionVisitor {
+    fn visit_item_fn(&mut self, node: &ItemFn) {
+        self.note(&node.sig.ident, node.sig.fn_token.span, node.block.brace_token.span.close());
+        syn::visit::visit_item_fn(self, node);
+    }
+
+    fn visit_item_impl(&mut self, node: &ItemImpl) {
+        let outer = self.self_type.replace(node.self_ty.to_token_stream().to_string().replace(' ', ""));
+        syn::visit::visit_item_impl(self, node);
+        self.self_type = outer;
+    }
+
+    fn visit_impl_item_fn(&mut self, node: &ImplItemFn) {
+        self.note(&node.sig.ident, node.sig.fn_token.span, node.block.brace_token.span.close());
+        syn::visit::visit_impl_item_fn(self, node);
+    }
+
+    fn visit_trait_item_fn(&mut self, node: &TraitItemFn) {
+        if let Some(block) = &node.default {
+            self.note(&node.sig.ident, node.sig.fn_token.span, block.brace_token.span.close());
+        }
+        syn::visit::visit_trait_item_fn(self, node);
+    }
+}
+
+// Function to find the files of the crate at `crate_path` longer than `max_file_lines` and the
+// functions longer than `max_fn_lines`, in the order of their files and lines
+pub fn check(crate_path: &Path, max_file_lines: usize, max_fn_lines: usize) -> Result<Vec<Violation>, String> {
+    let root = tree::resolve_crate_root(crate_path).ok_or_else(|| format!("Found no crate root at {:?}", crate_path))?;
+    let mut violations = Vec::new();
+    for module_file in tree::crate_module_files(&root) {
+        let (content, _) = encoding::read_source(&module_file.path, true)?;
+        let syntax_tree = syn::parse_file(&content).map_err(|e| format!("Failed to parse {:?}: {}", module_file.path, e))?;
+        let display_path = module_file.path.display();
+
+        let file_lines = content.lines().count();
+        if file_lines > max_file_lines {
+            // A crate root can be split by itself, a module file along with the rest of the crate
+            let suggestion = if module_file.module_path.is_empty() {
+                format!("refactor {}", display_path)
+            } else {
+                format!("refactor --min-lines {} {}", max_file_lines, crate_path.display())
+            };
+            violations.push(Violation {
+                path: module_file.path.clone(),
+                line: 1,
+                column: 1,
+                item: None,
+                lines: file_lines,
+                limit: max_file_lines,
+                suggestion,
+            });
+        }
+
+        let mut visitor = FunctionVisitor {
+            self_type: None,
+            functions: Vec::new(),
+        };
+        visitor.visit_file(&syntax_tree);
+        for (name, start, lines) in visitor.functions.into_iter().filter(|(_, _, lines)| *lines > max_fn_lines) {
+            violations.push(Violation {
+                path: module_file.path.clone(),
+                line: start.line,
+                column: start.column + 1,
+                suggestion: format!("extract the steps of `{}` into functions of their own", name),
+                item: Some(name),
+                lines,
+                limit: max_fn_lines,
+            });
+        }
+    }
+    violations.sort_by(|a, b| a.path.cmp(&b.path).then(a.line.cmp(&b.line)));
+    Ok(violations)
+}
+
+// Function to print the violations like compiler diagnostics
+pub fn print_violations(violations: &[Violation]) {
+    for violation in violations {
+        let what = match &violation.item {
+            Some(name) => format!("function `{}`", name),
+            None => "file".to_string(),
+        };
+        println!(
+            "{}:{}:{}: {} has {} lines, more than the limit of {}",
+            violation.path.display(),
+            violation.line,
+            violation.column,
+            what,
+            violation.lines,
+            violation.limit
+        );
+        println!("    suggestion: {}", violation.suggestion);
+    }
+    match violations.len() {
+        0 => println!("No file or function exceeds the thresholds."),
+        1 => println!("1 violation."),
+        count => println!("{} violations.", count),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn long_files_and_functions_are_reported_in_order() {
+        let dir = std::env::temp_dir().join(format!("refactor-gate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.rs"), "mod parser;\n\nfn main() {\n    let a = 1;\n    let b = 2;\n    println!(\"{}\", a + b);\n}\n").unwrap();
+        fs::write(
+            dir.join("src/parser.rs"),
+            "pub struct Parser;\n\nimpl Parser {\n    pub fn parse(&self) {\n        let a = 1;\n        let _ = a;\n    }\n}\n\ntrait Step {\n    fn run(&self) {\n        let b = 2;\n        let _ = b;\n    }\n}\n",
+        )
+        .unwrap();
+
+        let violations = check(&dir, 10, 3).unwrap();
+        let found: Vec<(String, usize, usize, Option<&str>, usize)> = violations
+            .iter()
+            .map(|violation| (violation.path.strip_prefix(&dir).unwrap().display().to_string(), violation.line, violation.column, violation.item.as_deref(), violation.lines))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("src/main.rs".to_string(), 3, 1, Some("main"), 5),
+                ("src/parser.rs".to_string(), 1, 1, None, 15),
+                ("src/parser.rs".to_string(), 4, 9, Some("Parser::parse"), 4),
+                ("src/parser.rs".to_string(), 11, 5, Some("run"), 4),
+            ]
+        );
+        assert_eq!(violations[1].suggestion, format!("refactor --min-lines 10 {}", dir.display()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,384 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::{Fields, ImplItem, Item};
+
+use crate::{collect_idents, idents, state_machine, tree};
+
+// A struct is only a god object with at least this many inherent methods and fields
+const MIN_METHODS: usize = 20;
+const MIN_FIELDS: usize = 10;
+// Two field clusters merge when at least this share of the methods using either uses both
+const CLUSTER_SIMILARITY: f64 = 0.3;
+// Clusters of fewer fields stay on the struct rather than becoming a component of their own
+const MIN_COMPONENT_FIELDS: usize = 2;
+
+// A struct proposed to be split into a component per cluster of fields its methods use together
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "schema", rename = "refactor/god-object/v1"))]
+pub struct Proposal {
+    pub struct_name: String,
+    pub defined_in: PathBuf,
+    pub field_count: usize,
+    pub method_count: usize,
+    // The crate's modules that mention the struct, by path
+    pub used_in: Vec<String>,
+    pub components: Vec<Component>,
+    // Methods using the fields of several components, which stay on the struct to coordinate them
+    pub coordinating_methods: Vec<String>,
+    // Methods using no field at all, which can go wherever their callers are
+    pub stateless_methods: Vec<String>,
+    // Fields left on the struct: those no method uses and those of clusters too small to split
+    pub remaining_fields: Vec<String>,
+}
+
+// A struct to split out of a god object, with its own impl in a submodule
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Component {
+    pub name: String,
+    pub module_file: PathBuf,
+    pub fields: Vec<String>,
+    pub methods: Vec<String>,
+}
+
+// A struct with named fields and where it is defined
+struct StructInfo {
+    fields: Vec<String>,
+    defined_in: PathBuf,
+    module_dir: PathBuf,
+}
+
+// An inherent method and the fields of `self` it uses
+struct Method {
+    name: String,
+    fields: HashSet<String>,
+}
+
+// Function to find the god objects of the crate rooted at `root` and propose how to split them
+pub fn find_god_objects(root: &Path) -> Vec<Proposal> {
+    let module_files = tree::crate_module_files(root);
+    let mut structs: HashMap<String, StructInfo> = HashMap::new();
+    let mut methods: HashMap<String, Vec<Method>> = HashMap::new();
+    let mut mentions: HashMap<String, BTreeSet<String>> = HashMap::new();
+
+    for module_file in &module_files {
+        let Some(content) = fs::read_to_string(&module_file.path).ok() else {
+            continue;
+        };
+        let Ok(syntax_tree) = syn::parse_file(&content) else {
+            continue;
+        };
+        collect_items(&syntax_tree.items, module_file, &mut structs, &mut methods);
+
+        let mut idents = HashSet::new();
+        collect_idents(content.parse().unwrap_or_default(), &mut idents);
+        let module_path = if module_file.module_path.is_empty() {
+            "crate".to_string()
+        } else {
+            module_file.module_path.join("::")
+        };
+        for ident in idents {
+            mentions.entry(ident).or_default().insert(module_path.clone());
+        }
+    }
+
+    let mut proposals = Vec::new();
+    for (struct_name, info) in &structs {
+        let Some(struct_methods) = methods.get(struct_name) else {
+            continue;
+        };
+        let used_in: Vec<String> = mentions.get(struct_name).map(|modules| modules.iter().cloned().collect()).unwrap_or_default();
+        // Used everywhere means by at least half of the crate's modules
+        if struct_methods.len() < MIN_METHODS
+            || info.fields.len() < MIN_FIELDS
+            || used_in.len() < module_files.len().div_ceil(2)
+        {
+            continue;
+        }
+        proposals.push(propose_split(struct_name, info, struct_methods, used_in));
+    }
+    proposals.sort_by(|a, b| b.method_count.cmp(&a.method_count).then_with(|| a.struct_name.cmp(&b.struct_name)));
+    proposals
+}
+
+// Function to collect the structs with named fields and the inherent methods among `items`,
+// looking into inline modules too
+fn collect_items(
+    items: &[Item],
+    module_file: &tree::ModuleFile,
+    structs: &mut HashMap<String, StructInfo>,
+    methods: &mut HashMap<String, Vec<Method>>,
+) {
+    for item in items {
+        match item {
+            Item::Struct(item_struct) => {
+                if let Fields::Named(fields) = &item_struct.fields {
+                    structs.insert(
+                        item_struct.ident.to_string(),
+                        StructInfo {
+                            fields: fields.named.iter().filter_map(|field| field.ident.as_ref()).map(|ident| ident.to_string()).collect(),
+                            defined_in: module_file.path.clone(),
+                            module_dir: module_file.module_dir.clone(),
+                        },
+                    );
+                }
+            }
+            Item::Impl(item_impl) if item_impl.trait_.is_none() => {
+                let syn::Type::Path(self_ty) = &*item_impl.self_ty else {
+                    continue;
+                };
+                let Some(segment) = self_ty.path.segments.last() else {
+                    continue;
+                };
+                for impl_item in &item_impl.items {
+                    if let ImplItem::Fn(func) = impl_item {
+                        if func.sig.receiver().is_some() {
+                            let mut fields = HashSet::new();
+                            self_fields(quote::quote!(#func).into_iter().collect(), &mut fields);
+                            methods.entry(segment.ident.to_string()).or_default().push(Method { name: func.sig.ident.to_string(), fields });
+                        }
+                    }
+                }
+            }
+            Item::Mod(item_mod) => {
+                if let Some((_, items)) = &item_mod.content {
+                    collect_items(items, module_file, structs, methods);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Function to find the fields read through `self.field`, scanning tokens so that fields used
+// inside macros such as `println!("{}", self.name)` count too. `self.method()` is a call
+fn self_fields(tokens: Vec<proc_macro2::TokenTree>, fields: &mut HashSet<String>) {
+    use proc_macro2::TokenTree;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Ident(ident) if ident == "self" => {
+                if let (Some(TokenTree::Punct(dot)), Some(TokenTree::Ident(field))) = (tokens.get(index + 1), tokens.get(index + 2)) {
+                    let is_call = match tokens.get(index + 3) {
+                        Some(TokenTree::Group(group)) => group.delimiter() == proc_macro2::Delimiter::Parenthesis,
+                        Some(TokenTree::Punct(punct)) => punct.as_char() == ':',
+                        _ => false,
+                    };
+                    if dot.as_char() == '.' && !is_call {
+                        fields.insert(field.to_string());
+                    }
+                }
+            }
+            TokenTree::Group(group) => self_fields(group.stream().into_iter().collect(), fields),
+            _ => {}
+        }
+    }
+}
+
+// Function to cluster the fields of a struct by the methods using them and turn each cluster
+// into a component
+fn propose_split(struct_name: &str, info: &StructInfo, methods: &[Method], used_in: Vec<String>) -> Proposal {
+    // Step 1: Start with a cluster per field some method uses, with the methods using it
+    let mut clusters: Vec<(Vec<&String>, HashSet<usize>)> = info
+        .fields
+        .iter()
+        .map(|field| {
+            let users = methods.iter().enumerate().filter(|(_, method)| method.fields.contains(field)).map(|(index, _)| index).collect();
+            (vec![field], users)
+        })
+        .collect();
+    let mut remaining_fields: Vec<String> = clusters.iter().filter(|(_, users)| users.is_empty()).map(|(fields, _)| fields[0].clone()).collect();
+    clusters.retain(|(_, users)| !users.is_empty());
+
+    // Step 2: Merge the two clusters whose methods overlap the most until no pair overlaps enough
+    while clusters.len() > 1 {
+        let mut best: Option<(f64, usize, usize)> = None;
+        for i in 0..clusters.len() {
+            for j in i + 1..clusters.len() {
+                let shared = clusters[i].1.intersection(&clusters[j].1).count() as f64;
+                let similarity = shared / clusters[i].1.union(&clusters[j].1).count() as f64;
+                if best.is_none_or(|(best, _, _)| similarity > best) {
+                    best = Some((similarity, i, j));
+                }
+            }
+        }
+        match best {
+            Some((similarity, i, j)) if similarity >= CLUSTER_SIMILARITY => {
+                let (fields, users) = clusters.remove(j);
+                clusters[i].0.extend(fields);
+                clusters[i].1.extend(users);
+            }
+            _ => break,
+        }
+    }
+    for (fields, _) in clusters.iter().filter(|(fields, _)| fields.len() < MIN_COMPONENT_FIELDS) {
+        remaining_fields.extend(fields.iter().map(|field| field.to_string()));
+    }
+    clusters.retain(|(fields, _)| fields.len() >= MIN_COMPONENT_FIELDS);
+
+    // Step 3: Give each method to the one component whose fields it uses, if there is only one
+    let mut components: Vec<Component> = Vec::new();
+    let mut taken = HashSet::new();
+    let struct_module = state_machine::module_name(struct_name);
+    let struct_dir = info.module_dir.join(idents::module_ident(&struct_module).trim_start_matches("r#"));
+    for (fields, _) in &clusters {
+        let word = component_word(fields);
+        let module = idents::unique_module_ident(idents::module_ident(&word), &mut taken);
+        components.push(Component {
+            name: format!("{}{}", struct_name, pascal_case(module.trim_start_matches("r#"))),
+            module_file: struct_dir.join(idents::module_file_name(&module)),
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            methods: Vec::new(),
+        });
+    }
+    let mut coordinating_methods = Vec::new();
+    let mut stateless_methods = Vec::new();
+    for method in methods {
+        let owners: BTreeSet<usize> = components
+            .iter()
+            .enumerate()
+            .filter(|(_, component)| component.fields.iter().any(|field| method.fields.contains(field)))
+            .map(|(index, _)| index)
+            .collect();
+        let uses_remaining = remaining_fields.iter().any(|field| method.fields.contains(field));
+        match (owners.len(), uses_remaining) {
+            (0, false) => stateless_methods.push(method.name.clone()),
+            (1, false) => components[*owners.first().unwrap()].methods.push(method.name.clone()),
+            _ => coordinating_methods.push(method.name.clone()),
+        }
+    }
+    remaining_fields.sort_by_key(|field| info.fields.iter().position(|f| f == field));
+
+    Proposal {
+        struct_name: struct_name.to_string(),
+        defined_in: info.defined_in.clone(),
+        field_count: info.fields.len(),
+        method_count: methods.len(),
+        used_in,
+        components,
+        coordinating_methods,
+        stateless_methods,
+        remaining_fields,
+    }
+}
+
+// Function to name a component after the word most of its fields share, such as `db` for
+// `db_pool` and `db_url`, falling back to its first field
+fn component_word(fields: &[&String]) -> String {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for field in fields {
+        let words: BTreeSet<&str> = field.split('_').filter(|word| !word.is_empty()).collect();
+        for word in words {
+            match counts.iter_mut().find(|(counted, _)| *counted == word) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((word, 1)),
+            }
+        }
+    }
+    // Ties go to the word seen first, as the fields are in declaration order
+    let best = counts.iter().fold(None, |best: Option<&(&str, usize)>, counted| match best {
+        Some(best) if best.1 >= counted.1 => Some(best),
+        _ => Some(counted),
+    });
+    match best {
+        Some((word, count)) if *count >= 2 && *count * 2 >= fields.len() => word.to_string(),
+        _ => fields[0].to_string(),
+    }
+}
+
+// Function to turn a snake_case module name into the CamelCase of a type name
+fn pascal_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+        })
+        .collect()
+}
+
+// Function to print the proposals for review
+pub fn print_proposals(proposals: &[Proposal]) {
+    if proposals.is_empty() {
+        println!("No struct has at least {} methods and {} fields used across the crate.", MIN_METHODS, MIN_FIELDS);
+        return;
+    }
+    for proposal in proposals {
+        println!(
+            "`{}` in {}: {} fields, {} methods, used in {} module{}",
+            proposal.struct_name,
+            proposal.defined_in.display(),
+            proposal.field_count,
+            proposal.method_count,
+            proposal.used_in.len(),
+            if proposal.used_in.len() == 1 { "" } else { "s" }
+        );
+        if proposal.components.is_empty() {
+            println!("  Its methods don't use its fields in separable clusters.");
+        }
+        for component in &proposal.components {
+            println!("  {} ({})", component.name, component.module_file.display());
+            println!("    fields: {}", component.fields.join(", "));
+            if !component.methods.is_empty() {
+                println!("    methods: {}", component.methods.join(", "));
+            }
+        }
+        if !proposal.coordinating_methods.is_empty() {
+            println!("  Stay on `{}`, using several components: {}", proposal.struct_name, proposal.coordinating_methods.join(", "));
+        }
+        if !proposal.remaining_fields.is_empty() {
+            println!("  Fields staying on `{}`: {}", proposal.struct_name, proposal.remaining_fields.join(", "));
+        }
+        if !proposal.stateless_methods.is_empty() {
+            println!("  Methods using no field: {}", proposal.stateless_methods.join(", "));
+        }
+        println!();
+    }
+    println!("Nothing was changed; move the fields and methods by hand once the plan looks right.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_used_together_become_components() {
+        let info = StructInfo {
+            fields: ["db_pool", "db_url", "cache_map", "cache_size", "name", "unused"].map(String::from).to_vec(),
+            defined_in: PathBuf::from("src/server.rs"),
+            module_dir: PathBuf::from("src/server_dir"),
+        };
+        let method = |name: &str, fields: &[&str]| Method {
+            name: name.to_string(),
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+        };
+        let methods = [
+            method("connect", &["db_pool", "db_url"]),
+            method("query", &["db_pool"]),
+            method("get", &["cache_map", "cache_size"]),
+            method("evict", &["cache_size"]),
+            method("sync", &["db_pool", "cache_map"]),
+            method("label", &["name"]),
+            method("helper", &[]),
+        ];
+        let proposal = propose_split("Server", &info, &methods, vec!["crate".to_string()]);
+        let [db, cache] = &proposal.components[..] else {
+            panic!("Expected two components, found {}", proposal.components.len());
+        };
+        assert_eq!((db.name.as_str(), db.module_file.as_path()), ("ServerDb", Path::new("src/server_dir/server/db.rs")));
+        assert_eq!((&db.fields[..], &db.methods[..]), (&["db_pool", "db_url"].map(String::from)[..], &["connect", "query"].map(String::from)[..]));
+        assert_eq!((cache.name.as_str(), &cache.fields[..]), ("ServerCache", &["cache_map", "cache_size"].map(String::from)[..]));
+        assert_eq!(cache.methods, ["get", "evict"]);
+        assert_eq!(proposal.coordinating_methods, ["sync", "label"]);
+        assert_eq!(proposal.stateless_methods, ["helper"]);
+        assert_eq!(proposal.remaining_fields, ["name", "unused"]);
+    }
+
+    #[test]
+    fn fields_read_in_macros_count_and_method_calls_do_not() {
+        let func: syn::ImplItemFn = syn::parse_str("fn show(&self) { let _ = self.count; self.refresh(); println!(\"{}\", self.name); }").unwrap();
+        let mut fields = HashSet::new();
+        self_fields(quote::quote!(#func).into_iter().collect(), &mut fields);
+        assert_eq!(fields, HashSet::from(["count".to_string(), "name".to_string()]));
+        assert_eq!(component_word(&[&"db_pool".to_string(), &"db_url".to_string(), &"timeout".to_string()]), "db");
+        assert_eq!(component_word(&[&"host".to_string(), &"port".to_string()]), "host");
+    }
+}
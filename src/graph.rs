@@ -0,0 +1,280 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use syn::ext::IdentExt;
+use syn::visit::Visit;
+use syn::{Item, UseTree};
+
+use crate::output::json_escape;
+use crate::{encoding, tree, verify};
+
+// The formats of architecture tools the graph can be written in
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    // GraphML, read by yEd, Gephi and most graph libraries
+    GraphMl,
+    // A list of nodes with the modules each one depends on, and how often
+    Json,
+    // A workspace of the Structurizr DSL, with one component per module
+    Structurizr,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Option<Format> {
+        match name {
+            "graphml" => Some(Format::GraphMl),
+            "json" => Some(Format::Json),
+            "structurizr" => Some(Format::Structurizr),
+            _ => None,
+        }
+    }
+}
+
+// The modules of a crate and which of them use which, counting the paths that lead from one
+// into the other
+pub struct ImportGraph {
+    pub name: String,
+    pub modules: Vec<String>,
+    pub edges: BTreeMap<(String, String), usize>,
+}
+
+// Collects the paths a module writes out, in its imports and its code, leaving out the modules
+// inside it, which are nodes of their own
+#[derive(Default)]
+struct PathVisitor {
+    paths: Vec<Vec<String>>,
+}
+
+impl PathVisitor {
+    fn note_use_tree(&mut self, tree: &UseTree, prefix: &mut Vec<String>) {
+        match tree {
+            UseTree::Path(use_path) => {
+                prefix.push(use_path.ident.to_string());
+                self.note_use_tree(&use_path.tree, prefix);
+                prefix.pop();
+            }
+            UseTree::Name(use_name) => self.paths.push([prefix.as_slice(), &[use_name.ident.to_string()]].concat()),
+            UseTree::Rename(use_rename) => self.paths.push([prefix.as_slice(), &[use_rename.ident.to_string()]].concat()),
+            UseTree::Glob(_) => self.paths.push(prefix.clone()),
+            UseTree::Group(group) => group.items.iter().for_each(|tree| self.note_use_tree(tree, prefix)),
+        }
+    }
+}
+
+impl Visit<'_> for PathVisitor {
+    fn visit_path(&mut self, node: &syn::Path) {
+        // Paths starting with `::` name other crates
+        if node.leading_colon.is_none() {
+            self.paths.push(node.segments.iter().map(|segment| segment.ident.to_string()).collect());
+        }
+        syn::visit::visit_path(self, node);
+    }
+
+    fn visit_item_use(&mut self, node: &syn::ItemUse) {
+        if node.leading_colon.is_none() {
+            self.note_use_tree(&node.tree, &mut Vec::new());
+        }
+    }
+
+    fn visit_item_mod(&mut self, _node: &syn::ItemMod) {}
+}
+
+// Function to gather every module below `module_path` with its items, inline modules included
+fn collect_modules(items: Vec<Item>, module_path: Vec<String>, module_dir: &Path, modules: &mut Vec<(Vec<String>, Vec<Item>)>) -> Result<(), String> {
+    for item in &items {
+        let Item::Mod(item_mod) = item else {
+            continue;
+        };
+        let mut child_path = module_path.clone();
+        child_path.push(item_mod.ident.to_string());
+        let file_name = item_mod.ident.unraw().to_string();
+        let child_dir = module_dir.join(&file_name);
+        match &item_mod.content {
+            Some((_, child_items)) => collect_modules(child_items.clone(), child_path, &child_dir, modules)?,
+            None => {
+                let Some(file) = tree::declared_module_file(item_mod, &file_name, module_dir) else {
+                    continue;
+                };
+                let (content, _) = encoding::read_source(&file, true)?;
+                let syntax_tree = syn::parse_file(&content).map_err(|e| format!("Failed to parse {:?}: {}", file, e))?;
+                collect_modules(syntax_tree.items, child_path, &child_dir, modules)?;
+            }
+        }
+    }
+    modules.push((module_path, items));
+    Ok(())
+}
+
+// Function to resolve a path written in `module_path` to the module path it leads into, or None
+// when it leads out of the crate or to something the module imported
+fn resolve(module_path: &[String], path: &[String], known: &BTreeSet<Vec<String>>) -> Option<Vec<String>> {
+    let (first, rest) = path.split_first()?;
+    let mut resolved = match first.as_str() {
+        "crate" => rest.to_vec(),
+        "self" => [module_path, rest].concat(),
+        "super" => {
+            let supers = path.iter().take_while(|segment| *segment == "super").count();
+            let base = module_path.get(..module_path.len().checked_sub(supers)?)?;
+            [base, &path[supers..]].concat()
+        }
+        _ => {
+            let child = [module_path, std::slice::from_ref(first)].concat();
+            if !known.contains(&child) {
+                return None;
+            }
+            [module_path, path].concat()
+        }
+    };
+    // The longest part naming a module is the one the path leads into, the rest is an item of it
+    while !known.contains(&resolved) {
+        resolved.pop()?;
+    }
+    Some(resolved)
+}
+
+fn display_name(module_path: &[String]) -> String {
+    std::iter::once("crate").chain(module_path.iter().map(String::as_str)).collect::<Vec<_>>().join("::")
+}
+
+// Function to build the graph of the modules of the crate at `crate_path` and the paths between them
+pub fn import_graph(crate_path: &Path) -> Result<ImportGraph, String> {
+    let root = tree::resolve_crate_root(crate_path).ok_or_else(|| format!("Found no crate root at {:?}", crate_path))?;
+    let (content, _) = encoding::read_source(&root, true)?;
+    let syntax_tree = syn::parse_file(&content).map_err(|e| format!("Failed to parse {:?}: {}", root, e))?;
+    let root_dir = root.parent().unwrap_or(Path::new("."));
+    let mut modules = Vec::new();
+    collect_modules(syntax_tree.items, Vec::new(), root_dir, &mut modules)?;
+    modules.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let known: BTreeSet<Vec<String>> = modules.iter().map(|(module_path, _)| module_path.clone()).collect();
+    let mut edges = BTreeMap::new();
+    for (module_path, items) in &modules {
+        let mut visitor = PathVisitor::default();
+        items.iter().for_each(|item| visitor.visit_item(item));
+        for target in visitor.paths.iter().filter_map(|path| resolve(module_path, path, &known)) {
+            if target != *module_path {
+                *edges.entry((display_name(module_path), display_name(&target))).or_default() += 1;
+            }
+        }
+    }
+
+    let package_dir = verify::find_package_root(&root).unwrap_or_else(|| root_dir.to_path_buf());
+    let name = package_dir
+        .canonicalize()
+        .unwrap_or(package_dir)
+        .file_name()
+        .map_or_else(|| "crate".to_string(), |name| name.to_string_lossy().into_owned());
+    Ok(ImportGraph {
+        name,
+        modules: modules.iter().map(|(module_path, _)| display_name(module_path)).collect(),
+        edges,
+    })
+}
+
+// Function to write the graph in the given format
+pub fn render(graph: &ImportGraph, format: Format) -> String {
+    let mut lines = Vec::new();
+    match format {
+        Format::GraphMl => {
+            lines.push(r#"<?xml version="1.0" encoding="UTF-8"?>"#.to_string());
+            lines.push(r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#.to_string());
+            lines.push(r#"  <key id="weight" for="edge" attr.name="weight" attr.type="int"/>"#.to_string());
+            lines.push(format!(r#"  <graph id="{}" edgedefault="directed">"#, xml_escape(&graph.name)));
+            for module in &graph.modules {
+                lines.push(format!(r#"    <node id="{}"/>"#, xml_escape(module)));
+            }
+            for ((source, target), weight) in &graph.edges {
+                lines.push(format!(
+                    r#"    <edge source="{}" target="{}"><data key="weight">{}</data></edge>"#,
+                    xml_escape(source),
+                    xml_escape(target),
+                    weight
+                ));
+            }
+            lines.push("  </graph>".to_string());
+            lines.push("</graphml>".to_string());
+        }
+        Format::Json => {
+            lines.push("{".to_string());
+            lines.push(r#"  "schema": "refactor/import-graph/v1","#.to_string());
+            lines.push(format!(r#"  "name": "{}","#, json_escape(&graph.name)));
+            let nodes: Vec<String> = graph.modules.iter().map(|module| format!(r#""{}""#, json_escape(module))).collect();
+            lines.push(format!(r#"  "nodes": [{}],"#, nodes.join(", ")));
+            lines.push(r#"  "adjacency": {"#.to_string());
+            for (index, module) in graph.modules.iter().enumerate() {
+                let targets: Vec<String> = graph
+                    .edges
+                    .iter()
+                    .filter(|((source, _), _)| source == module)
+                    .map(|((_, target), weight)| format!(r#"{{"target": "{}", "weight": {}}}"#, json_escape(target), weight))
+                    .collect();
+                let comma = if index + 1 < graph.modules.len() { "," } else { "" };
+                lines.push(format!(r#"    "{}": [{}]{}"#, json_escape(module), targets.join(", "), comma));
+            }
+            lines.push("  }".to_string());
+            lines.push("}".to_string());
+        }
+        Format::Structurizr => {
+            // Module paths aren't identifiers of the DSL, so the elements are numbered instead. Its
+            // strings are quoted like JSON ones
+            let identifier = |module: &String| format!("m{}", graph.modules.iter().position(|other| other == module).unwrap_or(0));
+            lines.push("workspace {".to_string());
+            lines.push("    model {".to_string());
+            lines.push(format!(r#"        system = softwareSystem "{}" {{"#, json_escape(&graph.name)));
+            lines.push(format!(r#"            modules = container "{}" "The modules of the crate" "Rust" {{"#, json_escape(&graph.name)));
+            for module in &graph.modules {
+                lines.push(format!(r#"                {} = component "{}" "" "Rust module""#, identifier(module), json_escape(module)));
+            }
+            lines.push("            }".to_string());
+            lines.push("        }".to_string());
+            for ((source, target), weight) in &graph.edges {
+                let paths = if *weight == 1 { "1 path".to_string() } else { format!("{} paths", weight) };
+                lines.push(format!(r#"        {} -> {} "Uses" "{}""#, identifier(source), identifier(target), paths));
+            }
+            lines.push("    }".to_string());
+            lines.push("    views {".to_string());
+            lines.push("        component modules {".to_string());
+            lines.push("            include *".to_string());
+            lines.push("            autoLayout".to_string());
+            lines.push("        }".to_string());
+            lines.push("    }".to_string());
+            lines.push("}".to_string());
+        }
+    }
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_resolve_to_the_module_they_lead_into() {
+        let module = |path: &str| path.split("::").filter(|segment| !segment.is_empty()).map(String::from).collect::<Vec<_>>();
+        let known: BTreeSet<Vec<String>> = ["", "net", "net::tcp", "util"].into_iter().map(module).collect();
+        let resolved = |from: &str, path: &str| resolve(&module(from), &module(path), &known);
+        assert_eq!(resolved("net::tcp", "super::super::util::parse"), Some(module("util")));
+        assert_eq!(resolved("net", "tcp::connect"), Some(module("net::tcp")));
+        assert_eq!(resolved("", "crate::net::tcp::Stream::new"), Some(module("net::tcp")));
+        assert_eq!(resolved("net", "self::send"), Some(module("net")));
+        // Imported names and paths above the crate lead nowhere the graph knows
+        assert_eq!(resolved("net", "fs::read"), None);
+        assert_eq!(resolved("net", "super::super::outside"), None);
+    }
+
+    #[test]
+    fn graphml_escapes_its_ids() {
+        let graph = ImportGraph {
+            name: "a&b".to_string(),
+            modules: vec!["crate".to_string(), "crate::net".to_string()],
+            edges: BTreeMap::from([(("crate".to_string(), "crate::net".to_string()), 2)]),
+        };
+        let graphml = render(&graph, Format::GraphMl);
+        assert!(graphml.contains(r#"<graph id="a&amp;b" edgedefault="directed">"#), "{}", graphml);
+        assert!(graphml.contains(r#"<edge source="crate" target="crate::net"><data key="weight">2</data></edge>"#), "{}", graphml);
+    }
+}
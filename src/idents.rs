@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use syn::Ident;
+
+// File names Windows reserves whatever the extension, so `con.rs` cannot be created there
+const RESERVED_FILE_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8", "com9", "lpt1",
+    "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+// Function to sanitize a filename by removing invalid characters
+fn sanitize_filename(filename: &str) -> String {
+    filename.chars().filter(|c| c.is_alphanumeric() || *c == '_').collect()
+}
+
+// Function to turn a group name into a valid module identifier, written as a raw identifier
+// when it is a keyword, such as `r#type` for the state machine of a `Type` enum
+pub fn module_ident(name: &str) -> String {
+    let mut ident = sanitize_filename(name.strip_prefix("r#").unwrap_or(name));
+    // Letters and digits outside of the identifier character set only pass the filter above
+    if syn::parse_str::<Ident>(&ident).is_err() && syn::parse_str::<Ident>(&format!("r#{}", ident)).is_err() {
+        ident.retain(|c| c.is_ascii_alphanumeric() || c == '_');
+    }
+    if ident.is_empty() || ident.starts_with(|c: char| c.is_numeric()) {
+        ident.insert(0, '_');
+    }
+    // A module named like a reserved file would have no file to live in on Windows
+    if RESERVED_FILE_NAMES.contains(&ident.to_lowercase().as_str()) {
+        ident.push('_');
+    }
+    if syn::parse_str::<Ident>(&ident).is_ok() {
+        ident
+    } else if syn::parse_str::<Ident>(&format!("r#{}", ident)).is_ok() {
+        format!("r#{}", ident)
+    } else {
+        // `_`, `crate`, `self`, `super` and `Self` cannot be raw identifiers
+        format!("{}_", ident)
+    }
+}
+
+// Function to make a module identifier unique among the modules generated next to it, since
+// different group names can escape to the same identifier, such as `net-io` and `net_io`
+pub fn unique_module_ident(ident: String, taken: &mut HashSet<String>) -> String {
+    let unraw = |ident: &str| ident.strip_prefix("r#").unwrap_or(ident).to_lowercase();
+    let mut unique = ident.clone();
+    let mut suffix = 2;
+    // Files are compared without case, as case-insensitive file systems would
+    while !taken.insert(unraw(&unique)) {
+        unique = format!("{}_{}", ident.strip_prefix("r#").unwrap_or(&ident), suffix);
+        suffix += 1;
+    }
+    unique
+}
+
+// Function to check that a module identifier is valid Rust and names a file every platform can
+// create, so that a bad name fails here instead of in the compiler
+pub fn check_module_ident(ident: &str) -> Result<(), String> {
+    if syn::parse_str::<Ident>(ident).is_err() {
+        return Err(format!("`{}` is not a valid module name", ident));
+    }
+    let file_name = ident.strip_prefix("r#").unwrap_or(ident);
+    if RESERVED_FILE_NAMES.contains(&file_name.to_lowercase().as_str()) {
+        return Err(format!("`{}` is not a valid module name, `{}.rs` is reserved on Windows", ident, file_name));
+    }
+    Ok(())
+}
+
+// Function to name the file of a module, without the `r#` of a raw identifier
+pub fn module_file_name(module_ident: &str) -> String {
+    format!("{}.rs", module_ident.strip_prefix("r#").unwrap_or(module_ident))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_names_become_identifiers_every_platform_can_name_a_file_after() {
+        assert_eq!(module_ident("net-io"), "netio");
+        assert_eq!(module_ident("type"), "r#type");
+        assert_eq!(module_ident("r#match"), "r#match");
+        assert_eq!(module_ident("2d"), "_2d");
+        assert_eq!(module_ident("con"), "con_");
+        assert_eq!(module_ident("self"), "self_");
+        assert_eq!(module_ident("!!"), "__");
+        assert_eq!(module_file_name("r#type"), "type.rs");
+    }
+
+    #[test]
+    fn identifiers_are_made_unique_without_case() {
+        let mut taken = HashSet::from(["util".to_string()]);
+        assert_eq!(unique_module_ident("Util".to_string(), &mut taken), "Util_2");
+        assert_eq!(unique_module_ident("util".to_string(), &mut taken), "util_3");
+        assert_eq!(unique_module_ident("r#type".to_string(), &mut taken), "r#type");
+        assert_eq!(unique_module_ident("type".to_string(), &mut taken), "type_2");
+    }
+
+    #[test]
+    fn bad_module_names_are_reported() {
+        assert!(check_module_ident("r#type").is_ok());
+        assert_eq!(check_module_ident("net-io").unwrap_err(), "`net-io` is not a valid module name");
+        assert!(check_module_ident("Aux").unwrap_err().contains("`Aux.rs` is reserved on Windows"));
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use quote::ToTokens;
+use syn::{Item, UseTree};
+
+use crate::{format_glue, has_rustfmt_skip};
+
+// How the imports at the top of a generated module are laid out
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImportStyle {
+    // In the order of the imports they were copied from, merged only where they overlap
+    Original,
+    // Sorted and grouped by origin, std first, then other crates, then the crate itself, with a
+    // blank line between the groups
+    Grouped,
+    // One `use` per imported item, sorted by origin
+    PerLine,
+    // One `use` tree per crate, such as `use std::{fs, io::{self, Read}};`, sorted by origin
+    Merged,
+}
+
+impl ImportStyle {
+    pub fn parse(name: &str) -> Option<ImportStyle> {
+        match name {
+            "original" => Some(ImportStyle::Original),
+            "grouped" => Some(ImportStyle::Grouped),
+            "per-line" => Some(ImportStyle::PerLine),
+            "merged" => Some(ImportStyle::Merged),
+            _ => None,
+        }
+    }
+
+    // Function to write the style back the way `parse` reads it
+    pub fn name(self) -> &'static str {
+        match self {
+            ImportStyle::Original => "original",
+            ImportStyle::Grouped => "grouped",
+            ImportStyle::PerLine => "per-line",
+            ImportStyle::Merged => "merged",
+        }
+    }
+}
+
+// Where an import leads: to std, to another crate or into the crate itself
+fn origin(first_segment: &str) -> u8 {
+    match first_segment {
+        "std" | "core" | "alloc" => 0,
+        "crate" | "self" | "super" => 2,
+        _ => 1,
+    }
+}
+
+// Function to split a `use` tree into the paths of the names it imports, the last segment being
+// the name as written there, such as `Read as R` or `*`. `use std::io::{self}` is `use std::io`
+fn leaves(tree: &UseTree, prefix: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+    match tree {
+        UseTree::Path(use_path) => {
+            prefix.push(use_path.ident.to_string());
+            leaves(&use_path.tree, prefix, paths);
+            prefix.pop();
+        }
+        UseTree::Name(use_name) if use_name.ident == "self" && !prefix.is_empty() => paths.push(prefix.clone()),
+        UseTree::Rename(use_rename) if use_rename.ident == "self" && !prefix.is_empty() => {
+            let mut path = prefix.clone();
+            let name = path.pop().unwrap_or_default();
+            path.push(format!("{} as {}", name, use_rename.rename));
+            paths.push(path);
+        }
+        UseTree::Group(group) => group.items.iter().for_each(|tree| leaves(tree, prefix, paths)),
+        _ => paths.push([prefix.as_slice(), &[tree.to_token_stream().to_string()]].concat()),
+    }
+}
+
+// Function to write the paths below a common prefix as the items of a `use` list, nesting the
+// paths that share their next segment
+fn tree_items(paths: &[Vec<String>]) -> Vec<String> {
+    let mut by_segment: BTreeMap<&String, Vec<Vec<String>>> = BTreeMap::new();
+    for path in paths {
+        if let Some((first, rest)) = path.split_first() {
+            by_segment.entry(first).or_default().push(rest.to_vec());
+        }
+    }
+    let mut items = Vec::new();
+    // `self` comes first in a list, as rustfmt puts it
+    let (selves, others): (Vec<_>, Vec<_>) = by_segment.into_iter().partition(|(segment, _)| *segment == "self");
+    for (segment, rests) in selves.into_iter().chain(others) {
+        let imports_itself = rests.iter().any(Vec::is_empty);
+        let below: Vec<Vec<String>> = rests.into_iter().filter(|rest| !rest.is_empty()).collect();
+        if below.is_empty() {
+            items.push(segment.clone());
+            continue;
+        }
+        let mut inner = tree_items(&below);
+        if imports_itself {
+            inner.insert(0, "self".to_string());
+        }
+        match inner.as_slice() {
+            [item] => items.push(format!("{}::{}", segment, item)),
+            _ => items.push(format!("{}::{{{}}}", segment, inner.join(", "))),
+        }
+    }
+    items
+}
+
+// Function to lay out the `use` statements of a generated module in the given style. Statements
+// are keyed by their attributes and visibility, which each written statement keeps
+pub fn arrange(imports: Vec<String>, style: ImportStyle) -> Vec<String> {
+    if style == ImportStyle::Original || imports.is_empty() {
+        return imports;
+    }
+
+    // The paths of every statement, by its attributes, visibility and leading `::`
+    let mut statements: Vec<(String, Vec<String>)> = Vec::new();
+    for import in &imports {
+        // Imports that aren't plain `use` statements, or that rustfmt is told to leave alone, are
+        // kept as they are
+        let Ok(file) = syn::parse_file(import) else {
+            return imports;
+        };
+        for item in &file.items {
+            let Item::Use(item_use) = item else {
+                return imports;
+            };
+            if has_rustfmt_skip(&item_use.attrs) {
+                return imports;
+            }
+            let attrs: String = item_use.attrs.iter().map(|attr| format!("{} ", attr.to_token_stream())).collect();
+            let key = format!("{}{} use {}", attrs, item_use.vis.to_token_stream(), if item_use.leading_colon.is_some() { "::" } else { "" });
+            let mut paths = Vec::new();
+            leaves(&item_use.tree, &mut Vec::new(), &mut paths);
+            statements.extend(paths.into_iter().map(|path| (key.clone(), path)));
+        }
+    }
+    statements.sort_by(|(key, path), (other_key, other_path)| {
+        let first = |path: &[String]| origin(path.first().map_or("", String::as_str));
+        first(path).cmp(&first(other_path)).then_with(|| path.cmp(other_path)).then_with(|| key.cmp(other_key))
+    });
+    statements.dedup();
+
+    let written: Vec<(u8, String)> = match style {
+        ImportStyle::PerLine => statements
+            .iter()
+            .map(|(key, path)| (origin(&path[0]), format!("{}{};", key, path.join("::"))))
+            .collect(),
+        // One tree per key and first segment, or per statement when grouping
+        _ => {
+            let parents: Vec<(&String, String)> = statements.iter().map(|(key, path)| (key, path[..path.len() - 1].join("::"))).collect();
+            // By origin, the path the tree starts with and the statement's key
+            type TreeKey = (u8, String, String);
+            let mut trees: Vec<(TreeKey, Vec<Vec<String>>)> = Vec::new();
+            for (key, path) in &statements {
+                // A module imported alongside items from inside it becomes the `self` of their list
+                let full = path.join("::");
+                // Names of the crate root in 2015 code, such as `use Config;`, can't share a list
+                let root = if style == ImportStyle::Merged || path.len() == 1 {
+                    path[0].clone()
+                } else if parents.iter().any(|(other_key, parent)| *other_key == key && *parent == full) {
+                    full
+                } else {
+                    path[..path.len() - 1].join("::")
+                };
+                let tree_key = (origin(&path[0]), root, key.clone());
+                match trees.iter_mut().find(|(other, _)| *other == tree_key) {
+                    Some((_, paths)) => paths.push(path.clone()),
+                    None => trees.push((tree_key, vec![path.clone()])),
+                }
+            }
+            trees
+                .into_iter()
+                .map(|((origin, _, key), paths)| match tree_items(&paths).as_slice() {
+                    [item] => (origin, format!("{}{};", key, item)),
+                    items => (origin, format!("{}{{{}}};", key, items.join(", "))),
+                })
+                .collect()
+        }
+    };
+
+    let mut arranged = Vec::new();
+    let mut previous_origin = None;
+    for (origin, statement) in written {
+        let statement = format_glue(statement.trim_start());
+        // Grouped imports get a blank line between origins
+        if style == ImportStyle::Grouped && previous_origin.is_some_and(|previous| previous != origin) {
+            arranged.push(String::new());
+        }
+        previous_origin = Some(origin);
+        arranged.push(statement);
+    }
+    arranged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imports() -> Vec<String> {
+        vec!["use crate::model::Config;".to_string(), "use std::io::Read;".to_string(), "use serde::Serialize;".to_string(), "use std::{fs, io};".to_string()]
+    }
+
+    #[test]
+    fn every_style_reads_back_by_its_name() {
+        for style in [ImportStyle::Original, ImportStyle::Grouped, ImportStyle::PerLine, ImportStyle::Merged] {
+            assert!(ImportStyle::parse(style.name()) == Some(style));
+        }
+        assert!(ImportStyle::parse("sorted").is_none());
+    }
+
+    #[test]
+    fn original_imports_are_left_as_they_are() {
+        assert_eq!(arrange(imports(), ImportStyle::Original), imports());
+    }
+
+    #[test]
+    fn grouped_imports_are_split_by_origin() {
+        assert_eq!(
+            arrange(imports(), ImportStyle::Grouped),
+            vec!["use std::fs;", "use std::io::{self, Read};", "", "use serde::Serialize;", "", "use crate::model::Config;"]
+        );
+    }
+
+    #[test]
+    fn per_line_imports_name_one_item_each() {
+        assert_eq!(
+            arrange(imports(), ImportStyle::PerLine),
+            vec!["use std::fs;", "use std::io;", "use std::io::Read;", "use serde::Serialize;", "use crate::model::Config;"]
+        );
+    }
+
+    #[test]
+    fn merged_imports_share_one_tree_per_crate() {
+        assert_eq!(
+            arrange(imports(), ImportStyle::Merged),
+            vec!["use std::{\n    fs,\n    io::{self, Read},\n};", "use serde::Serialize;", "use crate::model::Config;"]
+        );
+    }
+
+    #[test]
+    fn imports_rustfmt_is_told_to_skip_are_kept() {
+        let imports = vec!["#[rustfmt::skip]\nuse std::{io,  fs};".to_string(), "use std::fmt;".to_string()];
+        assert_eq!(arrange(imports.clone(), ImportStyle::Merged), imports);
+    }
+}
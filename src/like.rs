@@ -0,0 +1,186 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use syn::Item;
+
+use crate::tree;
+
+// A word of a module's name counts this many times as much as one of the names it defines
+const NAME_WEIGHT: usize = 3;
+
+// A top-level module of the model crate and the words its name and items are made of
+pub struct ModelModule {
+    pub name: String,
+    name_words: HashSet<String>,
+    // How many of the module's items use each word in their name
+    item_words: HashMap<String, usize>,
+}
+
+impl ModelModule {
+    // Function to describe a module by the words its items would be named with, for a layout
+    // chosen up front rather than read from a crate
+    pub fn from_words(name: &str, item_words: &[&str]) -> ModelModule {
+        ModelModule {
+            name: name.to_string(),
+            name_words: words(name).into_iter().collect(),
+            item_words: item_words.iter().map(|word| (word.to_string(), 1)).collect(),
+        }
+    }
+}
+
+// Function to read the top-level modules of the crate to imitate, leaving out the ones named like
+// the groups the planner keeps for itself
+pub fn model_modules(crate_path: &Path, reserved: impl Fn(&str) -> bool) -> Result<Vec<ModelModule>, String> {
+    let root = tree::resolve_crate_root(crate_path).ok_or_else(|| format!("Found no crate root at {:?}", crate_path))?;
+    let mut modules: BTreeMap<String, ModelModule> = BTreeMap::new();
+    for module_file in tree::crate_module_files(&root) {
+        let Some(name) = module_file.module_path.first() else {
+            continue;
+        };
+        let name = name.strip_prefix("r#").unwrap_or(name).to_string();
+        if name == "tests" || reserved(&name) {
+            continue;
+        }
+        let content = fs::read_to_string(&module_file.path).map_err(|e| format!("Failed to read {:?}: {}", module_file.path, e))?;
+        let syntax_tree = syn::parse_file(&content).map_err(|e| format!("Failed to parse {:?}: {}", module_file.path, e))?;
+        let module = modules.entry(name.clone()).or_insert_with(|| ModelModule {
+            name_words: words(&name).into_iter().collect(),
+            name,
+            item_words: HashMap::new(),
+        });
+        let mut names = Vec::new();
+        item_names(&syntax_tree.items, &mut names);
+        for word in names.iter().flat_map(|name| words(name)) {
+            *module.item_words.entry(word).or_default() += 1;
+        }
+    }
+
+    // Words most of the model's modules use in their items say nothing about any of them
+    let mut module_frequency: HashMap<String, usize> = HashMap::new();
+    for module in modules.values() {
+        for word in module.item_words.keys() {
+            *module_frequency.entry(word.clone()).or_default() += 1;
+        }
+    }
+    let module_count = modules.len();
+    for module in modules.values_mut() {
+        module.item_words.retain(|word, _| module_count < 3 || module_frequency[word] * 2 <= module_count);
+    }
+    Ok(modules.into_values().collect())
+}
+
+// Function to collect the names of the items defined among `items`, including inline modules
+fn item_names(items: &[Item], names: &mut Vec<String>) {
+    for item in items {
+        match item {
+            Item::Fn(func) => names.push(func.sig.ident.to_string()),
+            Item::Struct(item_struct) => names.push(item_struct.ident.to_string()),
+            Item::Enum(item_enum) => names.push(item_enum.ident.to_string()),
+            Item::Trait(item_trait) => names.push(item_trait.ident.to_string()),
+            Item::Type(item_type) => names.push(item_type.ident.to_string()),
+            Item::Const(item_const) => names.push(item_const.ident.to_string()),
+            Item::Static(item_static) => names.push(item_static.ident.to_string()),
+            Item::Impl(item_impl) => {
+                for impl_item in &item_impl.items {
+                    if let syn::ImplItem::Fn(func) = impl_item {
+                        names.push(func.sig.ident.to_string());
+                    }
+                }
+            }
+            Item::Mod(item_mod) => {
+                names.push(item_mod.ident.to_string());
+                if let Some((_, items)) = &item_mod.content {
+                    item_names(items, names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Function to split an identifier into its lowercase words, at underscores and at the humps of
+// CamelCase, such as `http` and `client` for both `http_client` and `HttpClient`
+fn words(ident: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in ident.strip_prefix("r#").unwrap_or(ident).split('_') {
+        let mut word = String::new();
+        let mut previous_lower = false;
+        for c in part.chars() {
+            if c.is_uppercase() && previous_lower {
+                words.push(std::mem::take(&mut word));
+            }
+            previous_lower = c.is_lowercase() || c.is_ascii_digit();
+            word.extend(c.to_lowercase());
+        }
+        words.push(word);
+    }
+    words.retain(|word| word.len() >= 3);
+    words
+}
+
+// Function to find the model module a function belongs in by the words its name shares with the
+// module's name and items, returning the module and the word that weighed the most
+pub fn best_module<'a>(func_name: &str, modules: &'a [ModelModule]) -> Option<(&'a ModelModule, String)> {
+    let mut best: Option<(usize, &ModelModule, String)> = None;
+    for module in modules {
+        let mut score = 0;
+        let mut strongest = (0, String::new());
+        for word in words(func_name) {
+            let weight = NAME_WEIGHT * usize::from(module.name_words.contains(&word)) + module.item_words.get(&word).copied().unwrap_or(0);
+            score += weight;
+            if weight > strongest.0 {
+                strongest = (weight, word);
+            }
+        }
+        // Modules come sorted by name, so ties go to the first one
+        if score > 0 && best.as_ref().is_none_or(|(best, _, _)| score > *best) {
+            best = Some((score, module, strongest.1));
+        }
+    }
+    best.map(|(_, module, word)| (module, word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identifiers_split_into_words_at_underscores_and_humps() {
+        assert_eq!(words("HttpClient"), ["http", "client"]);
+        assert_eq!(words("read_http2_body"), ["read", "http2", "body"]);
+        assert_eq!(words("r#type_id"), ["type"]);
+    }
+
+    #[test]
+    fn functions_go_to_the_module_sharing_the_most_words() {
+        let modules = [ModelModule::from_words("storage", &["file", "read", "write"]), ModelModule::from_words("text", &["parse", "word"])];
+        let (module, word) = best_module("read_words", &modules).unwrap();
+        assert_eq!((module.name.as_str(), word.as_str()), ("storage", "read"));
+        // A word of the module's name weighs more than one of its items
+        let (module, word) = best_module("parse_storage_words", &modules).unwrap();
+        assert_eq!((module.name.as_str(), word.as_str()), ("storage", "storage"));
+        assert!(best_module("main", &modules).is_none());
+    }
+
+    #[test]
+    fn model_modules_leave_out_reserved_names_and_tests() {
+        let dir = std::env::temp_dir().join(format!("refactor-like-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        let files = [
+            ("src/lib.rs", "mod net;\nmod storage;\nmod general;\n#[cfg(test)]\nmod tests {}\n"),
+            ("src/net.rs", "pub fn send_packet() {}\npub struct Packet;\n"),
+            ("src/storage.rs", "pub fn read_file() {}\nmod cache { fn evict_entry() {} }\n"),
+            ("src/general.rs", "pub fn anything() {}\n"),
+        ];
+        for (path, content) in files {
+            fs::write(dir.join(path), content).unwrap();
+        }
+        let modules = model_modules(&dir, |name| name == "general").unwrap();
+        let names: Vec<&str> = modules.iter().map(|module| module.name.as_str()).collect();
+        assert_eq!(names, ["net", "storage"]);
+        assert_eq!(modules[0].item_words["packet"], 2);
+        assert!(modules[1].item_words.contains_key("evict") && modules[1].item_words.contains_key("cache"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,175 @@
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Advisory lock taken by every run that writes, relative to the package root
+pub const LOCK_FILE: &str = ".refactor/lock";
+// A lock this old is taken to be left behind by a run that died without removing it, where
+// whether that run is still alive can't be told
+const STALE_AFTER: Duration = Duration::from_secs(10 * 60);
+// How often a run told to wait checks whether the lock is free, and for how long at most
+const WAIT_INTERVAL: Duration = Duration::from_millis(200);
+const WAIT_LIMIT: Duration = Duration::from_secs(5 * 60);
+
+// The lock held by this run, removed again when dropped
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Who holds a lock, as written into the lock file
+#[derive(PartialEq)]
+struct Holder {
+    pid: u32,
+    started: u64,
+}
+
+impl Holder {
+    fn read(path: &Path) -> Option<Holder> {
+        let content = fs::read_to_string(path).ok()?;
+        let mut lines = content.lines();
+        Some(Holder {
+            pid: lines.next()?.trim().parse().ok()?,
+            started: lines.next()?.trim().parse().ok()?,
+        })
+    }
+
+    // Function to check whether the run holding the lock is gone: its process no longer exists,
+    // where `/proc` can tell, and otherwise it started too long ago
+    fn is_stale(&self) -> bool {
+        let proc = Path::new("/proc");
+        if proc.is_dir() {
+            return !proc.join(self.pid.to_string()).exists();
+        }
+        now().saturating_sub(self.started) > STALE_AFTER.as_secs()
+    }
+}
+
+// Function to throw away the lock at `path` that `holder` left behind. Runs finding it stale at
+// the same time each rename it to a name of their own first, which only one of them manages, and
+// a lock renamed after a faster run already replaced the stale one is put back
+fn break_lock(path: &Path, holder: Option<&Holder>) {
+    let claimed = path.with_extension(format!("stale.{}", std::process::id()));
+    if fs::rename(path, &claimed).is_err() {
+        return;
+    }
+    if Holder::read(&claimed).as_ref() != holder {
+        // Linking only succeeds while no other run has taken the lock in the meantime
+        let _ = fs::hard_link(&claimed, path);
+    }
+    let _ = fs::remove_file(&claimed);
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+// Function to take the lock of the package at `package_root`, so that only one run writes to it at
+// a time. When another run holds it, `wait` queues behind it instead of failing right away
+pub fn acquire(package_root: &Path, wait: bool) -> Result<Lock, String> {
+    let path = package_root.join(LOCK_FILE);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+    }
+    let waiting_since = SystemTime::now();
+    loop {
+        // Creating the file only if it doesn't exist yet is what makes taking the lock atomic
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(mut file) => {
+                let lock = Lock { path: path.clone() };
+                writeln!(file, "{}\n{}", std::process::id(), now()).map_err(|e| format!("Failed to write {:?}: {}", path, e))?;
+                return Ok(lock);
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(format!("Failed to create {:?}: {}", path, e)),
+        }
+
+        match Holder::read(&path) {
+            Some(holder) if holder.is_stale() => {
+                eprintln!("Warning: removing the stale lock {:?} of process {}", path, holder.pid);
+                break_lock(&path, Some(&holder));
+            }
+            Some(holder) if !wait || waiting_since.elapsed().unwrap_or_default() > WAIT_LIMIT => {
+                return Err(format!(
+                    "Another run (process {}, started {}s ago) is writing to this package and holds {:?}; pass --wait to queue behind it, or remove the file if that run is gone",
+                    holder.pid,
+                    now().saturating_sub(holder.started),
+                    path
+                ));
+            }
+            Some(_) => thread::sleep(WAIT_INTERVAL),
+            // The other run may be halfway through writing the file, or just removed it, but a
+            // file that stays unreadable wasn't written by a run at all
+            None if waiting_since.elapsed().unwrap_or_default() > Duration::from_secs(1) => {
+                eprintln!("Warning: removing the unreadable lock {:?}", path);
+                break_lock(&path, None);
+            }
+            None => thread::sleep(WAIT_INTERVAL),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("refactor-lock-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn a_held_lock_turns_other_runs_away_until_dropped() {
+        let dir = package("held");
+        let lock = acquire(&dir, false).unwrap();
+        let error = acquire(&dir, false).err().unwrap();
+        assert!(error.contains(&format!("process {}", std::process::id())), "{}", error);
+        assert!(error.contains("--wait"), "{}", error);
+
+        drop(lock);
+        assert!(!dir.join(LOCK_FILE).exists());
+        drop(acquire(&dir, false).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn waiting_runs_take_the_lock_once_it_is_released() {
+        let dir = package("wait");
+        let lock = acquire(&dir, false).unwrap();
+        let waiter = {
+            let dir = dir.clone();
+            thread::spawn(move || acquire(&dir, true).map(drop))
+        };
+        thread::sleep(WAIT_INTERVAL * 2);
+        drop(lock);
+        waiter.join().unwrap().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn locks_of_runs_that_are_gone_are_broken() {
+        let dir = package("stale");
+        let path = dir.join(LOCK_FILE);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        // No process has this id, and the lock is older than any run may take
+        fs::write(&path, format!("{}\n{}\n", u32::MAX, 0)).unwrap();
+        let lock = acquire(&dir, false).unwrap();
+        assert_eq!(Holder::read(&path).map(|holder| holder.pid), Some(std::process::id()));
+        drop(lock);
+
+        // A stale lock already replaced by a faster run is put back rather than thrown away
+        fs::write(&path, format!("{}\n{}\n", std::process::id(), now())).unwrap();
+        break_lock(&path, Some(&Holder { pid: u32::MAX, started: 0 }));
+        assert_eq!(Holder::read(&path).map(|holder| holder.pid), Some(std::process::id()));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
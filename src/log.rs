@@ -0,0 +1,120 @@
+use std::fmt::Arguments;
+use std::sync::OnceLock;
+use std::time::Instant;
+
+// How much a run tells on stderr about what it does, each level including the ones before it.
+// Warnings are shown unless --log-level error turns them off. These few levels and one line per
+// event are all the phases need, so they are written here rather than taken from `tracing`,
+// leaving syn, quote and proc-macro2 the only dependencies and the build possible offline
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error,
+    Warn,
+    // What each phase did as a whole, such as the files it parsed or wrote
+    Info,
+    // Why each function went where it did, and each module and formatting pass
+    Debug,
+    // Everything else the phases look at
+    Trace,
+}
+
+impl Level {
+    // Function to read a level as --log-level names it
+    pub fn parse(name: &str) -> Option<Level> {
+        match name {
+            "error" => Some(Level::Error),
+            "warn" => Some(Level::Warn),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            "trace" => Some(Level::Trace),
+            _ => None,
+        }
+    }
+
+    // Function to raise the level by one for each -v
+    pub fn more_verbose(self) -> Level {
+        match self {
+            Level::Error => Level::Warn,
+            Level::Warn => Level::Info,
+            Level::Info => Level::Debug,
+            Level::Debug | Level::Trace => Level::Trace,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+// Set from -v or --log-level once the arguments are parsed, along with when the run started
+static LEVEL: OnceLock<(Level, Instant)> = OnceLock::new();
+
+// Function to set how much the run logs
+pub fn init(level: Level) {
+    LEVEL.set((level, Instant::now())).expect("Logging was set up before the arguments were parsed");
+}
+
+// Function to check whether messages of a level are shown
+pub fn enabled(level: Level) -> bool {
+    level <= LEVEL.get().map_or(Level::Warn, |(set, _)| *set)
+}
+
+// Function to log a message of one phase of the run, such as `parse` or `group`, with the time
+// since the run started. The message is only formatted when its level is shown
+pub fn event(level: Level, phase: &str, message: Arguments) {
+    if enabled(level) {
+        let elapsed = LEVEL.get().map_or(0.0, |(_, start)| start.elapsed().as_secs_f64());
+        eprintln!("{}", line(elapsed, level, phase, message));
+    }
+}
+
+// Function to write the line of a message, such as `[   0.012s INFO  parse] 40 items`
+fn line(elapsed: f64, level: Level, phase: &str, message: Arguments) -> String {
+    format!("[{:>8.3}s {:<5} {}] {}", elapsed, level.name(), phase, message)
+}
+
+// Function to log what a phase did as a whole
+pub fn info(phase: &str, message: Arguments) {
+    event(Level::Info, phase, message);
+}
+
+// Function to log a single decision of a phase
+pub fn debug(phase: &str, message: Arguments) {
+    event(Level::Debug, phase, message);
+}
+
+// Function to log the details a decision was made from
+pub fn trace(phase: &str, message: Arguments) {
+    event(Level::Trace, phase, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_level_includes_the_ones_before_it() {
+        let levels = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace];
+        for pair in levels.windows(2) {
+            assert!(pair[0] < pair[1]);
+            assert_eq!(pair[0].more_verbose(), pair[1]);
+        }
+        assert_eq!(Level::Trace.more_verbose(), Level::Trace);
+        for level in levels {
+            assert_eq!(Level::parse(&level.name().to_lowercase()), Some(level));
+        }
+        assert_eq!(Level::parse("verbose"), None);
+    }
+
+    #[test]
+    fn lines_give_the_time_level_and_phase() {
+        assert_eq!(line(1.5, Level::Info, "parse", format_args!("{} items", 40)), "[   1.500s INFO  parse] 40 items");
+        assert_eq!(line(12.0625, Level::Trace, "group", format_args!("done")), "[  12.062s TRACE group] done");
+    }
+}
@@ -1,233 +1,5567 @@
 use std::fs;
 use syn::{File, Item, visit::Visit, UseTree};
 use std::path::{Path, PathBuf};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::env;
-use std::process::Command;
+use std::process::{Command, ExitCode};
+use std::sync::{Mutex, OnceLock};
 use std::collections::HashSet;
 use syn::visit::visit_item_fn;
-use syn::{ExprPath, ItemFn};
+use syn::spanned::Spanned;
+use syn::ext::IdentExt;
+use syn::{Attribute, Expr, ExprPath, ForeignItem, Ident, ItemFn, ItemMod, Macro, Token};
+use syn::parse::Parser;
+use syn::punctuated::Punctuated;
+use std::ops::Range;
+use quote::ToTokens;
+use proc_macro2::TokenTree;
+use import_style::ImportStyle;
+use tree::ModuleTree;
+
+mod api;
+mod audit;
+mod batch;
+mod cli;
+mod config;
+mod crate_split;
+mod diff;
+mod doc_links;
+mod dry_run;
+mod encoding;
+mod explain;
+mod gate;
+mod graph;
+mod god_object;
+mod idents;
+mod import_style;
+mod like;
+mod lock;
+mod log;
+mod naming;
+mod output;
+mod plan;
+mod preview;
+mod progress;
+mod recipe;
+mod rename;
+mod report;
+mod review;
+mod revert;
+mod script;
+mod spans;
+mod state_machine;
+mod stats;
+mod strategy;
+mod summary;
+mod teach;
+mod tree;
+mod verify;
+mod watch;
+
+// Literal arrays of at least this many elements are tables, such as lookup tables, which are
+// usually laid out by hand
+const LITERAL_TABLE_MIN_ELEMENTS: usize = 16;
+
+// Functions with at most this many statements that call into an extern block are
+// treated as thin safe wrappers and kept in the ffi module with their declarations
+const MAX_FFI_WRAPPER_STMTS: usize = 3;
+
+// A function referenced from at least this share of the other groups (and from at least
+// `HOT_ITEM_MIN_GROUPS` of them) is considered hot and pinned into the common module
+const HOT_ITEM_GROUP_SHARE: f64 = 0.75;
+const HOT_ITEM_MIN_GROUPS: usize = 2;
+
+// An import needed by at least this share of the generated modules (and by at least
+// `PRELUDE_MIN_MODULES` of them) moves into a prelude module they all import with one glob
+const PRELUDE_MODULE_SHARE: f64 = 0.75;
+const PRELUDE_MIN_MODULES: usize = 4;
+
+// Functions in this group stay in the module they are declared in, which a plan can ask for too
+const STAY_GROUP: &str = "self";
+
+// Inline modules with fewer functions than this are kept as they are by default, rather than
+// split into files of a function or two each
+const DEFAULT_MIN_INLINE_FNS: usize = 4;
+
+// Domain-neutral generic helpers go into this group with `--strategy generic=support`
+const GENERIC_SUPPORT_GROUP: &str = "generic_support";
+
+// Groups a rule put their functions in, which keep them however small they are or whoever calls them
+const PINNED_GROUPS: &[&str] = &["ffi", "common", "expand", "asm", GENERIC_SUPPORT_GROUP];
+
+// Function to tell whether a group is one the tool names itself, the default group or a pinned
+// one, which a model module, a call cluster or a state type mustn't take the name of
+fn is_reserved_group(group: &str) -> bool {
+    group == "general" || PINNED_GROUPS.contains(&group)
+}
+
+// Methods of commonly imported traits, so that a module calling e.g. `.write_all()` gets the
+// `use std::io::Write;` it needs. Modules calling methods while the parent imports traits missing
+// here fall back to a `use crate::*;` (or `use super::*;`), which takes those traits along
+const KNOWN_TRAIT_METHODS: &[(&str, &[&str])] = &[
+    ("Read", &["read", "read_to_end", "read_to_string", "read_exact", "bytes", "take", "chain"]),
+    ("Write", &["write", "write_all", "write_fmt", "flush", "write_str", "write_char"]),
+    ("BufRead", &["lines", "read_line", "read_until", "split", "fill_buf", "consume"]),
+    ("Seek", &["seek", "rewind", "stream_position"]),
+    ("Hasher", &["finish", "write_u8", "write_u32", "write_u64", "write_usize"]),
+    ("Hash", &["hash"]),
+    ("FromStr", &["from_str"]),
+    ("Error", &["source", "description"]),
+    ("PermissionsExt", &["mode", "set_mode", "from_mode"]),
+    ("OpenOptionsExt", &["mode", "custom_flags"]),
+    ("OsStrExt", &["as_bytes", "from_bytes", "encode_wide"]),
+    ("OsStringExt", &["into_vec", "from_vec", "from_wide"]),
+    ("CommandExt", &["exec", "uid", "gid", "creation_flags"]),
+    ("Rng", &["gen", "gen_range", "gen_bool", "fill", "sample"]),
+    ("AsyncReadExt", &["read", "read_to_end", "read_to_string", "read_exact", "read_buf"]),
+    ("AsyncWriteExt", &["write", "write_all", "flush", "shutdown"]),
+    ("StreamExt", &["next", "map", "filter", "collect", "for_each"]),
+    ("FutureExt", &["boxed", "map", "then", "fuse"]),
+    ("Itertools", &["join", "sorted", "unique", "dedup", "tuple_windows", "collect_vec"]),
+    ("Context", &["context", "with_context"]),
+];
+
+// Traits of the std prelude that `#![no_std]` crates import from `alloc`, and so do the modules
+// calling their methods. With std they are in scope everywhere and an import would be redundant
+const ALLOC_TRAIT_METHODS: &[(&str, &[&str])] = &[
+    ("ToString", &["to_string"]),
+    ("ToOwned", &["to_owned"]),
+];
+
+// Macros of the std prelude, which code names without importing them
+const STD_MACROS: &[&str] = &[
+    "assert", "assert_eq", "assert_ne", "cfg", "column", "compile_error", "concat", "dbg", "debug_assert",
+    "debug_assert_eq", "debug_assert_ne", "env", "eprint", "eprintln", "file", "format", "format_args",
+    "include", "include_bytes", "include_str", "line", "matches", "module_path", "option_env", "panic",
+    "print", "println", "stringify", "thread_local", "todo", "unimplemented", "unreachable", "vec", "write",
+    "writeln",
+];
+
+// Names no path takes from a module: primitive types and path keywords
+const BUILTIN_NAMES: &[&str] = &[
+    "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64", "i128", "isize",
+    "f32", "f64", "self", "Self", "super", "crate",
+];
+
+// Items of the prelude by their path below `std`, `core` or `alloc`, with the edition that added
+// them and whether `#![no_std]` crates have them in scope as well. Copying an import of one of
+// them into a generated module would be redundant
+const PRELUDE_ITEMS: &[(&str, u16, bool)] = &[
+    ("marker::Copy", 2015, true),
+    ("marker::Send", 2015, true),
+    ("marker::Sized", 2015, true),
+    ("marker::Sync", 2015, true),
+    ("marker::Unpin", 2015, true),
+    ("ops::Drop", 2015, true),
+    ("ops::Fn", 2015, true),
+    ("ops::FnMut", 2015, true),
+    ("ops::FnOnce", 2015, true),
+    ("mem::drop", 2015, true),
+    ("clone::Clone", 2015, true),
+    ("cmp::Eq", 2015, true),
+    ("cmp::Ord", 2015, true),
+    ("cmp::PartialEq", 2015, true),
+    ("cmp::PartialOrd", 2015, true),
+    ("convert::AsMut", 2015, true),
+    ("convert::AsRef", 2015, true),
+    ("convert::From", 2015, true),
+    ("convert::Into", 2015, true),
+    ("default::Default", 2015, true),
+    ("iter::DoubleEndedIterator", 2015, true),
+    ("iter::ExactSizeIterator", 2015, true),
+    ("iter::Extend", 2015, true),
+    ("iter::IntoIterator", 2015, true),
+    ("iter::Iterator", 2015, true),
+    ("option::Option", 2015, true),
+    ("option::Option::Some", 2015, true),
+    ("option::Option::None", 2015, true),
+    ("result::Result", 2015, true),
+    ("result::Result::Ok", 2015, true),
+    ("result::Result::Err", 2015, true),
+    ("boxed::Box", 2015, false),
+    ("borrow::ToOwned", 2015, false),
+    ("string::String", 2015, false),
+    ("string::ToString", 2015, false),
+    ("vec::Vec", 2015, false),
+    ("convert::TryFrom", 2021, true),
+    ("convert::TryInto", 2021, true),
+    ("iter::FromIterator", 2021, true),
+    ("future::Future", 2024, true),
+    ("future::IntoFuture", 2024, true),
+];
+
+// Macros whose code is assembly, tying the items using them to symbols and sections
+const ASM_MACROS: &[&str] = &["asm", "global_asm", "naked_asm"];
+
+// Names of the marked regions the tool owns inside the generated root file
+const MODS_REGION: &str = "mods";
+const REEXPORTS_REGION: &str = "reexports";
+const SMOKE_REGION: &str = "smoke";
+
+// What the tool was asked to do
+#[derive(PartialEq)]
+enum Mode {
+    // Split the input file and write the generated modules
+    Refactor,
+    // Print the current and proposed module trees without writing anything
+    Tree,
+    // Rename a module across the crate
+    RenameMod { old: String, new: String },
+    // Propose how to split the crate's god objects without writing anything
+    GodObjects,
+    // Report the generated re-exports nothing uses anymore, and remove them with --fix
+    AuditReexports,
+    // Fail when a file or function of the crate is longer than allowed, such as in CI
+    Gate { max_file_lines: usize, max_fn_lines: usize },
+    // Write the graph of which modules use which for architecture tools, to a file or stdout
+    Graph { format: graph::Format, output: Option<String> },
+    // Explain the errors `cargo check` reports after an apply by the plan decisions behind them
+    ExplainError,
+    // Print where each planned function is in the source and where it goes, for editors
+    Spans,
+    // Write a page showing the input next to the files it would be split into, and open it
+    // in the browser with --open
+    Preview { open: bool },
+    // List the built-in recipes
+    Recipes,
+    // Write the plan of a refactoring without carrying it out
+    Plan,
+    // Carry out a plan on the input it was made for
+    Apply,
+    // Undo what the last run that wrote files did
+    Revert,
+    // Show the plan again every time the input changes
+    Watch,
+    // Refactor several inputs, or the files glob patterns match, one after the other
+    Batch { inputs: Vec<String> },
+}
+
+// Checks run on the package after the refactoring, which is rolled back if one fails
+#[derive(PartialEq)]
+enum Verify {
+    // `cargo check` must not report any warning it didn't report before
+    Warnings,
+}
+
+// Where the generated files are written
+#[derive(PartialEq)]
+enum Emit {
+    // Into the directory of the input, next to it
+    Files,
+    // Nowhere, they are printed instead
+    Memory,
+    // Into a commit on a new `refactor` branch, leaving the working tree as it is
+    Git,
+    // Onto stdout, one after the other below a line naming each, or as JSON
+    Stdout,
+    Json,
+}
+
+// What becomes of items containing assembly or placing themselves with `#[link_section]` and
+// `#[used]`, whose placement the compiler cannot check
+#[derive(PartialEq)]
+enum AsmPolicy {
+    // Keep them where they are declared
+    Keep,
+    // Move them together into an `asm` module
+    Group,
+    // Group them like any other item
+    Free,
+}
+
+// Command line options controlling the refactoring
+struct Options {
+    mode: Mode,
+    input_file: String,
+    // Write each inline `mod foo { ... }` into its own file instead of keeping it inline
+    promote_inline_mods: bool,
+    // Add a test to the root that names an item through every generated module and re-export
+    smoke_tests: bool,
+    // Set when the input has no `main`; generated modules are then kept private so that the
+    // library's public API stays exactly as it was
+    library: bool,
+    // Set when the input is `#![no_std]`
+    no_std: bool,
+    // Let the refactoring of a library change its public API, which is an error otherwise
+    allow_api_changes: bool,
+    // Edition of the crate, which decides what its prelude holds and how rustfmt formats it. Taken
+    // from its manifest unless --edition names one
+    edition: u16,
+    forced_edition: Option<u16>,
+    verify: Option<Verify>,
+    emit: Emit,
+    asm: AsmPolicy,
+    // Explain each structural decision together with the Rust concept behind it
+    teach: bool,
+    // Move functions only called from one other group next to their caller
+    inline_single_use: bool,
+    // Read input that isn't valid UTF-8 anyway, replacing the invalid bytes
+    lossy: bool,
+    // An edited plan to apply, and the groups chosen by hand in it and in earlier plans
+    plan_file: Option<String>,
+    assignments: plan::Assignments,
+    // Write the result as a new package in this directory, with a Cargo.toml and the modules under
+    // `src/`, such as when a cargo script has outgrown its single file
+    package_dir: Option<String>,
+    // Write the generated modules and root into this directory instead of next to the input
+    target_dir: Option<String>,
+    // Write the generated root over the input, which `refactor revert` puts back
+    in_place: bool,
+    // Read the input from stdin instead, under `input_file` as its name
+    stdin: bool,
+    // Files of a crate given as a directory are split when they are longer than this and have at
+    // least `min_fns` functions; smaller ones are left alone
+    max_file_lines: usize,
+    min_fns: usize,
+    // A built-in recipe setting the other options up for a common shape of file
+    recipe: Option<&'static recipe::Recipe>,
+    // A crate whose module layout to imitate, and its top-level modules once read
+    like_crate: Option<String>,
+    model_modules: Vec<like::ModelModule>,
+    // Apply what an audit found instead of only reporting it
+    fix: bool,
+    // Queue behind another run writing to the same package instead of failing
+    wait: bool,
+    // Spell out the `use crate::*;` of generated modules as the names they take
+    explicit_imports: bool,
+    // Print what would be written instead of writing it, as a summary or as a unified diff
+    dry_run: bool,
+    diff: bool,
+    // Print the run as one JSON object instead of the summary, with --format json
+    json_report: bool,
+    // Write nothing and exit with 1 when the input is large enough that it would be split
+    check: bool,
+    // Ask for each proposed module whether to write it, skip it or rename it
+    interactive: bool,
+    // Where to write the size of each module the run writes, or would write
+    stats: Option<String>,
+    // How much the phases of the run log on stderr, from -v or --log-level
+    log_level: log::Level,
+    // How the imports of generated modules are laid out
+    import_style: ImportStyle,
+    // Write generated code as it is emitted, without running rustfmt or prettyplease over it
+    no_format: bool,
+    // The rustfmt.toml to format with instead of the nearest one above the input
+    rustfmt_config: Option<String>,
+    // How each kind of item is placed
+    strategies: strategy::Strategies,
+    // Functions that stay where they are, by name or path, with `*` and `?` wildcards
+    exclude: Vec<String>,
+    // Groups of fewer functions are merged into the misc module, or stay where they are when
+    // it is `self`
+    min_module_items: usize,
+    misc_module: String,
+    // Inline modules with fewer functions, counting those of their own inline modules, are kept as
+    // they are
+    min_inline_fns: usize,
+    // Put in front of the name of every generated module instead of the `_mod` after it
+    module_prefix: Option<String>,
+    // Names given to generated modules during an --interactive review, by their proposed paths
+    module_renames: HashMap<String, String>,
+    // Breaks the ties between equally good placements, recorded for the run to be reproduced
+    seed: u64,
+}
+
+// The options of a run given no flags, which parse_args changes flag by flag
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            mode: Mode::Refactor,
+            input_file: String::new(),
+            promote_inline_mods: false,
+            smoke_tests: false,
+            library: false,
+            no_std: false,
+            allow_api_changes: false,
+            edition: 2015,
+            forced_edition: None,
+            verify: None,
+            emit: Emit::Files,
+            asm: AsmPolicy::Keep,
+            teach: false,
+            inline_single_use: false,
+            lossy: false,
+            plan_file: None,
+            assignments: plan::Assignments::default(),
+            package_dir: None,
+            target_dir: None,
+            in_place: false,
+            stdin: false,
+            max_file_lines: gate::DEFAULT_MAX_FILE_LINES,
+            min_fns: 0,
+            recipe: None,
+            like_crate: None,
+            model_modules: Vec::new(),
+            fix: false,
+            wait: false,
+            explicit_imports: false,
+            dry_run: false,
+            diff: false,
+            json_report: false,
+            check: false,
+            interactive: false,
+            stats: None,
+            log_level: log::Level::Warn,
+            import_style: ImportStyle::Original,
+            no_format: false,
+            rustfmt_config: None,
+            strategies: strategy::Strategies::default(),
+            exclude: Vec::new(),
+            min_module_items: 0,
+            misc_module: "misc".to_string(),
+            min_inline_fns: DEFAULT_MIN_INLINE_FNS,
+            module_prefix: None,
+            module_renames: HashMap::new(),
+            seed: 0,
+        }
+    }
+}
+
+// What is left of a module after its functions were split out into module files
+struct RefactoredItems {
+    imports: Vec<String>,
+    other_items: Vec<String>,
+    mod_declarations: Vec<String>,
+    use_statements: Vec<String>,
+    main_function: Option<String>,
+    files: Vec<GeneratedFile>,
+    tree: ModuleTree,
+    // Absolute paths of moved items, mapped to where they live now
+    moves: HashMap<Vec<String>, Vec<String>>,
+    smoke_targets: Vec<SmokeTarget>,
+    plan: Vec<plan::PlanEntry>,
+    decisions: Vec<teach::Decision>,
+    // Functions only called from another module, and where they could go instead
+    suggestions: Vec<String>,
+}
+
+// The items of a module sorted by what the grouping does with them, along with what its inline
+// modules were refactored into
+struct CollectedItems<'a> {
+    // Every import in its original order, the index of the import bringing in each name, and
+    // the path of each, such as `serde_json::Value` for `Value`
+    imports: Vec<String>,
+    imported_names: HashMap<String, usize>,
+    import_path_of: HashMap<String, String>,
+    aliases: HashMap<String, Vec<String>>,
+    // The functions that may move, their parsed code, and where their code starts in the source
+    // their spans index into
+    functions: HashMap<String, String>,
+    func_asts: HashMap<String, ItemFn>,
+    func_offsets: HashMap<String, usize>,
+    // Later declarations of a function under another `#[cfg]`, such as the `#[cfg(not(unix))]`
+    // version of a `#[cfg(unix)]` one, with their code, AST and offset. They go wherever the first
+    // declaration goes, so that every configuration finds its version in the same module
+    cfg_twins: HashMap<String, Vec<(String, ItemFn, usize)>>,
+    main_function: Option<String>,
+    main_ast: Option<&'a ItemFn>,
+    test_module: Option<(&'a ItemMod, Range<usize>, usize)>,
+    // Other items like constants and types, which stay unless they move along with a group
+    other_items: Vec<String>,
+    // Extern blocks together with their link attributes
+    ffi_items: Vec<String>,
+    // Functions, statics and `global_asm!` kept together with --asm group
+    asm_functions: HashSet<String>,
+    asm_items: Vec<String>,
+    asm_names: Vec<String>,
+    // Functions declared by extern blocks, and everything they declare, which moves into ffi
+    foreign_functions: HashSet<String>,
+    foreign_names: HashSet<String>,
+    proc_macro_impls: HashSet<String>,
+    // Enums and structs defined here (mapped to whether they are enums), with the indices in
+    // `other_items` of their definitions and impls
+    state_types: HashMap<String, bool>,
+    state_type_vis: HashMap<String, syn::Visibility>,
+    type_items: HashMap<String, Vec<usize>>,
+    trait_impl_items: HashSet<usize>,
+    // Names declared here, which imports of them only resolve from, and the `macro_rules!`
+    // macros, which are only in scope below their definition
+    local_names: HashSet<String>,
+    local_macros: HashSet<String>,
+    files: Vec<GeneratedFile>,
+    child_trees: Vec<ModuleTree>,
+    moves: HashMap<Vec<String>, Vec<String>>,
+    smoke_targets: Vec<SmokeTarget>,
+    plan_entries: Vec<plan::PlanEntry>,
+    decisions: Vec<teach::Decision>,
+    suggestions: Vec<String>,
+    item_count: usize,
+}
+
+// The group each function that may move was placed in, and why
+struct Placement {
+    assigned_categories: HashMap<String, String>,
+    placement_reasons: HashMap<String, String>,
+    // Groups driving a state type, mapped to the type
+    state_groups: HashMap<String, String>,
+}
+
+// The code and imports of each group, and what moves along with its functions
+struct Groups {
+    assigned_categories: HashMap<String, String>,
+    placement_reasons: HashMap<String, String>,
+    state_groups: HashMap<String, String>,
+    grouped_functions: HashMap<String, Vec<(String, String)>>,
+    group_imports: HashMap<String, HashSet<String>>,
+    // Whether each of those imports is only maybe used, being a trait taken for a method that
+    // another imported trait has as well
+    import_doubts: HashMap<String, HashMap<String, bool>>,
+    // The functions of the common group by the visibility they are re-exported with
+    common_functions: BTreeMap<String, Vec<String>>,
+    // Set when all the functions stay where they are, see stays_in_place
+    keeps_in_place: bool,
+    split_tests: Option<(usize, SplitTests)>,
+    state_items: HashMap<String, Vec<String>>,
+    moved_types: HashMap<String, Vec<String>>,
+}
+
+// The module each group is written to, the groups named after their docs, and the module names
+// taken next to them
+struct ModuleNames {
+    module_names: HashMap<String, String>,
+    doc_names: HashMap<String, String>,
+    module_idents: HashSet<String>,
+}
+
+// A generated module and an item that is visible through it, checked by the smoke test
+struct SmokeTarget {
+    module_path: Vec<String>,
+    item: Option<String>,
+}
+
+// What becomes of an existing `#[cfg(test)] mod tests` once the functions it tests have moved
+struct SplitTests {
+    // The module as it stays behind, or None when all of its tests moved out
+    remaining: Option<String>,
+    imports: Vec<String>,
+    // The tests moving into each group, by name, and the imports they use
+    moved: HashMap<String, Vec<(String, String)>>,
+    used_imports: HashMap<String, HashSet<usize>>,
+}
+
+// A file the refactoring wants to write
+struct GeneratedFile {
+    path: PathBuf,
+    code: String,
+    // Module the file defines, and the module its code was originally written in
+    module_path: Vec<String>,
+    written_in: Vec<String>,
+}
 
 struct CrateUsageVisitor<'a> {
-    imported_functions: &'a HashMap<String, String>,
-    used_crates: HashSet<String>,
+    // Names brought into scope by the module's imports, mapped to the import's index
+    imported_names: &'a HashMap<String, usize>,
+    // The names renamed imports bring in, by the name they rename, such as `coll` for
+    // `collections` after `use std::collections as coll;`
+    aliases: &'a HashMap<String, Vec<String>>,
+    // The imported names the code uses
+    used_imports: HashSet<String>,
+    // Names declared by `use` statements and items inside the blocks being visited, which shadow
+    // the module's imports and travel with the function anyway
+    local_names: Vec<HashSet<String>>,
+    // Traits imported because one of their methods is called, with that method
+    trait_methods: Vec<(String, String)>,
+    // The path each imported name brings in, and the modules the code names, such as `io` in
+    // `io::stdout()` or `fmt` for a `Formatter` imported from `std::fmt`
+    import_paths: &'a HashMap<String, String>,
+    named_modules: HashSet<String>,
+    // Methods that several imported traits have, such as `write_fmt` with both `fmt::Write` and
+    // `io::Write` imported, with the traits, which are told apart once all the code is visited
+    ambiguous_methods: Vec<(String, Vec<String>)>,
+    // Traits taken for such a method although the code names none of their modules, which may
+    // well go unused
+    unsure_traits: HashSet<String>,
+    // Whether the crate is `#![no_std]`, so that the alloc prelude traits have to be imported
+    no_std: bool,
+}
+
+impl CrateUsageVisitor<'_> {
+    fn is_local(&self, name: &str) -> bool {
+        self.local_names.iter().any(|names| names.contains(name))
+    }
+
+    fn note_name(&mut self, name: &str) -> bool {
+        if self.is_local(name) || !self.imported_names.contains_key(name) {
+            return false;
+        }
+        // Whatever a module exports, naming it names the module too
+        if let Some(module) = self.import_paths.get(name).and_then(|path| import_module(path)) {
+            self.named_modules.insert(module.to_string());
+        }
+        self.used_imports.insert(name.to_string())
+    }
+
+    fn note_path(&mut self, path: &syn::Path) {
+        if let Some(segment) = path.segments.first() {
+            self.note_name(&segment.ident.to_string());
+        }
+        self.named_modules.extend(path.segments.iter().rev().skip(1).map(|segment| segment.ident.to_string()));
+    }
+
+    fn note_trait_method(&mut self, method: &str) {
+        let alloc_traits = if self.no_std { ALLOC_TRAIT_METHODS } else { &[] };
+        let mut candidates = Vec::new();
+        for (trait_name, methods) in KNOWN_TRAIT_METHODS.iter().chain(alloc_traits) {
+            if !methods.contains(&method) {
+                continue;
+            }
+            // A trait may be imported under another name, or as `_` for its methods alone
+            let names: Vec<String> = std::iter::once(trait_name.to_string())
+                .chain(self.aliases.get(*trait_name).into_iter().flatten().cloned())
+                .filter(|name| self.imported_names.contains_key(name) && !self.is_local(name))
+                .collect();
+            if names.iter().any(|name| self.used_imports.contains(name)) {
+                continue;
+            }
+            candidates.extend(names);
+        }
+        match candidates.as_slice() {
+            [] => {}
+            [name] => {
+                let name = name.clone();
+                if self.note_name(&name) {
+                    self.trait_methods.push((name, method.to_string()));
+                }
+            }
+            _ => self.ambiguous_methods.push((method.to_string(), candidates)),
+        }
+    }
+
+    // Function to take the traits of the methods several imported traits have once the code is
+    // visited: all of them, since the receiver's type is unknown, but those from a module the
+    // code names nowhere are only maybe used
+    fn note_ambiguous_methods(&mut self) {
+        for (method, names) in std::mem::take(&mut self.ambiguous_methods) {
+            for name in names {
+                let module_named = self.import_paths.get(&name).and_then(|path| import_module(path)).is_some_and(|module| self.named_modules.contains(module));
+                if !module_named && !self.used_imports.contains(&name) {
+                    self.unsure_traits.insert(name.clone());
+                }
+                if self.used_imports.insert(name.clone()) {
+                    self.trait_methods.push((name, method.clone()));
+                }
+            }
+        }
+    }
+}
+
+// Function to find the last segment of the module an import takes its name from, such as `io`
+// for `std::io::Write` or `fmt` for `std::fmt::Write as FmtWrite`
+fn import_module(path: &str) -> Option<&str> {
+    let path = path.split(" as ").next().unwrap_or(path);
+    let (module, _) = path.rsplit_once("::")?;
+    module.rsplit("::").next()
+}
+
+impl<'a> Visit<'_> for CrateUsageVisitor<'a> {
+    fn visit_expr_path(&mut self, node: &ExprPath) {
+        self.note_path(&node.path);
+        syn::visit::visit_expr_path(self, node);
+    }
+
+    fn visit_block(&mut self, node: &syn::Block) {
+        let local_names = node
+            .stmts
+            .iter()
+            .filter_map(|stmt| match stmt {
+                syn::Stmt::Item(item) => Some(item),
+                _ => None,
+            })
+            .flat_map(declared_names)
+            .collect();
+        self.local_names.push(local_names);
+        syn::visit::visit_block(self, node);
+        self.local_names.pop();
+    }
+
+    // Local imports start from a name in scope, which may be one the module imports, such as the
+    // `io` of a `use io::Write;` in a function relying on the module's `use std::io;`
+    fn visit_item_use(&mut self, node: &syn::ItemUse) {
+        for name in use_root_names(node) {
+            self.note_name(&name);
+        }
+    }
+
+    // Struct literals and patterns, such as `Config { .. }` and `Value::String(text)`
+    fn visit_expr_struct(&mut self, node: &syn::ExprStruct) {
+        self.note_path(&node.path);
+        syn::visit::visit_expr_struct(self, node);
+    }
+
+    fn visit_pat_struct(&mut self, node: &syn::PatStruct) {
+        self.note_path(&node.path);
+        syn::visit::visit_pat_struct(self, node);
+    }
+
+    fn visit_pat_tuple_struct(&mut self, node: &syn::PatTupleStruct) {
+        self.note_path(&node.path);
+        syn::visit::visit_pat_tuple_struct(self, node);
+    }
+
+    // Traits named in bounds, such as `T: Serialize` or `where T: Into<Config>`
+    fn visit_trait_bound(&mut self, node: &syn::TraitBound) {
+        self.note_path(&node.path);
+        syn::visit::visit_trait_bound(self, node);
+    }
+
+    // Traits implemented by the items of a function body, such as `impl Display for Row`
+    fn visit_item_impl(&mut self, node: &syn::ItemImpl) {
+        if let Some((_, path, _)) = &node.trait_ {
+            self.note_path(path);
+        }
+        syn::visit::visit_item_impl(self, node);
+    }
+
+    // Method calls need the trait they come from in scope
+    fn visit_expr_method_call(&mut self, node: &syn::ExprMethodCall) {
+        self.note_trait_method(&node.method.to_string());
+        syn::visit::visit_expr_method_call(self, node);
+    }
+
+    // Types wherever they are named: in signatures, generics, fields, `let` bindings, casts and
+    // turbofish, such as the `DateTime` in `fn log(at: DateTime<Utc>)`
+    fn visit_type_path(&mut self, node: &syn::TypePath) {
+        self.note_path(&node.path);
+        syn::visit::visit_type_path(self, node);
+    }
+
+    // Macros imported by name, such as `use alloc::format;`, and the `write_fmt` that `write!`
+    // and `writeln!` call
+    fn visit_macro(&mut self, node: &Macro) {
+        if node.path.leading_colon.is_none() {
+            self.note_path(&node.path);
+        }
+        if node.path.is_ident("write") || node.path.is_ident("writeln") {
+            self.note_trait_method("write_fmt");
+        }
+        let args = macro_args(node);
+        if args.is_empty() {
+            for name in macro_token_paths(node.tokens.clone()) {
+                self.note_name(&name);
+            }
+        }
+        for expr in args {
+            self.visit_expr(&expr);
+        }
+    }
+}
+
+// Collects the names a piece of code takes from the module around it: the first segment of every
+// path, and the methods it calls, which may need a trait in scope
+#[derive(Default)]
+struct NameUsageVisitor {
+    names: HashSet<String>,
+    methods: HashSet<String>,
+    // Items declared inside function bodies, which shadow the module's names
+    local_names: HashSet<String>,
+    // Variables and generic parameters, which paths name without taking anything from the module
+    bound_names: HashSet<String>,
+}
+
+impl NameUsageVisitor {
+    // Function to list the names used, leaving out the ones declared locally
+    fn used_names(&self) -> impl Iterator<Item = &String> {
+        self.names.iter().filter(|name| !self.local_names.contains(*name))
+    }
+}
+
+impl Visit<'_> for NameUsageVisitor {
+    fn visit_path(&mut self, node: &syn::Path) {
+        if let (None, Some(segment)) = (node.leading_colon, node.segments.first()) {
+            self.names.insert(segment.ident.to_string());
+        }
+        syn::visit::visit_path(self, node);
+    }
+
+    fn visit_block(&mut self, node: &syn::Block) {
+        for stmt in &node.stmts {
+            if let syn::Stmt::Item(item) = stmt {
+                self.local_names.extend(declared_names(item));
+            }
+        }
+        syn::visit::visit_block(self, node);
+    }
+
+    // Local imports start from a name in scope, which may be one the module imports, such as the
+    // `io` of a `use io::Write;` in a function relying on the module's `use std::io;`
+    fn visit_item_use(&mut self, node: &syn::ItemUse) {
+        self.names.extend(use_root_names(node));
+    }
+
+    fn visit_expr_method_call(&mut self, node: &syn::ExprMethodCall) {
+        self.methods.insert(node.method.to_string());
+        syn::visit::visit_expr_method_call(self, node);
+    }
+
+    // Patterns naming an uppercase ident are constants or unit structs rather than bindings
+    fn visit_pat_ident(&mut self, node: &syn::PatIdent) {
+        let name = node.ident.to_string();
+        if !name.starts_with(char::is_uppercase) {
+            self.bound_names.insert(name);
+        }
+        syn::visit::visit_pat_ident(self, node);
+    }
+
+    fn visit_type_param(&mut self, node: &syn::TypeParam) {
+        self.bound_names.insert(node.ident.to_string());
+        syn::visit::visit_type_param(self, node);
+    }
+
+    fn visit_const_param(&mut self, node: &syn::ConstParam) {
+        self.bound_names.insert(node.ident.to_string());
+        syn::visit::visit_const_param(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &Macro) {
+        self.visit_path(&node.path);
+        if node.path.is_ident("write") || node.path.is_ident("writeln") {
+            self.methods.insert("write_fmt".to_string());
+        }
+        let args = macro_args(node);
+        if args.is_empty() {
+            self.names.extend(macro_token_paths(node.tokens.clone()));
+        }
+        for expr in args {
+            // Names captured by a format string, such as the `LIMIT` in `println!("{LIMIT}")`
+            if let Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(text), .. }) = &expr {
+                self.names.extend(format_captures(&text.value()));
+            }
+            self.visit_expr(&expr);
+        }
+    }
+}
+
+// What a generated module can take from the module it is split out of
+struct ParentNames {
+    // Names declared or imported there, including the functions moving into generated modules
+    declared: HashSet<String>,
+    // Traits declared there, and the known traits imported there, with their methods
+    traits: Vec<(String, Vec<String>)>,
+    // Imports never named by a path, which are likely traits imported for their methods
+    method_only_imports: HashSet<String>,
+    // Whether it has names the tool cannot see, from glob imports or item macros
+    opaque: bool,
+}
+
+struct ForeignCallVisitor<'a> {
+    foreign_functions: &'a HashSet<String>,
+    calls_foreign: bool,
+}
+
+impl<'a> Visit<'_> for ForeignCallVisitor<'a> {
+    fn visit_expr_path(&mut self, node: &ExprPath) {
+        if let Some(segment) = node.path.segments.last() {
+            if self.foreign_functions.contains(&segment.ident.to_string()) {
+                self.calls_foreign = true;
+            }
+        }
+        syn::visit::visit_expr_path(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &Macro) {
+        for expr in macro_args(node) {
+            self.visit_expr(&expr);
+        }
+    }
+}
+
+// Collects every path a function writes out, in expressions, types, patterns, struct literals,
+// qualified calls and turbofish alike, such as `serde_json::to_string` or `Value`
+#[derive(Default)]
+struct PathVisitor {
+    paths: Vec<String>,
+}
+
+impl Visit<'_> for PathVisitor {
+    fn visit_path(&mut self, node: &syn::Path) {
+        let segments: Vec<String> = node.segments.iter().map(|segment| segment.ident.to_string()).collect();
+        self.paths.push(segments.join("::"));
+        syn::visit::visit_path(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &Macro) {
+        self.visit_path(&node.path);
+        for expr in macro_args(node) {
+            self.visit_expr(&expr);
+        }
+    }
+}
+
+struct ReferenceVisitor {
+    referenced_names: HashSet<String>,
+    // Whether `super::name` paths name items of the module being analyzed, as they do in its tests
+    through_super: bool,
+}
+
+impl Visit<'_> for ReferenceVisitor {
+    fn visit_expr_path(&mut self, node: &ExprPath) {
+        if let Some(ident) = node.path.get_ident() {
+            self.referenced_names.insert(ident.to_string());
+        } else if let (true, [first, second]) = (self.through_super, &node.path.segments.iter().collect::<Vec<_>>()[..]) {
+            if first.ident == "super" {
+                self.referenced_names.insert(second.ident.to_string());
+            }
+        }
+        syn::visit::visit_expr_path(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &Macro) {
+        for expr in macro_args(node) {
+            self.visit_expr(&expr);
+        }
+    }
+}
+
+// Collects the edits that keep `self::` and `super::` paths of an item naming the same items
+// once the item sits one module deeper
+struct RelativePathVisitor {
+    edits: Vec<(Range<usize>, String)>,
+    // How many modules declared inside the item the visitor is in, whose own `self` and first
+    // `super`s stay inside the item
+    nesting: usize,
+}
+
+impl RelativePathVisitor {
+    // Function to anchor a path starting with `first` followed by `supers` more `super`s
+    fn anchor(&mut self, first: &Ident, has_rest: bool, supers: usize) {
+        if first == "self" && has_rest && self.nesting == 0 {
+            self.edits.push((first.span().byte_range(), "super".to_string()));
+        } else if first == "super" && supers + 1 >= self.nesting {
+            let start = first.span().byte_range().start;
+            self.edits.push((start..start, "super::".to_string()));
+        }
+    }
+
+    // Function to anchor the paths among the tokens of a macro, which needn't parse as Rust,
+    // such as the body of a `macro_rules!` or the `sym` operands of `asm!`
+    fn anchor_tokens(&mut self, tokens: proc_macro2::TokenStream) {
+        let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+        let is_path_sep = |index: usize| {
+            matches!((tokens.get(index), tokens.get(index + 1)), (Some(TokenTree::Punct(a)), Some(TokenTree::Punct(b)))
+                if a.as_char() == ':' && a.spacing() == proc_macro2::Spacing::Joint && b.as_char() == ':')
+        };
+        let mut index = 0;
+        while index < tokens.len() {
+            let starts_path = index == 0 || !matches!(&tokens[index - 1], TokenTree::Punct(punct) if punct.as_char() == ':' || punct.as_char() == '$');
+            match &tokens[index] {
+                TokenTree::Ident(first) if starts_path && (first == "self" || first == "super") => {
+                    let mut supers = 0;
+                    let mut next = index + 1;
+                    while is_path_sep(next) && matches!(tokens.get(next + 2), Some(TokenTree::Ident(ident)) if ident == "super") {
+                        supers += 1;
+                        next += 3;
+                    }
+                    self.anchor(first, is_path_sep(next), supers);
+                    index = next;
+                }
+                TokenTree::Group(group) => {
+                    self.anchor_tokens(group.stream());
+                    index += 1;
+                }
+                _ => index += 1,
+            }
+        }
+    }
+}
+
+// Function to count the `super`s following the first segment of a path
+fn following_supers<'a>(segments: impl Iterator<Item = &'a Ident>) -> usize {
+    segments.skip(1).take_while(|ident| *ident == "super").count()
+}
+
+impl Visit<'_> for RelativePathVisitor {
+    fn visit_path(&mut self, node: &syn::Path) {
+        if node.leading_colon.is_none() {
+            if let Some(first) = node.segments.first() {
+                let supers = following_supers(node.segments.iter().map(|segment| &segment.ident));
+                self.anchor(&first.ident, node.segments.len() > 1, supers);
+            }
+        }
+        syn::visit::visit_path(self, node);
+    }
+
+    fn visit_item_use(&mut self, node: &syn::ItemUse) {
+        if let (None, UseTree::Path(use_path)) = (node.leading_colon, &node.tree) {
+            let mut idents = vec![&use_path.ident];
+            let mut tree = &*use_path.tree;
+            while let UseTree::Path(inner) = tree {
+                idents.push(&inner.ident);
+                tree = &inner.tree;
+            }
+            self.anchor(&use_path.ident, true, following_supers(idents.into_iter()));
+        }
+    }
+
+    fn visit_macro(&mut self, node: &Macro) {
+        self.anchor_tokens(node.tokens.clone());
+    }
+
+    // Visibilities are relocated separately
+    fn visit_visibility(&mut self, _node: &syn::Visibility) {}
+
+    // Paths inside a module declared in the item are relative to it, up to where they leave it
+    fn visit_item_mod(&mut self, node: &ItemMod) {
+        self.nesting += 1;
+        syn::visit::visit_item_mod(self, node);
+        self.nesting -= 1;
+    }
+}
+
+// Collects the edits that keep the relative paths of included files pointing at the same files
+// once an item is written to a file `prefix` directories below
+struct IncludePathVisitor {
+    prefix: String,
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl Visit<'_> for IncludePathVisitor {
+    fn visit_macro(&mut self, node: &Macro) {
+        let args = macro_args(node);
+        let is_include = ["include", "include_str", "include_bytes"].iter().any(|name| node.path.is_ident(name));
+        if let (true, Some(Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(path), .. }))) = (is_include, args.first()) {
+            if Path::new(&path.value()).is_relative() {
+                self.edits.push((path.span().byte_range(), format!("{:?}", format!("{}{}", self.prefix, path.value()))));
+            }
+        }
+        for expr in args {
+            self.visit_expr(&expr);
+        }
+    }
+}
+
+// Collects the edits that point intra-doc links at the new paths of moved items
+struct DocLinkVisitor<'a> {
+    source: &'a str,
+    moves: &'a HashMap<Vec<String>, Vec<String>>,
+    // Module the docs were written in, and whether they now live somewhere else
+    module_path: Vec<String>,
+    relocated: bool,
+    // Whether the doc comment lines visited last left a fenced code block open
+    in_code_block: bool,
+    edits: Vec<(Range<usize>, String)>,
+}
+
+impl Visit<'_> for DocLinkVisitor<'_> {
+    fn visit_item_mod(&mut self, node: &ItemMod) {
+        for attr in &node.attrs {
+            self.visit_attribute(attr);
+        }
+        self.module_path.push(node.ident.to_string());
+        if let Some((_, items)) = &node.content {
+            for item in items {
+                self.visit_item(item);
+            }
+        }
+        self.module_path.pop();
+    }
+
+    fn visit_attribute(&mut self, node: &Attribute) {
+        if !node.path().is_ident("doc") {
+            return;
+        }
+        // Examples in code blocks are left as written: the re-exports keep every moved item
+        // reachable at its old path, so doc-tests naming them that way keep compiling
+        for link in doc_links::find_doc_links(self.source, node.span().byte_range(), &mut self.in_code_block) {
+            let Some(absolute) = doc_links::absolute_link_path(&link.path, &self.module_path) else {
+                continue;
+            };
+            // Plain shorthand links like [`Item`] keep resolving through the generated re-exports
+            let is_relative_to_module = link.path.starts_with("self::") || link.path.starts_with("super::");
+            if link.shorthand && !is_relative_to_module && !link.path.starts_with("crate::") {
+                continue;
+            }
+
+            match self.moves.get(&absolute) {
+                Some(new_path) => self.edits.push((link.range, format!("crate::{}", new_path.join("::")))),
+                // Paths relative to the old module stop resolving once the docs have moved
+                None if self.relocated && is_relative_to_module => {
+                    self.edits.push((link.range, format!("crate::{}", absolute.join("::"))))
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+// Why a run failed, and the status it exits with: 2 when the command line can't be run or a
+// checking mode couldn't come to a verdict, and 1 otherwise
+struct Failure {
+    message: String,
+    status: u8,
+}
+
+impl Failure {
+    fn new(status: u8, message: String) -> Failure {
+        Failure { message, status }
+    }
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure::new(1, message)
+    }
+}
+
+// The file being split, as it was read
+struct Input {
+    path: PathBuf,
+    original: Vec<u8>,
+    // The code as text, without the header of a cargo script, and how it was encoded
+    content: String,
+    style: encoding::Style,
+    script_header: script::ScriptHeader,
+}
+
+fn main() -> ExitCode {
+    // Get command line arguments for input file and options
+    let args: Vec<String> = env::args().collect();
+    match run(&args[1..]) {
+        Ok(status) => status,
+        Err(failure) => {
+            progress::clear();
+            eprintln!("Error: {}", failure.message);
+            ExitCode::from(failure.status)
+        }
+    }
+}
+
+// Function to carry out what the arguments after the program name ask for, returning the status
+// the run exits with
+fn run(args: &[String]) -> Result<ExitCode, Failure> {
+    if args.first().is_some_and(|arg| arg == "help") || args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print!("{}", cli::help());
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.first().is_some_and(|arg| arg == "completions") {
+        let shell = args.get(1).ok_or_else(|| Failure::new(2, "completions needs a shell: bash, zsh or fish".to_string()))?;
+        print!("{}", cli::completions(shell).map_err(|e| Failure::new(2, e))?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    cli::check(args).map_err(|e| Failure::new(2, format!("{}\nRun `refactor --help` for the options.", e)))?;
+    // The project's config file sets the defaults, which the flags given here override
+    let defaults = match config::find(args).map_err(|e| Failure::new(2, e))? {
+        Some(path) => config::flags(&path).map_err(|e| Failure::new(2, e))?,
+        None => Vec::new(),
+    };
+    let mut options = parse_args(args, &defaults).map_err(|e| Failure::new(2, e))?;
+
+    NO_FORMAT.set(options.no_format).expect("Formatting was decided before the arguments were parsed");
+    log::init(options.log_level);
+    progress::start();
+
+    // `apply` splits the input a plan was made for, the way the plan says
+    if options.mode == Mode::Apply {
+        let plan_file = options.plan_file.clone().unwrap_or_default();
+        options.input_file = plan::planned_input(Path::new(&plan_file))?.display().to_string();
+        options.mode = Mode::Refactor;
+    }
+    // What the run was asked to do, for the record that lets it be reverted
+    let command = format!("refactor {}", args.join(" "));
+
+    // Runs that write take the package's lock first, so that two of them can't interleave their
+    // writes, such as the hooks of two developers firing at once. It is held until the run ends
+    let writes = match options.mode {
+        Mode::Refactor | Mode::Apply => matches!(options.emit, Emit::Files | Emit::Git) && !options.dry_run && !options.diff,
+        Mode::Plan | Mode::Revert => true,
+        Mode::RenameMod { .. } => true,
+        Mode::AuditReexports => options.fix,
+        Mode::Tree | Mode::GodObjects | Mode::Gate { .. } | Mode::Graph { .. } | Mode::ExplainError | Mode::Spans | Mode::Preview { .. } | Mode::Recipes | Mode::Watch | Mode::Batch { .. } => false,
+    };
+    let _lock = match writes {
+        true => {
+            let input = Path::new(&options.input_file);
+            let lock_root = verify::find_package_root(input)
+                .unwrap_or_else(|| if input.is_dir() { input.to_path_buf() } else { input.parent().unwrap_or(Path::new(".")).to_path_buf() });
+            Some(lock::acquire(&lock_root, options.wait)?)
+        }
+        false => None,
+    };
+
+    match &options.mode {
+        Mode::RenameMod { old, new } => return rename_mod(&options.input_file, old, new),
+        Mode::Recipes => {
+            recipe::print_recipes();
+            return Ok(ExitCode::SUCCESS);
+        }
+        Mode::Watch => return watch_input(&options, args),
+        Mode::Batch { inputs } => return refactor_batch(&options, inputs, args),
+        Mode::Revert => return revert_run(&options.input_file),
+        _ => {}
+    }
+
+    // Input from stdin is named but never read from where its name points
+    if options.stdin && (options.mode != Mode::Refactor || matches!(options.emit, Emit::Files | Emit::Git)) {
+        return Err("input from stdin is only refactored, and written to stdout with --emit stdout or --emit json".to_string().into());
+    }
+    let input_path = match options.stdin {
+        true => PathBuf::from(&options.input_file),
+        false => {
+            let input = Path::new(&options.input_file);
+            let missing = match input.is_dir() {
+                true => format!("{}: no crate root found in it", options.input_file),
+                false => format!("{}: not found", options.input_file),
+            };
+            // With --check, an input that couldn't be checked is told apart from one that would be split
+            tree::resolve_crate_root(input).ok_or_else(|| Failure::new(if options.check { 2 } else { 1 }, missing))?
+        }
+    };
+    match &options.mode {
+        Mode::AuditReexports => audit_reexports(&input_path, options.fix),
+        Mode::GodObjects => {
+            god_object::print_proposals(&god_object::find_god_objects(&input_path));
+            Ok(ExitCode::SUCCESS)
+        }
+        Mode::Gate { max_file_lines, max_fn_lines } => gate_crate(&input_path, *max_file_lines, *max_fn_lines),
+        Mode::Graph { format, output } => write_graph(&input_path, *format, output.as_deref()),
+        Mode::ExplainError => explain_errors(&input_path),
+        // A directory or package has every file of its crate split that is too long, each in place
+        Mode::Refactor if Path::new(&options.input_file).is_dir() => refactor_crate_dir(&mut options, &input_path, &command),
+        _ => {
+            let status = if options.check { 2 } else { 1 };
+            refactor_file(&mut options, &input_path, &command).map_err(|failure| Failure::new(status, failure.message))
+        }
+    }
+}
+
+// Function to rename a module across the crate, printing each change
+fn rename_mod(input_file: &str, old: &str, new: &str) -> Result<ExitCode, Failure> {
+    let changes = rename::rename_module(Path::new(input_file), old, new)?;
+    changes.iter().for_each(|change| println!("{}", change));
+    Ok(ExitCode::SUCCESS)
+}
+
+// Function to show the plan of the input again every time it changes, until interrupted
+fn watch_input(options: &Options, args: &[String]) -> Result<ExitCode, Failure> {
+    // Each plan is made with the flags given to the watch, which are all but its path
+    let mut flags = args[1..].to_vec();
+    if let Some(index) = flags.iter().rposition(|arg| *arg == options.input_file) {
+        flags.remove(index);
+    }
+    watch::watch(Path::new(&options.input_file), &flags)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+// Function to refactor each of the inputs of a batch, exiting with the worst status among them
+fn refactor_batch(options: &Options, inputs: &[String], args: &[String]) -> Result<ExitCode, Failure> {
+    // Each input is refactored with the flags given to the whole run, which are all but the inputs
+    let mut flags = args.to_vec();
+    for input in inputs.iter().rev() {
+        if let Some(index) = flags.iter().rposition(|arg| arg == input) {
+            flags.remove(index);
+        }
+    }
+    let writes = matches!(options.emit, Emit::Files | Emit::Git) && !options.dry_run && !options.diff;
+    let files = batch::input_files(inputs).and_then(|files| match batch::shared_output_dir(&files) {
+        Some((file, other)) if writes && !options.in_place => Err(format!(
+            "{:?} and {:?} would write their roots to the same directory; pass --in-place to refactor both",
+            file, other
+        )),
+        _ => Ok(files),
+    });
+    let status = files.and_then(|files| batch::run(&files, &flags, options.check)).map_err(|e| Failure::new(if options.check { 2 } else { 1 }, e))?;
+    Ok(ExitCode::from(u8::try_from(status).unwrap_or(1)))
+}
+
+// Function to undo what the last run that wrote files below the package of `input_file` did
+fn revert_run(input_file: &str) -> Result<ExitCode, Failure> {
+    let path = Path::new(input_file);
+    let root = verify::find_package_root(path).unwrap_or_else(|| path.to_path_buf());
+    let done = revert::revert(&root)?;
+    done.iter().for_each(|change| println!("  {}", change));
+    println!("Reverted the last run.");
+    Ok(ExitCode::SUCCESS)
+}
+
+// Function to report the generated re-exports of the crate nothing uses anymore, and to remove
+// them with `fix`
+fn audit_reexports(input_path: &Path, fix: bool) -> Result<ExitCode, Failure> {
+    let audit = audit::audit_reexports(input_path, fix)?;
+    audit::print_audit(&audit, fix);
+    Ok(ExitCode::SUCCESS)
+}
+
+// Function to print the files and functions of the crate that are longer than allowed, exiting
+// with 1 when there are any
+fn gate_crate(input_path: &Path, max_file_lines: usize, max_fn_lines: usize) -> Result<ExitCode, Failure> {
+    let violations = gate::check(input_path, max_file_lines, max_fn_lines).map_err(|e| Failure::new(2, e))?;
+    gate::print_violations(&violations);
+    Ok(if violations.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+// Function to write the graph of which modules of the crate use which to `output`, or to stdout
+fn write_graph(input_path: &Path, format: graph::Format, output: Option<&str>) -> Result<ExitCode, Failure> {
+    let rendered = graph::render(&graph::import_graph(input_path)?, format);
+    match output {
+        Some(output) => fs::write(output, rendered).map_err(|e| format!("Failed to write {:?}: {}", output, e))?,
+        None => print!("{}", rendered),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Function to explain the errors `cargo check` reports by the plan decisions behind them, exiting
+// with 1 when there are any
+fn explain_errors(input_path: &Path) -> Result<ExitCode, Failure> {
+    let explanations = explain::explain_errors(input_path).map_err(|e| Failure::new(2, e))?;
+    explain::print_explanations(&explanations);
+    Ok(if explanations.is_empty() { ExitCode::SUCCESS } else { ExitCode::FAILURE })
+}
+
+// Function to split every file of the crate rooted at `root_path` that is too long, each in place
+fn refactor_crate_dir(options: &mut Options, root_path: &Path, command: &str) -> Result<ExitCode, Failure> {
+    if options.package_dir.is_some() || options.target_dir.is_some() || options.plan_file.is_some() {
+        return Err("a whole crate is split in place, without --package, --output-dir or --plan".to_string().into());
+    }
+    if options.emit != Emit::Files && options.verify.is_some() {
+        return Err("--verify checks the files written to the crate, so it needs --emit files".to_string().into());
+    }
+    let root_tree = encoding::read_source(root_path, true)
+        .ok()
+        .and_then(|(content, _)| syn::parse_file(&content).ok())
+        .ok_or_else(|| format!("Failed to parse the crate root {:?}", root_path))?;
+    let package_manifest = verify::find_package_root(root_path).and_then(|root| fs::read_to_string(root.join("Cargo.toml")).ok());
+    options.edition = options.forced_edition.unwrap_or_else(|| script::edition(&script::ScriptHeader::default(), package_manifest.as_deref()));
+    options.no_std = has_crate_attr(&root_tree.attrs, "no_std");
+    options.library = !has_crate_attr(&root_tree.attrs, "no_main")
+        && !root_tree.items.iter().any(|item| matches!(item, Item::Fn(func) if is_entry_point(func)));
+    // With --check, a crate that couldn't be checked is told apart from one that would be split
+    let split = configure_rustfmt(options, root_path)
+        .and_then(|_| crate_split::refactor_crate(root_path, options, command))
+        .map_err(|e| Failure::new(if options.check { 2 } else { 1 }, e))?;
+    if options.check && split > 0 {
+        let files = if split == 1 { "1 file of the crate is".to_string() } else { format!("{} files of the crate are", split) };
+        eprintln!("{} too large and would be split; run `refactor {}` to split them.", files, options.input_file);
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Function to find the options of a single file that contradict each other before anything is read
+fn check_file_options(options: &Options) -> Result<(), String> {
+    if options.package_dir.is_some() && options.verify.is_some() {
+        return Err("--verify checks the package the input belongs to, which --package doesn't write to".to_string());
+    }
+    if options.target_dir.is_some() && (options.verify.is_some() || options.package_dir.is_some()) {
+        return Err("--output-dir writes somewhere else than --verify checks and --package writes to".to_string());
+    }
+    if options.in_place && (options.package_dir.is_some() || options.target_dir.is_some() || options.emit != Emit::Files) {
+        return Err("--in-place rewrites the input, so it can't write somewhere else with --package, --output-dir or --emit".to_string());
+    }
+    if options.misc_module != STAY_GROUP {
+        idents::check_module_ident(&options.misc_module).map_err(|e| format!("--misc-module {}: {}", options.misc_module, e))?;
+    }
+    if (options.dry_run || options.diff) && options.verify.is_some() {
+        return Err("--verify checks the written files, which --dry-run and --diff don't write".to_string());
+    }
+    if options.emit != Emit::Files && options.verify.is_some() {
+        return Err("--verify checks the files written next to the input, so it needs --emit files".to_string());
+    }
+    if options.json_report && (options.diff || matches!(options.emit, Emit::Stdout | Emit::Json)) {
+        return Err("--format json prints the report to stdout, where --diff and --emit stdout or json print too".to_string());
+    }
+    Ok(())
+}
+
+// Function to read the file at `input_path`, or stdin, as it is used to split it
+fn read_input(options: &Options, input_path: &Path) -> Result<Input, String> {
+    // BOMs and CRLF newlines are dropped on read and put back on write
+    let original = match options.stdin {
+        true => {
+            let mut original = Vec::new();
+            std::io::Read::read_to_end(&mut std::io::stdin(), &mut original).map_err(|e| format!("Failed to read stdin: {}", e))?;
+            original
+        }
+        false => fs::read(input_path).map_err(|e| format!("Failed to read {:?}: {}", input_path, e))?,
+    };
+    let (content, style) = encoding::decode(&original, options.lossy).map_err(|e| format!("Failed to read {:?}: {}", input_path, e))?;
+    // The `#!` line and `---` manifest of a cargo script aren't Rust, so they are set aside and
+    // put back in front of the generated root
+    let (script_header, content) = script::split_header(&content);
+    Ok(Input {
+        path: input_path.to_path_buf(),
+        original,
+        content,
+        style,
+        script_header,
+    })
+}
+
+// Function to split the file at `input_path`, or the one read from stdin, and write, print or
+// check what it is split into as the mode and options ask
+fn refactor_file(options: &mut Options, input_path: &Path, command: &str) -> Result<ExitCode, Failure> {
+    let input = read_input(options, input_path)?;
+    let content = &input.content;
+    let package_manifest = verify::find_package_root(input_path).and_then(|root| fs::read_to_string(root.join("Cargo.toml")).ok());
+    options.edition = options.forced_edition.unwrap_or_else(|| script::edition(&input.script_header, package_manifest.as_deref()));
+    configure_rustfmt(options, input_path)?;
+    let output_dir = input_path.parent().expect("Failed to get parent directory");
+    check_file_options(options)?;
+
+    // The plan is kept below the package the run writes to, where it stays out of the way of
+    // the sources, and names its input relative to itself
+    let plan_root = match (&options.package_dir, &options.target_dir) {
+        (Some(dir), _) | (None, Some(dir)) => PathBuf::from(dir),
+        (None, None) => verify::find_package_root(input_path).unwrap_or_else(|| output_dir.to_path_buf()),
+    };
+    let plan_path = plan_root.join(plan::PLAN_FILE);
+
+    // Groups chosen by hand: the ones remembered from earlier plans, next to the plan, then the
+    // plan being applied
+    let overrides_path = plan_root.join(plan::OVERRIDES_FILE);
+    options.assignments.by_fingerprint = plan::read_assignments(&overrides_path);
+    if let Some(plan_file) = &options.plan_file {
+        options.assignments.by_path = plan::read_assignments(Path::new(plan_file));
+    }
+    // A recipe's modules take the place of a model crate's, unless --like names one
+    if let Some(recipe) = options.recipe {
+        options.model_modules = recipe.model_modules();
+    }
+    if let Some(like_crate) = &options.like_crate {
+        options.model_modules = like::model_modules(Path::new(like_crate), is_reserved_group)?;
+    }
+
+    // Step 1: Parse the Rust source file into an AST
+    let line_count = content.lines().count();
+    if line_count >= progress::LARGE_INPUT_LINES {
+        progress::announce(format_args!("Parsing {} lines", line_count));
+    }
+    let syntax_tree: File = syn::parse_file(content).map_err(|e| format!("Unable to parse {:?}: {}", input_path, e))?;
+    log::info("parse", format_args!("{} has {} items in {} lines", input_path.display(), syntax_tree.items.len(), line_count));
+    if options.check && !crate_split::is_oversized(content, options) {
+        println!("{} is within the limits and would not be split.", input_path.display());
+        return Ok(ExitCode::SUCCESS);
+    }
+    let source = parsed_source(content);
+    let items_start = attrs_end(&syntax_tree.attrs).unwrap_or(0);
+    // `#![no_std]` crates keep their attribute at the root and import what std's prelude would
+    // have provided, and with `#![no_main]` they are still binaries without a `main`
+    options.no_std = has_crate_attr(&syntax_tree.attrs, "no_std");
+    options.library = !has_crate_attr(&syntax_tree.attrs, "no_main")
+        && !syntax_tree
+            .items
+            .iter()
+            .any(|item| matches!(item, Item::Fn(func) if is_entry_point(func)));
+
+    // Steps 2-4: Group the functions and generate separate module files
+    let mut refactored = refactor_reviewed(source, &syntax_tree.items, items_start, output_dir, &[], options)?;
+
+    if options.mode == Mode::Tree {
+        let current_tree = tree::current_tree("crate", &syntax_tree.items, output_dir);
+        tree::print_side_by_side(&current_tree, &refactored.tree);
+        return Ok(ExitCode::SUCCESS);
+    }
+    if options.mode == Mode::Spans {
+        // The header's lines were blanked out to keep line numbers, which a newline each stands for
+        let header = &input.script_header.text;
+        let source_start = header.len() - header.matches('\n').count() + content.len() - source.len();
+        spans::print_spans(&spans::plan_spans(source, &syntax_tree.items, items_start, &refactored.plan), spans::file_offsets(&input.original, source_start));
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    // Intra-doc links to moved items (or written relative to a module the docs moved out of)
+    // have to point to the items' new paths
+    for file in &mut refactored.files {
+        file.code = rewrite_doc_links(&file.code, &file.written_in, &file.module_path, &refactored.moves);
+    }
+
+    // Step 5: Create the root file with all module imports and other items, followed by the main
+    // function for binaries; libraries get a tmp_lib.rs instead of a tmp_main.rs
+    let root_file_name = if options.library { "tmp_lib.rs" } else { "tmp_main.rs" };
+    let mut tmp_main_path: PathBuf = output_dir.join(root_file_name);
+    // A new package gets its root under its proper name, and without the script header
+    let package_src = options.package_dir.as_ref().map(|dir| Path::new(dir).join("src"));
+    if let Some(package_src) = &package_src {
+        tmp_main_path = package_src.join(if options.library { "lib.rs" } else { "main.rs" });
+    }
+    if let Some(target_dir) = &options.target_dir {
+        tmp_main_path = Path::new(target_dir).join(root_file_name);
+    }
+    if options.in_place || options.stdin {
+        tmp_main_path = input_path.to_path_buf();
+    }
+    // If a previous run left marked regions behind, only those are updated, keeping the user's
+    // edits. In place, the file there is the input being refactored
+    let existing_main = (!options.in_place && !options.stdin)
+        .then(|| encoding::read_source(&tmp_main_path, options.lossy).ok())
+        .flatten()
+        .map(|(existing, _)| existing);
+    let header = if package_src.is_some() { "" } else { input.script_header.text.trim_end() };
+    let main_code = root_code(&source[..items_start], header, &mut refactored, existing_main.as_deref(), options.smoke_tests);
+    refactored.files.push(GeneratedFile {
+        path: tmp_main_path.clone(),
+        code: main_code,
+        module_path: Vec::new(),
+        written_in: Vec::new(),
+    });
+
+    // Write the plan for the user to edit, and remember how an applied plan differed from the proposal
+    let reproducibility = plan::reproducibility(options.seed, &config_key(options));
+    let plan_code = plan::render_plan(&refactored.plan, &reproducibility, &plan::input_name(&plan_path, input_path));
+    if options.mode == Mode::Plan {
+        if let Some(dir) = plan_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+        }
+        fs::write(&plan_path, &plan_code).map_err(|e| format!("Failed to write {:?}: {}", plan_path, e))?;
+        println!("Plan written to {}; apply it with `refactor apply {}`.", plan_path.display(), plan_path.display());
+        return Ok(ExitCode::SUCCESS);
+    }
+    let overrides_code = options
+        .plan_file
+        .as_ref()
+        .map(|_| plan::render_overrides(&options.assignments.by_fingerprint, &refactored.plan, &options.assignments.by_path));
+
+    // --output-dir takes everything else the run writes as well
+    if let Some(target_dir) = &options.target_dir {
+        relocate_files(&mut refactored.files, output_dir, Path::new(target_dir), &syntax_tree.items, "the modules to resolve");
+    }
+
+    // A new package gets the generated files under `src/`, next to a Cargo.toml built from the
+    // script's manifest. An existing Cargo.toml is left alone
+    if let (Some(package_dir), Some(package_src)) = (&options.package_dir, &package_src) {
+        relocate_files(&mut refactored.files, output_dir, package_src, &syntax_tree.items, "the package to build");
+
+        let manifest_path = Path::new(package_dir).join("Cargo.toml");
+        if manifest_path.exists() {
+            warn(format!("{:?} already exists and was kept as it is", manifest_path));
+        } else {
+            let package_name = script::package_name(&input_path.file_stem().unwrap_or_default().to_string_lossy());
+            let manifest = input.script_header.manifest.clone().or_else(|| script::doc_manifest(&syntax_tree.attrs));
+            refactored.files.push(GeneratedFile {
+                path: manifest_path,
+                code: script::package_manifest(manifest.as_deref(), &package_name),
+                module_path: Vec::new(),
+                written_in: Vec::new(),
+            });
+        }
+    }
+    refactored.files.push(GeneratedFile {
+        path: plan_path,
+        code: plan_code,
+        module_path: Vec::new(),
+        written_in: Vec::new(),
+    });
+    if let Some(code) = overrides_code {
+        refactored.files.push(GeneratedFile {
+            path: overrides_path,
+            code,
+            module_path: Vec::new(),
+            written_in: Vec::new(),
+        });
+    }
+    let files = &refactored.files;
+
+    // A library's public API must come out of the refactoring as it went in, which is checked
+    // on the files about to be written before any of them is
+    if options.library {
+        let generated: HashMap<PathBuf, String> = files.iter().map(|file| (file.path.clone(), file.code.clone())).collect();
+        let root_code = &generated[&tmp_main_path];
+        let tmp_dir = tmp_main_path.parent().unwrap_or(Path::new("."));
+        let before = api::public_api(input_path, content, &api::Sources { generated: &HashMap::new(), written_to: None });
+        let after = api::public_api(&tmp_main_path, root_code, &api::Sources { generated: &generated, written_to: Some((tmp_dir, output_dir)) });
+        match (before, after) {
+            (Ok(before), Ok(after)) => {
+                let changes = api::diff(&before, &after);
+                if !changes.is_empty() && !options.allow_api_changes {
+                    return Err(format!(
+                        "the refactoring would change the public API of the library:\n  {}\nNothing was written; pass --allow-api-changes to accept the changes.",
+                        changes.join("\n  ")
+                    )
+                    .into());
+                }
+                if !changes.is_empty() {
+                    warn(format!("the public API of the library changes:\n  {}", changes.join("\n  ")));
+                }
+            }
+            (Err(e), _) | (_, Err(e)) => warn(format!("could not compare the public API: {}", e)),
+        }
+    }
+
+    if let Mode::Preview { open } = options.mode {
+        let spans = spans::plan_spans(source, &syntax_tree.items, items_start, &refactored.plan);
+        let page = preview::render(input_path, source, &spans, files, &tmp_main_path, &refactored.tree);
+        let page_path = preview::preview_path(input_path);
+        fs::write(&page_path, page).map_err(|e| format!("Failed to write {:?}: {}", page_path, e))?;
+        println!("Preview written to {}", page_path.display());
+        if open {
+            if let Err(e) = preview::open(&page_path) {
+                warn(e);
+            }
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    // The report of a run is made of the plan and the files, whether or not they get written
+    let report = |written: bool| {
+        let spans = spans::plan_spans(source, &syntax_tree.items, items_start, &refactored.plan);
+        let run = report::Run {
+            input_path,
+            content,
+            source_start: content.len() - source.len(),
+            spans: &spans,
+            moves: &refactored.moves,
+            files,
+            root_path: &tmp_main_path,
+            written,
+        };
+        report::render(&run, &WARNINGS.lock().expect("A warning was being written by a thread that panicked"))
+    };
+    if let Some(stats_path) = &options.stats {
+        stats::write(Path::new(stats_path), files)?;
+    }
+    if options.dry_run && options.json_report {
+        print!("{}", report(false));
+        return Ok(check_status(options, files));
+    }
+    if options.dry_run {
+        let current_tree = tree::current_tree("crate", &syntax_tree.items, output_dir);
+        dry_run::print_summary(files, &tmp_main_path, content, &current_tree, &refactored.tree);
+        return Ok(check_status(options, files));
+    }
+    // The diff shows the files as they would be written, with the root in place of the input it
+    // replaces
+    if options.diff {
+        for file in files {
+            let replaces_input = file.path == tmp_main_path && options.package_dir.is_none() && options.target_dir.is_none();
+            let target = if replaces_input { input_path } else { file.path.as_path() };
+            let old = fs::read(target).ok().map(|bytes| String::from_utf8_lossy(&bytes).into_owned());
+            let new = input.style.restore(&file.code, file.path == tmp_main_path);
+            let old_name = old.as_ref().map_or_else(|| "/dev/null".to_string(), |_| diff::file_label("a", target));
+            print!("{}", diff::unified(old.as_deref().unwrap_or_default(), &new, &old_name, &diff::file_label("b", target)));
+        }
+        return Ok(check_status(options, files));
+    }
+
+    let summary = write_files(options, &input, files, &tmp_main_path, &refactored.moves, command)?;
+
+    // What is printed for another program is all that goes to stdout
+    if matches!(options.emit, Emit::Stdout | Emit::Json) {
+        print!("{}", summary);
+        return Ok(ExitCode::SUCCESS);
+    }
+    if options.json_report {
+        print!("{}", report(true));
+        return Ok(ExitCode::SUCCESS);
+    }
+    let headline = match (&options.emit, &options.package_dir, &options.target_dir) {
+        (Emit::Files, Some(package_dir), _) => format!("Refactoring complete. Check the new package in {}.", package_dir),
+        (Emit::Files, None, Some(target_dir)) => format!("Refactoring complete. Check the output files in {}.", target_dir),
+        (Emit::Files, None, None) if options.in_place => {
+            format!("Refactoring complete. {} is the new root; `refactor revert` puts the original back.", input_path.display())
+        }
+        (Emit::Files, None, None) => "Refactoring complete. Check the output files in the same directory as the input file.".to_string(),
+        _ => "Refactoring complete.".to_string(),
+    };
+    print!("{}", summary::render(&headline, files, &tmp_main_path, content, &refactored.decisions));
+    println!();
+    println!("Made by {}; the same options and --seed {} reproduce it.", reproducibility, options.seed);
+    if !summary.is_empty() {
+        println!("{}", summary);
+    }
+    if options.package_dir.is_none() && (input.script_header.shebang || input.script_header.manifest.is_some()) {
+        println!("This is a cargo script; pass --package <dir> to turn it into a package with a Cargo.toml instead.");
+    }
+    if !refactored.suggestions.is_empty() {
+        println!("Single-use functions:");
+        refactored.suggestions.iter().for_each(|suggestion| println!("  {}", suggestion));
+    }
+    if options.teach {
+        teach::print_report(&refactored.decisions);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+// Function to write the root a split leaves behind: the crate's attributes, the imports and
+// items that stay, marked regions declaring and re-exporting the generated modules, and `main`.
+// A root an earlier run left behind only has its regions updated
+fn root_code(crate_attrs: &str, header: &str, refactored: &mut RefactoredItems, existing: Option<&str>, smoke_tests: bool) -> String {
+    let mut tmp_main = String::new();
+
+    // Crate-level inner attributes and docs (`#![...]`, `//!`) must stay at the very top
+    let crate_attrs = trim_blank_lines(crate_attrs);
+    if !crate_attrs.is_empty() {
+        tmp_main.push_str(&crate_attrs);
+        tmp_main.push_str("\n\n");
+    }
+
+    // Include all imports not associated with any function group
+    for import in &refactored.imports {
+        tmp_main.push_str(import);
+        tmp_main.push_str("\n\n");
+    }
+
+    // Include all other items (constants, types, etc.)
+    for item in &refactored.other_items {
+        tmp_main.push_str(item);
+        tmp_main.push_str("\n\n");
+    }
+
+    // Include all function module declarations and public use imports in marked regions,
+    // sorted so that re-running the tool produces stable region contents
+    refactored.mod_declarations.sort();
+    refactored.use_statements.sort();
+    let mods_region = format_glue(&refactored.mod_declarations.join("\n"));
+    let reexports_region = format_glue(&refactored.use_statements.join("\n"));
+    tmp_main.push_str(&wrap_region(MODS_REGION, &mods_region));
+    tmp_main.push_str("\n\n");
+    tmp_main.push_str(&wrap_region(REEXPORTS_REGION, &reexports_region));
+    tmp_main.push_str("\n\n");
+
+    // Optionally check that every generated module and re-export path resolves under `cargo test`
+    let smoke_region = smoke_tests.then(|| format_glue(&smoke_test_module(&refactored.smoke_targets)));
+    if let Some(smoke_region) = &smoke_region {
+        tmp_main.push_str(&wrap_region(SMOKE_REGION, smoke_region));
+        tmp_main.push_str("\n\n");
+    }
+
+    // Include the main function
+    if let Some(main_func) = &refactored.main_function {
+        tmp_main.push_str(main_func);
+        tmp_main.push_str("\n\n");
+    }
+
+    let updated_main = existing.and_then(|existing| {
+        let updated = replace_region(existing, MODS_REGION, &mods_region)?;
+        let updated = replace_region(&updated, REEXPORTS_REGION, &reexports_region)?;
+        match &smoke_region {
+            // The smoke test region is optional, so add it to older files that lack it
+            Some(smoke_region) => Some(replace_region(&updated, SMOKE_REGION, smoke_region).unwrap_or_else(|| {
+                format!("{}\n\n{}\n", updated.trim_end(), wrap_region(SMOKE_REGION, smoke_region))
+            })),
+            None => Some(updated),
+        }
+    });
+    updated_main.unwrap_or_else(|| {
+        let main_code = rewrite_doc_links(&tmp_main, &[], &[], &refactored.moves);
+        if header.is_empty() {
+            format!("{}\n", main_code.trim_end())
+        } else {
+            format!("{}\n\n{}\n", header, main_code.trim_end())
+        }
+    })
+}
+
+// Function to write the generated files to where --emit asks for, creating the directories of
+// nested modules as needed, and to check them with --verify. Returns what the output sink has
+// to tell about them
+fn write_files(
+    options: &Options,
+    input: &Input,
+    files: &[GeneratedFile],
+    root_path: &Path,
+    moves: &HashMap<Vec<String>, Vec<String>>,
+    command: &str,
+) -> Result<String, Failure> {
+    let input_path = input.path.as_path();
+    let output_dir = input_path.parent().unwrap_or(Path::new(""));
+    // Warnings of the package as it is, to compare against once the new files are in place
+    let verify_package = match &options.verify {
+        Some(_) => {
+            let package_root = verify::find_package_root(input_path).ok_or_else(|| "--verify needs the input to be part of a cargo package".to_string())?;
+            let warnings = verify::cargo_warnings(&package_root)?;
+            Some((package_root, warnings))
+        }
+        None => None,
+    };
+
+    // What the files held is recorded before any of them is written, so that the run can be
+    // reverted until the next one writes, even when it dies halfway
+    if options.emit == Emit::Files {
+        let root = verify::find_package_root(input_path).unwrap_or_else(|| output_dir.to_path_buf());
+        let backups: Vec<verify::Backup> = files.iter().map(|file| verify::backup(&file.path)).collect();
+        revert::record(&root, &backups, moves, command).map_err(|e| format!("Nothing was written, as the run couldn't be made revertible: {}", e))?;
+    }
+
+    // Step 6: Write all generated files to where --emit asked for, creating the directories of
+    // nested modules as needed
+    let mut file_system = output::FileSystem::default();
+    let mut memory = output::Memory::default();
+    let mut stdout = output::Stdout::default();
+    let mut git_index;
+    let sink: &mut dyn output::OutputSink = match options.emit {
+        Emit::Files => &mut file_system,
+        Emit::Memory => &mut memory,
+        Emit::Stdout | Emit::Json => {
+            stdout.json = options.emit == Emit::Json;
+            &mut stdout
+        }
+        Emit::Git => {
+            git_index = output::GitIndex::new(output_dir)?;
+            &mut git_index
+        }
+    };
+    for file in files {
+        let code = input.style.restore(&file.code, file.path == root_path);
+        // A commit holds the crate as it would be with the new root in place of the input, and
+        // none of the plan, which only concerns the run
+        let path = match options.emit {
+            Emit::Git if file.path.ends_with(plan::PLAN_FILE) => continue,
+            Emit::Git if file.path == root_path && options.package_dir.is_none() && options.target_dir.is_none() => input_path,
+            _ => file.path.as_path(),
+        };
+        log::debug("write", format_args!("{} ({} lines)", path.display(), code.lines().count()));
+        sink.write(path, code.as_bytes())?;
+    }
+    log::info("write", format_args!("{} files", files.len()));
+    let summary = sink.finish()?;
+
+    // Step 7: Check the package with the new root swapped in, rolling everything back on failure.
+    // In place, the new root is already there to stay
+    if let Some((package_root, warnings_before)) = verify_package {
+        let root_code = files
+            .iter()
+            .find(|file| file.path == root_path)
+            .map(|file| file.code.clone())
+            .expect("The generated root is missing");
+        if !options.in_place {
+            fs::write(input_path, input.style.restore(&root_code, true)).map_err(|e| format!("Failed to swap in the generated root: {}", e))?;
+        }
+        let warnings_after = verify::cargo_warnings(&package_root);
+        if !options.in_place {
+            fs::write(input_path, &input.original).map_err(|e| format!("Failed to restore the original root: {}", e))?;
+        }
+
+        let failure = match warnings_after {
+            Ok(warnings_after) => {
+                let new_warnings = verify::new_warnings(&warnings_before, &warnings_after);
+                (!new_warnings.is_empty()).then(|| format!("The refactoring introduced new warnings:\n{}", new_warnings.join("\n")))
+            }
+            Err(e) => Some(e),
+        };
+        if let Some(failure) = failure {
+            eprintln!("{}", failure);
+            file_system.rollback();
+            return Err("verification failed, the generated files were rolled back".to_string().into());
+        }
+    }
+    Ok(summary)
+}
+
+// Function to end a --check run with 1 when the input would be split, as `cargo fmt --check` ends
+// when it would format
+fn check_status(options: &Options, files: &[GeneratedFile]) -> ExitCode {
+    if options.check && files.iter().any(|file| !file.module_path.is_empty()) {
+        eprintln!("{} is too large and would be split; run `refactor {}` to split it.", options.input_file, options.input_file);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+// Function to move the files about to be written from `from` to the same place below `to`,
+// warning about the files of `mod foo;` declarations, which stay behind
+fn relocate_files(files: &mut [GeneratedFile], from: &Path, to: &Path, items: &[Item], purpose: &str) {
+    for file in files.iter_mut() {
+        if let Ok(relative) = file.path.strip_prefix(from) {
+            file.path = to.join(relative);
+        }
+    }
+    for item in items {
+        if let Item::Mod(item_mod) = item {
+            if item_mod.content.is_none() {
+                warn(format!("copy the file of `mod {};` into {:?} for {}", item_mod.ident, to, purpose));
+            }
+        }
+    }
+}
+
+// Function to write down the options that shape the output of a refactoring, along with the groups
+// chosen by hand, so that runs agreeing on them can be told apart from runs that don't
+fn config_key(options: &Options) -> String {
+    let asm = match options.asm {
+        AsmPolicy::Keep => "keep",
+        AsmPolicy::Group => "group",
+        AsmPolicy::Free => "free",
+    };
+    let mut lines = vec![
+        format!("strategy={}", strategy::spec(&options.strategies)),
+        format!("asm={}", asm),
+        format!("min-module-items={}", options.min_module_items),
+        format!("min-inline-fns={}", options.min_inline_fns),
+        format!("misc-module={}", options.misc_module),
+        format!("module-prefix={}", options.module_prefix.as_deref().unwrap_or("")),
+        format!("recipe={}", options.recipe.map_or("", |recipe| recipe.name)),
+        format!("like={}", options.like_crate.as_deref().unwrap_or("")),
+        format!("promote-inline-mods={}", options.promote_inline_mods),
+        format!("smoke-tests={}", options.smoke_tests),
+        format!("inline-single-use={}", options.inline_single_use),
+        format!("edition={}", options.edition),
+        format!("no-std={}", options.no_std),
+        format!("explicit-imports={}", options.explicit_imports),
+        format!("no-format={}", options.no_format),
+        format!("import-style={}", options.import_style.name()),
+        format!("exclude={}", options.exclude.join(",")),
+    ];
+    let by_path: BTreeMap<&String, &String> = options.assignments.by_path.iter().collect();
+    let by_fingerprint: BTreeMap<&String, &String> = options.assignments.by_fingerprint.iter().collect();
+    lines.extend(by_path.into_iter().chain(by_fingerprint).map(|(key, group)| format!("{} = {}", key, group)));
+    lines.join("\n")
+}
+
+// Function to group the functions among `items` like refactor_items and, with --interactive,
+// have each proposed module approved first. The functions of skipped modules stay where they
+// are, and the modules are generated again when any was skipped or renamed
+fn refactor_reviewed(
+    source: &str,
+    items: &[Item],
+    items_start: usize,
+    output_dir: &Path,
+    module_path: &[String],
+    options: &mut Options,
+) -> Result<RefactoredItems, String> {
+    let refactored = refactor_items(source, items, items_start, output_dir, module_path, options)?;
+    if !options.interactive || options.mode != Mode::Refactor || refactored.files.is_empty() {
+        return Ok(refactored);
+    }
+    let review = review::review(&refactored.files, &refactored.moves, options.stdin)?;
+    if review.approved_all() {
+        return Ok(refactored);
+    }
+    options.exclude.extend(review.skipped);
+    options.module_renames.extend(review.renamed);
+    refactor_items(source, items, items_start, output_dir, module_path, options)
+}
+
+// Function to group the functions among `items` into module files written to `output_dir`,
+// where `items_start` is the offset in `source` at which the list of items begins: the items are
+// collected, the functions placed in groups, the groups filled and named, and the modules emitted
+fn refactor_items(
+    source: &str,
+    items: &[Item],
+    items_start: usize,
+    output_dir: &Path,
+    module_path: &[String],
+    options: &Options,
+) -> Result<RefactoredItems, String> {
+    // Step 2: Analyze the AST and group logic based on dependencies and control flow
+    let mut collected = collect_items(source, items, items_start, output_dir, module_path, options)?;
+    // Step 3: Group functions into modules based on functionality keywords
+    let placement = place_functions(&mut collected, items, module_path, options);
+    let groups = fill_groups(&mut collected, placement, source, module_path, options);
+    let names = name_modules(&collected, &groups, items, module_path, options)?;
+    // Step 4: Refactor logic into separate files based on grouped functions
+    emit_modules(collected, groups, names, items, output_dir, module_path, options)
+}
+
+// Function to sort the items of a module into what the grouping works on: its imports, the
+// functions that may move and the items that stay, refactoring its inline modules on the way
+fn collect_items<'a>(
+    source: &str,
+    items: &'a [Item],
+    items_start: usize,
+    output_dir: &Path,
+    module_path: &[String],
+    options: &Options,
+) -> Result<CollectedItems<'a>, String> {
+    let is_root = module_path.is_empty();
+    let mut imports = Vec::new(); // Collect every import in its original order
+    let mut imported_names: HashMap<String, usize> = HashMap::new();
+    // The path each of those imports, such as `serde_json::Value` for `Value`
+    let mut import_path_of: HashMap<String, String> = HashMap::new();
+    let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+    let mut functions = HashMap::new();
+    // The parsed functions, and where their code starts in the source their spans index into
+    let mut func_asts: HashMap<String, ItemFn> = HashMap::new();
+    let mut func_offsets = HashMap::new();
+    // Later declarations of a function under another `#[cfg]`, such as the `#[cfg(not(unix))]`
+    // version of a `#[cfg(unix)]` one, with their code, AST and offset. They go wherever the first
+    // declaration goes, so that every configuration finds its version in the same module
+    let mut cfg_twins: HashMap<String, Vec<(String, ItemFn, usize)>> = HashMap::new();
+    let mut main_function = None;
+    let mut main_ast = None;
+    let mut test_module = None;
+    let mut other_items = Vec::new(); // Collect other items like constants, types, etc.
+    let mut ffi_items = Vec::new(); // Collect extern blocks together with their link attributes
+    // Functions, statics and `global_asm!` kept together with --asm group
+    let mut asm_functions = HashSet::new();
+    let mut asm_items = Vec::new();
+    let mut asm_names = Vec::new();
+    // Functions declared by extern blocks, and everything they declare, which moves into ffi
+    let mut foreign_functions = HashSet::new();
+    let mut foreign_names = HashSet::new();
+    let mut proc_macro_impls = HashSet::new();
+    // Enums and structs defined here (mapped to whether they are enums), with the indices in
+    // `other_items` of their definitions and impls
+    let mut state_types = HashMap::new();
+    let mut state_type_vis = HashMap::new();
+    let mut type_items: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut trait_impl_items = HashSet::new();
+    let mut files = Vec::new();
+    let mut child_trees = Vec::new();
+    let mut moves = HashMap::new();
+    let mut smoke_targets = Vec::new();
+    let mut plan_entries = Vec::new();
+    let mut decisions = Vec::new();
+    let mut suggestions = Vec::new();
+    let mut item_count = 0;
+
+    // Take each item's code straight from the source so that comments survive the split
+    let item_ranges = item_source_ranges(source, items, items_start);
+    // Imports of items declared here, such as `use helpers::clamp;` for a `mod helpers` next to
+    // them, only resolve here, so generated modules take their names from here instead
+    let local_names: HashSet<String> = items
+        .iter()
+        .filter(|item| !matches!(item, Item::Use(_)))
+        .flat_map(declared_names)
+        .chain(items.iter().filter(|_| !is_root).filter_map(extern_crate_name))
+        .chain(["self", "super"].map(String::from))
+        .collect();
+    // `macro_rules!` macros defined here, which are only in scope below their definition
+    let local_macros = macro_rules_names(items);
+
+    for (item, item_range) in items.iter().zip(item_ranges) {
+        let item_code = source[item_range.clone()].to_string();
+        match item {
+            Item::Use(use_item) if imports_local_macro(use_item, &local_macros) => {
+                // Re-exports of a macro defined here, such as `pub(crate) use shout;`, only resolve
+                // below the macro, so they keep their place among the other items
+                item_count += 1;
+                other_items.push(item_code);
+            }
+            Item::Use(use_item) => {
+                // Remember which name each import brings into scope, to find the functions using it
+                let is_local = use_item.leading_colon.is_none() && use_root_segment(&use_item.tree).is_some_and(|root| local_names.contains(&root));
+                // Re-exports such as `pub use internal::Thing;` are part of this module's API and
+                // stay here alone, so generated modules take their names from here too
+                let is_reexport = !matches!(use_item.vis, syn::Visibility::Inherited);
+                for (name, path) in import_paths(use_item).into_iter().filter(|_| !is_local && !is_reexport) {
+                    if let Some(original) = renamed_from(&path) {
+                        aliases.entry(original.to_string()).or_default().push(name.clone());
+                    }
+                    import_path_of.insert(name.clone(), path);
+                    imported_names.insert(name, imports.len());
+                }
+                imports.push(item_code);
+            }
+            Item::ExternCrate(extern_crate) => {
+                // `extern crate alloc;` and the like stay in front of the imports, where they were.
+                // At the crate root they also put the crate in every module's extern prelude, and
+                // `#[macro_use]` its macros in every module's scope, so nothing else needs to know
+                if is_root && has_any_attr(&extern_crate.attrs, &["macro_use"]) {
+                    decisions.push(teach::Decision {
+                        subject: format!("extern crate {}", extern_crate.ident),
+                        change: "stays at the crate root, the only place its `#[macro_use]` makes the crate's macros visible to every module".to_string(),
+                        concept: teach::Concept::RootOnly,
+                    });
+                }
+                item_count += 1;
+                imports.push(item_code);
+            }
+            Item::Fn(func) => {
+                // Collect functions to group them later by name
+                let func_name = func.sig.ident.to_string();
+                if is_root && is_entry_point(func) {
+                    item_count += 1;
+                    main_function = Some(item_code);
+                    main_ast = Some(func);
+                } else if is_exported_symbol(func) {
+                    // Exported symbols are part of the ABI and stay exactly where and as they are
+                    item_count += 1;
+                    decisions.push(teach::Decision {
+                        subject: item_path_of(module_path, &func_name),
+                        change: "stays where it is, unchanged, as an exported symbol".to_string(),
+                        concept: teach::Concept::Exports,
+                    });
+                    other_items.push(item_code);
+                } else if is_excluded(&options.exclude, &item_path_of(module_path, &func_name)) {
+                    item_count += 1;
+                    decisions.push(teach::Decision {
+                        subject: item_path_of(module_path, &func_name),
+                        change: "stays where it is, as it was excluded".to_string(),
+                        concept: teach::Concept::Placement,
+                    });
+                    other_items.push(item_code);
+                } else if options.asm == AsmPolicy::Keep && is_placement_sensitive(item) {
+                    // Assembly and linker placement stay where they were written
+                    item_count += 1;
+                    decisions.push(teach::Decision {
+                        subject: item_path_of(module_path, &func_name),
+                        change: "stays where it is, with the assembly or linker placement it uses".to_string(),
+                        concept: teach::Concept::LowLevel,
+                    });
+                    other_items.push(item_code);
+                } else if is_root && is_proc_macro_entry(func) {
+                    // Exported macros must stay at the root of a proc-macro crate, so only their
+                    // bodies move, into an `_impl` function the stub left behind delegates to
+                    item_count += 1;
+                    match split_proc_macro_entry(source, func, item_range) {
+                        Some((stub, implementation)) => {
+                            let impl_name = format!("{}_impl", func.sig.ident.unraw());
+                            decisions.push(teach::Decision {
+                                subject: func_name.clone(),
+                                change: format!("stays at the root and delegates to `{}`", impl_name),
+                                concept: teach::Concept::RootOnly,
+                            });
+                            // The implementation is new code, so it is the one function that gets parsed
+                            let impl_ast = syn::parse_str(&implementation)
+                                .map_err(|e| format!("Failed to parse the implementation split from `{}`: {}", func_name, e))?;
+                            proc_macro_impls.insert(impl_name.clone());
+                            func_asts.insert(impl_name.clone(), impl_ast);
+                            func_offsets.insert(impl_name.clone(), 0);
+                            functions.insert(impl_name, implementation);
+                            other_items.push(stub);
+                        }
+                        None => other_items.push(item_code),
+                    }
+                } else if func_asts.contains_key(&func_name) {
+                    cfg_twins.entry(func_name).or_default().push((item_code, func.clone(), item_range.start));
+                } else {
+                    if options.asm == AsmPolicy::Group && is_placement_sensitive(item) {
+                        asm_functions.insert(func_name.clone());
+                    }
+                    func_asts.insert(func_name.clone(), func.clone());
+                    func_offsets.insert(func_name.clone(), item_range.start);
+                    functions.insert(func_name.clone(), item_code);
+                }
+            }
+            Item::Mod(item_mod) if is_test_module(item_mod) => {
+                // Unit tests are split up once the functions they test have found their modules
+                item_count += 1;
+                test_module = Some((item_mod, item_range, other_items.len()));
+                other_items.push(item_code);
+            }
+            Item::Mod(item_mod) if item_mod.content.as_ref().is_some_and(|(_, mod_items)| crate_split::function_count(mod_items) < options.min_inline_fns) => {
+                // Inline modules too small to be worth splitting are kept as they are
+                log::debug("inline", format_args!("{} has fewer than {} functions", item_path_of(module_path, &item_mod.ident.to_string()), options.min_inline_fns));
+                child_trees.push(tree::module_tree(item_mod, output_dir));
+                other_items.push(item_code);
+            }
+            Item::Mod(item_mod) if item_mod.content.is_some() => {
+                // Descend into inline modules and refactor their contents too
+                let (module_code, refactored) = refactor_inline_module(source, item_mod, item_range, output_dir, module_path, options)?;
+                other_items.push(module_code);
+                files.extend(refactored.files);
+                moves.extend(refactored.moves);
+                smoke_targets.extend(refactored.smoke_targets);
+                plan_entries.extend(refactored.plan);
+                decisions.extend(refactored.decisions);
+                suggestions.extend(refactored.suggestions);
+                child_trees.push(refactored.tree);
+            }
+            Item::Mod(item_mod) => {
+                // Modules declared in other files are kept as they are
+                if tree::declared_module_file(item_mod, &item_mod.ident.unraw().to_string(), output_dir).is_none() {
+                    warn(format!(
+                        "the file of `mod {}` was not found, so its contents were not analyzed",
+                        item_path_of(module_path, &item_mod.ident.to_string())
+                    ));
+                }
+                child_trees.push(tree::module_tree(item_mod, output_dir));
+                other_items.push(item_code);
+            }
+            Item::Macro(item_macro) if item_macro.mac.path.is_ident("include") => {
+                // Included items stay where they are, in the file the path is relative to
+                let source_dir = output_dir.ancestors().nth(module_path.len()).unwrap_or(output_dir);
+                if let Some(path) = include_path(&item_macro.mac).filter(|path| !source_dir.join(path).is_file()) {
+                    warn(format!("`include!(\"{}\")` was not found, so the items it includes were not analyzed", path));
+                }
+                item_count += 1;
+                other_items.push(item_code);
+            }
+            Item::Macro(item_macro) if item_macro.ident.is_some() => {
+                // `macro_rules!` definitions never move: exported ones stay reachable at the crate
+                // root, and the others keep their place in front of the generated `mod` declarations
+                // (emitted after all other items), so every moved function still has them in textual
+                // scope. `#[macro_use]` modules are kept as declared for the same reason
+                item_count += 1;
+                other_items.push(item_code);
+            }
+            Item::Static(_) | Item::Macro(_) if options.asm == AsmPolicy::Group && is_placement_sensitive(item) => {
+                // Statics placed by the linker and global assembly move into the asm module together
+                if let Item::Static(item_static) = item {
+                    asm_names.push(item_static.ident.to_string());
+                    decisions.push(teach::Decision {
+                        subject: item_path_of(module_path, &item_static.ident.to_string()),
+                        change: "moves to the `asm` module with the other items whose placement matters".to_string(),
+                        concept: teach::Concept::LowLevel,
+                    });
+                }
+                let item_code = anchor_relative_paths(&relocate_item_visibility(&item_code));
+                asm_items.push(anchor_include_paths(&item_code, module_path.len()));
+            }
+            Item::ForeignMod(foreign_mod) => {
+                // Collect extern blocks and remember the functions they declare
+                for foreign_item in &foreign_mod.items {
+                    match foreign_item {
+                        ForeignItem::Fn(foreign_fn) => {
+                            foreign_functions.insert(foreign_fn.sig.ident.to_string());
+                            foreign_names.insert(foreign_fn.sig.ident.to_string());
+                        }
+                        ForeignItem::Static(foreign_static) => {
+                            foreign_names.insert(foreign_static.ident.to_string());
+                        }
+                        _ => {}
+                    }
+                }
+                // The functions and statics they declare are raised like moved functions, to be
+                // seen from where they were, and their signatures can name types through `self::`
+                // and `super::` too
+                ffi_items.push(anchor_relative_paths(&relocate_item_visibility(&item_code)));
+            }
+            _ => {
+                // Collect all other items (constants, types, etc.), remembering where types and
+                // their impls are in case a state machine takes them along
+                let state_type = match item {
+                    Item::Enum(item_enum) => {
+                        state_types.insert(item_enum.ident.to_string(), true);
+                        state_type_vis.insert(item_enum.ident.to_string(), item_enum.vis.clone());
+                        Some(item_enum.ident.to_string())
+                    }
+                    Item::Struct(item_struct) => {
+                        state_types.insert(item_struct.ident.to_string(), false);
+                        state_type_vis.insert(item_struct.ident.to_string(), item_struct.vis.clone());
+                        Some(item_struct.ident.to_string())
+                    }
+                    Item::Impl(item_impl) => match &*item_impl.self_ty {
+                        // Impls for the type with generic arguments, such as `Parser<'a>`, count too
+                        syn::Type::Path(type_path) if type_path.qself.is_none() && type_path.path.segments.len() == 1 => {
+                            if item_impl.trait_.is_some() {
+                                trait_impl_items.insert(other_items.len());
+                            }
+                            Some(type_path.path.segments[0].ident.to_string())
+                        }
+                        _ => None,
+                    },
+                    _ => None,
+                };
+                if let Some(state_type) = state_type {
+                    type_items.entry(state_type).or_default().push(other_items.len());
+                }
+                item_count += 1;
+                other_items.push(item_code);
+            }
+        }
+    }
+
+    Ok(CollectedItems {
+        imports,
+        imported_names,
+        import_path_of,
+        aliases,
+        functions,
+        func_asts,
+        func_offsets,
+        cfg_twins,
+        main_function,
+        main_ast,
+        test_module,
+        other_items,
+        ffi_items,
+        asm_functions,
+        asm_items,
+        asm_names,
+        foreign_functions,
+        foreign_names,
+        proc_macro_impls,
+        state_types,
+        state_type_vis,
+        type_items,
+        trait_impl_items,
+        local_names,
+        local_macros,
+        files,
+        child_trees,
+        moves,
+        smoke_targets,
+        plan_entries,
+        decisions,
+        suggestions,
+        item_count,
+    })
+}
+
+// Function to place each function that may move in a group, by the rules in turn and then by the
+// groups chosen by hand, noting the plan entry and the reason of each placement
+fn place_functions(collected: &mut CollectedItems, items: &[Item], module_path: &[String], options: &Options) -> Placement {
+    let is_root = module_path.is_empty();
+    let CollectedItems { ref func_asts, ref asm_functions, ref foreign_functions, ref proc_macro_impls, ref import_path_of, ref local_names, ref state_types, main_ast, ref mut plan_entries, ref mut suggestions, .. } = *collected;
+    // Define categories based on function name keywords and crate usage
+    let categories = vec![
+        ("network", vec!["connect", "send", "receive", "http", "socket"]),
+        ("file_io", vec!["read", "write", "file", "open", "close"]),
+        ("crypto", vec!["encrypt", "decrypt", "hash", "key"]),
+        ("util", vec!["helper", "utility", "convert", "parse"]),
+        ("windows_system", vec!["windows", "handle", "service", "process", "thread"]),
+        ("core_std", vec!["core", "std", "sync", "mutex", "ptr"]),
+        ("serde", vec!["serde", "json", "deserialize"]),
+        ("rand", vec!["rand", "rng"]),
+        ("memory", vec!["mem", "memory", "alloc", "ptr"]),
+        // Add more categories as needed
+    ];
+
+    // With `--strategy fn=calls`, the functions calling each other are grouped together, after
+    // the one driving them, unless an earlier rule places them
+    let mut call_groups = HashMap::new();
+    if options.strategies.functions == strategy::FunctionStrategy::Calls {
+        let candidates: HashSet<String> = func_asts
+            .iter()
+            .filter(|(func_name, func_ast)| {
+                !asm_functions.contains(*func_name)
+                    && !is_ffi_wrapper(func_ast, foreign_functions)
+                    && !proc_macro_impls.contains(*func_name)
+                    && like::best_module(func_name, &options.model_modules).is_none()
+            })
+            .map(|(func_name, _)| func_name.clone())
+            .collect();
+        for (driver, members) in strategy::call_clusters(func_asts, &candidates, options.seed) {
+            if is_reserved_group(&driver) {
+                continue;
+            }
+            for member in members {
+                call_groups.insert(member, driver.clone());
+            }
+        }
+    }
+
+    let mut assigned_categories = HashMap::new();
+    let mut placement_reasons = HashMap::new();
+    // The types declared here, which generic helpers don't name
+    let domain_types: HashSet<String> = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Struct(item) => Some(item.ident.to_string()),
+            Item::Enum(item) => Some(item.ident.to_string()),
+            Item::Union(item) => Some(item.ident.to_string()),
+            Item::Type(item) => Some(item.ident.to_string()),
+            Item::Trait(item) => Some(item.ident.to_string()),
+            _ => None,
+        })
+        .collect();
+    let generic_group = match options.strategies.generics {
+        strategy::GenericStrategy::Group => None,
+        strategy::GenericStrategy::Support => Some(GENERIC_SUPPORT_GROUP),
+        strategy::GenericStrategy::Stay => Some(STAY_GROUP),
+    };
+
+    for (index, (func_name, func_ast)) in func_asts.iter().enumerate() {
+        progress::step("Grouping functions", index + 1, func_asts.len());
+        let generic_params = generic_group.and_then(|_| strategy::generic_helper_params(func_ast, &domain_types));
+        let mut assigned_category = "general".to_string();
+
+        let mut reason = "its name matches no category".to_string();
+        if asm_functions.contains(func_name) {
+            assigned_category = "asm".to_string();
+            reason = "it uses assembly or linker placement, which is kept together".to_string();
+        } else if is_ffi_wrapper(func_ast, foreign_functions) {
+            // Thin safe wrappers stay next to the extern block they wrap
+            assigned_category = "ffi".to_string();
+            reason = "it is a thin wrapper around an extern function".to_string();
+        } else if proc_macro_impls.contains(func_name) {
+            // Bodies of exported proc macros are kept together
+            assigned_category = "expand".to_string();
+            reason = "it is the body of an exported proc macro".to_string();
+        } else if let (Some(group), Some(params)) = (generic_group, &generic_params) {
+            // Helpers generic enough for any domain belong to none of them
+            assigned_category = group.to_string();
+            reason = format!("it is generic over `{}` and names none of the crate's types", params.join("`, `"));
+        } else if let Some((module, word)) = like::best_module(func_name, &options.model_modules) {
+            // With --like or a recipe, the modules of the model crate or the recipe come first
+            assigned_category = module.name.clone();
+            reason = match options.recipe.filter(|_| options.like_crate.is_none()) {
+                Some(recipe) => format!("its name shares `{}` with the `{}` module of the {} recipe", word, module.name, recipe.name),
+                None => format!("its name shares `{}` with the `{}` module of the crate it imitates", word, module.name),
+            };
+        } else if let Some(driver) = call_groups.get(func_name) {
+            assigned_category = driver.clone();
+            reason = if driver == func_name {
+                "it drives the functions it calls".to_string()
+            } else {
+                format!("it is part of the calls `{}` drives", driver)
+            };
+        } else {
+            // Assign the function to a category based on keywords or crate usage
+            for (category, keywords) in &categories {
+                if let Some(keyword) = keywords.iter().find(|keyword| func_name.contains(*keyword)) {
+                    assigned_category = category.to_string();
+                    reason = format!("its name contains `{}`", keyword);
+                    break;
+                }
+            }
+            if assigned_category == "general" {
+                if let Some((category, path, keyword)) = crate_category(func_ast, &categories, import_path_of, local_names) {
+                    assigned_category = category.to_string();
+                    reason = format!("it uses `{}`, which contains `{}`", path, keyword);
+                }
+            }
+        }
+        log::trace("group", format_args!("`{}` first goes to `{}` because {}", item_path_of(module_path, func_name), assigned_category, reason));
+        placement_reasons.insert(func_name.clone(), reason);
+
+        assigned_categories.insert(func_name.clone(), assigned_category);
+    }
+
+    // Functions listed in the same table of function pointers, such as `static HANDLERS: [fn(&Ctx);
+    // 2] = [on_open, on_close];`, are kept together in the group most of them went to
+    for (table, members) in function_tables(items, func_asts) {
+        let members: Vec<String> = members
+            .into_iter()
+            .filter(|member| !["asm", "ffi", "expand"].contains(&assigned_categories[member].as_str()))
+            .collect();
+        let mut counts: BTreeMap<&String, usize> = BTreeMap::new();
+        for member in &members {
+            *counts.entry(&assigned_categories[member]).or_default() += 1;
+        }
+        let Some(group) = counts
+            .iter()
+            .max_by_key(|(group, count)| (**count, std::cmp::Reverse((strategy::tie_break(options.seed, group), **group))))
+            .map(|(group, _)| group.to_string())
+        else {
+            continue;
+        };
+        let lead = members.iter().find(|member| assigned_categories[*member] == group).cloned().unwrap_or_default();
+        let moving: Vec<&String> = members.iter().filter(|member| assigned_categories[*member] != group).collect();
+        for member in moving {
+            assigned_categories.insert(member.clone(), group.clone());
+            placement_reasons.insert(member.clone(), format!("it is in the `{}` table along with `{}`", table, lead));
+        }
+    }
+
+    // Functions that together drive a state type defined here are kept with it, in a module named
+    // after the type
+    let state_machines = state_machine::find_state_machines(func_asts, state_types);
+    let mut state_groups = HashMap::new();
+    for (func_name, state_type) in &state_machines {
+        let group = state_machine::module_name(state_type);
+        assigned_categories.insert(func_name.clone(), group.clone());
+        placement_reasons.insert(func_name.clone(), format!("it drives the `{}` state type", state_type));
+        state_groups.insert(group, state_type.clone());
+    }
+
+    // Pin functions that nearly every group references into the common module, so that no
+    // single group ends up owning a helper everyone else depends on
+    for func_name in find_hot_functions(func_asts, &assigned_categories, main_ast) {
+        if !state_machines.contains_key(&func_name) && !asm_functions.contains(&func_name) {
+            placement_reasons.insert(func_name.clone(), "nearly every other group uses it".to_string());
+            assigned_categories.insert(func_name, "common".to_string());
+        }
+    }
+
+    // A function only one function of another group calls widens the surface between the two
+    // modules for nothing, so it is flagged, or with --inline-single-use moved next to its caller.
+    // Moving a function can separate it from its own single-use helpers, hence the passes
+    let single_callers = find_single_callers(func_asts, main_ast);
+    let proposed_categories = assigned_categories.clone();
+    for _ in 0..func_asts.len() {
+        let mut moved = false;
+        for (func_name, caller) in &single_callers {
+            let group = &assigned_categories[func_name];
+            let caller_group = &assigned_categories[caller];
+            let pinned = state_groups.contains_key(group) || PINNED_GROUPS.contains(&group.as_str());
+            if pinned || group == caller_group {
+                continue;
+            }
+            if !options.inline_single_use {
+                continue;
+            }
+            placement_reasons.insert(func_name.clone(), format!("its only caller `{}` is there", caller));
+            assigned_categories.insert(func_name.clone(), caller_group.clone());
+            moved = true;
+        }
+        if !moved {
+            break;
+        }
+    }
+    let inlined_categories = assigned_categories.clone();
+
+    // Groups too small for a file of their own are merged into one, rather than littering the
+    // crate with one-function modules
+    let mut rationales = HashMap::new();
+    if options.min_module_items > 0 {
+        let mut group_sizes: HashMap<String, usize> = HashMap::new();
+        for group in assigned_categories.values() {
+            *group_sizes.entry(group.clone()).or_default() += 1;
+        }
+        for (func_name, group) in &mut assigned_categories {
+            let pinned = state_groups.contains_key(group) || PINNED_GROUPS.contains(&group.as_str());
+            if pinned || *group == options.misc_module || group_sizes[group] >= options.min_module_items {
+                continue;
+            }
+            let rationale = format!("merged from `{}`, which has fewer than {} functions", group, options.min_module_items);
+            placement_reasons.insert(func_name.clone(), format!("its group `{}` has fewer than {} functions", group, options.min_module_items));
+            rationales.insert(func_name.clone(), rationale);
+            *group = options.misc_module.clone();
+        }
+    }
+
+    // Groups chosen by hand in an applied plan, or remembered from earlier ones, win over the proposal
+    for (func_name, assigned_category) in &mut assigned_categories {
+        let mut item_path = module_path.to_vec();
+        item_path.push(func_name.clone());
+        let fingerprint = plan::fingerprint(&item_path, &func_asts[func_name]);
+        let proposed = assigned_category.clone();
+        let mut rationale = rationales.remove(func_name);
+        if let Some(group) = options.assignments.group_for(&item_path, &fingerprint) {
+            *assigned_category = group.clone();
+            placement_reasons.insert(func_name.clone(), "it was placed there by hand in a plan".to_string());
+            rationale = None;
+        }
+        plan_entries.push(plan::PlanEntry {
+            item_path,
+            fingerprint,
+            proposed,
+            group: assigned_category.clone(),
+            rationale,
+        });
+    }
+
+    // Single-use helpers are told about where they end up, once the groups chosen by hand are in,
+    // so that no suggestion asks to move a function the plan already moved
+    for (func_name, caller) in &single_callers {
+        let group = &assigned_categories[func_name];
+        let caller_group = &assigned_categories[caller];
+        if group != caller_group {
+            let pinned = state_groups.contains_key(group) || PINNED_GROUPS.contains(&group.as_str());
+            if !options.inline_single_use && !pinned {
+                suggestions.push(format!(
+                    "`{}` is only called by `{}` in the `{}` group; inline it there or move it along with --inline-single-use",
+                    item_path_of(module_path, func_name),
+                    caller,
+                    caller_group
+                ));
+            }
+        } else if inlined_categories[func_name] != proposed_categories[func_name] {
+            suggestions.push(format!(
+                "moved `{}` to the `{}` group, next to its only caller `{}`",
+                item_path_of(module_path, func_name),
+                group,
+                caller
+            ));
+        }
+    }
+    let mut placed: Vec<(&String, &String)> = assigned_categories.iter().collect();
+    placed.sort();
+    for (func_name, group) in &placed {
+        let reason = placement_reasons.get(*func_name).map_or("nothing else claimed it", String::as_str);
+        log::debug("group", format_args!("`{}` goes to `{}` because {}", item_path_of(module_path, func_name), group, reason));
+    }
+    log::info(
+        "group",
+        format_args!(
+            "{} functions of `{}` in {} groups",
+            placed.len(),
+            if is_root { "crate".to_string() } else { format!("crate::{}", module_path.join("::")) },
+            placed.iter().map(|(_, group)| group).collect::<HashSet<_>>().len()
+        ),
+    );
+
+    Placement {
+        assigned_categories,
+        placement_reasons,
+        state_groups,
+    }
+}
+
+// Function to gather the code and imports of each group, the tests following its functions and
+// the types moving along with them
+fn fill_groups(collected: &mut CollectedItems, placement: Placement, source: &str, module_path: &[String], options: &Options) -> Groups {
+    let Placement { assigned_categories, placement_reasons, mut state_groups } = placement;
+    let mut grouped_functions: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    let mut group_imports: HashMap<String, HashSet<String>> = HashMap::new();
+    // Whether each of those imports is only maybe used, being a trait taken for a method that
+    // another imported trait has as well
+    let mut import_doubts: HashMap<String, HashMap<String, bool>> = HashMap::new();
+    if !collected.ffi_items.is_empty() {
+        // Extern blocks always get a module, even when nothing wraps them
+        grouped_functions.entry("ffi".to_string()).or_default();
+    }
+    if !collected.asm_items.is_empty() {
+        // So do the statics and global assembly of --asm group
+        grouped_functions.entry("asm".to_string()).or_default();
+    }
+
+    let CollectedItems {
+        ref functions,
+        ref func_asts,
+        ref func_offsets,
+        ref cfg_twins,
+        ref imported_names,
+        ref aliases,
+        ref import_path_of,
+        ref test_module,
+        ref mut other_items,
+        ref type_items,
+        ref trait_impl_items,
+        ref state_types,
+        ref mut decisions,
+        ref mut item_count,
+        ..
+    } = *collected;
+    // Every declaration of a function, its cfg twins included, for what looks into their code
+    let declarations_of = |func_name: &String| declarations_of(func_asts, cfg_twins, func_name);
+
+    let mut common_functions: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (func_name, assigned_category) in &assigned_categories {
+        if assigned_category == "common" {
+            let visibility = visibility_code(&func_asts[func_name].vis);
+            common_functions.entry(visibility).or_default().push(func_name.clone());
+        }
+    }
+    common_functions.values_mut().for_each(|names| names.sort());
+
+    // Functions go into their modules in the order they were written in
+    let mut function_order: Vec<(&String, &String)> = functions.iter().collect();
+    function_order.sort_by_key(|(func_name, _)| (func_offsets[*func_name], *func_name));
+    for (func_name, func_code) in function_order {
+        let assigned_category = &assigned_categories[func_name];
+        let func_ast = &func_asts[func_name];
+
+        // Moving into a child module shifts what `self` and `super` refer to, so the visibility is
+        // rewritten to keep the function visible to exactly the modules that could see it before,
+        // and relative paths in its body to keep naming the same items
+        let (mut func_code, anchored_paths) = relocate_function(func_code, func_ast, func_offsets[func_name], module_path.len());
+        for (twin_code, twin, twin_offset) in cfg_twins.get(func_name).into_iter().flatten() {
+            func_code.push_str("\n\n");
+            func_code.push_str(&relocate_function(twin_code, twin, *twin_offset, module_path.len()).0);
+        }
+        if let Some(visibility) = relocated_visibility(&func_ast.vis) {
+            let declared = visibility_code(&func_ast.vis);
+            decisions.push(teach::Decision {
+                subject: item_path_of(module_path, func_name),
+                change: format!("`{}` becomes `{}`", if declared.is_empty() { "private" } else { &declared }, visibility),
+                concept: teach::Concept::Privacy,
+            });
+        }
+        if anchored_paths {
+            decisions.push(teach::Decision {
+                subject: item_path_of(module_path, func_name),
+                change: "its `self::` and `super::` paths get one more `super`".to_string(),
+                concept: teach::Concept::RelativePaths,
+            });
+        }
+
+        // Group functions by their assigned category
+        grouped_functions
+            .entry(assigned_category.clone())
+            .or_default()
+            .push((func_name.clone(), func_code));
+
+        // Determine imports required by the category
+        let mut visitor = CrateUsageVisitor {
+            imported_names,
+            aliases,
+            used_imports: HashSet::new(),
+            local_names: Vec::new(),
+            trait_methods: Vec::new(),
+            import_paths: import_path_of,
+            named_modules: HashSet::new(),
+            ambiguous_methods: Vec::new(),
+            unsure_traits: HashSet::new(),
+            no_std: options.no_std,
+        };
+        for declaration in declarations_of(func_name) {
+            visit_item_fn(&mut visitor, declaration);
+        }
+        visitor.note_ambiguous_methods();
+        for (trait_name, method) in visitor.trait_methods {
+            decisions.push(teach::Decision {
+                subject: item_path_of(module_path, func_name),
+                change: format!("imports `{}` for its call to `.{}()`", trait_name, method),
+                concept: teach::Concept::TraitScope,
+            });
+        }
+        // An import stays only maybe used while no function of the group surely uses it
+        let group_doubts = import_doubts.entry(assigned_category.clone()).or_default();
+        for name in &visitor.used_imports {
+            let unsure = visitor.unsure_traits.contains(name);
+            *group_doubts.entry(name.clone()).or_insert(unsure) &= unsure;
+        }
+        group_imports
+            .entry(assigned_category.clone())
+            .or_default()
+            .extend(visitor.used_imports);
+    }
+
+    // Functions stay where they are when they are all `general`, rather than moving into a
+    // `general_mod` of their own, and so do the ones put in the stay group
+    let keeps_in_place = grouped_functions
+        .iter()
+        .filter(|(group, _)| *group == "general" || *group == STAY_GROUP)
+        .map(|(_, funcs)| funcs.len())
+        .sum::<usize>()
+        == functions.len();
+    let stays = |group: &str| stays_in_place(group, keeps_in_place);
+
+    // Tests of an existing `#[cfg(test)] mod tests` follow the functions they test, unless those
+    // all stay where they are
+    let moving_categories: HashMap<String, String> = assigned_categories
+        .iter()
+        .filter(|(_, group)| !stays(group))
+        .map(|(func_name, group)| (func_name.clone(), group.clone()))
+        .collect();
+    let split_tests = test_module
+        .clone()
+        .filter(|_| !keeps_in_place)
+        .map(|(item_mod, item_range, index)| (index, split_test_module(source, item_mod, item_range, &moving_categories, options.no_std)));
+    let mut dropped_test_module = None;
+    if let Some((index, split)) = &split_tests {
+        match &split.remaining {
+            Some(remaining) => other_items[*index] = remaining.clone(),
+            None => dropped_test_module = Some(*index),
+        }
+    }
+
+    // State types and their impls move along with the functions driving them, unless the plan
+    // took all of those functions elsewhere
+    let mut state_items: HashMap<String, Vec<String>> = HashMap::new();
+    let mut moved_types: HashMap<String, Vec<String>> = HashMap::new();
+    let mut moved_type_items = HashSet::new();
+    // With `--strategy impl=stay`, trait impls stay behind and reach the moved fields through
+    // their widened visibility
+    let moves_with_type = |index: &usize| options.strategies.impls == strategy::ImplStrategy::WithType || !trait_impl_items.contains(index);
+    for (group, state_type) in &state_groups {
+        if grouped_functions.contains_key(group) {
+            moved_types.entry(group.clone()).or_default().push(state_type.clone());
+            for &index in type_items[state_type].iter().filter(|index| moves_with_type(index)) {
+                moved_type_items.insert(index);
+                let item_code = anchor_relative_paths(&relocate_item_visibility(&other_items[index]));
+                state_items.entry(group.clone()).or_default().push(anchor_include_paths(&item_code, module_path.len()));
+            }
+        }
+    }
+
+    // With `--strategy type=clusters`, the other types referring to each other move together:
+    // next to the functions using them when only one group does, or else into their own module
+    if options.strategies.types == strategy::TypeStrategy::Clusters {
+        let mut references = HashMap::new();
+        for (type_name, indices) in type_items.iter().filter(|(type_name, _)| state_types.contains_key(*type_name)) {
+            if indices.iter().any(|index| moved_type_items.contains(index)) {
+                continue;
+            }
+            let mut idents = HashSet::new();
+            for &index in indices {
+                collect_idents(other_items[index].parse().unwrap_or_default(), &mut idents);
+            }
+            references.insert(type_name.clone(), idents);
+        }
+        let func_idents: HashMap<&String, HashSet<String>> = func_asts
+            .keys()
+            .map(|func_name| {
+                let mut idents = HashSet::new();
+                for declaration in declarations_of(func_name) {
+                    collect_idents(declaration.to_token_stream(), &mut idents);
+                }
+                (func_name, idents)
+            })
+            .collect();
+
+        for cluster in strategy::type_clusters(&references, options.seed) {
+            let users: BTreeSet<&String> = assigned_categories
+                .iter()
+                .filter(|(func_name, _)| cluster.iter().any(|type_name| func_idents[func_name].contains(type_name)))
+                .map(|(_, group)| group)
+                .collect();
+            let users: Vec<&String> = users
+                .into_iter()
+                .filter(|group| !(PINNED_GROUPS.contains(&group.as_str()) || stays(group)))
+                .collect();
+            let group = match users[..] {
+                [group] => group.clone(),
+                _ => {
+                    let mut group = state_machine::module_name(&cluster[0]);
+                    if is_reserved_group(&group) {
+                        group.push_str("_types");
+                    }
+                    if !grouped_functions.contains_key(&group) {
+                        grouped_functions.insert(group.clone(), Vec::new());
+                        state_groups.insert(group.clone(), cluster[0].clone());
+                    }
+                    group
+                }
+            };
+            for type_name in &cluster {
+                decisions.push(teach::Decision {
+                    subject: item_path_of(module_path, type_name),
+                    change: format!("moves to the `{}` group together with the types it refers to or is referred to by", group),
+                    concept: teach::Concept::Placement,
+                });
+                for &index in type_items[type_name].iter().filter(|index| moves_with_type(index)) {
+                    moved_type_items.insert(index);
+                    let item_code = anchor_relative_paths(&relocate_item_visibility(&other_items[index]));
+                    state_items.entry(group.clone()).or_default().push(anchor_include_paths(&item_code, module_path.len()));
+                }
+            }
+            moved_types.entry(group).or_default().extend(cluster);
+        }
+    }
+    *item_count -= moved_type_items.len() + usize::from(dropped_test_module.is_some());
+    *other_items = std::mem::take(other_items)
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| !moved_type_items.contains(index) && dropped_test_module != Some(*index))
+        .map(|(_, item)| item)
+        .collect();
+
+    Groups {
+        assigned_categories,
+        placement_reasons,
+        state_groups,
+        grouped_functions,
+        group_imports,
+        import_doubts,
+        common_functions,
+        keeps_in_place,
+        split_tests,
+        state_items,
+        moved_types,
+    }
+}
+
+// Function to name the module of each group: after a model crate's module, the words its docs
+// share or the group itself, unique among the modules declared next to it
+fn name_modules(collected: &CollectedItems, groups: &Groups, items: &[Item], module_path: &[String], options: &Options) -> Result<ModuleNames, String> {
+    let Groups { ref grouped_functions, ref state_groups, keeps_in_place, .. } = *groups;
+    let stays = |group: &str| stays_in_place(group, keeps_in_place);
+    // Groups taking after a module of the model crate keep its name where nothing else claims it
+    let model_names: HashSet<&str> = options.model_modules.iter().map(|module| module.name.as_str()).collect();
+
+    // Groups whose docs share a vocabulary are named after it, such as `http` for functions that
+    // all handle HTTP, unless the name is taken by something the module's paths could also mean
+    let named_groups: BTreeMap<&String, Vec<&ItemFn>> = grouped_functions
+        .iter()
+        .filter(|(group, _)| !PINNED_GROUPS.contains(&group.as_str()) && !state_groups.contains_key(*group))
+        .filter(|(group, _)| !model_names.contains(group.as_str()))
+        .filter(|(group, _)| !stays(group) && **group != options.misc_module)
+        .map(|(group, funcs)| (group, funcs.iter().map(|(func_name, _)| &collected.func_asts[func_name]).collect()))
+        .collect();
+    let mut taken_names: HashSet<String> = ["std", "core", "alloc"].iter().chain(PINNED_GROUPS).map(|name| name.to_string()).collect();
+    taken_names.extend(state_groups.keys().cloned());
+    taken_names.extend(grouped_functions.keys().map(|group| generated_module_name(group, options)));
+    taken_names.extend(items.iter().flat_map(declared_names));
+    taken_names.extend(items.iter().filter_map(|item| match item {
+        Item::Use(use_item) => use_root_segment(&use_item.tree),
+        _ => None,
+    }));
+    let mut doc_names = HashMap::new();
+    for (group, name) in naming::doc_module_names(&named_groups).into_iter().collect::<BTreeMap<_, _>>() {
+        if taken_names.insert(name.clone()) {
+            doc_names.insert(group, name);
+        }
+    }
+
+    // Turn the group names into valid module identifiers, unique among the modules declared here,
+    // in a stable order so that reruns keep handing out the same suffixes
+    let mut group_names: Vec<&String> = grouped_functions.keys().collect();
+    group_names.sort();
+    let mut module_idents: HashSet<String> = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Mod(item_mod) => Some(item_mod.ident.unraw().to_string().to_lowercase()),
+            _ => None,
+        })
+        .collect();
+    let mut module_names = HashMap::new();
+    for group_name in group_names {
+        let is_special = PINNED_GROUPS.contains(&group_name.as_str());
+        let keeps_name = (model_names.contains(group_name.as_str()) && !taken_names.contains(group_name)) || *group_name == options.misc_module;
+        let module_name = if let Some(prefix) = &options.module_prefix {
+            // A prefix marks every generated module, whatever its name comes from
+            idents::module_ident(&format!("{}{}", prefix, doc_names.get(group_name).unwrap_or(group_name)))
+        } else if is_special || state_groups.contains_key(group_name) || keeps_name {
+            idents::module_ident(group_name)
+        } else if let Some(doc_name) = doc_names.get(group_name) {
+            idents::module_ident(doc_name)
+        } else {
+            idents::module_ident(&generated_module_name(group_name, options))
+        };
+        let module_name = idents::unique_module_ident(module_name, &mut module_idents);
+        // A module renamed during the review takes the name it was given
+        let module_name = match options.module_renames.get(&item_path_of(module_path, &module_name)) {
+            Some(name) => idents::unique_module_ident(name.clone(), &mut module_idents),
+            None => module_name,
+        };
+        idents::check_module_ident(&module_name).map_err(|e| format!("the group `{}` can't be a module: {}", group_name, e))?;
+        module_names.insert(group_name.clone(), module_name);
+    }
+
+    Ok(ModuleNames { module_names, doc_names, module_idents })
+}
+
+// Function to write the module of each group, with the imports it takes from its parent and the
+// declarations and re-exports the parent gets in return
+fn emit_modules(
+    collected: CollectedItems,
+    groups: Groups,
+    names: ModuleNames,
+    items: &[Item],
+    output_dir: &Path,
+    module_path: &[String],
+    options: &Options,
+) -> Result<RefactoredItems, String> {
+    let is_root = module_path.is_empty();
+    let CollectedItems {
+        imports,
+        functions,
+        imported_names,
+        import_path_of,
+        func_asts,
+        cfg_twins,
+        main_function,
+        other_items,
+        ffi_items,
+        asm_items,
+        asm_names,
+        foreign_names,
+        state_type_vis,
+        local_macros,
+        mut files,
+        mut child_trees,
+        mut moves,
+        mut smoke_targets,
+        plan_entries,
+        mut decisions,
+        suggestions,
+        mut item_count,
+        ..
+    } = collected;
+    let Groups {
+        assigned_categories,
+        placement_reasons,
+        grouped_functions,
+        mut group_imports,
+        import_doubts,
+        common_functions,
+        keeps_in_place,
+        split_tests,
+        state_items,
+        moved_types,
+        ..
+    } = groups;
+    let ModuleNames { module_names, doc_names, mut module_idents } = names;
+    let mut other_items = other_items;
+    let stays = |group: &str| stays_in_place(group, keeps_in_place);
+    let declarations_of = |func_name: &String| declarations_of(&func_asts, &cfg_twins, func_name);
+    let mut mod_declarations = Vec::new();
+    let mut use_statements = Vec::new();
+
+    // Generated modules import exactly the names they take from this module, unless it has names
+    // that can't be told apart without a glob
+    let glob_names = glob_import_names(items, output_dir);
+    let parent_names = parent_names(items, &func_asts, glob_names, is_root, options.no_std, options.edition);
+    let parent_prefix = if is_root { "crate" } else { "super" };
+    // What the generated modules import from this one, None once one of them uses a glob
+    let mut taken_by_modules = Some(HashSet::new());
+    // The statics of --asm group, and whether the items left behind name any of them
+    let asm_statics: Vec<syn::ItemStatic> = asm_items
+        .iter()
+        .filter_map(|item_code| match syn::parse_str::<Item>(item_code) {
+            Ok(Item::Static(item_static)) => Some(item_static),
+            _ => None,
+        })
+        .collect();
+    let asm_name_set: HashSet<String> = asm_names.iter().cloned().collect();
+    // A library's public statics are named from outside the crate, so they stay re-exported too
+    let asm_names_used = items.iter().filter(|item| !is_placement_sensitive(item)).any(|item| mentions_any(item.to_token_stream(), &asm_name_set))
+        || options.library && asm_statics.iter().any(|item_static| matches!(item_static.vis, syn::Visibility::Public(_)));
+
+    // Find the names each module's code takes from the parent, including the items moving along.
+    // Simple imports the module names anywhere, such as a type in a signature, are copied too
+    let mut usages: HashMap<&String, NameUsageVisitor> = HashMap::new();
+    for (group_name, funcs) in grouped_functions.iter().filter(|(group_name, _)| !stays(group_name)) {
+        let mut usage = NameUsageVisitor::default();
+        for declaration in funcs.iter().flat_map(|(func_name, _)| declarations_of(func_name)) {
+            usage.visit_item_fn(declaration);
+        }
+        let carried_items = state_items.get(group_name).into_iter().flatten()
+            .chain(ffi_items.iter().filter(|_| group_name == "ffi"))
+            .chain(asm_items.iter().filter(|_| group_name == "asm"));
+        for item_code in carried_items {
+            if let Ok(item) = syn::parse_str::<Item>(item_code) {
+                usage.visit_item(&item);
+            }
+        }
+        // A parent whose names can't all be known is taken along with a glob, its imports included
+        if parent_names.opaque {
+            group_imports.remove(group_name);
+        } else {
+            for name in usage.used_names() {
+                if imported_names.contains_key(name) {
+                    group_imports.entry(group_name.clone()).or_default().insert(name.clone());
+                }
+            }
+        }
+        usages.insert(group_name, usage);
+    }
+    // Nor do they copy imports of what the prelude has in scope anyway
+    for names in group_imports.values_mut() {
+        names.retain(|name| !import_path_of.get(name).is_some_and(|path| in_prelude(path, options.edition, options.no_std)));
+    }
+
+    // Imports nearly every module needs are collected into a prelude instead of being repeated
+    let mut import_counts: HashMap<&String, usize> = HashMap::new();
+    for group_name in usages.keys() {
+        for name in group_imports.get(*group_name).into_iter().flatten() {
+            *import_counts.entry(name).or_default() += 1;
+        }
+    }
+    let prelude_names: HashSet<String> = import_counts
+        .into_iter()
+        .filter(|(_, count)| *count >= PRELUDE_MIN_MODULES && *count as f64 >= usages.len() as f64 * PRELUDE_MODULE_SHARE)
+        .map(|(name, _)| name.clone())
+        .collect();
+    let prelude_name = (!prelude_names.is_empty()).then(|| idents::unique_module_ident("prelude".to_string(), &mut module_idents));
+
+    // Step 4: Refactor logic into separate files based on grouped functions, after keeping the
+    // functions that stay where they are in their original order
+    let mut twins_seen: HashMap<String, usize> = HashMap::new();
+    for item in items {
+        if let Item::Fn(func) = item {
+            let func_name = func.sig.ident.to_string();
+            if let Some(func_code) = functions.get(&func_name).filter(|_| stays(&assigned_categories[&func_name])) {
+                // The cfg twins of a function keep their own places among the items
+                let seen = twins_seen.entry(func_name.clone()).or_default();
+                let func_code = match seen.checked_sub(1) {
+                    None => func_code,
+                    Some(twin) => &cfg_twins[&func_name][twin].0,
+                };
+                *seen += 1;
+                item_count += 1;
+                other_items.push(func_code.clone());
+            }
+        }
+    }
+    let mut group_order: Vec<(&String, &Vec<(String, String)>)> = grouped_functions.iter().collect();
+    group_order.sort_by_key(|(group_name, _)| *group_name);
+    let module_count = group_order.iter().filter(|(group_name, _)| !stays(group_name)).count();
+    for (index, (group_name, funcs)) in group_order.into_iter().filter(|(group_name, _)| !stays(group_name)).enumerate() {
+        progress::step("Formatting modules", index + 1, module_count);
+
+        let module_name = module_names[group_name].clone();
+        let group_types: &[String] = moved_types.get(group_name).map_or(&[], Vec::as_slice);
+
+        let usage = &usages[group_name];
+        let mut own_names: HashSet<String> = funcs.iter().map(|(func_name, _)| func_name.clone()).collect();
+        own_names.extend(group_types.iter().cloned());
+        own_names.extend(foreign_names.iter().filter(|_| group_name == "ffi").cloned());
+        own_names.extend(asm_names.iter().filter(|_| group_name == "asm").cloned());
+
+        let copied_names = group_imports.get(group_name).cloned().unwrap_or_default();
+        let taken_names = taken_parent_names(usage, &parent_names, &own_names, &copied_names);
+        note_taken_names(&mut taken_by_modules, taken_names.as_ref());
+
+        // Add `use crate::{...};` (or `use super::{...};` below the root) with the names taken from
+        // the parent, or a glob when they can't all be found; only this generated glue is formatted,
+        // moved code is copied verbatim to keep git blame intact
+        let mut glue = String::new();
+
+        // When every declaration in the group is behind the same `#[cfg]`, gate the whole module
+        // with it. Its declaration and re-export are gated along with it below, so that other
+        // configurations compile without the module
+        let func_asts_in_group: Vec<&ItemFn> = funcs.iter().flat_map(|(func_name, _)| declarations_of(func_name)).collect();
+        let module_cfgs = shared_cfg_attrs(&func_asts_in_group);
+        for cfg in &module_cfgs {
+            glue.push_str(&format!("#![{}]\n", cfg));
+        }
+        // With --explicit-imports, the glob is spelled out as the names the module's code leaves free
+        let explicit_names = if taken_names.is_none() && options.explicit_imports {
+            explicit_parent_names(usage, &parent_names, &own_names, &copied_names, &local_macros, options)
+        } else {
+            None
+        };
+        let mut parent_imports = Vec::new();
+        match taken_names.as_ref().or(explicit_names.as_ref()) {
+            None => parent_imports.push(format!("use {}::*;", parent_prefix)),
+            Some(names) if names.is_empty() => {}
+            Some(names) => parent_imports.push(format!("use {}::{{{}}};", parent_prefix, names.join(", "))),
+        }
+        if let Some(prelude_name) = prelude_name.as_ref().filter(|_| copied_names.iter().any(|name| prelude_names.contains(name))) {
+            parent_imports.push(format!("use {}::{}::*;", parent_prefix, prelude_name));
+        }
+
+        // Include relevant imports for this module, in their original order
+        let mut copied_imports = Vec::new();
+        if let Some(used_names) = group_imports.get(group_name) {
+            let used_names: HashSet<String> = used_names.difference(&prelude_names).cloned().collect();
+            let used_names = &used_names;
+            // The traits that may go unused are imported apart from the rest, and allowed to
+            let doubts = import_doubts.get(group_name);
+            let (unsure_names, sure_names): (HashSet<String>, HashSet<String>) =
+                used_names.iter().cloned().partition(|name| doubts.and_then(|doubts| doubts.get(name)).copied().unwrap_or(false));
+            let mut copied = Vec::new();
+            for (names, prefix) in [(&sure_names, ""), (&unsure_names, "#[allow(unused_imports)]\n")] {
+                let used_imports: BTreeSet<usize> = names.iter().map(|name| imported_names[name]).collect();
+                copied.extend(used_imports.into_iter().map(|index| format!("{}{}", prefix, copied_import(&imports[index], names))));
+            }
+            copied_imports = merge_imports(copied);
+        }
+        // Unless a style lays them out, the imports from the parent come first and the copied ones follow
+        let module_imports = if options.import_style == ImportStyle::Original {
+            glue.push_str(&parent_imports.join("\n"));
+            copied_imports
+        } else {
+            import_style::arrange(parent_imports.into_iter().chain(copied_imports).collect(), options.import_style)
+        };
+        let mut module_code = format_glue(&glue);
+        if !module_code.is_empty() {
+            module_code.push('\n');
+        }
+        for import in module_imports {
+            module_code.push_str(&import);
+            module_code.push('\n');
+        }
+        if !module_code.is_empty() {
+            module_code.push('\n');
+        }
+
+        // A state machine's module starts with its state type and the type's impls
+        for item_code in state_items.get(group_name).into_iter().flatten() {
+            module_code.push_str(&skip_literal_tables(&dedent_item(item_code)));
+            module_code.push_str("\n\n");
+        }
+
+        // The ffi module carries the extern blocks ahead of their wrappers
+        if group_name == "ffi" {
+            for ffi_item in &ffi_items {
+                module_code.push_str(&dedent_item(ffi_item));
+                module_code.push_str("\n\n");
+            }
+        }
+        if group_name == "asm" {
+            for asm_item in &asm_items {
+                module_code.push_str(&skip_literal_tables(&dedent_item(asm_item)));
+                module_code.push_str("\n\n");
+            }
+        }
+
+        // Add the functions to the module
+        for (_func_name, func_code) in funcs {
+            module_code.push_str(&skip_literal_tables(&dedent_item(func_code)));
+            module_code.push_str("\n\n");
+        }
+
+        let mut generated_module_path = module_path.to_vec();
+        generated_module_path.push(module_name.clone());
+        let module_subject = generated_module_path.join("::");
+
+        // Followed by the tests that only call functions of this group
+        if let Some((_, split)) = split_tests.as_ref().filter(|(_, split)| split.moved.contains_key(group_name)) {
+            // Tests see the module's names through `use super::*;`, and import the other names they
+            // take from the parent
+            let mut test_usage = NameUsageVisitor::default();
+            for (_, test_code) in &split.moved[group_name] {
+                if let Ok(test) = syn::parse_str::<ItemFn>(test_code) {
+                    test_usage.visit_item_fn(&test);
+                }
+            }
+            let test_names = taken_names.as_ref().map(|names| {
+                let mut seen_names = own_names.clone();
+                seen_names.extend(copied_names.iter().cloned());
+                seen_names.extend(names.iter().cloned());
+                seen_names.extend(import_leaves(split.used_imports[group_name].iter().map(|index| &split.imports[*index])));
+                taken_parent_names(&test_usage, &parent_names, &seen_names, &HashSet::new())
+            });
+            note_taken_names(&mut taken_by_modules, test_names.as_ref().and_then(Option::as_ref));
+            let test_prefix = if is_root { "crate" } else { "super::super" };
+            let test_import = match test_names {
+                Some(None) => format!("use {}::*;", test_prefix),
+                Some(Some(names)) if !names.is_empty() => format!("use {}::{{{}}};", test_prefix, names.join(", ")),
+                _ => String::new(),
+            };
+            module_code.push_str(&test_block(split, group_name, &test_import, options.import_style));
+            for (test_name, _) in &split.moved[group_name] {
+                let mut test_path = module_path.to_vec();
+                test_path.push("tests".to_string());
+                decisions.push(teach::Decision {
+                    subject: item_path_of(&test_path, test_name),
+                    change: format!("moves to `{}::tests` because it only calls functions moving there", module_subject),
+                    concept: teach::Concept::Placement,
+                });
+            }
+        }
+        let moved_names = funcs.iter().map(|(func_name, _)| func_name);
+        let moved_foreign = foreign_names.iter().filter(|_| group_name == "ffi");
+        let moved_asm = asm_names.iter().filter(|_| group_name == "asm");
+        for name in moved_names.chain(moved_foreign).chain(moved_asm).chain(group_types) {
+            let mut old_path = module_path.to_vec();
+            old_path.push(name.clone());
+            let mut new_path = generated_module_path.clone();
+            new_path.push(name.clone());
+            moves.insert(old_path, new_path);
+        }
+
+        // Only items visible from the crate root can be named by the smoke test
+        let mut visible_items: Vec<&String> = funcs
+            .iter()
+            .filter(|(func_name, _)| match &func_asts[func_name].vis {
+                syn::Visibility::Public(_) => true,
+                syn::Visibility::Restricted(restricted) => is_root || restricted.path.is_ident("crate"),
+                syn::Visibility::Inherited => is_root,
+            })
+            .map(|(func_name, _)| func_name)
+            .collect();
+        visible_items.sort();
+        // A gated module isn't there in every configuration for a smoke test to find
+        if module_cfgs.is_empty() {
+            smoke_targets.push(SmokeTarget {
+                module_path: generated_module_path.clone(),
+                item: visible_items.first().map(|item| item.to_string()),
+            });
+        }
+
+        let output_path: PathBuf = output_dir.join(idents::module_file_name(&module_name));
+        log::debug("emit", format_args!("`crate::{}` becomes {} with {} functions", module_subject, output_path.display(), funcs.len()));
+        files.push(GeneratedFile {
+            path: output_path,
+            code: format!("{}\n", module_code.trim_end()),
+            module_path: generated_module_path,
+            written_in: module_path.to_vec(),
+        });
+        // Libraries keep the generated modules private, their items are reachable through the re-exports
+        let module_visibility = if options.library { "" } else { "pub " };
+        child_trees.push(ModuleTree {
+            name: module_name.clone(),
+            visibility: if options.library { "priv" } else { "pub" }.to_string(),
+            item_count: funcs.len()
+                + if group_name == "ffi" { ffi_items.len() } else { 0 }
+                + if group_name == "asm" { asm_items.len() } else { 0 }
+                + state_items.get(group_name).map_or(0, Vec::len),
+            children: Vec::new(),
+        });
+
+        if doc_names.contains_key(group_name) {
+            decisions.push(teach::Decision {
+                subject: module_subject.clone(),
+                change: format!("is named after the words its functions' docs share, not `{}`", generated_module_name(group_name, options)),
+                concept: teach::Concept::Placement,
+            });
+        }
+        let mut placements: Vec<&String> = funcs.iter().map(|(func_name, _)| func_name).collect();
+        placements.sort();
+        for func_name in placements {
+            decisions.push(teach::Decision {
+                subject: item_path_of(module_path, func_name),
+                change: format!("moves to `{}` because {}", module_subject, placement_reasons[func_name]),
+                concept: teach::Concept::Placement,
+            });
+        }
+
+        // Create module declaration and use statement, behind the module's gate if it has one
+        let gate: String = module_cfgs.iter().map(|cfg| format!("#[{}]\n", cfg)).collect();
+        mod_declarations.push(format!("{}{}", gate, mod_declaration(module_visibility, &module_name)));
+        let reexports_start = use_statements.len();
+        if group_name == "common" {
+            // Hot items are re-exported by name with the visibility they were declared with,
+            // keeping the root's dependency on them explicit
+            for (visibility, names) in &common_functions {
+                use_statements.push(format!("{}{}", gate, format!("{} use {}::{{{}}};", visibility, module_name, names.join(", ")).trim_start()));
+            }
+        } else if group_name == "asm" && funcs.is_empty() && !asm_names_used {
+            // Statics placed by the linker and global assembly are reached by the linker rather
+            // than by name, and a re-export nothing uses is a warning
+        } else {
+            // Re-export with the widest visibility among the group's functions, so that a group of
+            // private or restricted functions doesn't claim a `pub` re-export it cannot honour
+            let visibility = funcs
+                .iter()
+                .map(|(func_name, _)| &func_asts[func_name].vis)
+                .chain(group_types.iter().map(|type_name| &state_type_vis[type_name]))
+                .chain(asm_statics.iter().filter(|_| group_name == "asm").map(|item_static| &item_static.vis))
+                .min_by_key(|vis| visibility_scope(vis, module_path).map_or(0, |scope| scope.len() + 1))
+                .map_or_else(|| "pub".to_string(), visibility_code);
+            use_statements.push(format!("{}{}", gate, format!("{} use {}::*;", visibility, module_name).trim_start()));
+        }
+        for use_statement in &use_statements[reexports_start..] {
+            decisions.push(teach::Decision {
+                subject: module_subject.clone(),
+                change: format!("is re-exported with `{}`", use_statement),
+                concept: teach::Concept::ReExports,
+            });
+        }
+    }
+
+    // The prelude re-exports its imports to the generated modules next to it, and no further
+    if let Some(prelude_name) = &prelude_name {
+        let used_imports: BTreeSet<usize> = prelude_names.iter().map(|name| imported_names[name]).collect();
+        let copied: Vec<String> = used_imports.into_iter().map(|index| copied_import(&imports[index], &prelude_names)).collect();
+        let mut reexports = Vec::new();
+        for import in import_style::arrange(merge_imports(copied), options.import_style) {
+            let file = syn::parse_file(&import).map_err(|e| format!("Failed to parse the copied import `{}`: {}", import, e))?;
+            for mut item in file.items {
+                if let Item::Use(item_use) = &mut item {
+                    item_use.vis = syn::parse_quote!(pub(super));
+                }
+                reexports.push(item.to_token_stream().to_string());
+            }
+        }
+        let mut generated_module_path = module_path.to_vec();
+        generated_module_path.push(prelude_name.clone());
+        let mut names: Vec<&String> = prelude_names.iter().collect();
+        names.sort();
+        decisions.push(teach::Decision {
+            subject: generated_module_path.join("::"),
+            change: format!(
+                "collects the imports nearly every generated module needs, `{}`, which they take with one `use {}::{}::*;`",
+                names.iter().map(|name| name.as_str()).collect::<Vec<_>>().join("`, `"),
+                parent_prefix,
+                prelude_name
+            ),
+            concept: teach::Concept::ReExports,
+        });
+        files.push(GeneratedFile {
+            path: output_dir.join(idents::module_file_name(prelude_name)),
+            code: format!("{}\n", format_glue(&reexports.join("\n")).trim_end()),
+            module_path: generated_module_path,
+            written_in: module_path.to_vec(),
+        });
+        child_trees.push(ModuleTree {
+            name: prelude_name.clone(),
+            visibility: "priv".to_string(),
+            item_count: 0,
+            children: Vec::new(),
+        });
+        mod_declarations.push(mod_declaration("", prelude_name));
+    }
+
+    // Imports only the moved functions used have nothing left to do here. Modules declared in
+    // files are read too, their code can use the imports through `super::`
+    let declared_files: Vec<String> = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Mod(item_mod) if item_mod.content.is_none() => {
+                tree::declared_module_file(item_mod, &item_mod.ident.unraw().to_string(), output_dir)
+            }
+            _ => None,
+        })
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .collect();
+    let imports = match &taken_by_modules {
+        Some(taken_names) => {
+            let kept_code: Vec<&String> = other_items.iter().chain(&main_function).chain(&declared_files).collect();
+            prune_imports(imports, &kept_code, taken_names, &parent_names.method_only_imports, options.no_std)
+        }
+        None => imports,
+    };
+
+    progress::clear();
+    Ok(RefactoredItems {
+        imports,
+        other_items,
+        mod_declarations,
+        use_statements,
+        main_function,
+        files,
+        moves,
+        smoke_targets,
+        plan: plan_entries,
+        decisions,
+        suggestions,
+        tree: ModuleTree {
+            name: "crate".to_string(),
+            visibility: String::new(),
+            item_count,
+            children: child_trees,
+        },
+    })
+}
+
+// Function to find every declaration of a function, its cfg twins included
+fn declarations_of<'a>(func_asts: &'a HashMap<String, ItemFn>, cfg_twins: &'a HashMap<String, Vec<(String, ItemFn, usize)>>, func_name: &String) -> Vec<&'a ItemFn> {
+    std::iter::once(&func_asts[func_name]).chain(cfg_twins.get(func_name).into_iter().flatten().map(|(_, twin, _)| twin)).collect()
+}
+
+// Function to tell whether the functions of a group stay where they are: the ones put in the stay
+// group, and the `general` ones when there are no others, rather than moving into a `general_mod`
+fn stays_in_place(group: &str, keeps_in_place: bool) -> bool {
+    group == STAY_GROUP || (group == "general" && keeps_in_place)
+}
+
+// Function to refactor the contents of an inline module, returning the code that replaces it
+fn refactor_inline_module(
+    source: &str,
+    item_mod: &ItemMod,
+    item_range: Range<usize>,
+    output_dir: &Path,
+    parent_module_path: &[String],
+    options: &Options,
+) -> Result<(String, RefactoredItems), String> {
+    let module_name = item_mod.ident.to_string();
+    let mut module_path = parent_module_path.to_vec();
+    module_path.push(module_name.clone());
+    let (brace, items) = item_mod.content.as_ref().expect("Inline module without content");
+    let body_start = brace.span.open().byte_range().end;
+    let items_start = attrs_end(&item_mod.attrs).map_or(body_start, |end| end.max(body_start));
+    let module_dir = output_dir.join(item_mod.ident.unraw().to_string());
+    let mut refactored = refactor_items(source, items, items_start, &module_dir, &module_path, options)?;
+    refactored.tree.name = module_name.clone();
+    refactored.tree.visibility = tree::visibility_label(&item_mod.vis);
+
+    // Outer attributes, doc comments and the visibility are kept exactly as written
+    let brace_start = brace.span.open().byte_range().start;
+    let declaration = source[item_range.start..brace_start].trim_end();
+    let declaration_indent = leading_indent(declaration);
+    let body_indent = items
+        .first()
+        .map(|item| {
+            let item_start = item.span().byte_range().start;
+            let line_start = source[..item_start].rfind('\n').map_or(0, |i| i + 1);
+            source[line_start..item_start].to_string()
+        })
+        .filter(|indent| indent.trim().is_empty() && indent.len() > declaration_indent.len())
+        .unwrap_or_else(|| format!("{}    ", declaration_indent));
+
+    // Inner attributes and comments at the top of the body stay at the top of the module
+    let mut module_code = String::new();
+    let prelude = trim_blank_lines(&source[body_start..items_start]);
+    if !prelude.is_empty() {
+        module_code.push_str(&prelude);
+        module_code.push_str("\n\n");
+    }
+    for code in refactored.imports.iter().chain(&refactored.other_items) {
+        module_code.push_str(code);
+        module_code.push_str("\n\n");
+    }
+
+    // Generated declarations are formatted and indented to match the rest of the body
+    for glue in [&refactored.mod_declarations, &refactored.use_statements] {
+        if !glue.is_empty() {
+            module_code.push_str(&indent_lines(&format_glue(&glue.join("\n")), &body_indent));
+            module_code.push_str("\n\n");
+        }
+    }
+
+    if options.promote_inline_mods {
+        // Promote the inline module to its own file next to the root
+        // A `#[path]` file keeps its children next to it, so a non-ASCII module goes in its own directory
+        let file_name = if module_name.is_ascii() {
+            idents::module_file_name(&module_name)
+        } else {
+            format!("{}/mod.rs", module_name)
+        };
+        refactored.files.push(GeneratedFile {
+            path: output_dir.join(&file_name),
+            code: format!("{}\n", dedent(&module_code, &body_indent).trim_end()),
+            written_in: module_path.clone(),
+            module_path,
+        });
+        if module_name.is_ascii() {
+            Ok((format!("{};", declaration), refactored))
+        } else {
+            Ok((format!("#[path = \"{}\"] {};", file_name, declaration), refactored))
+        }
+    } else {
+        let module_code = format!("{}\n{}}}", module_code.trim_end(), declaration_indent);
+        Ok((format!("{} {{\n{}", declaration, module_code), refactored))
+    }
+}
+
+// Function to check whether an inline module is a `#[cfg(test)]` module of unit tests
+fn is_test_module(item_mod: &ItemMod) -> bool {
+    item_mod.content.is_some()
+        && item_mod.attrs.iter().any(|attr| {
+            attr.path().is_ident("cfg") && attr.meta.require_list().is_ok_and(|list| list.tokens.to_string() == "test")
+        })
+}
+
+// Function to check whether a function is a test, including ones run by `#[tokio::test]` and the like
+fn is_test_function(func: &ItemFn) -> bool {
+    func.attrs
+        .iter()
+        .any(|attr| attr.path().segments.last().is_some_and(|segment| segment.ident == "test"))
+}
+
+// Function to check whether `tokens` mention any of `names`
+fn mentions_any(tokens: proc_macro2::TokenStream, names: &HashSet<String>) -> bool {
+    tokens.into_iter().any(|token| match token {
+        proc_macro2::TokenTree::Ident(ident) => names.contains(&ident.to_string()),
+        proc_macro2::TokenTree::Group(group) => mentions_any(group.stream(), names),
+        _ => false,
+    })
+}
+
+// Function to split the tests of a `#[cfg(test)]` module between the groups: a test calling
+// functions of just one group moves into that group's tests, and tests calling functions of
+// several groups (or using helpers of the module) stay behind
+fn split_test_module(
+    source: &str,
+    item_mod: &ItemMod,
+    item_range: Range<usize>,
+    assigned_categories: &HashMap<String, String>,
+    no_std: bool,
+) -> SplitTests {
+    let (brace, items) = item_mod.content.as_ref().expect("Inline module without content");
+    let body_start = brace.span.open().byte_range().end;
+    let items_start = attrs_end(&item_mod.attrs).map_or(body_start, |end| end.max(body_start));
+    let item_ranges = item_source_ranges(source, items, items_start);
+    let mut split = SplitTests {
+        remaining: None,
+        imports: Vec::new(),
+        moved: HashMap::new(),
+        used_imports: HashMap::new(),
+    };
+
+    let mut imported_names = HashMap::new();
+    let mut import_path_of = HashMap::new();
+    let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+    let mut import_indices = HashMap::new();
+    for (index, item) in items.iter().enumerate() {
+        if let Item::Use(use_item) = item {
+            for (name, path) in import_paths(use_item) {
+                if let Some(original) = renamed_from(&path) {
+                    aliases.entry(original.to_string()).or_default().push(name.clone());
+                }
+                import_path_of.insert(name.clone(), path);
+                imported_names.insert(name, split.imports.len());
+            }
+            import_indices.insert(index, split.imports.len());
+            split.imports.push(source[item_ranges[index].clone()].to_string());
+        }
+    }
+    let module_names: HashSet<String> = items
+        .iter()
+        .filter(|item| !matches!(item, Item::Use(_)))
+        .flat_map(declared_names)
+        .collect();
+
+    let mut staying = Vec::new();
+    let mut staying_imports = HashSet::new();
+    for (index, item) in items.iter().enumerate() {
+        if matches!(item, Item::Use(_)) {
+            continue;
+        }
+        let mut usage = CrateUsageVisitor {
+            imported_names: &imported_names,
+            aliases: &aliases,
+            used_imports: HashSet::new(),
+            local_names: Vec::new(),
+            trait_methods: Vec::new(),
+            import_paths: &import_path_of,
+            named_modules: HashSet::new(),
+            ambiguous_methods: Vec::new(),
+            unsure_traits: HashSet::new(),
+            no_std,
+        };
+        usage.visit_item(item);
+        usage.note_ambiguous_methods();
+
+        let group = match item {
+            Item::Fn(func) if is_test_function(func) && !mentions_any(func.block.to_token_stream(), &module_names) => {
+                let mut references = ReferenceVisitor {
+                    referenced_names: HashSet::new(),
+                    through_super: true,
+                };
+                references.visit_item_fn(func);
+                let groups: HashSet<&String> = references
+                    .referenced_names
+                    .iter()
+                    .filter_map(|name| assigned_categories.get(name))
+                    .collect();
+                (groups.len() == 1).then(|| groups.into_iter().next().cloned()).flatten().map(|group| (group, func))
+            }
+            _ => None,
+        };
+        match group {
+            Some((group, func)) => {
+                split
+                    .moved
+                    .entry(group.clone())
+                    .or_default()
+                    .push((func.sig.ident.to_string(), source[item_ranges[index].clone()].to_string()));
+                split.used_imports.entry(group).or_default().extend(usage.used_imports.iter().map(|name| imported_names[name]));
+            }
+            None => {
+                staying.push(index);
+                staying_imports.extend(usage.used_imports.iter().map(|name| imported_names[name]));
+            }
+        }
+    }
+
+    if split.moved.is_empty() {
+        split.remaining = Some(source[item_range].to_string());
+        return split;
+    }
+    if staying.is_empty() {
+        return split;
+    }
+
+    // What stays keeps its place, along with the imports it still uses and any glob imports
+    let brace_start = brace.span.open().byte_range().start;
+    let declaration = source[item_range.start..brace_start].trim_end();
+    let mut module_code = String::new();
+    let prelude = trim_blank_lines(&source[body_start..items_start]);
+    if !prelude.is_empty() {
+        module_code.push_str(&prelude);
+        module_code.push_str("\n\n");
+    }
+    for (index, item) in items.iter().enumerate() {
+        let keep = match item {
+            Item::Use(use_item) => has_glob(&use_item.tree) || staying_imports.contains(&import_indices[&index]),
+            _ => staying.contains(&index),
+        };
+        if keep {
+            module_code.push_str(&source[item_ranges[index].clone()]);
+            module_code.push_str("\n\n");
+        }
+    }
+    split.remaining = Some(format!("{} {{\n{}\n{}}}", declaration, module_code.trim_end(), leading_indent(declaration)));
+    split
+}
+
+// Function to write the tests moving into a group as the `tests` module of the group's file
+fn test_block(split: &SplitTests, group: &str, parent_import: &str, import_style: ImportStyle) -> String {
+    let mut block = "#[cfg(test)]\nmod tests {\n    use super::*;\n".to_string();
+    if !parent_import.is_empty() {
+        block.push_str(&format!("    {}\n", parent_import));
+    }
+    let mut used_imports: Vec<usize> = split.used_imports[group].iter().copied().collect();
+    used_imports.sort();
+    let copied = used_imports.into_iter().map(|index| dedent_item(&split.imports[index])).collect();
+    for import in import_style::arrange(merge_imports(copied), import_style) {
+        block.push_str(&indent_lines(&import, "    "));
+        block.push('\n');
+    }
+    for (_, test_code) in &split.moved[group] {
+        block.push('\n');
+        block.push_str(&indent_lines(&dedent_item(test_code), "    "));
+        block.push('\n');
+    }
+    block.push_str("}\n");
+    block
+}
+
+// Function to parse the command line arguments after the flags of the config file, naming what
+// is wrong with them when they don't make a run
+fn parse_args(args: &[String], defaults: &[String]) -> Result<Options, String> {
+    let mut options = Options::default();
+    // What the flags give that only decides the mode, or how the inputs are read
+    let mut positional = Vec::new();
+    let mut stdin_name = "main.rs".to_string();
+    let mut max_fn_lines = gate::DEFAULT_MAX_FN_LINES;
+    let mut format_name = None;
+    let mut graph_output = None;
+    let mut open = false;
+
+    let (command, args) = match args.first().map(String::as_str) {
+        Some(command) if cli::COMMANDS.iter().any(|known| known.name == command) => (command, &args[1..]),
+        _ => ("", args),
+    };
+
+    // A recipe sets the options up first, wherever it is given, so that the other flags change
+    // them. One given on the command line replaces that of the config file
+    let args: Vec<&String> = defaults.iter().chain(args).collect();
+    let recipe = match args.iter().rposition(|arg| *arg == "--recipe") {
+        Some(index) => {
+            let name = args.get(index + 1).ok_or("`--recipe` needs a value: <name>")?;
+            Some(recipe::find(name).ok_or_else(|| format!("there is no recipe `{}`; `refactor recipes` lists them", name))?)
+        }
+        None => None,
+    };
+    if let Some(recipe) = recipe {
+        options.strategies = strategy::parse(recipe.strategies, options.strategies)?;
+        options.min_module_items = recipe.min_module_items;
+        options.misc_module = recipe.misc_module.to_string();
+    }
+    options.recipe = recipe;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            positional.push(arg.clone());
+            continue;
+        }
+        // Which flags there are and which of them take a value is up to the table of cli
+        let flag = cli::find(arg).ok_or_else(|| cli::unknown_flag(arg))?;
+        let value = match flag.value {
+            Some(_) => cli::flag_value(flag, &mut args)?.clone(),
+            None => String::new(),
+        };
+        let invalid = || format!("`{}` takes {}, not `{}`", flag.name, flag.value.unwrap_or_default(), value);
+        match flag.name {
+            "--promote-inline-mods" => options.promote_inline_mods = true,
+            "--smoke-tests" => options.smoke_tests = true,
+            "--teach" => options.teach = true,
+            "--lossy" => options.lossy = true,
+            "--fix" => options.fix = true,
+            "--wait" => options.wait = true,
+            "--allow-api-changes" => options.allow_api_changes = true,
+            "--explicit-imports" => options.explicit_imports = true,
+            "--no-format" => options.no_format = true,
+            "--rustfmt-config" => options.rustfmt_config = Some(value),
+            "--edition" => options.forced_edition = Some(value.parse().map_err(|_| invalid())?),
+            "--dry-run" => options.dry_run = true,
+            "--diff" => options.diff = true,
+            "--check" => options.check = true,
+            "--interactive" => options.interactive = true,
+            "--stats" => options.stats = Some(value),
+            "-v" => options.log_level = options.log_level.more_verbose(),
+            "-vv" => options.log_level = options.log_level.more_verbose().more_verbose(),
+            "--log-level" => options.log_level = log::Level::parse(&value).ok_or_else(invalid)?,
+            "--open" => open = true,
+            "--in-place" => options.in_place = true,
+            "--stdin-name" => stdin_name = value,
+            "--inline-single-use" => options.inline_single_use = true,
+            "--plan" => options.plan_file = Some(value),
+            "--package" => options.package_dir = Some(value),
+            "--output-dir" => options.target_dir = Some(value),
+            "--like" => options.like_crate = Some(value),
+            "--strategy" => options.strategies = strategy::parse(&value, options.strategies).map_err(|e| format!("`--strategy {}`: {}", value, e))?,
+            "--exclude" => options.exclude.extend(value.split(',').map(str::trim).filter(|pattern| !pattern.is_empty()).map(String::from)),
+            // Read before the other flags, or by the time they are
+            "--recipe" | "--config" | "--no-config" | "--help" | "-h" => {}
+            // What a gate reports and what splitting a crate skips are the same size
+            "--max-file-lines" | "--min-lines" => options.max_file_lines = value.parse().map_err(|_| invalid())?,
+            "--min-fns" => options.min_fns = value.parse().map_err(|_| invalid())?,
+            "--min-inline-fns" => options.min_inline_fns = value.parse().map_err(|_| invalid())?,
+            "--max-fn-lines" => max_fn_lines = value.parse().map_err(|_| invalid())?,
+            "--format" => format_name = Some(value),
+            "--output" => graph_output = Some(value),
+            "--min-module-items" => options.min_module_items = value.parse().map_err(|_| invalid())?,
+            "--seed" => options.seed = value.parse().map_err(|_| invalid())?,
+            "--import-style" => options.import_style = ImportStyle::parse(&value).ok_or_else(invalid)?,
+            "--misc-module" => options.misc_module = value,
+            "--module-prefix" => {
+                // The prefix starts every module name, so it has to be able to start an identifier
+                let starts_ident = value.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+                if !starts_ident || !value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                    return Err(format!("`--module-prefix {}`: a prefix starts with a letter or `_` and holds only letters, digits and `_`", value));
+                }
+                options.module_prefix = Some(value);
+            }
+            "--emit" => match value.as_str() {
+                "files" => options.emit = Emit::Files,
+                "memory" => options.emit = Emit::Memory,
+                "git" => options.emit = Emit::Git,
+                "stdout" => options.emit = Emit::Stdout,
+                "json" => options.emit = Emit::Json,
+                _ => return Err(invalid()),
+            },
+            "--asm" => match value.as_str() {
+                "keep" => options.asm = AsmPolicy::Keep,
+                "group" => options.asm = AsmPolicy::Group,
+                "free" => options.asm = AsmPolicy::Free,
+                _ => return Err(invalid()),
+            },
+            "--verify" => match value.as_str() {
+                "warnings" => options.verify = Some(Verify::Warnings),
+                _ => return Err(invalid()),
+            },
+            name => unreachable!("the flag `{}` is listed without being parsed", name),
+        }
+    }
+
+    // `-` reads the input from stdin and prints what it becomes, as the file --stdin-name names
+    let stdin = positional.iter().any(|path| path == "-");
+    options.stdin = stdin;
+    if stdin {
+        positional.iter_mut().filter(|path| *path == "-").for_each(|path| *path = stdin_name.clone());
+        if options.emit == Emit::Files {
+            options.emit = Emit::Stdout;
+        }
+    }
+    let graph_format = match format_name.as_deref() {
+        Some(name) if command == "graph" => {
+            graph::Format::parse(name).ok_or_else(|| format!("`--format` of a graph takes json, graphml or structurizr, not `{}`", name))?
+        }
+        _ => graph::Format::Json,
+    };
+    (options.mode, options.input_file) = match (command, positional.as_slice()) {
+        ("tree", [path]) => (Mode::Tree, path.clone()),
+        ("god-objects", [path]) => (Mode::GodObjects, path.clone()),
+        ("audit-reexports", [path]) => (Mode::AuditReexports, path.clone()),
+        ("explain-error", [path]) => (Mode::ExplainError, path.clone()),
+        ("spans", [path]) => (Mode::Spans, path.clone()),
+        ("preview", [path]) => (Mode::Preview { open }, path.clone()),
+        ("recipes", []) => (Mode::Recipes, String::new()),
+        ("plan", [path]) => (Mode::Plan, path.clone()),
+        ("apply", [plan]) => {
+            options.plan_file = Some(plan.clone());
+            (Mode::Apply, String::new())
+        }
+        ("revert" | "undo", []) => (Mode::Revert, ".".to_string()),
+        ("revert" | "undo", [path]) => (Mode::Revert, path.clone()),
+        ("watch", [path]) => (Mode::Watch, path.clone()),
+        ("gate", [path]) => (Mode::Gate { max_file_lines: options.max_file_lines, max_fn_lines }, path.clone()),
+        ("graph", [path]) => (Mode::Graph { format: graph_format, output: graph_output }, path.clone()),
+        ("rename-mod", [old, new]) => (Mode::RenameMod { old: old.clone(), new: new.clone() }, ".".to_string()),
+        ("rename-mod", [old, new, path]) => (Mode::RenameMod { old: old.clone(), new: new.clone() }, path.clone()),
+        ("", [path]) if !batch::is_glob(path) => (Mode::Refactor, path.clone()),
+        ("", paths) if !paths.is_empty() && !stdin => (Mode::Batch { inputs: paths.to_vec() }, String::new()),
+        ("", []) => return Err(format!("no input given\n{}", cli::usage().trim_end())),
+        ("", _) => return Err("`-` is the only input of a run that reads stdin".to_string()),
+        (command, _) => {
+            let args = cli::COMMANDS.iter().find(|known| known.name == command).map_or("", |known| known.args);
+            return Err(format!("`refactor {}` takes {}", command, if args.is_empty() { "no arguments" } else { args }));
+        }
+    };
+
+    // Graphs have formats of their own, and everything else is reported as text or JSON
+    options.json_report = match (command, format_name.as_deref()) {
+        ("graph", _) | (_, None | Some("text")) => false,
+        (_, Some("json")) => true,
+        (_, Some(name)) => return Err(format!("`--format` takes text or json, not `{}`", name)),
+    };
+
+    // A check shows what it would write, as a diff when asked to
+    options.dry_run |= options.check && !options.diff;
+    Ok(options)
+}
+
+// Every warning of the run so far, for the JSON report to list
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+// Function to tell the user about something the run skipped or couldn't do, without stopping it
+fn warn(message: String) {
+    progress::clear();
+    if log::enabled(log::Level::Warn) {
+        eprintln!("Warning: {}", message);
+    }
+    WARNINGS.lock().expect("A warning was being written by a thread that panicked").push(message);
+}
+
+// Set from --no-format once the arguments are parsed, for the code to be written as generated
+static NO_FORMAT: OnceLock<bool> = OnceLock::new();
+
+// Function to check whether generated code is formatted at all
+fn formats_code() -> bool {
+    !NO_FORMAT.get().copied().unwrap_or(false)
+}
+
+// Function to format Rust code using `rustfmt`
+fn rustfmt_code(code: &str) -> String {
+    if !formats_code() {
+        return code.to_string();
+    }
+    // Standalone builds format with prettyplease and never start another process
+    #[cfg(feature = "standalone")]
+    if let Ok(file) = syn::parse_file(code) {
+        log::trace("format", format_args!("{} lines with prettyplease", code.lines().count()));
+        return prettyplease::unparse(&file);
+    }
+
+    // Keep the code as it is rather than losing it when rustfmt can't run or rejects it
+    let start = std::time::Instant::now();
+    match run_rustfmt(code) {
+        Some(formatted) => {
+            log::trace("format", format_args!("{} lines with rustfmt in {:.0?}", code.lines().count(), start.elapsed()));
+            formatted
+        }
+        None => {
+            log::debug("format", format_args!("rustfmt left {} lines unformatted", code.lines().count()));
+            code.to_string()
+        }
+    }
+}
+
+// Function to format code with rustfmt itself, or None when it can't be run or rejects the code
+fn run_rustfmt(code: &str) -> Option<String> {
+    if !formats_code() || !can_run_rustfmt() {
+        return None;
+    }
+    // rustfmt going away halfway, or never starting, leaves the code unformatted rather than
+    // ending the run
+    let mut child = Command::new("rustfmt")
+        .args(RUSTFMT_ARGS.get().map_or(&[][..], Vec::as_slice))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        use std::io::Write;
+        stdin.write_all(code.as_bytes()).ok()?;
+    }
+
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+// Finds the literal tables of an item: arrays of literals at least `LITERAL_TABLE_MIN_ELEMENTS`
+// long, also as the arguments of `vec!`
+#[derive(Default)]
+struct LiteralTableVisitor {
+    found: bool,
+}
+
+impl LiteralTableVisitor {
+    fn note(&mut self, elems: &Punctuated<Expr, Token![,]>) {
+        self.found |= elems.len() >= LITERAL_TABLE_MIN_ELEMENTS && elems.iter().all(is_literal_data);
+    }
+}
+
+impl Visit<'_> for LiteralTableVisitor {
+    fn visit_expr_array(&mut self, node: &syn::ExprArray) {
+        self.note(&node.elems);
+        syn::visit::visit_expr_array(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &Macro) {
+        if node.path.is_ident("vec") {
+            self.note(&macro_args(node));
+        }
+    }
+}
+
+// Function to check whether an expression is plain data: a literal, or arrays and tuples of them
+fn is_literal_data(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(_) => true,
+        Expr::Unary(unary) => matches!(unary.op, syn::UnOp::Neg(_)) && is_literal_data(&unary.expr),
+        Expr::Array(array) => array.elems.iter().all(is_literal_data),
+        Expr::Tuple(tuple) => tuple.elems.iter().all(is_literal_data),
+        Expr::Group(group) => is_literal_data(&group.expr),
+        _ => false,
+    }
+}
+
+// Function to mark an item holding literal tables with `#[rustfmt::skip]` when rustfmt would lay
+// it out differently: the tool moves it as written, and a later `cargo fmt` then keeps it that
+// way too. Nothing is marked when rustfmt can't tell, such as in standalone builds
+fn skip_literal_tables(code: &str) -> String {
+    let Ok(item) = syn::parse_str::<Item>(code) else {
+        return code.to_string();
+    };
+    if cfg!(feature = "standalone") || item_attrs(&item).is_some_and(has_rustfmt_skip) {
+        return code.to_string();
+    }
+    let mut visitor = LiteralTableVisitor::default();
+    visitor.visit_item(&item);
+    if !visitor.found || run_rustfmt(code).is_none_or(|formatted| formatted.trim_end() == code.trim_end()) {
+        return code.to_string();
+    }
+    // The attribute goes after the doc comments, in front of the other attributes
+    let mut marked = String::new();
+    let mut marking = true;
+    for line in code.split_inclusive('\n') {
+        if marking && !line.trim_start().starts_with("///") {
+            marked.push_str(&format!("{}#[rustfmt::skip]\n", leading_indent(line)));
+            marking = false;
+        }
+        marked.push_str(line);
+    }
+    marked
+}
+
+// Arguments every run of rustfmt gets, once the crate being split is known
+static RUSTFMT_ARGS: OnceLock<Vec<String>> = OnceLock::new();
+
+// Function to have rustfmt format code in the edition of the crate `input_path` belongs to, with
+// the rustfmt.toml named by --rustfmt-config or else the nearest one above the input, the way
+// `cargo fmt` would. prettyplease has no settings and formats standalone builds the same way
+fn configure_rustfmt(options: &Options, input_path: &Path) -> Result<(), String> {
+    let config = match &options.rustfmt_config {
+        Some(path) if !Path::new(path).is_file() => return Err(format!("The rustfmt config {:?} doesn't exist", path)),
+        Some(path) => Some(PathBuf::from(path)),
+        None => fs::canonicalize(input_path).ok().and_then(|path| {
+            path.ancestors()
+                .skip(1)
+                .flat_map(|dir| ["rustfmt.toml", ".rustfmt.toml"].map(|name| dir.join(name)))
+                .find(|path| path.is_file())
+        }),
+    };
+    let mut args = vec!["--edition".to_string(), options.edition.to_string()];
+    if let Some(config) = config {
+        args.push("--config-path".to_string());
+        args.push(config.display().to_string());
+    }
+    RUSTFMT_ARGS.set(args).map_err(|_| "rustfmt was configured twice".to_string())
+}
+
+// Function to check once whether rustfmt can be started here, since sandboxed CI and pre-commit
+// environments may not allow spawning processes at all
+fn can_run_rustfmt() -> bool {
+    static CAN_RUN_RUSTFMT: OnceLock<bool> = OnceLock::new();
+    *CAN_RUN_RUSTFMT.get_or_init(|| {
+        Command::new("rustfmt")
+            .arg("--version")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+// Function to format a snippet of generated glue code without a trailing newline
+fn format_glue(code: &str) -> String {
+    rustfmt_code(code).trim_end().to_string()
+}
+
+// Function to get the whitespace in front of the first line of `code`
+fn leading_indent(code: &str) -> &str {
+    &code[..code.len() - code.trim_start_matches([' ', '\t']).len()]
+}
+
+// Function to move an item extracted from a nested module to the top level of a new file
+fn dedent_item(code: &str) -> String {
+    dedent(code, leading_indent(code))
+}
+
+// Function to remove `indent` from the start of every line of `code` that carries it
+fn dedent(code: &str, indent: &str) -> String {
+    if indent.is_empty() {
+        return code.to_string();
+    }
+    code.lines()
+        .map(|line| line.strip_prefix(indent).unwrap_or(line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Function to add `indent` in front of every non-empty line of `code`
+fn indent_lines(code: &str, indent: &str) -> String {
+    code.lines()
+        .map(|line| if line.is_empty() { line.to_string() } else { format!("{}{}", indent, line) })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Function to drop the blank lines around `text` while keeping the indentation of its first line
+fn trim_blank_lines(text: &str) -> String {
+    text.lines()
+        .skip_while(|line| line.trim().is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_end()
+        .to_string()
+}
+
+// Function to check whether a function is the binary's entry point, including decorated
+// async mains like `#[tokio::main]`, whose attributes travel with the item's source
+fn is_entry_point(func: &ItemFn) -> bool {
+    func.sig.ident == "main"
+}
+
+// Function to write the path of an item of `module_path`, such as `net::read_file`
+fn item_path_of(module_path: &[String], name: &str) -> String {
+    module_path.iter().map(String::as_str).chain([name]).collect::<Vec<_>>().join("::")
+}
+
+// Function to find the first segment of an import's path, such as `serde` in `use serde::Serialize;`
+fn use_root_segment(tree: &UseTree) -> Option<String> {
+    match tree {
+        UseTree::Path(use_path) => Some(use_path.ident.to_string()),
+        _ => None,
+    }
+}
+
+// Function to list the names in scope an import starts from, such as `io` and `fmt` for
+// `use {io::Write, fmt};`, unless it starts from the extern prelude with `::`
+fn use_root_names(item_use: &syn::ItemUse) -> Vec<String> {
+    fn root_names(tree: &UseTree, names: &mut Vec<String>) {
+        match tree {
+            UseTree::Path(use_path) => names.push(use_path.ident.to_string()),
+            UseTree::Name(use_name) => names.push(use_name.ident.to_string()),
+            UseTree::Rename(use_rename) => names.push(use_rename.ident.to_string()),
+            UseTree::Group(group) => group.items.iter().for_each(|tree| root_names(tree, names)),
+            UseTree::Glob(_) => {}
+        }
+    }
+    let mut names = Vec::new();
+    if item_use.leading_colon.is_none() {
+        root_names(&item_use.tree, &mut names);
+    }
+    names
+}
+
+// Function to list the names an item declares in the scope it is written in
+fn declared_names(item: &Item) -> Vec<String> {
+    fn use_tree_names(tree: &UseTree, names: &mut Vec<String>) {
+        match tree {
+            UseTree::Path(use_path) => use_tree_names(&use_path.tree, names),
+            UseTree::Name(use_name) => names.push(use_name.ident.to_string()),
+            UseTree::Rename(use_rename) => names.push(use_rename.rename.to_string()),
+            UseTree::Group(group) => group.items.iter().for_each(|tree| use_tree_names(tree, names)),
+            UseTree::Glob(_) => {}
+        }
+    }
+
+    let ident = match item {
+        Item::Use(item_use) => {
+            let mut names = Vec::new();
+            use_tree_names(&item_use.tree, &mut names);
+            return names;
+        }
+        Item::Fn(item) => &item.sig.ident,
+        Item::Struct(item) => &item.ident,
+        Item::Enum(item) => &item.ident,
+        Item::Union(item) => &item.ident,
+        Item::Trait(item) => &item.ident,
+        Item::Type(item) => &item.ident,
+        Item::Const(item) => &item.ident,
+        Item::Static(item) => &item.ident,
+        Item::Mod(item) => &item.ident,
+        _ => return Vec::new(),
+    };
+    vec![ident.to_string()]
+}
+
+// Function to find the name an `extern crate` declares, such as `ml` for `extern crate mlog as ml;`
+fn extern_crate_name(item: &Item) -> Option<String> {
+    match item {
+        Item::ExternCrate(extern_crate) => Some(extern_crate.rename.as_ref().map_or(&extern_crate.ident, |(_, rename)| rename).to_string()),
+        _ => None,
+    }
+}
+
+// Function to list the names an import brings into scope, each with the path it imports, such as
+// `io` for `std::io` and `Read` for `std::io::Read` in `use std::{fs, io::{self, Read}};`. What a
+// glob brings in isn't known, so globs add nothing
+fn import_paths(item_use: &syn::ItemUse) -> Vec<(String, String)> {
+    fn walk(tree: &UseTree, prefix: &mut Vec<String>, paths: &mut Vec<(String, String)>) {
+        match tree {
+            UseTree::Path(use_path) => {
+                prefix.push(use_path.ident.to_string());
+                walk(&use_path.tree, prefix, paths);
+                prefix.pop();
+            }
+            // `use std::io::{self}` brings `io` into scope
+            UseTree::Name(use_name) if use_name.ident == "self" => {
+                if let Some(parent) = prefix.last() {
+                    paths.push((parent.clone(), prefix.join("::")));
+                }
+            }
+            UseTree::Name(use_name) => {
+                prefix.push(use_name.ident.to_string());
+                paths.push((use_name.ident.to_string(), prefix.join("::")));
+                prefix.pop();
+            }
+            UseTree::Rename(use_rename) => {
+                if use_rename.ident != "self" {
+                    prefix.push(use_rename.ident.to_string());
+                }
+                if let Some(original) = prefix.last() {
+                    paths.push((renamed_name(original, &use_rename.rename), format!("{} as {}", prefix.join("::"), use_rename.rename)));
+                }
+                if use_rename.ident != "self" {
+                    prefix.pop();
+                }
+            }
+            UseTree::Group(group) => group.items.iter().for_each(|tree| walk(tree, prefix, paths)),
+            UseTree::Glob(_) => {}
+        }
+    }
+
+    // `use ::std::fs;` keeps its leading `::`
+    let mut prefix = if item_use.leading_colon.is_some() { vec![String::new()] } else { Vec::new() };
+    let mut paths = Vec::new();
+    walk(&item_use.tree, &mut prefix, &mut paths);
+    paths
+}
+
+// Function to find the names the glob imports among `items` bring in, for globs of the modules and
+// enums declared there, such as `use helpers::*;` for a `mod helpers` next to it. None when a glob
+// imports from elsewhere, or from a module whose names aren't all known
+fn glob_import_names(items: &[Item], module_dir: &Path) -> Option<HashSet<String>> {
+    fn globs(tree: &UseTree, prefix: &mut Vec<String>, paths: &mut Vec<Vec<String>>) {
+        match tree {
+            UseTree::Path(use_path) => {
+                prefix.push(use_path.ident.to_string());
+                globs(&use_path.tree, prefix, paths);
+                prefix.pop();
+            }
+            UseTree::Group(group) => group.items.iter().for_each(|tree| globs(tree, prefix, paths)),
+            UseTree::Glob(_) => paths.push(prefix.clone()),
+            UseTree::Name(_) | UseTree::Rename(_) => {}
+        }
+    }
+
+    let mut names = HashSet::new();
+    for item in items {
+        if let Item::Use(item_use) = item {
+            let mut paths = Vec::new();
+            globs(&item_use.tree, &mut Vec::new(), &mut paths);
+            if item_use.leading_colon.is_some() && !paths.is_empty() {
+                return None;
+            }
+            for path in paths {
+                names.extend(module_names_at(items, &path, module_dir)?);
+            }
+        }
+    }
+    Some(names)
+}
+
+// Function to list the names the module or enum at `path` below `items` lets a glob import,
+// reading the file of a `mod foo;` from `module_dir`
+fn module_names_at(items: &[Item], path: &[String], module_dir: &Path) -> Option<HashSet<String>> {
+    let Some((first, rest)) = path.split_first() else {
+        let mut names = HashSet::new();
+        for item in items {
+            let visibility = match item {
+                Item::Use(item_use) if has_glob(&item_use.tree) && !matches!(item_use.vis, syn::Visibility::Inherited) => return None,
+                // Item macros may define anything
+                Item::Macro(item_macro) if item_macro.ident.is_none() => return None,
+                Item::Use(item) => &item.vis,
+                Item::Fn(item) => &item.vis,
+                Item::Struct(item) => &item.vis,
+                Item::Enum(item) => &item.vis,
+                Item::Union(item) => &item.vis,
+                Item::Trait(item) => &item.vis,
+                Item::Type(item) => &item.vis,
+                Item::Const(item) => &item.vis,
+                Item::Static(item) => &item.vis,
+                Item::Mod(item) => &item.vis,
+                _ => continue,
+            };
+            if !matches!(visibility, syn::Visibility::Inherited) {
+                names.extend(declared_names(item));
+            }
+        }
+        return Some(names);
+    };
+    if first == "self" {
+        return module_names_at(items, rest, module_dir);
+    }
+    for item in items {
+        match item {
+            Item::Mod(item_mod) if item_mod.ident == first => {
+                let file_name = item_mod.ident.unraw().to_string();
+                return match &item_mod.content {
+                    Some((_, items)) => module_names_at(items, rest, &module_dir.join(&file_name)),
+                    None => {
+                        let file = tree::declared_module_file(item_mod, &file_name, module_dir)?;
+                        let syntax_tree = syn::parse_file(&fs::read_to_string(&file).ok()?).ok()?;
+                        module_names_at(&syntax_tree.items, rest, &module_dir.join(&file_name))
+                    }
+                };
+            }
+            Item::Enum(item_enum) if item_enum.ident == first && rest.is_empty() => {
+                return Some(item_enum.variants.iter().map(|variant| variant.ident.to_string()).collect());
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Function to name what a renamed import brings into scope. Imports renamed to `_` bring in no
+// name, only the methods of a trait, so each gets a name of its own that no code can mention,
+// such as `Read as _`
+fn renamed_name(original: &str, rename: &Ident) -> String {
+    if rename == "_" {
+        format!("{} as _", original)
+    } else {
+        rename.to_string()
+    }
+}
+
+// Function to find which name a renamed import renames from its path, such as `Read` for
+// `std::io::Read as _`
+fn renamed_from(path: &str) -> Option<&str> {
+    let (path, _) = path.split_once(" as ")?;
+    path.rsplit("::").next()
+}
+
+// Function to list the names the imports among `import_codes` bring into scope
+fn import_leaves<'a>(import_codes: impl Iterator<Item = &'a String>) -> HashSet<String> {
+    import_codes
+        .filter_map(|import_code| syn::parse_str::<syn::ItemUse>(import_code).ok())
+        .flat_map(|item_use| import_paths(&item_use))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+// Function to copy an import into a generated module: as written when the module uses all of its
+// names, or else as one import per name used out of it, keeping its attributes
+fn copied_import(import_code: &str, used_names: &HashSet<String>) -> String {
+    let Ok(item_use) = syn::parse_str::<syn::ItemUse>(import_code) else {
+        return dedent_item(import_code);
+    };
+    let paths = import_paths(&item_use);
+    // Imports rustfmt is told to leave alone are copied as written as well
+    if has_glob(&item_use.tree) || has_rustfmt_skip(&item_use.attrs) || paths.iter().all(|(name, _)| used_names.contains(name)) {
+        return dedent_item(import_code);
+    }
+    let attrs: String = item_use.attrs.iter().map(|attr| format!("{} ", attr.to_token_stream())).collect();
+    let imports: Vec<String> = paths
+        .iter()
+        .filter(|(name, _)| used_names.contains(name))
+        .map(|(_, path)| format!("{}use {};", attrs, path))
+        .collect();
+    format_glue(&imports.join("\n"))
+}
+
+// Function to merge the imports a generated module copies when they overlap, such as
+// `use std::io;` copied from one import and `use std::io::{self, Read};` from another, which
+// would import `io` twice. Imports sharing a path are unified into one list without duplicates,
+// and imports overlapping with none are kept as written
+fn merge_imports(imports: Vec<String>) -> Vec<String> {
+    // Every name imported, as the import's attributes and visibility, the path leading to the name
+    // and the name as written there, such as `Read as R` or `*`
+    type Leaf = (String, Vec<String>, String);
+    type ImportPath = (String, Vec<String>);
+    fn walk(tree: &UseTree, prefix: &mut Vec<String>, key: &str, leaves: &mut Vec<Leaf>) {
+        match tree {
+            UseTree::Path(use_path) => {
+                prefix.push(use_path.ident.to_string());
+                walk(&use_path.tree, prefix, key, leaves);
+                prefix.pop();
+            }
+            // `use std::io::{self}` is `use std::io`
+            UseTree::Name(use_name) if use_name.ident == "self" && !prefix.is_empty() => {
+                let mut parent = prefix.clone();
+                let name = parent.pop().unwrap_or_default();
+                leaves.push((key.to_string(), parent, name));
+            }
+            UseTree::Rename(use_rename) if use_rename.ident == "self" && !prefix.is_empty() => {
+                let mut parent = prefix.clone();
+                let name = parent.pop().unwrap_or_default();
+                leaves.push((key.to_string(), parent, format!("{} as {}", name, use_rename.rename)));
+            }
+            UseTree::Group(group) => group.items.iter().for_each(|tree| walk(tree, prefix, key, leaves)),
+            _ => leaves.push((key.to_string(), prefix.clone(), tree.to_token_stream().to_string())),
+        }
+    }
+
+    // The leaves of each `use` statement, by the import it was copied as
+    let mut statements: Vec<(usize, Vec<Leaf>)> = Vec::new();
+    for (index, import) in imports.iter().enumerate() {
+        // A copied import may hold several `use` lines, or none that parse
+        let Ok(file) = syn::parse_file(import) else {
+            return imports;
+        };
+        for item in &file.items {
+            let Item::Use(item_use) = item else {
+                return imports;
+            };
+            if has_rustfmt_skip(&item_use.attrs) {
+                return imports;
+            }
+            let attrs: String = item_use.attrs.iter().map(|attr| format!("{} ", attr.to_token_stream())).collect();
+            let key = format!("{}{} use {}", attrs, item_use.vis.to_token_stream(), if item_use.leading_colon.is_some() { "::" } else { "" });
+            let mut statement = Vec::new();
+            walk(&item_use.tree, &mut Vec::new(), &key, &mut statement);
+            statements.push((index, statement));
+        }
+    }
+
+    // Imports overlap when they import from the same path, or one imports the module the other
+    // imports from
+    let overlaps = |a: &[Leaf], b: &[Leaf]| {
+        a.iter().any(|(key, prefix, name)| {
+            b.iter().any(|(other_key, other_prefix, other_name)| {
+                key == other_key
+                    && ((prefix == other_prefix && (!prefix.is_empty() || name == other_name))
+                        || other_prefix.split_last().is_some_and(|(last, parent)| parent == prefix.as_slice() && last == name)
+                        || prefix.split_last().is_some_and(|(last, parent)| parent == other_prefix.as_slice() && last == other_name))
+            })
+        })
+    };
+    let mut touched = vec![false; imports.len()];
+    for (i, (import, leaves)) in statements.iter().enumerate() {
+        touched[*import] |= statements.iter().enumerate().any(|(j, (_, other))| i != j && overlaps(leaves, other));
+    }
+    if !touched.contains(&true) {
+        return imports;
+    }
+
+    // The names imported from each path, in the order they were first imported
+    let mut paths: Vec<(ImportPath, Vec<String>)> = Vec::new();
+    for (key, prefix, name) in statements.iter().filter(|(import, _)| touched[*import]).flat_map(|(_, leaves)| leaves.iter().cloned()) {
+        match paths.iter_mut().find(|(path, _)| path.0 == key && path.1 == prefix) {
+            Some((_, names)) if names.contains(&name) => {}
+            Some((_, names)) => names.push(name),
+            None => paths.push(((key, prefix), vec![name])),
+        }
+    }
+    // A module imported alongside names from inside it becomes the `self` of their list
+    for index in 0..paths.len() {
+        let ((key, prefix), _) = &paths[index];
+        let Some((last, parent)) = prefix.split_last() else {
+            continue;
+        };
+        let (key, last, parent) = (key.clone(), last.clone(), parent.to_vec());
+        if let Some((_, names)) = paths.iter_mut().find(|(path, _)| path.0 == key && path.1 == parent) {
+            if let Some(position) = names.iter().position(|name| *name == last) {
+                names.remove(position);
+                paths[index].1.insert(0, "self".to_string());
+            }
+        }
+    }
+
+    let mut merged = Vec::new();
+    for ((key, prefix), names) in paths.into_iter().filter(|(_, names)| !names.is_empty()) {
+        let path: Vec<String> = prefix.iter().cloned().chain([String::new()]).collect();
+        let statements: Vec<String> = match names.as_slice() {
+            [name] if name == "self" => vec![format!("{}{};", key, prefix.join("::"))],
+            [name] => vec![format!("{}{}{};", key, path.join("::"), name)],
+            _ if prefix.is_empty() => names.iter().map(|name| format!("{}{};", key, name)).collect(),
+            _ => vec![format!("{}{}{{{}}};", key, path.join("::"), names.join(", "))],
+        };
+        merged.extend(statements.iter().map(|statement| format_glue(statement.trim_start())));
+    }
+
+    // The merged imports take the place of the first import they replace
+    let first_touched = touched.iter().position(|touched| *touched).unwrap_or(0);
+    let mut result = Vec::new();
+    for (index, import) in imports.into_iter().enumerate() {
+        if index == first_touched {
+            result.append(&mut merged);
+        }
+        if !touched[index] {
+            result.push(import);
+        }
+    }
+    result
+}
+
+// Function to check whether an import uses a glob, such as `use std::io::*;`
+fn has_glob(tree: &UseTree) -> bool {
+    match tree {
+        UseTree::Path(use_path) => has_glob(&use_path.tree),
+        UseTree::Group(group) => group.items.iter().any(has_glob),
+        UseTree::Glob(_) => true,
+        UseTree::Name(_) | UseTree::Rename(_) => false,
+    }
+}
+
+// Function to find what generated modules can take from the module `items` are split out of
+// with `glob_names` the names its glob imports bring in, if they are all known
+fn parent_names(
+    items: &[Item],
+    func_asts: &HashMap<String, ItemFn>,
+    glob_names: Option<HashSet<String>>,
+    is_root: bool,
+    no_std: bool,
+    edition: u16,
+) -> ParentNames {
+    let mut usage = NameUsageVisitor::default();
+    let mut import_paths_of_parent = Vec::new();
+    let mut parent = ParentNames {
+        declared: func_asts.keys().cloned().collect(),
+        traits: Vec::new(),
+        method_only_imports: HashSet::new(),
+        opaque: false,
+    };
+    for item in items {
+        match item {
+            Item::Use(item_use) => {
+                parent.opaque |= has_glob(&item_use.tree) && glob_names.is_none();
+                import_paths_of_parent.extend(import_paths(item_use));
+            }
+            // `macro_rules!` only defines a macro, which moved code finds by textual scope, and
+            // `global_asm!` defines no names at all
+            Item::Macro(item_macro) if item_macro.ident.is_none() && !is_placement_sensitive(item) => parent.opaque = true,
+            Item::ForeignMod(foreign_mod) => {
+                parent.declared.extend(foreign_mod.items.iter().filter_map(|foreign_item| match foreign_item {
+                    ForeignItem::Fn(foreign_fn) => Some(foreign_fn.sig.ident.to_string()),
+                    ForeignItem::Static(foreign_static) => Some(foreign_static.ident.to_string()),
+                    _ => None,
+                }));
+                usage.visit_item(item);
+            }
+            Item::Trait(item_trait) => {
+                let methods = item_trait.items.iter().filter_map(|trait_item| match trait_item {
+                    syn::TraitItem::Fn(method) => Some(method.sig.ident.to_string()),
+                    _ => None,
+                });
+                parent.traits.push((item_trait.ident.to_string(), methods.collect()));
+                usage.visit_item(item);
+            }
+            _ => usage.visit_item(item),
+        }
+        parent.declared.extend(declared_names(item).into_iter().filter(|_| !matches!(item, Item::Use(_))));
+        // Below the crate root, `extern crate` only names the crate in its own module
+        parent.declared.extend(extern_crate_name(item).filter(|_| !is_root));
+    }
+
+    // Known traits are taken by the modules calling their methods, and other imports no path
+    // names are probably traits too, with methods nobody knows
+    let alloc_traits = if no_std { ALLOC_TRAIT_METHODS } else { &[] };
+    for (name, path) in import_paths_of_parent {
+        // Renamed traits are known by the name they rename, such as `Write` for `Write as W`
+        let original = renamed_from(&path).unwrap_or(&name);
+        let known_trait = KNOWN_TRAIT_METHODS.iter().chain(alloc_traits).find(|(trait_name, _)| *trait_name == original);
+        if let Some((_, methods)) = known_trait {
+            parent.traits.push((name.clone(), methods.iter().map(|method| method.to_string()).collect()));
+        } else if in_prelude(&path, edition, no_std) {
+            // What the prelude has, such as `Vec` or `TryInto` from 2021 on, is in scope everywhere
+            continue;
+        } else if (name.ends_with(" as _") || name.starts_with(char::is_uppercase)) && !usage.names.contains(&name) {
+            parent.method_only_imports.insert(name.clone());
+        }
+        parent.declared.insert(name);
+    }
+    parent.declared.extend(glob_names.into_iter().flatten());
+    // Macros defined here stay in front of the generated modules, which have them in textual
+    // scope already; importing them too would make them ambiguous
+    for name in macro_rules_names(items) {
+        parent.declared.remove(&name);
+    }
+    parent
 }
 
-impl<'a> Visit<'_> for CrateUsageVisitor<'a> {
-    fn visit_expr_path(&mut self, node: &ExprPath) {
-        if let Some(segment) = node.path.segments.first() {
-            let func_name = segment.ident.to_string();
-            if let Some(crate_name) = self.imported_functions.get(&func_name) {
-                self.used_crates.insert(crate_name.clone());
+// Function to check whether an import names one of the `macro_rules!` macros defined next to it
+fn imports_local_macro(item_use: &syn::ItemUse, local_macros: &HashSet<String>) -> bool {
+    item_use.leading_colon.is_none()
+        && import_paths(item_use).iter().any(|(_, path)| {
+            let root = path.split("::").next().unwrap_or_default();
+            local_macros.contains(root.split(" as ").next().unwrap_or_default())
+        })
+}
+
+// Function to list the names of the `macro_rules!` macros defined among `items`
+fn macro_rules_names(items: &[Item]) -> HashSet<String> {
+    items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Macro(item_macro) => item_macro.ident.as_ref().map(|ident| ident.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
+// Function to spell out what a generated module takes from its parent with a glob: the names its
+// code uses without declaring, binding or importing them itself, and that aren't in the prelude,
+// along with the traits whose methods it may call. An opaque parent may hold names the tool can't
+// see, so there the names it doesn't know are taken too. None when a trait imported as `_` is
+// needed, which only a glob can take
+fn explicit_parent_names(
+    usage: &NameUsageVisitor,
+    parent: &ParentNames,
+    own_names: &HashSet<String>,
+    copied_names: &HashSet<String>,
+    local_macros: &HashSet<String>,
+    options: &Options,
+) -> Option<Vec<String>> {
+    let prelude: HashSet<&str> = PRELUDE_ITEMS
+        .iter()
+        .filter(|(path, _, _)| in_prelude(&format!("core::{}", path), options.edition, options.no_std))
+        .filter_map(|(path, _, _)| path.rsplit("::").next())
+        .collect();
+    let mut names: BTreeSet<String> = usage
+        .used_names()
+        .filter(|name| !own_names.contains(*name) && !copied_names.contains(*name) && !usage.bound_names.contains(*name))
+        .filter(|name| !prelude.contains(name.as_str()) && !BUILTIN_NAMES.contains(&name.as_str()))
+        .filter(|name| !STD_MACROS.contains(&name.as_str()) && !local_macros.contains(*name))
+        .filter(|name| parent.declared.contains(*name) || parent.opaque)
+        .cloned()
+        .collect();
+    if !usage.methods.is_empty() {
+        names.extend(parent.method_only_imports.iter().filter(|name| !copied_names.contains(*name)).cloned());
+    }
+    for (trait_name, methods) in &parent.traits {
+        if methods.iter().any(|method| usage.methods.contains(method)) && !copied_names.contains(trait_name) {
+            names.insert(trait_name.clone());
+        }
+    }
+    if names.iter().any(|name| name.ends_with(" as _")) {
+        return None;
+    }
+    Some(names.into_iter().collect())
+}
+
+// Function to list the names code with `usage` takes from the parent, leaving out the ones the
+// module declares or imports itself, or None when only a glob can be sure to take them all
+fn taken_parent_names(
+    usage: &NameUsageVisitor,
+    parent: &ParentNames,
+    own_names: &HashSet<String>,
+    copied_names: &HashSet<String>,
+) -> Option<Vec<String>> {
+    let method_only_imports = parent.method_only_imports.iter().any(|name| !copied_names.contains(name));
+    if parent.opaque || (method_only_imports && !usage.methods.is_empty()) {
+        return None;
+    }
+    let mut names: BTreeSet<&String> = usage
+        .used_names()
+        .filter(|name| parent.declared.contains(*name))
+        .collect();
+    // Traits of the parent whose methods are called, which no path names
+    for (trait_name, methods) in &parent.traits {
+        if methods.iter().any(|method| usage.methods.contains(method)) {
+            // A trait imported as `_` has no name to take it by, so only a glob takes it along,
+            // unless the module already has the trait under another name
+            let other_name = parent.traits.iter().any(|(other, other_methods)| {
+                other != trait_name && other_methods == methods && (copied_names.contains(other) || usage.names.contains(other))
+            });
+            if trait_name.ends_with(" as _") && !copied_names.contains(trait_name) {
+                if other_name {
+                    continue;
+                }
+                return None;
             }
+            names.insert(trait_name);
         }
-        syn::visit::visit_expr_path(self, node);
     }
+    Some(
+        names
+            .into_iter()
+            .filter(|name| !own_names.contains(*name) && !copied_names.contains(*name))
+            .cloned()
+            .collect(),
+    )
 }
 
-fn main() {
-    // Get command line arguments for input file
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: refactor <input_file>");
-        return;
+// Function to add the names a generated module imports from its parent to the ones all of them
+// import, or to give up on knowing when it imports everything with a glob
+fn note_taken_names(taken_by_modules: &mut Option<HashSet<String>>, taken_names: Option<&Vec<String>>) {
+    match (taken_by_modules.as_mut(), taken_names) {
+        (Some(taken_by_modules), Some(taken_names)) => taken_by_modules.extend(taken_names.iter().cloned()),
+        _ => *taken_by_modules = None,
     }
+}
 
-    let file_path = &args[1];
-    let content = fs::read_to_string(file_path).expect("Failed to read the file");
-    let input_path = Path::new(file_path);
-    let output_dir = input_path.parent().expect("Failed to get parent directory");
+// Function to drop the imports nothing in `kept_code` (or a generated module, through `taken_names`)
+// uses. Grouped imports lose the names nobody uses, and every import stays when `kept_code` takes
+// them all with a `crate::*` glob
+fn prune_imports(
+    imports: Vec<String>,
+    kept_code: &[&String],
+    taken_names: &HashSet<String>,
+    method_only_imports: &HashSet<String>,
+    no_std: bool,
+) -> Vec<String> {
+    let mut idents = HashSet::new();
+    for code in kept_code {
+        // A `use super::*;` only takes the names its module goes on to mention, but `crate::*` may be
+        // in a module too deep down to read
+        if code.split_whitespace().collect::<String>().contains("crate::*") {
+            return imports;
+        }
+        match code.parse::<proc_macro2::TokenStream>() {
+            Ok(tokens) => collect_idents(tokens, &mut idents),
+            Err(_) => return imports,
+        }
+    }
+    // `write!` and `writeln!` call `write_fmt`
+    if idents.contains("write") || idents.contains("writeln") {
+        idents.insert("write_fmt".to_string());
+    }
 
-    // Step 1: Parse the Rust source file into an AST
-    let syntax_tree: File = syn::parse_file(&content).expect("Unable to parse file");
+    let alloc_traits = if no_std { ALLOC_TRAIT_METHODS } else { &[] };
+    // Renamed imports are used by their new name, but known traits by the name they rename
+    let is_used = |name: &str, original: &str| {
+        idents.contains(name)
+            || taken_names.contains(name)
+            // Traits may be imported for their methods alone, which only the known ones list
+            || method_only_imports.contains(name)
+            || KNOWN_TRAIT_METHODS
+                .iter()
+                .chain(alloc_traits)
+                .any(|(trait_name, methods)| *trait_name == original && methods.iter().any(|method| idents.contains(*method)))
+    };
 
-    // Step 2: Analyze the AST and group logic based on dependencies and control flow
-    let mut imported_functions = HashMap::new();
-    let mut functions = HashMap::new();
-    let mut main_function = None;
-    let mut other_items = Vec::new(); // Collect other items like constants, types, etc.
+    let mut pruned = Vec::new();
+    for import in imports {
+        // `extern crate` and re-exports stay as they are
+        let Ok(item_use) = syn::parse_str::<syn::ItemUse>(&import) else {
+            pruned.push(import);
+            continue;
+        };
+        if !matches!(item_use.vis, syn::Visibility::Inherited) {
+            pruned.push(import);
+            continue;
+        }
+        match prune_use_tree(&item_use.tree, None, &is_used) {
+            Some(tree) if tree.to_token_stream().to_string() == item_use.tree.to_token_stream().to_string() => pruned.push(import),
+            // What rustfmt is told to leave alone isn't rewritten either
+            Some(_) if has_rustfmt_skip(&item_use.attrs) => pruned.push(import),
+            Some(tree) => {
+                let item_use = syn::ItemUse { tree, ..item_use };
+                pruned.push(format_glue(&item_use.to_token_stream().to_string()));
+            }
+            None => {}
+        }
+    }
+    pruned
+}
 
-    for item in &syntax_tree.items {
-        match item {
-            Item::Use(use_item) => {
-                // Collect crate usage and the functions imported from each crate
-                if let UseTree::Path(use_path) = &use_item.tree {
-                    let crate_name = use_path.ident.to_string();
-                    imported_functions.insert(crate_name.clone(), item_to_string(use_item));
-                }
+// Function to remove the names `is_used` rejects from an import, or None when none is left
+fn prune_use_tree(tree: &UseTree, parent: Option<&Ident>, is_used: &dyn Fn(&str, &str) -> bool) -> Option<UseTree> {
+    match tree {
+        UseTree::Path(use_path) => {
+            let pruned = prune_use_tree(&use_path.tree, Some(&use_path.ident), is_used)?;
+            Some(UseTree::Path(syn::UsePath { tree: Box::new(pruned), ..use_path.clone() }))
+        }
+        // `use std::io::{self}` brings `io` into scope
+        UseTree::Name(use_name) if use_name.ident == "self" => {
+            parent.filter(|parent| is_used(&parent.to_string(), &parent.to_string())).map(|_| tree.clone())
+        }
+        UseTree::Name(use_name) => is_used(&use_name.ident.to_string(), &use_name.ident.to_string()).then(|| tree.clone()),
+        UseTree::Rename(use_rename) => {
+            let original = if use_rename.ident == "self" { parent?.to_string() } else { use_rename.ident.to_string() };
+            is_used(&renamed_name(&original, &use_rename.rename), &original).then(|| tree.clone())
+        }
+        UseTree::Glob(_) => Some(tree.clone()),
+        UseTree::Group(group) => {
+            let items: Punctuated<UseTree, Token![,]> = group.items.iter().filter_map(|tree| prune_use_tree(tree, parent, is_used)).collect();
+            if items.len() == group.items.len() {
+                return Some(tree.clone());
             }
-            Item::Fn(func) => {
-                // Collect functions to group them later by name
-                let func_name = func.sig.ident.to_string();
-                if func_name == "main" {
-                    main_function = Some(item_to_string(func));
-                } else {
-                    functions.insert(func_name.clone(), item_to_string(func));
+            match items.len() {
+                0 => None,
+                // A group left with one name is written without braces, as in `use std::fs;`
+                1 if !matches!(&items[0], UseTree::Name(use_name) if use_name.ident == "self") => items.into_iter().next(),
+                _ => Some(UseTree::Group(syn::UseGroup { items, ..group.clone() })),
+            }
+        }
+    }
+}
+
+// Function to collect every identifier among `tokens`, and the names format strings capture
+fn collect_idents(tokens: proc_macro2::TokenStream, idents: &mut HashSet<String>) {
+    for token in tokens {
+        match token {
+            proc_macro2::TokenTree::Ident(ident) => {
+                idents.insert(ident.to_string());
+            }
+            proc_macro2::TokenTree::Group(group) => collect_idents(group.stream(), idents),
+            proc_macro2::TokenTree::Literal(literal) => {
+                if let Ok(syn::Lit::Str(text)) = syn::parse_str::<syn::Lit>(&literal.to_string()) {
+                    idents.extend(format_captures(&text.value()));
                 }
             }
-            _ => {
-                // Collect all other items (constants, types, etc.)
-                other_items.push(item_to_string(item));
+            proc_macro2::TokenTree::Punct(_) => {}
+        }
+    }
+}
+
+// Function to find the names a format string captures, such as `name` in `"{name:>8}"`
+fn format_captures(text: &str) -> Vec<String> {
+    let mut captures = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        rest = &rest[start + 1..];
+        if let Some(escaped) = rest.strip_prefix('{') {
+            rest = escaped;
+            continue;
+        }
+        let end = rest.find(['}', ':']).unwrap_or(rest.len());
+        let name = &rest[..end];
+        if name.starts_with(|c: char| c.is_alphabetic() || c == '_') && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            captures.push(name.to_string());
+        }
+    }
+    captures
+}
+
+// Function to parse the arguments of a macro call as comma separated expressions, which is
+// what most macros in practice take; anything else yields no expressions
+fn macro_args(mac: &Macro) -> Punctuated<Expr, Token![,]> {
+    Punctuated::<Expr, Token![,]>::parse_terminated
+        .parse2(mac.tokens.clone())
+        .unwrap_or_default()
+}
+
+// Function to find the names starting paths and macro calls among tokens that don't parse as
+// expressions, such as the `shout` of `vec![shout!("a"); 2]` or the `fmt` of `fmt::Debug`
+fn macro_token_paths(tokens: proc_macro2::TokenStream) -> Vec<String> {
+    let tokens: Vec<TokenTree> = tokens.into_iter().collect();
+    let mut names = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            TokenTree::Ident(ident) => {
+                let after = |offset: usize, c: char| matches!(tokens.get(index + offset), Some(TokenTree::Punct(punct)) if punct.as_char() == c);
+                let follows_path = index > 0 && matches!(&tokens[index - 1], TokenTree::Punct(punct) if matches!(punct.as_char(), ':' | '.' | '$'));
+                // `a != b` is no macro call
+                let is_call = after(1, '!') && !after(2, '=');
+                if !follows_path && (is_call || (after(1, ':') && after(2, ':'))) {
+                    names.push(ident.to_string());
+                }
             }
+            TokenTree::Group(group) => names.extend(macro_token_paths(group.stream())),
+            _ => {}
         }
     }
+    names
+}
 
-    // Step 3: Group functions into modules based on functionality keywords
-    let mut grouped_functions: HashMap<String, Vec<(String, String)>> = HashMap::new();
-    let mut group_imports: HashMap<String, HashSet<String>> = HashMap::new();
+// Function to check whether a function exports a symbol under a fixed name, including the
+// `#[unsafe(no_mangle)]` spelling of the 2024 edition
+fn is_exported_symbol(func: &ItemFn) -> bool {
+    has_any_attr(&func.attrs, &["no_mangle", "export_name"])
+}
 
-    // Define categories based on function name keywords and crate usage
-    let categories = vec![
-        ("network", vec!["connect", "send", "receive", "http", "socket"]),
-        ("file_io", vec!["read", "write", "file", "open", "close"]),
-        ("crypto", vec!["encrypt", "decrypt", "hash", "key"]),
-        ("util", vec!["helper", "utility", "convert", "parse"]),
-        ("windows_system", vec!["windows", "handle", "service", "process", "thread"]),
-        ("core_std", vec!["core", "std", "sync", "mutex", "ptr"]),
-        ("serde", vec!["serde", "json", "deserialize"]),
-        ("rand", vec!["rand", "rng"]),
-        ("memory", vec!["mem", "memory", "alloc", "ptr"]),
-        // Add more categories as needed
-    ];
+// Function to name the module of a group that has no better name: with --module-prefix in front,
+// or else `_mod` after it, so that it can't clash with the items it holds
+fn generated_module_name(group: &str, options: &Options) -> String {
+    match &options.module_prefix {
+        Some(prefix) => format!("{}{}", prefix, group),
+        None => format!("{}_mod", group),
+    }
+}
 
-    for (func_name, func_code) in &functions {
-        let mut assigned_category = "general".to_string();
+// Function to check whether a function is kept in place by --exclude: a pattern matches its name,
+// or its path below the crate root, with or without `crate::`, when it names a module
+fn is_excluded(exclude: &[String], item_path: &str) -> bool {
+    let name = item_path.rsplit("::").next().unwrap_or(item_path);
+    exclude.iter().any(|pattern| match pattern.strip_prefix("crate::") {
+        Some(path_pattern) => wildcard_match(path_pattern, item_path),
+        None if pattern.contains("::") => wildcard_match(pattern, item_path),
+        None => wildcard_match(pattern, name),
+    })
+}
 
-        // Assign the function to a category based on keywords or crate usage
-        for (category, keywords) in &categories {
-            if keywords.iter().any(|keyword| func_name.contains(keyword)) {
-                assigned_category = category.to_string();
-                break;
+// Function to match text against a pattern in which `*` stands for any run of characters and
+// `?` for any one
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    // Where the last `*` was and the text it has swallowed up to, to backtrack to on a mismatch
+    let (mut p, mut t, mut star) = (0, 0, None);
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
             }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
         }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
 
-        // Group functions by their assigned category
-        grouped_functions
-            .entry(assigned_category.clone())
-            .or_default()
-            .push((func_name.clone(), func_code.clone()));
-
-        // Determine imports required by the category
-        let mut visitor = CrateUsageVisitor {
-            imported_functions: &imported_functions,
-            used_crates: HashSet::new(),
-        };
-        let func_ast: ItemFn = syn::parse_str(func_code).expect("Unable to parse function AST");
-        visit_item_fn(&mut visitor, &func_ast);
-        group_imports
-            .entry(assigned_category.clone())
-            .or_default()
-            .extend(visitor.used_crates);
+// Function to check whether an item uses assembly or places itself with `#[link_section]`,
+// `#[used]` or `#[naked]`, so that where it lives can matter to the linker
+fn is_placement_sensitive(item: &Item) -> bool {
+    let placement_attrs = ["link_section", "used", "naked"];
+    match item {
+        Item::Fn(func) => has_any_attr(&func.attrs, &placement_attrs) || invokes_asm(func.block.to_token_stream()),
+        Item::Static(item_static) => has_any_attr(&item_static.attrs, &placement_attrs),
+        Item::Macro(item_macro) => item_macro.mac.path.segments.last().is_some_and(|segment| segment.ident == "global_asm"),
+        _ => false,
     }
+}
 
-    let mut mod_declarations = Vec::new();
-    let mut use_statements = Vec::new();
+// Function to check whether `tokens` invoke one of the assembly macros, also inside other macros
+fn invokes_asm(tokens: proc_macro2::TokenStream) -> bool {
+    let tokens: Vec<proc_macro2::TokenTree> = tokens.into_iter().collect();
+    tokens.iter().enumerate().any(|(index, token)| match token {
+        proc_macro2::TokenTree::Ident(ident) => {
+            ASM_MACROS.iter().any(|name| ident == name)
+                && matches!(tokens.get(index + 1), Some(proc_macro2::TokenTree::Punct(punct)) if punct.as_char() == '!')
+        }
+        proc_macro2::TokenTree::Group(group) => invokes_asm(group.stream()),
+        _ => false,
+    })
+}
 
-    // Step 4: Refactor logic into separate files based on grouped functions
-    for (group_name, funcs) in &grouped_functions {
-        if group_name == "general" && funcs.len() == functions.len() {
-            // Skip creating a general_mod if all functions are grouped as general
-            continue;
+// Function to check whether `attrs` include one of `names`, also written as `#[unsafe(name)]`
+fn has_any_attr(attrs: &[Attribute], names: &[&str]) -> bool {
+    let is_named = |path: &syn::Path| names.iter().any(|name| path.is_ident(name));
+    attrs.iter().any(|attr| {
+        is_named(attr.path())
+            || (attr.path().is_ident("unsafe") && attr.parse_args::<syn::Meta>().is_ok_and(|meta| is_named(meta.path())))
+    })
+}
+
+// Function to check whether rustfmt is told to leave an item as written, by `#[rustfmt::skip]`
+// or the older `#[rustfmt_skip]`, also behind a `cfg_attr`
+fn has_rustfmt_skip(attrs: &[Attribute]) -> bool {
+    let is_skip = |path: &syn::Path| {
+        path.is_ident("rustfmt_skip") || (path.segments.len() == 2 && path.segments[0].ident == "rustfmt" && path.segments[1].ident == "skip")
+    };
+    attrs.iter().any(|attr| {
+        is_skip(attr.path())
+            || (attr.path().is_ident("cfg_attr")
+                && attr.meta.require_list().is_ok_and(|list| {
+                    let tokens = list.tokens.to_string();
+                    tokens.contains("rustfmt_skip") || tokens.contains("rustfmt :: skip")
+                }))
+    })
+}
+
+// Function to get the outer attributes of an item, for the kinds of items that have them
+fn item_attrs(item: &Item) -> Option<&[Attribute]> {
+    let attrs = match item {
+        Item::Const(item) => &item.attrs,
+        Item::Enum(item) => &item.attrs,
+        Item::ExternCrate(item) => &item.attrs,
+        Item::Fn(item) => &item.attrs,
+        Item::ForeignMod(item) => &item.attrs,
+        Item::Impl(item) => &item.attrs,
+        Item::Macro(item) => &item.attrs,
+        Item::Mod(item) => &item.attrs,
+        Item::Static(item) => &item.attrs,
+        Item::Struct(item) => &item.attrs,
+        Item::Trait(item) => &item.attrs,
+        Item::TraitAlias(item) => &item.attrs,
+        Item::Type(item) => &item.attrs,
+        Item::Union(item) => &item.attrs,
+        Item::Use(item) => &item.attrs,
+        _ => return None,
+    };
+    Some(attrs)
+}
+
+// Function to check whether a function is an exported macro of a proc-macro crate
+fn is_proc_macro_entry(func: &ItemFn) -> bool {
+    func.attrs.iter().any(|attr| {
+        let path = attr.path();
+        path.is_ident("proc_macro") || path.is_ident("proc_macro_derive") || path.is_ident("proc_macro_attribute")
+    })
+}
+
+// Function to split an exported proc macro into a stub that keeps its attributes and signature
+// and delegates to `<name>_impl`, and that implementation, which carries the original body.
+// Returns None when the arguments can't be forwarded by name
+fn split_proc_macro_entry(source: &str, func: &ItemFn, item_range: Range<usize>) -> Option<(String, String)> {
+    let mut args = Vec::new();
+    for input in &func.sig.inputs {
+        match input {
+            syn::FnArg::Typed(pat_type) => match &*pat_type.pat {
+                syn::Pat::Ident(pat_ident) => args.push(pat_ident.ident.to_string()),
+                _ => return None,
+            },
+            syn::FnArg::Receiver(_) => return None,
         }
-        
-        // Sanitize the module name to remove invalid characters
-        let sanitized_group_name = sanitize_filename(group_name);
-        let module_name = format!("{}_mod", sanitized_group_name);
-        let mut module_code = String::new();
+    }
+
+    let impl_name = format!("{}_impl", func.sig.ident.unraw());
+    let block_start = func.block.span().byte_range().start;
+    let indent = leading_indent(&source[item_range.clone()]);
+    let stub = format!(
+        "{}{{\n{}    {}({})\n{}}}",
+        &source[item_range.start..block_start],
+        indent,
+        impl_name,
+        args.join(", "),
+        indent
+    );
+
+    // The implementation keeps any `#[cfg]` of the entry point and the body exactly as written
+    let mut implementation = String::new();
+    for attr in func.attrs.iter().filter(|attr| attr.path().is_ident("cfg")) {
+        implementation.push_str(&format!("{}{}\n", indent, &source[attr.span().byte_range()]));
+    }
+    let sig_start = func.sig.span().byte_range().start;
+    let ident_range = func.sig.ident.span().byte_range();
+    implementation.push_str(&format!(
+        "{}pub(crate) {}{}{}",
+        indent,
+        &source[sig_start..ident_range.start],
+        impl_name,
+        &source[ident_range.end..func.span().byte_range().end]
+    ));
+    Some((stub, implementation))
+}
 
-        // Add `use crate::*;` to import everything from the main file
-        module_code.push_str("use crate::*;\n\n");
+// Function to check whether a function is a thin safe wrapper around an extern block
+fn is_ffi_wrapper(func: &ItemFn, foreign_functions: &HashSet<String>) -> bool {
+    if foreign_functions.is_empty() || func.block.stmts.len() > MAX_FFI_WRAPPER_STMTS {
+        return false;
+    }
+
+    let mut visitor = ForeignCallVisitor {
+        foreign_functions,
+        calls_foreign: false,
+    };
+    visit_item_fn(&mut visitor, func);
+    visitor.calls_foreign
+}
+
+// Function to find the functions referenced by nearly every group other than their own,
+// counting the root (where `main` lives) as a group of its own
+fn find_hot_functions(
+    func_asts: &HashMap<String, ItemFn>,
+    assigned_categories: &HashMap<String, String>,
+    main_ast: Option<&ItemFn>,
+) -> Vec<String> {
+    let mut referencing_groups: HashMap<&str, HashSet<&str>> = HashMap::new();
+    let referrers = func_asts
+        .iter()
+        .map(|(name, ast)| (assigned_categories[name].as_str(), ast))
+        .chain(main_ast.map(|ast| ("root", ast)));
 
-        // Include relevant imports for this module
-        if let Some(imports) = group_imports.get(group_name) {
-            for import in imports {
-                if let Some(import_code) = imported_functions.get(import) {
-                    module_code.push_str(import_code);
-                    module_code.push_str("\n");
+    let mut all_groups = HashSet::new();
+    for (group, ast) in referrers {
+        all_groups.insert(group);
+        let mut visitor = ReferenceVisitor {
+            referenced_names: HashSet::new(),
+            through_super: false,
+        };
+        visit_item_fn(&mut visitor, ast);
+        for name in visitor.referenced_names {
+            if let Some((func_name, own_group)) = assigned_categories.get_key_value(&name) {
+                if own_group != group {
+                    referencing_groups.entry(func_name.as_str()).or_default().insert(group);
                 }
             }
         }
-        module_code.push_str("\n");
+    }
 
-        // Add the functions to the module
-        for (_func_name, func_code) in funcs {
-            module_code.push_str(func_code);
-            module_code.push_str("\n\n");
+    let other_groups = all_groups.len().saturating_sub(1) as f64;
+    referencing_groups
+        .into_iter()
+        .filter(|(_, groups)| {
+            groups.len() >= HOT_ITEM_MIN_GROUPS && groups.len() as f64 >= other_groups * HOT_ITEM_GROUP_SHARE
+        })
+        .map(|(func_name, _)| func_name.to_string())
+        .collect()
+}
+
+// Function to find the category of the crates a function uses, from the paths it writes out and
+// the imports its names come from: the category the most of those paths count for, with one of
+// them and its keyword. Paths rooted at this module's own items don't count
+fn crate_category<'a>(
+    func_ast: &ItemFn,
+    categories: &'a [(&'a str, Vec<&'a str>)],
+    import_path_of: &HashMap<String, String>,
+    local_names: &HashSet<String>,
+) -> Option<(&'a str, String, &'a str)> {
+    let mut visitor = PathVisitor::default();
+    visitor.visit_item_fn(func_ast);
+    let mut crate_paths = BTreeSet::new();
+    for path in visitor.paths {
+        let (first, rest) = path.split_once("::").map_or((path.as_str(), None), |(first, rest)| (first, Some(rest)));
+        match (import_path_of.get(first), rest) {
+            (Some(import_path), Some(rest)) => crate_paths.insert(format!("{}::{}", import_path, rest)),
+            (Some(import_path), None) => crate_paths.insert(import_path.clone()),
+            (None, Some(_)) if !local_names.contains(first) && !["crate", "Self"].contains(&first) => crate_paths.insert(path.clone()),
+            _ => false,
+        };
+    }
+
+    // Each path counts for the category of its most specific segment with a keyword, so that
+    // `std::net::TcpStream::connect` counts for `connect` rather than `std`
+    let mut votes: Vec<(usize, &str, String, &str)> = Vec::new();
+    for path in crate_paths {
+        let lowercase = path.to_lowercase();
+        let vote = lowercase.rsplit("::").find_map(|segment| {
+            categories.iter().enumerate().find_map(|(index, (category, keywords))| {
+                keywords.iter().find(|keyword| segment.contains(*keyword)).map(|keyword| (index, *category, *keyword))
+            })
+        });
+        if let Some((index, category, keyword)) = vote {
+            votes.push((index, category, path, keyword));
         }
+    }
+    // Categories come in order of preference, so ties go to the first one
+    let count = |index: usize| votes.iter().filter(|vote| vote.0 == index).count();
+    let best = votes.iter().min_by_key(|vote| (usize::MAX - count(vote.0), vote.0))?;
+    Some((best.1, best.2.clone(), best.3))
+}
 
-        let output_path: PathBuf = output_dir.join(format!("{}.rs", module_name));
-        let formatted_code = rustfmt_code(&module_code);
-        fs::write(&output_path, formatted_code).unwrap_or_else(|e| panic!("Failed to write the refactored file: {:?} with error: {}", output_path, e));
+// Function to check whether `path` is where the prelude of the edition takes a name from, such as
+// `std::vec::Vec`, so that importing it again changes nothing
+fn in_prelude(path: &str, edition: u16, no_std: bool) -> bool {
+    let Some((root, rest)) = path.split_once("::") else {
+        return false;
+    };
+    ["std", "core", "alloc"].contains(&root)
+        && PRELUDE_ITEMS
+            .iter()
+            .any(|(item, since, in_core)| *item == rest && edition >= *since && (*in_core || !no_std))
+}
 
-        // Create module declaration and use statement
-        mod_declarations.push(format!("pub mod {};", module_name));
-        use_statements.push(format!("pub use {}::*;", module_name));
+// Function to find the statics and consts initialized with several of the functions, such as
+// arrays of handlers or structs of callbacks, with the functions each one lists in sorted order
+fn function_tables(items: &[Item], func_asts: &HashMap<String, ItemFn>) -> Vec<(String, Vec<String>)> {
+    let mut tables = Vec::new();
+    for item in items {
+        let (name, expr) = match item {
+            Item::Static(item_static) => (&item_static.ident, &item_static.expr),
+            Item::Const(item_const) => (&item_const.ident, &item_const.expr),
+            _ => continue,
+        };
+        let mut visitor = ReferenceVisitor {
+            referenced_names: HashSet::new(),
+            through_super: false,
+        };
+        visitor.visit_expr(expr);
+        let mut members: Vec<String> = visitor.referenced_names.into_iter().filter(|name| func_asts.contains_key(name)).collect();
+        if members.len() > 1 {
+            members.sort();
+            tables.push((name.to_string(), members));
+        }
     }
+    tables
+}
 
-    // Step 5: Extract the main function and create a tmp_main.rs file with all module imports and other items
-    if let Some(main_func) = main_function {
-        let mut tmp_main = String::new();
-        
-        // Include all imports not associated with any function group
-        for import in imported_functions.values() {
-            tmp_main.push_str(import);
-            tmp_main.push_str("\n\n");
+// Function to find the functions exactly one other function calls, mapped to that caller. Calls
+// from `main` count, but a function only `main` calls is left out since `main` has no group
+fn find_single_callers(func_asts: &HashMap<String, ItemFn>, main_ast: Option<&ItemFn>) -> BTreeMap<String, String> {
+    let mut callers: HashMap<&str, HashSet<&str>> = HashMap::new();
+    let referrers = func_asts
+        .iter()
+        .map(|(name, ast)| (name.as_str(), ast))
+        .chain(main_ast.map(|ast| ("main", ast)));
+    for (caller, ast) in referrers {
+        let mut visitor = ReferenceVisitor {
+            referenced_names: HashSet::new(),
+            through_super: false,
+        };
+        visit_item_fn(&mut visitor, ast);
+        for name in visitor.referenced_names {
+            if let Some((callee, _)) = func_asts.get_key_value(&name).filter(|(callee, _)| *callee != caller) {
+                callers.entry(callee.as_str()).or_default().insert(caller);
+            }
         }
+    }
+
+    callers
+        .into_iter()
+        .filter_map(|(callee, callers)| match callers.into_iter().collect::<Vec<_>>()[..] {
+            [caller] if main_ast.is_none_or(|_| caller != "main") => Some((callee.to_string(), caller.to_string())),
+            _ => None,
+        })
+        .collect()
+}
+
+// Function to find the `#[cfg(...)]` attributes shared by all of `funcs`
+fn shared_cfg_attrs(funcs: &[&ItemFn]) -> Vec<String> {
+    let cfgs_of = |func: &ItemFn| -> Vec<String> {
+        func.attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("cfg"))
+            .map(|attr| attr.meta.to_token_stream().to_string())
+            .collect()
+    };
+
+    let Some((first, rest)) = funcs.split_first() else {
+        return Vec::new();
+    };
+    let mut shared = cfgs_of(first);
+    for func in rest {
+        let cfgs = cfgs_of(func);
+        shared.retain(|cfg| cfgs.contains(cfg));
+    }
+    shared
+}
+
+// Function to write a visibility the way it is declared, with private items written as nothing
+fn visibility_code(vis: &syn::Visibility) -> String {
+    match vis {
+        syn::Visibility::Inherited => String::new(),
+        vis => tree::visibility_label(vis),
+    }
+}
 
-        // Include all other items (constants, types, etc.)
-        for item in &other_items {
-            tmp_main.push_str(item);
-            tmp_main.push_str("\n\n");
+// Function to find the module within which an item declared in `module_path` with `vis` is
+// visible, or None if it is `pub`
+fn visibility_scope(vis: &syn::Visibility, module_path: &[String]) -> Option<Vec<String>> {
+    match vis {
+        syn::Visibility::Public(_) => None,
+        syn::Visibility::Inherited => Some(module_path.to_vec()),
+        syn::Visibility::Restricted(restricted) => {
+            let path = restricted.path.to_token_stream().to_string().replace(' ', "");
+            Some(doc_links::absolute_link_path(&path, module_path).unwrap_or_default())
         }
+    }
+}
+
+// Function to find the visibility that an item declared with `vis` needs after moving into a child
+// module so that it is visible to exactly the same modules, or None if `vis` can stay as written.
+// Paths like `pub(in crate::foo)` are absolute and keep their meaning, and the item stays within
+// the named subtree since generated modules are always children of the module it came from
+fn relocated_visibility(vis: &syn::Visibility) -> Option<String> {
+    let path = match vis {
+        syn::Visibility::Public(_) => return None,
+        syn::Visibility::Inherited => return Some("pub(super)".to_string()),
+        syn::Visibility::Restricted(restricted) => &restricted.path,
+    };
+    let segments: Vec<String> = path.segments.iter().map(|segment| segment.ident.to_string()).collect();
+    if segments[0] == "crate" {
+        return None;
+    }
+
+    // `self` becomes `super`, and every `super` needs one more to climb out of the new module
+    let mut relocated = vec!["super".to_string()];
+    relocated.extend(segments.into_iter().filter(|segment| segment != "self"));
+    if relocated.len() == 1 {
+        Some("pub(super)".to_string())
+    } else {
+        Some(format!("pub(in {})", relocated.join("::")))
+    }
+}
+
+// Function to find the edit that relocates `vis`, where `insert_at` is the offset of whatever
+// follows a visibility that isn't written out
+fn visibility_edit(vis: &syn::Visibility, insert_at: usize) -> Option<(Range<usize>, String)> {
+    let visibility = relocated_visibility(vis)?;
+    match vis {
+        syn::Visibility::Inherited => Some((insert_at..insert_at, format!("{} ", visibility))),
+        vis => Some((vis.span().byte_range(), visibility)),
+    }
+}
+
+fn apply_edits(code: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    let mut code = code.to_string();
+    edits.sort_by_key(|(range, _)| range.start);
+    for (range, replacement) in edits.into_iter().rev() {
+        code.replace_range(range, &replacement);
+    }
+    code
+}
+
+// Function to rewrite the visibility, relative paths and included paths of a function that moves
+// into a child module `depth` directories below its source file, where `offset` is where
+// `func_code` starts in the source `func_ast` was parsed from. Also tells whether any path changed
+fn relocate_function(func_code: &str, func_ast: &ItemFn, offset: usize, depth: usize) -> (String, bool) {
+    let mut relative_paths = RelativePathVisitor { edits: Vec::new(), nesting: 0 };
+    relative_paths.visit_item_fn(func_ast);
+    let mut include_paths = IncludePathVisitor {
+        prefix: "../".repeat(depth),
+        edits: Vec::new(),
+    };
+    if depth > 0 {
+        include_paths.visit_item_fn(func_ast);
+    }
+    let anchored_paths = !relative_paths.edits.is_empty();
 
-        // Include all function module declarations
-        for mod_decl in &mod_declarations {
-            tmp_main.push_str(mod_decl);
-            tmp_main.push_str("\n\n");
+    let edits = visibility_edit(&func_ast.vis, func_ast.sig.span().byte_range().start)
+        .into_iter()
+        .chain(relative_paths.edits)
+        .chain(include_paths.edits)
+        .map(|(range, replacement)| (range.start - offset..range.end - offset, replacement))
+        .collect();
+    (apply_edits(func_code, edits), anchored_paths)
+}
+
+// Function to rewrite the visibilities of a type, an inherent impl or an extern block that moves
+// into a child module, including struct fields, methods and foreign items, so that its users keep
+// seeing all of it
+fn relocate_item_visibility(item_code: &str) -> String {
+    let Ok(item) = syn::parse_str::<Item>(item_code) else {
+        return item_code.to_string();
+    };
+    let mut edits = Vec::new();
+    match &item {
+        Item::Enum(item_enum) => edits.extend(visibility_edit(&item_enum.vis, item_enum.enum_token.span.byte_range().start)),
+        Item::Static(item_static) => edits.extend(visibility_edit(&item_static.vis, item_static.static_token.span.byte_range().start)),
+        Item::Struct(item_struct) => {
+            edits.extend(visibility_edit(&item_struct.vis, item_struct.struct_token.span.byte_range().start));
+            for field in &item_struct.fields {
+                let field_start = field.ident.as_ref().map_or_else(|| field.ty.span(), |ident| ident.span());
+                edits.extend(visibility_edit(&field.vis, field_start.byte_range().start));
+            }
+        }
+        Item::ForeignMod(foreign_mod) => {
+            for foreign_item in &foreign_mod.items {
+                match foreign_item {
+                    ForeignItem::Fn(foreign_fn) => edits.extend(visibility_edit(&foreign_fn.vis, foreign_fn.sig.span().byte_range().start)),
+                    ForeignItem::Static(foreign_static) => {
+                        edits.extend(visibility_edit(&foreign_static.vis, foreign_static.static_token.span.byte_range().start))
+                    }
+                    ForeignItem::Type(foreign_type) => {
+                        edits.extend(visibility_edit(&foreign_type.vis, foreign_type.type_token.span.byte_range().start))
+                    }
+                    _ => {}
+                }
+            }
         }
-        
-        // Include all function public use imports
-        for use_statement in &use_statements {
-            tmp_main.push_str(use_statement);
-            tmp_main.push_str("\n\n");
+        Item::Impl(item_impl) if item_impl.trait_.is_none() => {
+            for impl_item in &item_impl.items {
+                match impl_item {
+                    syn::ImplItem::Fn(method) => edits.extend(visibility_edit(&method.vis, method.sig.span().byte_range().start)),
+                    syn::ImplItem::Const(constant) => {
+                        edits.extend(visibility_edit(&constant.vis, constant.const_token.span.byte_range().start))
+                    }
+                    _ => {}
+                }
+            }
         }
+        _ => {}
+    }
+    apply_edits(item_code, edits)
+}
 
-        // Include the main function
-        tmp_main.push_str(&main_func);
-        tmp_main.push_str("\n\n");
+// Function to rewrite the `self::` and `super::` paths of an item moving into a child module
+fn anchor_relative_paths(item_code: &str) -> String {
+    let Ok(item) = syn::parse_str::<Item>(item_code) else {
+        return item_code.to_string();
+    };
+    let mut visitor = RelativePathVisitor { edits: Vec::new(), nesting: 0 };
+    visitor.visit_item(&item);
+    apply_edits(item_code, visitor.edits)
+}
+
+// Function to rewrite the relative paths of `include!`, `include_str!` and `include_bytes!` in an
+// item moving from its source file into a module file `depth` directories below it
+fn anchor_include_paths(item_code: &str, depth: usize) -> String {
+    if depth == 0 {
+        return item_code.to_string();
+    }
+    let Ok(item) = syn::parse_str::<Item>(item_code) else {
+        return item_code.to_string();
+    };
+    let mut visitor = IncludePathVisitor {
+        prefix: "../".repeat(depth),
+        edits: Vec::new(),
+    };
+    visitor.visit_item(&item);
+    apply_edits(item_code, visitor.edits)
+}
+
+// Function to read the path an `include!`-like macro call includes, when it is a string literal
+fn include_path(mac: &Macro) -> Option<String> {
+    match macro_args(mac).first() {
+        Some(Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(path), .. })) => Some(path.value()),
+        _ => None,
+    }
+}
+
+// Function to rewrite the intra-doc links in `code`, which lives in module `module_path` but
+// was written in module `written_in`, so that they follow the moved items
+fn rewrite_doc_links(
+    code: &str,
+    written_in: &[String],
+    module_path: &[String],
+    moves: &HashMap<Vec<String>, Vec<String>>,
+) -> String {
+    let Ok(syntax_tree) = syn::parse_file(code) else {
+        return code.to_string();
+    };
+    let source = parsed_source(code);
+    let source_offset = code.len() - source.len();
+
+    let mut visitor = DocLinkVisitor {
+        source,
+        moves,
+        module_path: written_in.to_vec(),
+        relocated: written_in != module_path,
+        in_code_block: false,
+        edits: Vec::new(),
+    };
+    visitor.visit_file(&syntax_tree);
 
-        let formatted_main_code = rustfmt_code(&tmp_main);
+    let mut edits = visitor.edits;
+    edits.sort_by_key(|(range, _)| range.start);
+    let mut rewritten = code.to_string();
+    for (range, replacement) in edits.iter().rev() {
+        rewritten.replace_range(range.start + source_offset..range.end + source_offset, replacement);
+    }
+    rewritten
+}
 
-        let tmp_main_path: PathBuf = output_dir.join("tmp_main.rs");
-        fs::write(tmp_main_path, formatted_main_code).expect("Failed to write the tmp_main file");
+// Function to generate a test module that names one item through each generated module and
+// through the re-export next to it, so `cargo test` fails if any of the new paths is broken
+fn smoke_test_module(smoke_targets: &[SmokeTarget]) -> String {
+    let mut targets: Vec<&SmokeTarget> = smoke_targets.iter().collect();
+    targets.sort_by(|a, b| a.module_path.cmp(&b.module_path));
+
+    let mut checks = Vec::new();
+    for target in targets {
+        let module_path = target.module_path.join("::");
+        match &target.item {
+            Some(item) => {
+                checks.push(format!("use crate::{}::{} as _;", module_path, item));
+                let parent = &target.module_path[..target.module_path.len() - 1];
+                let reexport: Vec<&str> = parent.iter().map(String::as_str).chain([item.as_str()]).collect();
+                checks.push(format!("use crate::{} as _;", reexport.join("::")));
+            }
+            None => checks.push(format!("use crate::{} as _;", module_path)),
+        }
     }
 
-    println!("Refactoring complete. Check the output files in the same directory as the input file.");
+    // Sorted here so that the test reads the same whichever formatter is used
+    checks.sort();
+    format!(
+        "#[cfg(test)]\nmod generated_smoke {{\n#[allow(unused_imports)]\nuse super::*;\n\n#[test]\n#[allow(unused_imports)]\nfn generated_modules_resolve() {{\n{}\n}}\n}}",
+        checks.join("\n")
+    )
 }
 
-// Function to format Rust code using `rustfmt`
-fn rustfmt_code(code: &str) -> String {
-    let mut child = Command::new("rustfmt")
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .spawn()
-        .expect("Failed to spawn rustfmt");
+// Function to wrap generated code in a named region that later runs can find again
+fn wrap_region(name: &str, body: &str) -> String {
+    format!("// <refactor:{name}>\n{body}\n// </refactor:{name}>")
+}
 
-    if let Some(mut stdin) = child.stdin.take() {
-        use std::io::Write;
-        stdin.write_all(code.as_bytes()).expect("Failed to write to rustfmt stdin");
+// Function to replace the body of a named region, returning None if the region is missing
+fn replace_region(source: &str, name: &str, body: &str) -> Option<String> {
+    let start_marker = format!("// <refactor:{}>", name);
+    let end_marker = format!("// </refactor:{}>", name);
+    let start = source.find(&start_marker)? + start_marker.len();
+    let end = start + source[start..].find(&end_marker)?;
+
+    // Keep the indentation in front of the end marker intact
+    let line_start = source[..end].rfind('\n').map_or(end, |i| i + 1);
+    let indent = &source[line_start..end];
+    if !indent.trim().is_empty() {
+        return None;
+    }
+
+    Some(format!("{}\n{}\n{}{}", &source[..start], body, &source[line_start..end], &source[end..]))
+}
+
+// Function to strip the BOM and shebang the same way `syn::parse_file` does, so that spans
+// of the parsed items index into the returned string
+fn parsed_source(content: &str) -> &str {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    if content.starts_with("#!") && !content[2..].trim_start().starts_with('[') {
+        return content.find('\n').map_or("", |idx| &content[idx..]);
+    }
+    content
+}
+
+// Function to check whether the crate attributes include `#![name]`, also behind a `cfg_attr`
+fn has_crate_attr(attrs: &[Attribute], name: &str) -> bool {
+    attrs.iter().filter(|attr| matches!(attr.style, syn::AttrStyle::Inner(_))).any(|attr| {
+        attr.path().is_ident(name)
+            || (attr.path().is_ident("cfg_attr")
+                && attr.meta.require_list().is_ok_and(|list| {
+                    list.tokens.clone().into_iter().any(|token| matches!(token, proc_macro2::TokenTree::Ident(ident) if ident == name))
+                }))
+    })
+}
+
+// Function to find where the inner attributes (`#![...]`, `//!`) among `attrs` end
+fn attrs_end(attrs: &[Attribute]) -> Option<usize> {
+    attrs
+        .iter()
+        .filter(|attr| matches!(attr.style, syn::AttrStyle::Inner(_)))
+        .map(|attr| attr.span().byte_range().end)
+        .max()
+}
+
+// Function to compute the source range of every item, extended to cover the comments in
+// front of it and any comment trailing on the same line as its end
+fn item_source_ranges(source: &str, items: &[Item], items_start: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut start = items_start;
+
+    for item in items {
+        let span = item.span().byte_range();
+        let leading = &source[start..span.start];
+        let mut item_start = span.start - leading.trim_start().len();
+
+        // Include the indentation of the item's first line so that moved lines stay aligned
+        let line_start = source[..item_start].rfind('\n').map_or(0, |i| i + 1);
+        if line_start >= start && source[line_start..item_start].trim().is_empty() {
+            item_start = line_start;
+        }
+
+        let line_end = source[span.end..].find('\n').map_or(source.len(), |i| span.end + i);
+        let trailing = source[span.end..line_end].trim();
+        let item_end = if trailing.starts_with("//") || trailing.starts_with("/*") {
+            line_end
+        } else {
+            span.end
+        };
+
+        ranges.push(item_start..item_end);
+        start = item_end;
     }
 
-    let output = child.wait_with_output().expect("Failed to read rustfmt output");
-    String::from_utf8(output.stdout).expect("Failed to convert rustfmt output to string")
+    ranges
 }
 
-// Helper function to convert syn items to strings
-fn item_to_string<T: quote::ToTokens>(item: &T) -> String {
-    item.to_token_stream().to_string()
+// Function to declare a module file. Non-ASCII modules need a `#[path]` to be declared as files
+fn mod_declaration(visibility: &str, module_ident: &str) -> String {
+    if module_ident.is_ascii() {
+        format!("{}mod {};", visibility, module_ident)
+    } else {
+        format!("#[path = \"{}\"]\n{}mod {};", idents::module_file_name(module_ident), visibility, module_ident)
+    }
 }
 
-// Function to sanitize a filename by removing invalid characters
-fn sanitize_filename(filename: &str) -> String {
-    filename.chars().filter(|c| c.is_alphanumeric() || *c == '_').collect()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Function to find the imports of the module written in `code` that its function `func_name`
+    // uses, which a module the function moves into has to copy
+    fn used_imports(code: &str, func_name: &str) -> Vec<String> {
+        let file = syn::parse_file(code).expect("The test module doesn't parse");
+        let mut imported_names = HashMap::new();
+        let mut import_path_of = HashMap::new();
+        let mut aliases: HashMap<String, Vec<String>> = HashMap::new();
+        for (index, item) in file.items.iter().enumerate() {
+            if let Item::Use(item_use) = item {
+                for (name, path) in import_paths(item_use) {
+                    if let Some(original) = renamed_from(&path) {
+                        aliases.entry(original.to_string()).or_default().push(name.clone());
+                    }
+                    import_path_of.insert(name.clone(), path);
+                    imported_names.insert(name, index);
+                }
+            }
+        }
+        let func = file
+            .items
+            .iter()
+            .find_map(|item| match item {
+                Item::Fn(func) if func.sig.ident == func_name => Some(func),
+                _ => None,
+            })
+            .expect("The test module lacks the function");
+        let mut visitor = CrateUsageVisitor {
+            imported_names: &imported_names,
+            aliases: &aliases,
+            used_imports: HashSet::new(),
+            local_names: Vec::new(),
+            trait_methods: Vec::new(),
+            import_paths: &import_path_of,
+            named_modules: HashSet::new(),
+            ambiguous_methods: Vec::new(),
+            unsure_traits: HashSet::new(),
+            no_std: false,
+        };
+        visitor.visit_item_fn(func);
+        visitor.note_ambiguous_methods();
+        let mut used: Vec<String> = visitor.used_imports.into_iter().collect();
+        used.sort();
+        used
+    }
+
+    #[test]
+    fn nested_function_takes_the_imports_it_uses() {
+        let code = r#"
+            use std::collections::HashMap;
+            use std::fmt;
+
+            fn count_words(text: &str) -> usize {
+                fn letters(word: &str) -> HashMap<char, usize> {
+                    let mut seen = HashMap::new();
+                    for c in word.chars() {
+                        *seen.entry(c).or_insert(0) += 1;
+                    }
+                    seen
+                }
+                text.split_whitespace().filter(|word| !letters(word).is_empty()).count()
+            }
+        "#;
+        assert_eq!(used_imports(code, "count_words"), ["HashMap"]);
+    }
+
+    #[test]
+    fn nested_struct_and_impl_take_the_imports_they_use() {
+        let code = r#"
+            use std::collections::BTreeMap;
+            use std::fmt::{self, Display};
+
+            fn render(pairs: &[(u32, u32)]) -> String {
+                struct Table(BTreeMap<u32, u32>);
+                impl Display for Table {
+                    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                        write!(f, "{} rows", self.0.len())
+                    }
+                }
+                Table(pairs.iter().copied().collect()).to_string()
+            }
+        "#;
+        assert_eq!(used_imports(code, "render"), ["BTreeMap", "Display", "fmt"]);
+    }
+
+    #[test]
+    fn local_import_takes_the_module_import_it_starts_from() {
+        let code = r#"
+            use std::io;
+            use std::path::Path;
+
+            fn write_output(text: &str) {
+                use io::Write;
+                io::stdout().write_all(text.as_bytes()).unwrap();
+            }
+
+            fn write_error(text: &str) {
+                use io::{Write as _};
+                std::io::stderr().write_all(text.as_bytes()).unwrap();
+            }
+        "#;
+        assert_eq!(used_imports(code, "write_output"), ["io"]);
+        assert_eq!(used_imports(code, "write_error"), ["io"]);
+    }
+
+    #[test]
+    fn local_import_shadows_the_module_trait() {
+        let code = r#"
+            use std::io::{self, Write};
+
+            fn flush_output() {
+                use std::fmt::Write;
+                let mut text = String::new();
+                text.write_str("done").unwrap();
+                println!("{}", text);
+            }
+        "#;
+        assert!(used_imports(code, "flush_output").is_empty());
+    }
 }
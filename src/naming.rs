@@ -0,0 +1,141 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use syn::{Attribute, ItemFn};
+
+// A name is only taken from the docs when a word is used by at least this many of the group's
+// functions, and by at least half of them
+const MIN_FUNCTIONS_SHARING: usize = 2;
+// A second word joins the name when it scores at least this share of the first one
+const SECOND_WORD_SHARE: f64 = 0.75;
+
+// Words that say nothing about what a group of functions is about
+const STOPWORDS: &[&str] = &[
+    "about", "after", "all", "also", "and", "any", "are", "back", "been", "before", "both", "but", "can", "does",
+    "each", "either", "empty", "every", "false", "for", "from", "function", "functions", "given", "has", "have",
+    "how", "into", "its", "may", "more", "must", "new", "none", "not", "one", "only", "other", "out", "over",
+    "panics", "result", "return", "returns", "same", "see", "should", "some", "such", "than", "that", "the",
+    "their", "them", "then", "there", "these", "this", "those", "through", "true", "two", "under", "until",
+    "use", "used", "uses", "using", "value", "was", "when", "where", "whether", "which", "while", "will",
+    "with", "without", "you", "your",
+];
+
+// Function to read the text of the doc comments among `attrs`, leaving out code blocks
+fn doc_text(attrs: &[Attribute]) -> String {
+    let mut text = String::new();
+    let mut in_code_block = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("doc")) {
+        if let syn::Meta::NameValue(syn::MetaNameValue {
+            value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(doc), .. }),
+            ..
+        }) = &attr.meta
+        {
+            for line in doc.value().lines() {
+                let line = line.trim();
+                if line.starts_with("```") || line.starts_with("~~~") {
+                    in_code_block = !in_code_block;
+                } else if !in_code_block {
+                    text.push_str(line);
+                    text.push('\n');
+                }
+            }
+        }
+    }
+    text
+}
+
+// Function to split doc text into the lowercase words worth naming a module after
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| word.len() >= 3 && word.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(str::to_ascii_lowercase)
+        .filter(|word| !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+// Function to name groups after the vocabulary their functions' docs share, scoring each word by
+// TF-IDF with the groups as the documents so that words every group uses don't win. Groups whose
+// docs are silent, or don't agree on a word, get no name
+pub fn doc_module_names(groups: &BTreeMap<&String, Vec<&ItemFn>>) -> HashMap<String, String> {
+    let mut group_words = BTreeMap::new();
+    for (group, funcs) in groups {
+        let func_words: Vec<Vec<String>> = funcs.iter().map(|func| words(&doc_text(&func.attrs))).collect();
+        if func_words.iter().any(|words| !words.is_empty()) {
+            group_words.insert(*group, func_words);
+        }
+    }
+
+    let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+    for func_words in group_words.values() {
+        let unique: HashSet<&str> = func_words.iter().flatten().map(String::as_str).collect();
+        for word in unique {
+            *document_frequency.entry(word).or_default() += 1;
+        }
+    }
+
+    let group_count = group_words.len() as f64;
+    let mut names = HashMap::new();
+    for (group, func_words) in &group_words {
+        let all_words: Vec<&str> = func_words.iter().flatten().map(String::as_str).collect();
+        let min_sharing = MIN_FUNCTIONS_SHARING.max(func_words.len().div_ceil(2));
+        let mut scores: Vec<(f64, usize, &str)> = Vec::new();
+        for (position, word) in all_words.iter().enumerate() {
+            if scores.iter().any(|(_, _, scored)| scored == word) {
+                continue;
+            }
+            let sharing = func_words.iter().filter(|words| words.iter().any(|w| w == word)).count();
+            if sharing < min_sharing {
+                continue;
+            }
+            let term_frequency = all_words.iter().filter(|w| *w == word).count() as f64 / all_words.len() as f64;
+            let inverse_document_frequency = ((1.0 + group_count) / (1.0 + document_frequency[word] as f64)).ln() + 1.0;
+            scores.push((term_frequency * inverse_document_frequency, position, word));
+        }
+        scores.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+        // The name reads the words in the order the docs use them, as in `database_connection`
+        let mut chosen: Vec<&(f64, usize, &str)> = scores.iter().take(1).collect();
+        if let (Some(first), Some(second)) = (scores.first(), scores.get(1)) {
+            if second.0 >= first.0 * SECOND_WORD_SHARE {
+                chosen.push(second);
+            }
+        }
+        chosen.sort_by_key(|(_, position, _)| *position);
+        if !chosen.is_empty() {
+            let name: Vec<&str> = chosen.iter().map(|(_, _, word)| *word).collect();
+            names.insert(group.to_string(), name.join("_"));
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn documented(docs: &[&str]) -> Vec<ItemFn> {
+        docs.iter().map(|doc| syn::parse_str(&format!("/// {}\nfn f() {{}}", doc)).unwrap()).collect()
+    }
+
+    #[test]
+    fn groups_are_named_after_the_words_their_docs_share() {
+        let (io, util, silent) = ("io".to_string(), "util".to_string(), "silent".to_string());
+        let io_funcs = documented(&["Opens the database connection", "Closes the database connection", "Reads a row"]);
+        let util_funcs = documented(&["Formats a row for the log", "Parses a row of the log"]);
+        let silent_funcs: Vec<ItemFn> = vec![syn::parse_str("fn quiet() {}").unwrap()];
+        let groups = BTreeMap::from([
+            (&io, io_funcs.iter().collect()),
+            (&util, util_funcs.iter().collect()),
+            (&silent, silent_funcs.iter().collect()),
+        ]);
+        let names = doc_module_names(&groups);
+        // `row` is in the docs of both groups, so it names neither
+        assert_eq!(names["io"], "database_connection");
+        assert_eq!(names["util"], "log");
+        assert!(!names.contains_key("silent"));
+    }
+
+    #[test]
+    fn code_blocks_and_stopwords_are_left_out() {
+        let func: ItemFn = syn::parse_str("/// Returns the key\n///\n/// ```\n/// let secret = key();\n/// ```\nfn key() {}").unwrap();
+        assert_eq!(words(&doc_text(&func.attrs)), ["key"]);
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::verify;
+
+// Branch the git sink commits the generated files to, or the first of `refactor-2`, `refactor-3`
+// and so on that doesn't exist yet
+const GIT_BRANCH: &str = "refactor";
+
+// Where the generated files go
+pub trait OutputSink {
+    // Function to write one generated file
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), String>;
+
+    // Function to finish once every file is written, returning what to tell the user about it
+    fn finish(&mut self) -> Result<String, String>;
+}
+
+// Writes the files in place, remembering what they held before so they can be rolled back
+#[derive(Default)]
+pub struct FileSystem {
+    backups: Vec<verify::Backup>,
+}
+
+impl FileSystem {
+    // Function to put back what every written file held before
+    pub fn rollback(&self) {
+        verify::rollback(&self.backups);
+    }
+}
+
+impl OutputSink for FileSystem {
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), String> {
+        self.backups.push(verify::backup(path));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create the output directory: {:?} with error: {}", parent, e))?;
+        }
+        fs::write(path, contents).map_err(|e| format!("Failed to write the refactored file: {:?} with error: {}", path, e))
+    }
+
+    // What the files held before is in the revert record of the run
+    fn finish(&mut self) -> Result<String, String> {
+        let mut seen = std::collections::HashSet::new();
+        let overwritten = self.backups.iter().filter(|backup| seen.insert(&backup.path) && backup.previous.is_some()).count();
+        Ok(match overwritten {
+            0 => String::new(),
+            1 => "`refactor revert` puts back the file the run overwrote.".to_string(),
+            overwritten => format!("`refactor revert` puts back the {} files the run overwrote.", overwritten),
+        })
+    }
+}
+
+// Keeps the files in a map instead of writing them anywhere
+#[derive(Default)]
+pub struct Memory {
+    pub files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl OutputSink for Memory {
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), String> {
+        self.files.insert(path.to_path_buf(), contents.to_vec());
+        Ok(())
+    }
+
+    // The files are shown rather than written, as a dry run
+    fn finish(&mut self) -> Result<String, String> {
+        let mut listing = String::new();
+        for (path, contents) in &self.files {
+            listing.push_str(&format!("==> {} <==\n{}\n", path.display(), String::from_utf8_lossy(contents).trim_end()));
+        }
+        Ok(listing)
+    }
+}
+
+// Prints the files for another program to read, such as an editor filter: each one after a line
+// naming it, or all of them as one JSON object `{"files": [{"path": ..., "contents": ...}]}`
+#[derive(Default)]
+pub struct Stdout {
+    pub json: bool,
+    files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl OutputSink for Stdout {
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), String> {
+        self.files.push((path.to_path_buf(), contents.to_vec()));
+        Ok(())
+    }
+
+    // Unlike the listing of `Memory`, the contents are printed exactly as they would be written
+    fn finish(&mut self) -> Result<String, String> {
+        if !self.json {
+            let mut listing = String::new();
+            for (path, contents) in &self.files {
+                listing.push_str(&format!("==> {} <==\n{}", path.display(), String::from_utf8_lossy(contents)));
+            }
+            return Ok(listing);
+        }
+        let files: Vec<String> = self
+            .files
+            .iter()
+            .map(|(path, contents)| {
+                format!(
+                    "    {{\"path\": \"{}\", \"contents\": \"{}\"}}",
+                    json_escape(&path.display().to_string()),
+                    json_escape(&String::from_utf8_lossy(contents))
+                )
+            })
+            .collect();
+        Ok(format!("{{\"files\": [\n{}\n]}}\n", files.join(",\n")))
+    }
+}
+
+// Function to write text as the contents of a JSON string
+pub fn json_escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Commits the files on top of HEAD to a new branch, through an index of its own so that neither
+// the working tree nor the user's index change, and no branch that exists is moved
+pub struct GitIndex {
+    repo_root: PathBuf,
+    index_file: PathBuf,
+    parent: Option<String>,
+    branch: String,
+}
+
+impl GitIndex {
+    // Function to start a commit in the repository containing `dir`
+    pub fn new(dir: &Path) -> Result<GitIndex, String> {
+        let dir = if dir.as_os_str().is_empty() { Path::new(".") } else { dir };
+        let repo_root = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"], None, None)?);
+        let index_file = repo_root.join(git(&repo_root, &["rev-parse", "--git-path", "refactor-index"], None, None)?);
+        let parent = git(&repo_root, &["rev-parse", "--verify", "--quiet", "HEAD"], None, None).ok();
+        // The branch checked out is taken even before its first commit
+        let checked_out = git(&repo_root, &["symbolic-ref", "--quiet", "HEAD"], None, None).ok();
+        let branch = std::iter::once(GIT_BRANCH.to_string())
+            .chain((2..).map(|n| format!("{}-{}", GIT_BRANCH, n)))
+            .find(|branch| {
+                let reference = format!("refs/heads/{}", branch);
+                checked_out.as_deref() != Some(reference.as_str()) && git(&repo_root, &["rev-parse", "--verify", "--quiet", &reference], None, None).is_err()
+            })
+            .expect("Some branch name is free");
+
+        let sink = GitIndex { repo_root, index_file, parent, branch };
+        match &sink.parent {
+            Some(parent) => sink.git(&["read-tree", parent], None)?,
+            None => sink.git(&["read-tree", "--empty"], None)?,
+        };
+        Ok(sink)
+    }
+
+    fn git(&self, args: &[&str], stdin: Option<&[u8]>) -> Result<String, String> {
+        git(&self.repo_root, args, Some(&self.index_file), stdin)
+    }
+
+    // Function to turn the path of a generated file into one relative to the repository root
+    fn repo_path(&self, path: &Path) -> Result<String, String> {
+        let absolute = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            env::current_dir().map_err(|e| format!("Failed to get the current directory: {}", e))?.join(path)
+        };
+        // Files that don't exist yet are resolved through the closest directory that does
+        let existing = absolute.ancestors().find(|ancestor| ancestor.exists()).unwrap_or(&absolute);
+        let canonical = fs::canonicalize(existing).map_err(|e| format!("Failed to resolve {:?}: {}", existing, e))?;
+        let canonical = canonical.join(absolute.strip_prefix(existing).unwrap_or(Path::new("")));
+        let repo_root = fs::canonicalize(&self.repo_root).unwrap_or_else(|_| self.repo_root.clone());
+        let relative = canonical
+            .strip_prefix(&repo_root)
+            .map_err(|_| format!("{:?} is outside of the git repository at {:?}", path, self.repo_root))?;
+        Ok(relative.to_string_lossy().replace('\\', "/"))
+    }
+}
+
+impl OutputSink for GitIndex {
+    fn write(&mut self, path: &Path, contents: &[u8]) -> Result<(), String> {
+        let repo_path = self.repo_path(path)?;
+        let blob = self.git(&["hash-object", "-w", "--stdin"], Some(contents))?;
+        self.git(&["update-index", "--add", "--cacheinfo", &format!("100644,{},{}", blob, repo_path)], None)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<String, String> {
+        let tree = self.git(&["write-tree"], None)?;
+        let mut args = vec!["commit-tree", &tree, "-m", "Split the crate into modules"];
+        if let Some(parent) = &self.parent {
+            args.extend(["-p", parent]);
+        }
+        let commit = self.git(&args, None)?;
+        let _ = fs::remove_file(&self.index_file);
+        // The empty old value makes git refuse a branch that was created since the name was picked
+        self.git(&["update-ref", "--create-reflog", "-m", "refactor", &format!("refs/heads/{}", self.branch), &commit, ""], None)?;
+        Ok(format!("Committed the generated files as {} on the new branch `{}`; the working tree is unchanged.", &commit[..12.min(commit.len())], self.branch))
+    }
+}
+
+// Function to run a git command in `dir`, optionally on another index and with input, returning
+// its trimmed output
+fn git(dir: &Path, args: &[&str], index_file: Option<&Path>, stdin: Option<&[u8]>) -> Result<String, String> {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(dir).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped());
+    if let Some(index_file) = index_file {
+        command.env("GIT_INDEX_FILE", index_file);
+    }
+    let mut child = command.spawn().map_err(|e| format!("Failed to run git: {}", e))?;
+    if let Some(mut child_stdin) = child.stdin.take() {
+        child_stdin
+            .write_all(stdin.unwrap_or_default())
+            .map_err(|e| format!("Failed to pass input to git: {}", e))?;
+    }
+    let output = child.wait_with_output().map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim_end()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_keeps_the_last_write_of_each_file_and_lists_them_by_path() {
+        let mut memory = Memory::default();
+        memory.write(Path::new("src/util_mod.rs"), b"fn old() {}\n").unwrap();
+        memory.write(Path::new("src/file_io_mod.rs"), b"fn read() {}\n").unwrap();
+        memory.write(Path::new("src/util_mod.rs"), b"fn parse() {}\n").unwrap();
+        assert_eq!(memory.files.len(), 2);
+        assert_eq!(
+            memory.finish().unwrap(),
+            "==> src/file_io_mod.rs <==\nfn read() {}\n==> src/util_mod.rs <==\nfn parse() {}\n"
+        );
+    }
+}
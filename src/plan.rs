@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use quote::ToTokens;
+use syn::ItemFn;
+
+// File the plan of every run is written to, relative to the root of the package it writes to
+pub const PLAN_FILE: &str = ".refactor/refactor.plan";
+// Line of a plan's header naming the input it splits
+const INPUT_HEADER: &str = "# Input: ";
+// File the groups chosen by hand in applied plans are remembered in, keyed by item fingerprint,
+// next to the plan
+pub const OVERRIDES_FILE: &str = ".refactor/overrides";
+
+// Where one function was placed, and where the tool would have placed it on its own
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "schema", rename = "refactor/plan-entry/v1"))]
+pub struct PlanEntry {
+    pub item_path: Vec<String>,
+    pub fingerprint: String,
+    pub proposed: String,
+    pub group: String,
+    // Why the proposal differs from what the grouping alone chose, such as a merged small group
+    pub rationale: Option<String>,
+}
+
+// Groups chosen by hand, either in a plan being applied or remembered from earlier plans
+#[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "schema", rename = "refactor/assignments/v1", default))]
+pub struct Assignments {
+    // Keyed by item path, such as `net::read_file`
+    pub by_path: HashMap<String, String>,
+    pub by_fingerprint: HashMap<String, String>,
+}
+
+impl Assignments {
+    // Function to find the group chosen by hand for an item, preferring the plan being applied
+    pub fn group_for(&self, item_path: &[String], fingerprint: &str) -> Option<&String> {
+        self.by_path
+            .get(&item_path.join("::"))
+            .or_else(|| self.by_fingerprint.get(fingerprint))
+    }
+}
+
+// Function to fingerprint a function by its module path and signature, so that the fingerprint
+// survives edits to its body and to the rest of the file. FNV-1a keeps it stable across toolchains
+pub fn fingerprint(item_path: &[String], func: &ItemFn) -> String {
+    let key = format!("{}|{}", item_path.join("::"), func.sig.to_token_stream());
+    format!("{:016x}", stable_hash(&key))
+}
+
+// Function to hash text with FNV-1a, which unlike the std hashers gives the same hash on every
+// toolchain and run
+pub fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+// Function to describe what a run needs to be reproduced: the tool version, the seed of its tie
+// breaking and a hash of the options shaping its output, as written by `config_key`
+pub fn reproducibility(seed: u64, config_key: &str) -> String {
+    format!("refactor {}, seed {}, config {:016x}", env!("CARGO_PKG_VERSION"), seed, stable_hash(config_key))
+}
+
+// Function to read `key = group` lines, skipping blank lines and `#` comments. A comment starts
+// a line or follows whitespace, so that raw identifiers such as `r#type` are read as keys
+pub fn read_assignments(path: &Path) -> HashMap<String, String> {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let line = line.split(" #").next().unwrap_or_default();
+            let (key, group) = line.split_once('=')?;
+            Some((key.trim().to_string(), group.trim().to_string()))
+        })
+        .filter(|(key, group)| !key.is_empty() && !group.is_empty())
+        .collect()
+}
+
+// Function to read the decisions of a written plan, by item path, with the group each item went to
+// and the rationale noted after it, if any
+pub fn read_decisions(path: &Path) -> HashMap<String, (String, Option<String>)> {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| {
+            let (line, rationale) = match line.split_once("  # ") {
+                Some((line, rationale)) => (line, Some(rationale.trim().to_string())),
+                None => (line, None),
+            };
+            let (key, group) = line.split_once('=')?;
+            Some((key.trim().to_string(), (group.trim().to_string(), rationale)))
+        })
+        .collect()
+}
+
+// Function to render the plan sorted by item path, for the user to edit and apply with
+// `refactor apply`, headed by the input it splits and what the run needs to be reproduced
+pub fn render_plan(entries: &[PlanEntry], reproducibility: &str, input: &str) -> String {
+    let mut lines: Vec<String> = entries
+        .iter()
+        .map(|entry| match &entry.rationale {
+            Some(rationale) => format!("{} = {}  # {}", entry.item_path.join("::"), entry.group, rationale),
+            None => format!("{} = {}", entry.item_path.join("::"), entry.group),
+        })
+        .collect();
+    lines.sort();
+    format!(
+        "# Where each function goes. Change a group (or rename one on all of its lines) and\n# apply it with `refactor apply {}`; the changes are remembered for future runs\n{}{}\n# Made by {}\n{}\n",
+        PLAN_FILE,
+        INPUT_HEADER,
+        input,
+        reproducibility,
+        lines.join("\n")
+    )
+}
+
+// Function to name the input of the plan at `plan_path` as its header does: relative to the plan
+// when they are in the same package, and by its absolute path otherwise
+pub fn input_name(plan_path: &Path, input: &Path) -> String {
+    let input = fs::canonicalize(input).unwrap_or_else(|_| input.to_path_buf());
+    let plan_dir = plan_path.parent().unwrap_or(Path::new("."));
+    let package_root = plan_dir.parent().and_then(|root| fs::canonicalize(root).ok());
+    match package_root.as_deref().and_then(|root| input.strip_prefix(root).ok()) {
+        Some(relative) => Path::new("..").join(relative).display().to_string(),
+        None => input.display().to_string(),
+    }
+}
+
+// Function to find the input a plan was made for, which its header names relative to the plan
+pub fn planned_input(plan_path: &Path) -> Result<PathBuf, String> {
+    let content = fs::read_to_string(plan_path).map_err(|e| format!("Failed to read {:?}: {}", plan_path, e))?;
+    let input = content
+        .lines()
+        .find_map(|line| line.strip_prefix(INPUT_HEADER))
+        .ok_or_else(|| format!("{:?} doesn't name the input it was made for; pass the input with --plan instead", plan_path))?;
+    Ok(normalize(&plan_path.parent().unwrap_or(Path::new(".")).join(input.trim())))
+}
+
+// Function to drop the `..` of a path against the directories before them, so that the input of
+// `.refactor/refactor.plan` reads `src/main.rs` rather than `.refactor/../src/main.rs`. The plan's
+// directory is the tool's own, so no symlink makes this differ from what the filesystem resolves
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    if normalized.as_os_str().is_empty() {
+        normalized.push(".");
+    }
+    normalized
+}
+
+// Function to render the remembered overrides after applying `plan`: items placed differently
+// than the tool proposed are remembered, and items put back where it proposed are forgotten
+pub fn render_overrides(remembered: &HashMap<String, String>, entries: &[PlanEntry], plan: &HashMap<String, String>) -> String {
+    let mut overrides: BTreeMap<String, (String, String)> = remembered
+        .iter()
+        .map(|(fingerprint, group)| (fingerprint.clone(), (group.clone(), String::new())))
+        .collect();
+
+    for entry in entries {
+        let item_path = entry.item_path.join("::");
+        let Some(group) = plan.get(&item_path) else {
+            continue;
+        };
+        if *group == entry.proposed {
+            overrides.remove(&entry.fingerprint);
+        } else {
+            overrides.insert(entry.fingerprint.clone(), (group.clone(), item_path));
+        }
+    }
+
+    // Keep the item path next to the fingerprint so the file stays readable
+    for entry in entries {
+        if let Some((_, item_path)) = overrides.get_mut(&entry.fingerprint) {
+            *item_path = entry.item_path.join("::");
+        }
+    }
+
+    let lines: Vec<String> = overrides
+        .iter()
+        .map(|(fingerprint, (group, item_path))| {
+            if item_path.is_empty() {
+                format!("{} = {}", fingerprint, group)
+            } else {
+                format!("{} = {}  # {}", fingerprint, group, item_path)
+            }
+        })
+        .collect();
+    format!("# Groups chosen by hand in applied plans, re-applied automatically\n{}\n", lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(item_path: &str, fingerprint: &str, proposed: &str, group: &str) -> PlanEntry {
+        PlanEntry {
+            item_path: item_path.split("::").map(String::from).collect(),
+            fingerprint: fingerprint.to_string(),
+            proposed: proposed.to_string(),
+            group: group.to_string(),
+            rationale: None,
+        }
+    }
+
+    #[test]
+    fn plans_read_back_as_written() {
+        let mut merged = entry("util::parse", "02", "parse", "misc");
+        merged.rationale = Some("merged from `parse`, which has fewer than 2 functions".to_string());
+        let entries = [entry("read_file", "01", "io", "io"), merged, entry("r#type", "03", "general", "general")];
+        let dir = std::env::temp_dir().join(format!("refactor-plan-{}", std::process::id()));
+        let plan_path = dir.join(PLAN_FILE);
+        fs::create_dir_all(plan_path.parent().unwrap()).unwrap();
+        fs::write(&plan_path, render_plan(&entries, "refactor 0.1.0, seed 0, config 0", "../src/main.rs")).unwrap();
+
+        let assignments = read_assignments(&plan_path);
+        assert_eq!(assignments.len(), 3);
+        assert_eq!(assignments["util::parse"], "misc");
+        assert_eq!(assignments["r#type"], "general");
+        let decisions = read_decisions(&plan_path);
+        assert_eq!(decisions["util::parse"].1.as_deref(), Some("merged from `parse`, which has fewer than 2 functions"));
+        assert_eq!(decisions["read_file"], ("io".to_string(), None));
+        assert_eq!(planned_input(&plan_path).unwrap(), dir.join("src/main.rs"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn paths_are_normalized_against_the_directories_before_them() {
+        assert_eq!(normalize(Path::new(".refactor/../src/main.rs")), Path::new("src/main.rs"));
+        assert_eq!(normalize(Path::new("/pkg/.refactor/../../lib.rs")), Path::new("/lib.rs"));
+        assert_eq!(normalize(Path::new("../../main.rs")), Path::new("../../main.rs"));
+        assert_eq!(normalize(Path::new("./.refactor/..")), Path::new("."));
+    }
+
+    #[test]
+    fn overrides_remember_only_what_differs_from_the_proposal() {
+        let remembered = HashMap::from([("01".to_string(), "net".to_string()), ("09".to_string(), "gone".to_string())]);
+        let entries = [entry("read_file", "01", "io", "io"), entry("parse", "02", "util", "util")];
+        let plan = HashMap::from([("read_file".to_string(), "io".to_string()), ("parse".to_string(), "io".to_string())]);
+        let overrides = render_overrides(&remembered, &entries, &plan);
+        // Put back where the tool proposed, `read_file` is forgotten; items not in the plan are kept
+        assert!(!overrides.contains("01 ="), "{}", overrides);
+        assert!(overrides.contains("\n02 = io  # parse\n"), "{}", overrides);
+        assert!(overrides.contains("\n09 = gone\n"), "{}", overrides);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn plan_entries_serialize_with_their_schema() {
+        let entry = entry("net::read_file", "0123456789abcdef", "io", "net");
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(json["schema"], "refactor/plan-entry/v1");
+
+        let mut json = json;
+        json["added_later"] = serde_json::Value::Bool(true);
+        let read: PlanEntry = serde_json::from_value(json).unwrap();
+        assert_eq!(read.item_path, entry.item_path);
+        assert_eq!(read.group, "net");
+    }
+}
@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::spans::PlanSpan;
+use crate::tree::ModuleTree;
+use crate::GeneratedFile;
+
+// Function to escape text for an HTML page, in element content as well as in attributes
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+// Function to write the original source with every planned function in a span whose tooltip
+// says where it goes and why
+fn annotated_source(source: &str, spans: &[PlanSpan]) -> String {
+    let mut sorted: Vec<&PlanSpan> = spans.iter().collect();
+    sorted.sort_by_key(|span| span.range.start);
+    let mut html = String::new();
+    let mut position = 0;
+    for span in sorted {
+        // Spans never overlap, but a function swallowed by an earlier span is left unmarked
+        if span.range.start < position {
+            continue;
+        }
+        html.push_str(&escape(&source[position..span.range.start]));
+        let tooltip = match span.rationale {
+            Some(rationale) => format!("{} goes to {}: {}", span.item_path.join("::"), span.group, rationale),
+            None => format!("{} goes to {}", span.item_path.join("::"), span.group),
+        };
+        html.push_str(&format!("<span class=\"planned\" title=\"{}\">", escape(&tooltip)));
+        html.push_str(&escape(&source[span.range.clone()]));
+        html.push_str("</span>");
+        position = span.range.end;
+    }
+    html.push_str(&escape(&source[position..]));
+    html
+}
+
+// Function to write the proposed tree as nested lists, each module linking to the file that
+// holds it. Inline modules have no file of their own and aren't links
+fn tree_list(tree: &ModuleTree, module_path: &mut Vec<String>, anchors: &HashMap<Vec<String>, usize>, html: &mut String) {
+    let label = format!("{} ({})", tree.name, tree.item_count);
+    match anchors.get(module_path.as_slice()) {
+        Some(index) => html.push_str(&format!("<li><a href=\"#file-{}\">{}</a>", index, escape(&label))),
+        None => html.push_str(&format!("<li>{}", escape(&label))),
+    }
+    let mut children: Vec<&ModuleTree> = tree.children.iter().collect();
+    children.sort_by(|a, b| a.name.cmp(&b.name));
+    if !children.is_empty() {
+        html.push_str("<ul>");
+        for child in children {
+            module_path.push(child.name.clone());
+            tree_list(child, module_path, anchors, html);
+            module_path.pop();
+        }
+        html.push_str("</ul>");
+    }
+    html.push_str("</li>");
+}
+
+// Function to build a page showing the input next to the files the refactoring would write,
+// which needs no server and no script to view
+pub fn render(input_path: &Path, source: &str, spans: &[PlanSpan], files: &[GeneratedFile], root_path: &Path, proposed: &ModuleTree) -> String {
+    // Generated modules by their path, and the root by the empty one
+    let anchors: HashMap<Vec<String>, usize> = files
+        .iter()
+        .enumerate()
+        .filter(|(_, file)| !file.module_path.is_empty() || file.path == root_path)
+        .map(|(index, file)| (file.module_path.clone(), index))
+        .collect();
+    let mut tree = String::new();
+    tree_list(proposed, &mut Vec::new(), &anchors, &mut tree);
+
+    let mut sections = String::new();
+    for (index, file) in files.iter().enumerate() {
+        sections.push_str(&format!(
+            "<section id=\"file-{}\"><h2>{}</h2><pre>{}</pre></section>\n",
+            index,
+            escape(&file.path.display().to_string()),
+            escape(&file.code)
+        ));
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Refactoring of {input}</title>
+<style>
+body {{ margin: 0; display: flex; height: 100vh; font-family: sans-serif; }}
+nav {{ width: 16em; overflow: auto; padding: 0 1em; border-right: 1px solid #ccc; }}
+nav ul {{ padding-left: 1.2em; }}
+.pane {{ flex: 1; overflow: auto; padding: 0 1em; }}
+.pane + .pane {{ border-left: 1px solid #ccc; }}
+pre {{ font-size: 13px; }}
+.planned {{ background: #eef4ff; cursor: help; }}
+.planned:hover {{ background: #d6e4ff; }}
+</style>
+</head>
+<body>
+<nav><h2>Proposed modules</h2><ul>{tree}</ul></nav>
+<div class="pane"><h2>Before: {input}</h2><pre>{source}</pre></div>
+<div class="pane">
+{sections}</div>
+</body>
+</html>
+"#,
+        input = escape(&input_path.display().to_string()),
+        tree = tree,
+        source = annotated_source(source, spans),
+        sections = sections,
+    )
+}
+
+// Function to find where the preview of `input_path` goes: a file of the temporary directory,
+// named after the input so that previews of different files don't replace each other
+pub fn preview_path(input_path: &Path) -> PathBuf {
+    let stem = input_path.file_stem().unwrap_or_default().to_string_lossy();
+    let hash = crate::plan::stable_hash(&input_path.display().to_string());
+    std::env::temp_dir().join(format!("refactor-preview-{}-{:08x}.html", stem, hash as u32))
+}
+
+// Function to open a page in the default browser, without waiting for it
+pub fn open(path: &Path) -> Result<(), String> {
+    let (program, args): (&str, &[&str]) = if cfg!(target_os = "macos") {
+        ("open", &[])
+    } else if cfg!(windows) {
+        ("cmd", &["/C", "start", ""])
+    } else {
+        ("xdg-open", &[])
+    };
+    Command::new(program)
+        .args(args)
+        .arg(path)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to run {}: {}", program, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(name: &str, children: Vec<ModuleTree>) -> ModuleTree {
+        ModuleTree { name: name.to_string(), visibility: String::new(), item_count: 1, children }
+    }
+
+    #[test]
+    fn planned_functions_are_marked_and_escaped() {
+        let source = "fn a() -> Vec<u8> {}\nfn b() {}\n";
+        let path = vec!["a".to_string()];
+        let inner = vec!["a".to_string(), "x".to_string()];
+        let spans = vec![
+            PlanSpan { item_path: &path, range: 0..20, group: "util", rationale: Some("a \"helper\"") },
+            PlanSpan { item_path: &inner, range: 3..4, group: "util", rationale: None },
+        ];
+        assert_eq!(
+            annotated_source(source, &spans),
+            "<span class=\"planned\" title=\"a goes to util: a &quot;helper&quot;\">fn a() -&gt; Vec&lt;u8&gt; {}</span>\nfn b() {}\n"
+        );
+    }
+
+    #[test]
+    fn only_modules_with_a_file_are_linked() {
+        let files = vec![
+            GeneratedFile { path: PathBuf::from("src/tmp_main.rs"), code: "mod util;".to_string(), module_path: Vec::new(), written_in: Vec::new() },
+            GeneratedFile { path: PathBuf::from("src/util.rs"), code: "mod inline {}".to_string(), module_path: vec!["util".to_string()], written_in: Vec::new() },
+        ];
+        let proposed = tree("crate", vec![tree("util", vec![tree("inline", Vec::new())])]);
+        let page = render(Path::new("src/main.rs"), "", &[], &files, Path::new("src/tmp_main.rs"), &proposed);
+        assert!(page.contains("<li><a href=\"#file-0\">crate (1)</a><ul><li><a href=\"#file-1\">util (1)</a><ul><li>inline (1)</li></ul></li></ul></li>"), "{}", page);
+        assert!(page.contains("<section id=\"file-1\"><h2>src/util.rs</h2><pre>mod inline {}</pre></section>"), "{}", page);
+    }
+
+    #[test]
+    fn previews_of_different_inputs_dont_replace_each_other() {
+        let path = preview_path(Path::new("src/main.rs"));
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("refactor-preview-main-"));
+        assert_eq!(path, preview_path(Path::new("src/main.rs")));
+        assert_ne!(path, preview_path(Path::new("src/bin/main.rs")));
+    }
+}
@@ -0,0 +1,105 @@
+use std::fmt::Arguments;
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::log;
+
+// Runs shorter than this never show their progress, which would only flicker
+const QUIET_START: Duration = Duration::from_millis(300);
+// Inputs of at least this many lines take long enough to parse that parsing them is shown at once
+pub const LARGE_INPUT_LINES: usize = 10_000;
+// The status line is redrawn at most this often
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+// When the run started, when the status line was last drawn, and whether it is on screen
+struct Status {
+    started: Option<Instant>,
+    drawn: Option<Instant>,
+    shown: bool,
+}
+
+impl Status {
+    // Function to check whether a status line is drawn at `now`: not before the run has taken
+    // long enough, nor sooner than the redraw interval after the last one, unless it is shown at once
+    fn due(&self, now: Instant, at_once: bool) -> bool {
+        let Some(started) = self.started else {
+            return false;
+        };
+        at_once || (now - started >= QUIET_START && self.drawn.is_none_or(|drawn| now - drawn >= REDRAW_INTERVAL))
+    }
+}
+
+static STATUS: Mutex<Status> = Mutex::new(Status { started: None, drawn: None, shown: false });
+
+// Function to start the clock of the run, whose progress is shown from then on
+pub fn start() {
+    STATUS.lock().expect("The progress was being shown by a thread that panicked").started = Some(Instant::now());
+}
+
+// Function to check whether progress is shown: only on a terminal, and not while the phases log,
+// since their lines would break up the status line
+fn enabled() -> bool {
+    std::io::stderr().is_terminal() && !log::enabled(log::Level::Info)
+}
+
+// Function to show what the run is doing on a status line of stderr, in place of the last one,
+// once the run has taken long enough for it to be worth watching
+pub fn status(message: Arguments) {
+    show(message, false);
+}
+
+// Function to show the status line right away, for a phase known to take long
+pub fn announce(message: Arguments) {
+    show(message, true);
+}
+
+fn show(message: Arguments, at_once: bool) {
+    if !enabled() {
+        return;
+    }
+    let mut status = STATUS.lock().expect("The progress was being shown by a thread that panicked");
+    let now = Instant::now();
+    if !status.due(now, at_once) {
+        return;
+    }
+    eprint!("\r\x1b[K{}", message);
+    let _ = std::io::stderr().flush();
+    status.drawn = Some(now);
+    status.shown = true;
+}
+
+// Function to show how far a phase over `total` things has come
+pub fn step(phase: &str, done: usize, total: usize) {
+    status(format_args!("{} {}/{}", phase, done, total));
+}
+
+// Function to take the status line off the screen before anything else is printed
+pub fn clear() {
+    let mut status = STATUS.lock().expect("The progress was being shown by a thread that panicked");
+    if status.shown {
+        eprint!("\r\x1b[K");
+        let _ = std::io::stderr().flush();
+        status.shown = false;
+        status.drawn = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_lines_wait_for_long_runs_and_between_redraws() {
+        let started = Instant::now();
+        let mut status = Status { started: None, drawn: None, shown: false };
+        assert!(!status.due(started + QUIET_START, true));
+        status.started = Some(started);
+        assert!(!status.due(started + QUIET_START / 2, false));
+        assert!(status.due(started + QUIET_START / 2, true));
+        assert!(status.due(started + QUIET_START, false));
+        status.drawn = Some(started + QUIET_START);
+        assert!(!status.due(started + QUIET_START + REDRAW_INTERVAL / 2, false));
+        assert!(status.due(started + QUIET_START + REDRAW_INTERVAL, false));
+    }
+}
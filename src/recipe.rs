@@ -0,0 +1,128 @@
+use crate::like::ModelModule;
+
+// A ready-made set of options for a common shape of large file. The flags given along with it
+// change what it sets, so a recipe is a starting point rather than a mode of its own
+pub struct Recipe {
+    pub name: &'static str,
+    pub summary: &'static str,
+    // Modules functions go to by the words of their names, as with --like, most specific first
+    pub modules: &'static [(&'static str, &'static [&'static str])],
+    pub strategies: &'static str,
+    pub min_module_items: usize,
+    // Small groups that don't make a module of their own are merged into this one
+    pub misc_module: &'static str,
+}
+
+pub const RECIPES: &[Recipe] = &[
+    Recipe {
+        name: "cli-monolith",
+        summary: "a command-line tool in one file: argument handling, input and output, and the logic in between",
+        modules: &[
+            ("cli", &["args", "arg", "argv", "cli", "command", "subcommand", "flag", "flags", "option", "options", "usage", "help", "opts"]),
+            ("io", &["read", "write", "file", "files", "load", "save", "open", "print", "path", "dir", "stdin", "stdout", "output", "input"]),
+            ("domain", &["process", "compute", "calculate", "apply", "validate", "transform", "run", "execute", "evaluate", "score"]),
+        ],
+        strategies: "fn=keywords,type=clusters",
+        min_module_items: 2,
+        misc_module: "domain",
+    },
+    Recipe {
+        name: "game-loop",
+        summary: "a game or simulation around a main loop: input, world updates, physics, rendering and sound",
+        modules: &[
+            ("input", &["input", "key", "keys", "mouse", "event", "events", "press", "pressed", "gamepad", "controller"]),
+            ("render", &["draw", "render", "sprite", "frame", "screen", "color", "texture", "camera", "window", "pixel"]),
+            ("physics", &["collide", "collision", "collisions", "velocity", "physics", "gravity", "position", "bounds", "intersect"]),
+            ("world", &["update", "tick", "spawn", "despawn", "entity", "entities", "world", "level", "player", "enemy", "score"]),
+            ("audio", &["sound", "audio", "music", "volume", "play"]),
+        ],
+        strategies: "fn=keywords,type=clusters,impl=with-type",
+        min_module_items: 2,
+        misc_module: "game",
+    },
+    Recipe {
+        name: "web-service",
+        summary: "an HTTP service: routes and handlers, authentication, storage and configuration",
+        modules: &[
+            ("auth", &["auth", "login", "logout", "token", "session", "password", "permission", "jwt"]),
+            ("routes", &["route", "routes", "handler", "handle", "endpoint", "request", "response", "get", "post", "put", "delete", "api"]),
+            ("db", &["database", "query", "insert", "select", "sql", "migrate", "migration", "repo", "repository", "store", "row"]),
+            ("config", &["config", "env", "setting", "settings"]),
+        ],
+        strategies: "fn=calls,type=clusters",
+        min_module_items: 2,
+        misc_module: "service",
+    },
+];
+
+// Function to find the recipe of a name
+pub fn find(name: &str) -> Option<&'static Recipe> {
+    RECIPES.iter().find(|recipe| recipe.name == name)
+}
+
+impl Recipe {
+    // Function to describe the recipe's modules the way --like describes a model crate's
+    pub fn model_modules(&self) -> Vec<ModelModule> {
+        self.modules.iter().map(|(name, words)| ModelModule::from_words(name, words)).collect()
+    }
+
+    // Function to write the flags the recipe stands for, which are the ones to change it with
+    pub fn flags(&self) -> String {
+        format!("--strategy {} --min-module-items {} --misc-module {}", self.strategies, self.min_module_items, self.misc_module)
+    }
+}
+
+// Function to print every recipe with what it is for, the modules it splits into and its flags
+pub fn print_recipes() {
+    for recipe in RECIPES {
+        println!("{}: {}", recipe.name, recipe.summary);
+        for (name, words) in recipe.modules {
+            println!("  {:<10} {}", name, words.join(", "));
+        }
+        println!("  flags: {}", recipe.flags());
+        println!();
+    }
+    println!("Use one with `refactor --recipe <name> <input_file>`; flags given along with it change what it sets.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn every_recipe_is_found_and_its_flags_parse() {
+        for recipe in RECIPES {
+            assert!(find(recipe.name).is_some_and(|found| found.name == recipe.name));
+            let options = crate::parse_args(&args(&["--recipe", recipe.name, "src/main.rs"]), &[]).unwrap();
+            let flags: Vec<String> = recipe.flags().split(' ').map(String::from).collect();
+            let flagged = crate::parse_args(&[flags, args(&["src/main.rs"])].concat(), &[]).unwrap();
+            assert_eq!(crate::strategy::spec(&options.strategies), crate::strategy::spec(&flagged.strategies));
+            assert_eq!((options.min_module_items, &options.misc_module), (flagged.min_module_items, &flagged.misc_module));
+        }
+        assert!(find("kitchen-sink").is_none());
+    }
+
+    #[test]
+    fn no_word_leads_to_two_modules_of_a_recipe() {
+        for recipe in RECIPES {
+            let mut words: Vec<&str> = recipe.modules.iter().flat_map(|(_, words)| words.iter().copied()).collect();
+            let count = words.len();
+            words.sort();
+            words.dedup();
+            assert_eq!(words.len(), count, "{}", recipe.name);
+        }
+    }
+
+    #[test]
+    fn flags_given_along_with_a_recipe_change_it() {
+        let options = crate::parse_args(&args(&["--min-module-items", "5", "--recipe", "cli-monolith", "src/main.rs"]), &[]).unwrap();
+        assert_eq!(options.min_module_items, 5);
+        assert_eq!(options.misc_module, "domain");
+        let error = crate::parse_args(&args(&["--recipe", "kitchen-sink", "src/main.rs"]), &[]).err().unwrap();
+        assert!(error.contains("there is no recipe `kitchen-sink`"), "{}", error);
+    }
+}
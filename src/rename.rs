@@ -0,0 +1,324 @@
+use std::collections::HashSet;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use syn::spanned::Spanned;
+use syn::visit::Visit;
+use syn::{Ident, Item, ItemMod, ItemUse, Macro, UseTree};
+
+use crate::{doc_links, idents, macro_args, parsed_source, tree};
+
+// Collects the edits needed in one file to rename the module at `old_path` to `new_name`
+struct RenameVisitor<'a> {
+    source: &'a str,
+    old_path: &'a [String],
+    new_name: &'a str,
+    // Module path of the module currently being visited, including inline modules
+    module_path: Vec<String>,
+    // Names under which the renamed module was imported into each module on the stack
+    aliases: Vec<HashSet<String>>,
+    // Whether the doc comment lines checked last left a fenced code block open
+    in_code_block: bool,
+    edits: Vec<Range<usize>>,
+}
+
+impl RenameVisitor<'_> {
+    // Function to enter a module, remembering under which names it imports the renamed module
+    fn enter_module(&mut self, items: &[Item]) {
+        let mut aliases = HashSet::new();
+        for item in items {
+            if let Item::Use(item_use) = item {
+                let mut prefix = Vec::new();
+                self.collect_aliases(&item_use.tree, &mut prefix, &mut aliases);
+            }
+        }
+        self.aliases.push(aliases);
+    }
+
+    fn collect_aliases(&self, tree: &UseTree, prefix: &mut Vec<Ident>, aliases: &mut HashSet<String>) {
+        match tree {
+            UseTree::Path(use_path) => {
+                prefix.push(use_path.ident.clone());
+                self.collect_aliases(&use_path.tree, prefix, aliases);
+                prefix.pop();
+            }
+            UseTree::Name(use_name) => {
+                prefix.push(use_name.ident.clone());
+                if self.names_renamed_module(prefix) {
+                    aliases.insert(self.old_path.last().cloned().unwrap_or_default());
+                }
+                prefix.pop();
+            }
+            UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.collect_aliases(tree, prefix, aliases);
+                }
+            }
+            UseTree::Rename(_) | UseTree::Glob(_) => {}
+        }
+    }
+
+    // Function to find which of `segments` names the renamed module, given that the path is
+    // written inside the module currently being visited
+    fn renamed_segment(&self, segments: &[Ident]) -> Option<usize> {
+        let first = segments.first()?.to_string();
+        let (mut base, mut consumed) = match first.as_str() {
+            "crate" => (Vec::new(), 1),
+            "self" => (self.module_path.clone(), 1),
+            _ => (self.module_path.clone(), 0),
+        };
+        while segments.get(consumed).is_some_and(|segment| segment == "super") {
+            base.pop()?;
+            consumed += 1;
+        }
+
+        // A module imported under its own name resolves like a path through its parent
+        let renamed_index = self.old_path.len() - 1;
+        let aliased = self.aliases.last().is_some_and(|aliases| aliases.contains(&first));
+        if consumed == 0 && aliased {
+            base = self.old_path[..renamed_index].to_vec();
+        }
+
+        // The renamed module has to be spelled out in the path rather than implied by its base
+        if renamed_index < base.len() {
+            return None;
+        }
+        let mut path = base.clone();
+        path.extend(segments[consumed..].iter().map(Ident::to_string));
+        if !path.starts_with(self.old_path) {
+            return None;
+        }
+        Some(renamed_index - base.len() + consumed)
+    }
+
+    // Function to check whether `segments` import the renamed module itself
+    fn names_renamed_module(&self, segments: &[Ident]) -> bool {
+        self.renamed_segment(segments) == Some(segments.len() - 1)
+    }
+
+    // Function to record an edit if `segments` spell out a path through the renamed module
+    fn check_path(&mut self, segments: &[Ident]) {
+        if let Some(index) = self.renamed_segment(segments) {
+            self.edits.push(segments[index].span().byte_range());
+        }
+    }
+
+    fn visit_use_tree_with_prefix(&mut self, tree: &UseTree, prefix: &mut Vec<Ident>) {
+        match tree {
+            UseTree::Path(use_path) => {
+                prefix.push(use_path.ident.clone());
+                self.visit_use_tree_with_prefix(&use_path.tree, prefix);
+                prefix.pop();
+            }
+            UseTree::Name(use_name) => {
+                prefix.push(use_name.ident.clone());
+                self.check_path(prefix);
+                prefix.pop();
+            }
+            UseTree::Rename(use_rename) => {
+                prefix.push(use_rename.ident.clone());
+                self.check_path(prefix);
+                prefix.pop();
+            }
+            UseTree::Glob(_) => self.check_path(prefix),
+            UseTree::Group(group) => {
+                for tree in &group.items {
+                    self.visit_use_tree_with_prefix(tree, prefix);
+                }
+            }
+        }
+    }
+
+    // Function to rewrite intra-doc links such as [`crate::utils::Item`] in a doc comment
+    fn check_doc_comment(&mut self, range: Range<usize>) {
+        for link in doc_links::find_doc_links(self.source, range, &mut self.in_code_block) {
+            let segments: Vec<&str> = link.path.split("::").collect();
+            if segments.len() < 2 {
+                continue;
+            }
+
+            // Doc links carry no spans of their own, so locate the renamed segment by position
+            let idents: Vec<Ident> = segments.iter().map(|segment| Ident::new(segment, proc_macro2::Span::call_site())).collect();
+            if let Some(index) = self.renamed_segment(&idents) {
+                let offset = link.range.start + segments[..index].iter().map(|segment| segment.len() + 2).sum::<usize>();
+                self.edits.push(offset..offset + segments[index].len());
+            }
+        }
+    }
+}
+
+impl Visit<'_> for RenameVisitor<'_> {
+    fn visit_item_mod(&mut self, node: &ItemMod) {
+        self.module_path.push(node.ident.to_string());
+        if self.module_path == self.old_path {
+            self.edits.push(node.ident.span().byte_range());
+        }
+        if let Some((_, items)) = &node.content {
+            self.enter_module(items);
+            syn::visit::visit_item_mod(self, node);
+            self.aliases.pop();
+        } else {
+            for attr in &node.attrs {
+                self.visit_attribute(attr);
+            }
+        }
+        self.module_path.pop();
+    }
+
+    fn visit_item_use(&mut self, node: &ItemUse) {
+        let mut prefix = Vec::new();
+        if node.leading_colon.is_none() {
+            self.visit_use_tree_with_prefix(&node.tree, &mut prefix);
+        }
+    }
+
+    fn visit_path(&mut self, node: &syn::Path) {
+        if node.leading_colon.is_none() {
+            let segments: Vec<Ident> = node.segments.iter().map(|segment| segment.ident.clone()).collect();
+            self.check_path(&segments);
+        }
+        syn::visit::visit_path(self, node);
+    }
+
+    fn visit_attribute(&mut self, node: &syn::Attribute) {
+        if node.path().is_ident("doc") {
+            self.check_doc_comment(node.span().byte_range());
+        }
+        syn::visit::visit_attribute(self, node);
+    }
+
+    fn visit_macro(&mut self, node: &Macro) {
+        for expr in &macro_args(node) {
+            self.visit_expr(expr);
+        }
+        syn::visit::visit_macro(self, node);
+    }
+}
+
+// Function to rename the module `old` (a path such as `utils` or `net::utils`) to `new_name`
+// across the crate at `crate_path`, returning a description of every change made
+pub fn rename_module(crate_path: &Path, old: &str, new_name: &str) -> Result<Vec<String>, String> {
+    idents::check_module_ident(new_name)?;
+    let root = tree::resolve_crate_root(crate_path).ok_or_else(|| format!("No crate root found at {:?}", crate_path))?;
+    let old_path: Vec<String> = old
+        .split("::")
+        .filter(|segment| *segment != "crate")
+        .map(str::to_string)
+        .collect();
+
+    let module_files = tree::crate_module_files(&root);
+    let mut changes = Vec::new();
+
+    // The module's own file (and the directory of its children) is checked to be free to take the
+    // new name before any reference to it is changed
+    let mut renames = Vec::new();
+    if let Some(module_file) = module_files.iter().find(|module_file| module_file.module_path == old_path) {
+        // A raw identifier such as `r#type` lives in `type.rs`
+        let new_file_name = new_name.strip_prefix("r#").unwrap_or(new_name);
+        let renamed_dir = module_file.module_dir.with_file_name(new_file_name);
+        if module_file.path.ends_with("mod.rs") {
+            renames.push((module_file.module_dir.clone(), renamed_dir));
+        } else {
+            renames.push((module_file.path.clone(), module_file.path.with_file_name(format!("{}.rs", new_file_name))));
+            if module_file.module_dir.is_dir() {
+                renames.push((module_file.module_dir.clone(), renamed_dir));
+            }
+        }
+    }
+    if let Some((from, to)) = renames.iter().find(|(_, to)| to.exists()) {
+        return Err(format!("Cannot rename {:?}: {:?} already exists", from, to));
+    }
+    let mut found = !renames.is_empty();
+
+    for module_file in &module_files {
+        let content = fs::read_to_string(&module_file.path).map_err(|e| format!("Failed to read {:?}: {}", module_file.path, e))?;
+        let syntax_tree = syn::parse_file(&content).map_err(|e| format!("Failed to parse {:?}: {}", module_file.path, e))?;
+        let source = parsed_source(&content);
+        let source_offset = content.len() - source.len();
+
+        let mut visitor = RenameVisitor {
+            source,
+            old_path: &old_path,
+            new_name,
+            module_path: module_file.module_path.clone(),
+            aliases: Vec::new(),
+            in_code_block: false,
+            edits: Vec::new(),
+        };
+        visitor.enter_module(&syntax_tree.items);
+        visitor.visit_file(&syntax_tree);
+
+        let mut edits = visitor.edits;
+        edits.sort_by_key(|range| range.start);
+        edits.dedup();
+        if edits.is_empty() {
+            continue;
+        }
+        found = true;
+
+        let mut updated = content.clone();
+        for range in edits.iter().rev() {
+            updated.replace_range(range.start + source_offset..range.end + source_offset, visitor.new_name);
+        }
+        fs::write(&module_file.path, updated).map_err(|e| format!("Failed to write {:?}: {}", module_file.path, e))?;
+        changes.push(format!("updated {} reference(s) in {}", edits.len(), module_file.path.display()));
+    }
+
+    // Move the module's own file (and the directory of its children) to the new name
+    for (from, to) in &renames {
+        fs::rename(from, to).map_err(|e| format!("Failed to rename {:?}: {}", from, e))?;
+        changes.push(format!("renamed {} -> {}", from.display(), to.display()));
+    }
+
+    if !found {
+        return Err(format!("Module `{}` was not found in the crate", old));
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("refactor-rename-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            fs::write(dir.join(path), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn paths_imports_and_doc_links_follow_the_renamed_module() {
+        let dir = package(
+            "references",
+            &[
+                ("src/main.rs", "mod net;\nuse net::utils;\n\n/// Calls [`crate::net::utils::send`]\nfn main() {\n    utils::send();\n    println!(\"{:?}\", crate::net::utils::send());\n}\n"),
+                ("src/net.rs", "pub mod utils;\nfn local() { self::utils::send(); super::net::utils::send(); }\n"),
+                ("src/net/utils.rs", "pub fn send() {}\n"),
+            ],
+        );
+        let changes = rename_module(&dir, "crate::net::utils", "wire").unwrap();
+        assert!(changes.last().is_some_and(|change| change.starts_with("renamed ") && change.ends_with("wire.rs")), "{:?}", changes);
+        assert_eq!(
+            fs::read_to_string(dir.join("src/main.rs")).unwrap(),
+            "mod net;\nuse net::wire;\n\n/// Calls [`crate::net::wire::send`]\nfn main() {\n    wire::send();\n    println!(\"{:?}\", crate::net::wire::send());\n}\n"
+        );
+        assert_eq!(fs::read_to_string(dir.join("src/net.rs")).unwrap(), "pub mod wire;\nfn local() { self::wire::send(); super::net::wire::send(); }\n");
+        assert!(dir.join("src/net/wire.rs").is_file() && !dir.join("src/net/utils.rs").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn renames_that_cannot_be_made_are_refused() {
+        let dir = package("refused", &[("src/lib.rs", "mod a;\nmod b;\n"), ("src/a.rs", ""), ("src/b.rs", "")]);
+        assert!(rename_module(&dir, "missing", "other").unwrap_err().contains("was not found"));
+        // Nothing is changed when the module's file can't take the new name
+        assert!(rename_module(&dir, "a", "b").unwrap_err().contains("already exists"));
+        assert_eq!(fs::read_to_string(dir.join("src/lib.rs")).unwrap(), "mod a;\nmod b;\n");
+        assert!(rename_module(&dir, "a", "not-a-name").is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
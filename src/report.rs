@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::dry_run;
+use crate::output::json_escape;
+use crate::spans::PlanSpan;
+use crate::GeneratedFile;
+
+// Function to write a list of strings as a JSON array
+fn string_array(values: &[String]) -> String {
+    let quoted: Vec<String> = values.iter().map(|value| format!("\"{}\"", json_escape(value))).collect();
+    format!("[{}]", quoted.join(", "))
+}
+
+// Function to write a list of objects as a JSON array, one per line
+fn object_array(objects: &[String]) -> String {
+    match objects {
+        [] => "[]".to_string(),
+        _ => format!("[\n{}\n  ]", objects.join(",\n")),
+    }
+}
+
+// What a run did, and the input it did it to
+pub struct Run<'a> {
+    pub input_path: &'a Path,
+    // The input as decoded, and where the source of its items starts in it, after a shebang
+    pub content: &'a str,
+    pub source_start: usize,
+    pub spans: &'a [PlanSpan<'a>],
+    pub moves: &'a HashMap<Vec<String>, Vec<String>>,
+    pub files: &'a [GeneratedFile],
+    pub root_path: &'a Path,
+    // False for dry runs
+    pub written: bool,
+}
+
+// Function to write the whole run as one JSON object for CI bots and editor plugins: the modules
+// it creates, every planned function with the lines it took up and where it goes, how the root's
+// imports change, and the warnings
+pub fn render(run: &Run, warnings: &[String]) -> String {
+    let Run {
+        input_path,
+        content,
+        source_start,
+        spans,
+        moves,
+        files,
+        root_path,
+        written,
+    } = *run;
+    let line_of = |offset: usize| content[..source_start + offset].matches('\n').count() + 1;
+
+    let modules: Vec<String> = files
+        .iter()
+        .filter(|file| !file.module_path.is_empty())
+        .map(|file| {
+            let (imports, functions, _) = dry_run::contents(&file.code);
+            format!(
+                "    {{\"module\": \"crate::{}\", \"file\": \"{}\", \"functions\": {}, \"lines\": {}, \"imports\": {}}}",
+                json_escape(&file.module_path.join("::")),
+                json_escape(&file.path.display().to_string()),
+                string_array(&functions),
+                file.code.lines().count(),
+                string_array(&imports)
+            )
+        })
+        .collect();
+
+    let items: Vec<String> = spans
+        .iter()
+        .map(|span| {
+            // The range ends after the item's last character, whose line is the item's last
+            let lines = (line_of(span.range.start), line_of(span.range.end.saturating_sub(1).max(span.range.start)));
+            let to = moves.get(span.item_path).map_or_else(|| "null".to_string(), |to| format!("\"crate::{}\"", json_escape(&to.join("::"))));
+            let rationale = span.rationale.map_or_else(|| "null".to_string(), |rationale| format!("\"{}\"", json_escape(rationale)));
+            format!(
+                "    {{\"item\": \"crate::{}\", \"group\": \"{}\", \"to\": {}, \"lines\": [{}, {}], \"rationale\": {}}}",
+                json_escape(&span.item_path.join("::")),
+                json_escape(span.group),
+                to,
+                lines.0,
+                lines.1,
+                rationale
+            )
+        })
+        .collect();
+
+    // Imports of the root are compared by their tokens, as the dry run compares them
+    let (before, _, _) = dry_run::contents(&content[source_start..]);
+    let (after, _, _) = files.iter().find(|file| file.path == root_path).map_or_else(Default::default, |root| dry_run::contents(&root.code));
+    let before_normalized: Vec<String> = before.iter().map(|import| dry_run::normalized(import)).collect();
+    let after_normalized: Vec<String> = after.iter().map(|import| dry_run::normalized(import)).collect();
+    let added: Vec<String> = after.iter().filter(|import| !before_normalized.contains(&dry_run::normalized(import))).cloned().collect();
+    let removed: Vec<String> = before.iter().filter(|import| !after_normalized.contains(&dry_run::normalized(import))).cloned().collect();
+
+    let mut json = String::from("{\n  \"schema\": \"refactor/report/v1\",\n");
+    json.push_str(&format!("  \"input\": \"{}\",\n", json_escape(&input_path.display().to_string())));
+    json.push_str(&format!("  \"written\": {},\n", written));
+    json.push_str(&format!("  \"root\": \"{}\",\n", json_escape(&root_path.display().to_string())));
+    json.push_str(&format!("  \"modules\": {},\n", object_array(&modules)));
+    json.push_str(&format!("  \"items\": {},\n", object_array(&items)));
+    json.push_str(&format!("  \"root_imports\": {{\"added\": {}, \"removed\": {}}},\n", string_array(&added), string_array(&removed)));
+    json.push_str(&format!("  \"warnings\": {}\n}}\n", string_array(warnings)));
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn reports_give_lines_of_the_input_as_written() {
+        let content = "#!/usr/bin/env run-cargo-script\nuse std::fs;\n\n// Loads it\nfn load() {\n    fs::read(\"a\").ok();\n}\nfn main() {}\n";
+        let source_start = content.find('\n').unwrap() + 1;
+        let source = &content[source_start..];
+        let load = vec!["load".to_string()];
+        let start = source.find("// Loads").unwrap();
+        let spans = vec![PlanSpan { item_path: &load, range: start..source.find("}\n").unwrap() + 1, group: "io", rationale: Some("its name contains \"load\"") }];
+        let moves = HashMap::from([(load.clone(), vec!["io_mod".to_string(), "load".to_string()])]);
+        let files = vec![
+            GeneratedFile { path: PathBuf::from("src/io_mod.rs"), code: "use std::fs;\n\npub fn load() {}\n".to_string(), module_path: vec!["io_mod".to_string()], written_in: Vec::new() },
+            GeneratedFile { path: PathBuf::from("src/tmp_main.rs"), code: "mod io_mod;\nuse io_mod::*;\n\nfn main() {}\n".to_string(), module_path: Vec::new(), written_in: Vec::new() },
+        ];
+        let run = Run { input_path: Path::new("src/main.rs"), content, source_start, spans: &spans, moves: &moves, files: &files, root_path: Path::new("src/tmp_main.rs"), written: false };
+        let report: serde_json::Value = serde_json::from_str(&render(&run, &["a warning".to_string()])).expect("The report isn't JSON");
+        assert_eq!(report["schema"], "refactor/report/v1");
+        assert_eq!(report["written"], false);
+        assert_eq!(report["modules"][0], serde_json::json!({"module": "crate::io_mod", "file": "src/io_mod.rs", "functions": ["load"], "lines": 3, "imports": ["use std::fs;"]}));
+        assert_eq!(report["items"][0], serde_json::json!({"item": "crate::load", "group": "io", "to": "crate::io_mod::load", "lines": [4, 7], "rationale": "its name contains \"load\""}));
+        assert_eq!(report["root_imports"], serde_json::json!({"added": ["use io_mod::*;"], "removed": ["use std::fs;"]}));
+        assert_eq!(report["warnings"], serde_json::json!(["a warning"]));
+    }
+}
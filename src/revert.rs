@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::verify::Backup;
+
+// Directory below the package root in which the last run that wrote files keeps what they held
+const REVERT_DIR: &str = ".refactor/revert";
+const MANIFEST_FILE: &str = "manifest";
+
+// Function to find where the revert record of a package lives
+fn revert_dir(root: &Path) -> PathBuf {
+    root.join(REVERT_DIR)
+}
+
+// Function to write a path relative to the package root when it is below it, as the manifest
+// records it, so that a moved checkout can still be reverted. The record is made before the run
+// writes, so the path is resolved through the nearest of its directories that already exists
+fn relative_to(root: &Path, path: &Path) -> PathBuf {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let absolute = path
+        .ancestors()
+        .find_map(|ancestor| fs::canonicalize(ancestor).ok().map(|existing| existing.join(path.strip_prefix(ancestor).unwrap_or(Path::new("")))))
+        .unwrap_or_else(|| path.clone());
+    absolute.strip_prefix(root).map_or(absolute.clone(), Path::to_path_buf)
+}
+
+// Function to remember what a run wrote, replacing the record of the run before: the files it
+// created, the ones it overwrote along with what they held, and the items it moved
+pub fn record(root: &Path, backups: &[Backup], moves: &HashMap<Vec<String>, Vec<String>>, command: &str) -> Result<(), String> {
+    let root = fs::canonicalize(root).map_err(|e| format!("Failed to find {:?}: {}", root, e))?;
+    let dir = revert_dir(&root);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).map_err(|e| format!("Failed to clear {:?}: {}", dir, e))?;
+    }
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {:?}: {}", dir, e))?;
+
+    let mut manifest = format!("# Written by `{}`; `refactor undo` or `refactor revert` puts back what was there before\n", command);
+    let mut seen = HashSet::new();
+    // A file written twice is put back as it was before the first write
+    for (index, backup) in backups.iter().enumerate().filter(|(_, backup)| seen.insert(backup.path.clone())) {
+        let path = relative_to(&root, &backup.path);
+        match &backup.previous {
+            Some(previous) => {
+                let copy = index.to_string();
+                fs::write(dir.join(&copy), previous).map_err(|e| format!("Failed to write {:?}: {}", dir.join(&copy), e))?;
+                manifest.push_str(&format!("modified {} {}\n", copy, path.display()));
+            }
+            None => manifest.push_str(&format!("created {}\n", path.display())),
+        }
+    }
+    let mut moved: Vec<String> = moves.iter().map(|(from, to)| format!("moved crate::{} crate::{}\n", from.join("::"), to.join("::"))).collect();
+    moved.sort();
+    manifest.extend(moved);
+    fs::write(dir.join(MANIFEST_FILE), manifest).map_err(|e| format!("Failed to write the revert manifest: {}", e))
+}
+
+// Function to undo the last recorded run of the package at `root`: the files it created are
+// removed and the ones it overwrote get their contents back. Returns what was done, file by file
+pub fn revert(root: &Path) -> Result<Vec<String>, String> {
+    let root = fs::canonicalize(root).map_err(|e| format!("Failed to find {:?}: {}", root, e))?;
+    let dir = revert_dir(&root);
+    let manifest = fs::read_to_string(dir.join(MANIFEST_FILE)).map_err(|_| format!("There is nothing to revert in {}", root.display()))?;
+
+    let mut done = Vec::new();
+    let mut moved = 0;
+    // Files are put back in the reverse order of their writes, so that directories empty out
+    for line in manifest.lines().filter(|line| !line.starts_with('#')).collect::<Vec<_>>().into_iter().rev() {
+        let (action, rest) = line.split_once(' ').ok_or_else(|| format!("Unreadable revert manifest line: {}", line))?;
+        match action {
+            "created" => {
+                let path = root.join(rest);
+                if path.exists() {
+                    fs::remove_file(&path).map_err(|e| format!("Failed to remove {:?}: {}", path, e))?;
+                    done.push(format!("removed {}", rest));
+                }
+                // Directories the run created for nested modules go too, once nothing is left in them
+                if let Some(parent) = path.parent().filter(|parent| *parent != root) {
+                    if fs::read_dir(parent).is_ok_and(|mut entries| entries.next().is_none()) {
+                        fs::remove_dir(parent).map_err(|e| format!("Failed to remove {:?}: {}", parent, e))?;
+                    }
+                }
+            }
+            "modified" => {
+                let (copy, path) = rest.split_once(' ').ok_or_else(|| format!("Unreadable revert manifest line: {}", line))?;
+                let previous = fs::read(dir.join(copy)).map_err(|e| format!("Failed to read the saved copy of {}: {}", path, e))?;
+                fs::write(root.join(path), previous).map_err(|e| format!("Failed to restore {}: {}", path, e))?;
+                done.push(format!("restored {}", path));
+            }
+            // Moved items go back with the files they were moved out of
+            "moved" => moved += 1,
+            _ => return Err(format!("Unreadable revert manifest line: {}", line)),
+        }
+    }
+    if moved > 0 {
+        done.push(format!("{} moved items are back where they were", moved));
+    }
+    fs::remove_dir_all(&dir).map_err(|e| format!("Failed to clear {:?}: {}", dir, e))?;
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::verify;
+
+    fn package(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("refactor-revert-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::canonicalize(dir).unwrap()
+    }
+
+    #[test]
+    fn a_recorded_run_is_put_back() {
+        let dir = package("round-trip");
+        let main = dir.join("src/main.rs");
+        fs::write(&main, "fn main() {}\n").unwrap();
+        // The run overwrites the root twice and creates a module in a directory of its own
+        let module = dir.join("src/engine/io_mod.rs");
+        let backups = [verify::backup(&main), verify::backup(&module), verify::backup(&main)];
+        record(&dir, &backups, &HashMap::new(), "refactor --in-place src/main.rs").unwrap();
+        let manifest = fs::read_to_string(revert_dir(&dir).join(MANIFEST_FILE)).unwrap();
+        assert_eq!(manifest.lines().skip(1).collect::<Vec<_>>(), ["modified 0 src/main.rs", "created src/engine/io_mod.rs"]);
+
+        fs::write(&main, "mod engine;\n").unwrap();
+        fs::create_dir_all(module.parent().unwrap()).unwrap();
+        fs::write(&module, "fn read() {}\n").unwrap();
+        let done = revert(&dir).unwrap();
+        assert_eq!(done, ["removed src/engine/io_mod.rs", "restored src/main.rs"]);
+        assert_eq!(fs::read_to_string(&main).unwrap(), "fn main() {}\n");
+        assert!(!dir.join("src/engine").exists());
+        assert!(revert(&dir).unwrap_err().starts_with("There is nothing to revert"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn moved_items_are_recorded_in_the_manifest() {
+        let dir = package("moves");
+        let moves = HashMap::from([
+            (vec!["parse".to_string()], vec!["util_mod".to_string(), "parse".to_string()]),
+            (vec!["engine".to_string(), "read".to_string()], vec!["engine".to_string(), "io_mod".to_string(), "read".to_string()]),
+        ]);
+        record(&dir, &[], &moves, "refactor src/main.rs").unwrap();
+        let manifest = fs::read_to_string(revert_dir(&dir).join(MANIFEST_FILE)).unwrap();
+        assert!(manifest.starts_with("# Written by `refactor src/main.rs`; `refactor undo`"), "{}", manifest);
+        assert_eq!(manifest.lines().skip(1).collect::<Vec<_>>(), ["moved crate::engine::read crate::engine::io_mod::read", "moved crate::parse crate::util_mod::parse"]);
+        assert_eq!(revert(&dir).unwrap(), ["2 moved items are back where they were"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+use crate::dry_run;
+use crate::idents;
+use crate::GeneratedFile;
+
+// What was decided about the proposed modules of a file: the items of the skipped ones, which
+// stay where they are, and the new names of the renamed ones, both by their paths below the crate
+#[derive(Default)]
+pub struct Review {
+    pub skipped: Vec<String>,
+    pub renamed: HashMap<String, String>,
+}
+
+impl Review {
+    // Function to check whether every module was approved as it was proposed
+    pub fn approved_all(&self) -> bool {
+        self.skipped.is_empty() && self.renamed.is_empty()
+    }
+}
+
+// Function to find where the answers come from: stdin, which a script can pipe them into, or the
+// terminal when stdin holds the input being refactored
+fn answers(stdin_is_input: bool) -> Result<Box<dyn BufRead>, String> {
+    if !stdin_is_input {
+        return Ok(Box::new(std::io::stdin().lock()));
+    }
+    match File::open("/dev/tty") {
+        Ok(terminal) => Ok(Box::new(BufReader::new(terminal))),
+        Err(_) => Err("--interactive reads its answers from the terminal while stdin holds the input, and there is no terminal".to_string()),
+    }
+}
+
+// Function to ask a question on stderr, which leaves stdout to what the run emits, and read
+// the answer
+fn ask(question: &str, input: &mut dyn BufRead) -> Result<String, String> {
+    eprint!("{}", question);
+    std::io::stderr().flush().map_err(|e| format!("Failed to ask: {}", e))?;
+    let mut answer = String::new();
+    match input.read_line(&mut answer) {
+        Ok(0) => Err("the answers ended before every module was approved; nothing was written".to_string()),
+        Ok(_) => Ok(answer.trim().to_string()),
+        Err(e) => Err(format!("Failed to read the answer: {}", e)),
+    }
+}
+
+// Function to show each proposed module with the functions moving into it and the imports it
+// gets, and ask whether to write it, skip it or write it under another name
+pub fn review(files: &[GeneratedFile], moves: &HashMap<Vec<String>, Vec<String>>, stdin_is_input: bool) -> Result<Review, String> {
+    review_answers(files, moves, &mut *answers(stdin_is_input)?)
+}
+
+// Function to review the proposed modules with the answers read from `input`
+fn review_answers(files: &[GeneratedFile], moves: &HashMap<Vec<String>, Vec<String>>, input: &mut dyn BufRead) -> Result<Review, String> {
+    let mut review = Review::default();
+    let modules: Vec<&GeneratedFile> = files.iter().filter(|file| !file.module_path.is_empty()).collect();
+    for (index, file) in modules.iter().enumerate() {
+        let module_path = file.module_path.join("::");
+        let mut members: Vec<(&Vec<String>, &Vec<String>)> = moves.iter().filter(|(_, to)| to[..to.len() - 1] == file.module_path[..]).collect();
+        members.sort();
+        let (imports, _, _) = dry_run::contents(&file.code);
+
+        eprintln!();
+        eprintln!("[{}/{}] crate::{} ({})", index + 1, modules.len(), module_path, file.path.display());
+        for (from, _) in &members {
+            eprintln!("  moves crate::{}", from.join("::"));
+        }
+        for import in &imports {
+            eprintln!("  imports {}", import.replace('\n', " "));
+        }
+        loop {
+            let answer = ask("Write this module? [y]es, [s]kip, [r]ename, [q]uit: ", input)?;
+            match answer.split_once(' ').map_or((answer.as_str(), ""), |(choice, name)| (choice, name.trim())) {
+                ("" | "y" | "yes", _) => break,
+                ("s" | "skip", _) => {
+                    review.skipped.extend(members.iter().map(|(from, _)| format!("crate::{}", from.join("::"))));
+                    break;
+                }
+                ("r" | "rename", name) => {
+                    let name = if name.is_empty() { ask("New name: ", input)? } else { name.to_string() };
+                    match idents::check_module_ident(&name) {
+                        Ok(()) => {
+                            review.renamed.insert(module_path.clone(), name);
+                            break;
+                        }
+                        Err(e) => eprintln!("{}", e),
+                    }
+                }
+                ("q" | "quit", _) => return Err("the review was stopped; nothing was written".to_string()),
+                _ => eprintln!("Answer y, s, r <name> or q."),
+            }
+        }
+    }
+    Ok(review)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn proposal() -> (Vec<GeneratedFile>, HashMap<Vec<String>, Vec<String>>) {
+        let module = |name: &str| GeneratedFile { path: PathBuf::from(format!("src/{}.rs", name)), code: String::new(), module_path: vec![name.to_string()], written_in: Vec::new() };
+        let files = vec![module("io_mod"), module("util_mod")];
+        let moves = HashMap::from([
+            (vec!["load".to_string()], vec!["io_mod".to_string(), "load".to_string()]),
+            (vec!["save".to_string()], vec!["io_mod".to_string(), "save".to_string()]),
+            (vec!["parse".to_string()], vec!["util_mod".to_string(), "parse".to_string()]),
+        ]);
+        (files, moves)
+    }
+
+    #[test]
+    fn skipped_modules_keep_their_items_in_place() {
+        let (files, moves) = proposal();
+        let review = review_answers(&files, &moves, &mut Cursor::new("s\n\n")).unwrap();
+        assert_eq!(review.skipped, vec!["crate::load", "crate::save"]);
+        assert!(review.renamed.is_empty() && !review.approved_all());
+        assert!(review_answers(&files, &moves, &mut Cursor::new("y\nyes\n")).unwrap().approved_all());
+    }
+
+    #[test]
+    fn renames_are_asked_again_until_the_name_is_valid() {
+        let (files, moves) = proposal();
+        let review = review_answers(&files, &moves, &mut Cursor::new("r fn\nmaybe\nr\nstorage\ny\n")).unwrap();
+        assert_eq!(review.renamed, HashMap::from([("io_mod".to_string(), "storage".to_string())]));
+    }
+
+    #[test]
+    fn quitting_or_running_out_of_answers_writes_nothing() {
+        let (files, moves) = proposal();
+        assert!(review_answers(&files, &moves, &mut Cursor::new("y\nq\n")).err().unwrap().contains("the review was stopped"));
+        assert!(review_answers(&files, &moves, &mut Cursor::new("y\n")).err().unwrap().contains("the answers ended"));
+    }
+}
@@ -0,0 +1,190 @@
+use syn::Attribute;
+
+// Edition of a package converted from a script that names none, the one cargo runs scripts with
+const SCRIPT_EDITION: &str = "2024";
+
+// What comes before the code of a cargo script: the `#!` line and the `---` fenced manifest
+#[derive(Default)]
+pub struct ScriptHeader {
+    // The header as written, to put back in front of the generated root
+    pub text: String,
+    pub shebang: bool,
+    // The TOML between the fences, without them
+    pub manifest: Option<String>,
+}
+
+// Function to split a cargo script into its header and its code. The header is replaced with
+// as many newlines as it spans so that line numbers in parse errors still match the input
+pub fn split_header(content: &str) -> (ScriptHeader, String) {
+    let mut header = ScriptHeader::default();
+    let mut rest = content;
+
+    // `#![attr]` is an inner attribute rather than a shebang, as `syn::parse_file` has it
+    if rest.starts_with("#!") && !rest[2..].trim_start().starts_with('[') {
+        let line_end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+        header.shebang = true;
+        rest = &rest[line_end..];
+    }
+
+    // The opening fence may follow blank lines and carry an infostring, as in `---cargo`
+    let blank = rest.len() - rest.trim_start_matches(['\n', ' ', '\t']).len();
+    let blank = rest[..blank].rfind('\n').map_or(0, |i| i + 1);
+    let fenced = &rest[blank..];
+    let dashes = fenced.len() - fenced.trim_start_matches('-').len();
+    if dashes >= 3 {
+        let fence = &fenced[..dashes];
+        let body_start = fenced.find('\n').map_or(fenced.len(), |i| i + 1);
+        let mut offset = body_start;
+        for line in fenced[body_start..].split_inclusive('\n') {
+            if line.trim_end() == fence {
+                header.manifest = Some(fenced[body_start..offset].to_string());
+                rest = &rest[blank + offset + line.len()..];
+                break;
+            }
+            offset += line.len();
+        }
+    }
+
+    header.text = content[..content.len() - rest.len()].to_string();
+    let code = format!("{}{}", "\n".repeat(header.text.matches('\n').count()), rest);
+    (header, code)
+}
+
+// Function to find a manifest embedded the older way, as a ```cargo block of the crate docs
+pub fn doc_manifest(attrs: &[Attribute]) -> Option<String> {
+    let mut manifest: Option<String> = None;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("doc") && matches!(attr.style, syn::AttrStyle::Inner(_))) {
+        if let syn::Meta::NameValue(syn::MetaNameValue {
+            value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(doc), .. }),
+            ..
+        }) = &attr.meta
+        {
+            for line in doc.value().lines() {
+                let line = line.strip_prefix(' ').unwrap_or(line);
+                match &mut manifest {
+                    None if line.trim() == "```cargo" => manifest = Some(String::new()),
+                    Some(_) if line.trim() == "```" => return manifest,
+                    Some(manifest) => {
+                        manifest.push_str(line);
+                        manifest.push('\n');
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+    None
+}
+
+// Function to read the edition a manifest sets for its package, such as 2021, or None when it
+// names none or takes it from the workspace
+pub fn manifest_edition(manifest: &str) -> Option<u16> {
+    let mut in_package = false;
+    for line in manifest.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_package = line == "[package]";
+        } else if let Some((key, value)) = line.split_once('=').filter(|_| in_package) {
+            if key.trim() == "edition" {
+                return value.trim().trim_matches(['"', '\'']).parse().ok();
+            }
+        }
+    }
+    None
+}
+
+// Function to find the edition of the code of a cargo script, or of the crate whose manifest is
+// `package_manifest`. Cargo takes 2015 when a package names none
+pub fn edition(header: &ScriptHeader, package_manifest: Option<&str>) -> u16 {
+    if header.shebang || header.manifest.is_some() {
+        let script_edition = SCRIPT_EDITION.parse().expect("The script edition is a year");
+        return header.manifest.as_deref().and_then(manifest_edition).unwrap_or(script_edition);
+    }
+    package_manifest.and_then(manifest_edition).unwrap_or(2015)
+}
+
+// Function to write the Cargo.toml of a package converted from a script, filling in the
+// `[package]` keys a script may leave out
+pub fn package_manifest(manifest: Option<&str>, package_name: &str) -> String {
+    let manifest = manifest.unwrap_or("").trim();
+    let mut lines: Vec<String> = manifest.lines().map(str::to_string).collect();
+    let package_start = lines.iter().position(|line| line.trim() == "[package]");
+    let package_end = package_start.map(|start| {
+        lines[start + 1..]
+            .iter()
+            .position(|line| line.trim_start().starts_with('['))
+            .map_or(lines.len(), |end| start + 1 + end)
+    });
+    let has_key = |key: &str| match (package_start, package_end) {
+        (Some(start), Some(end)) => lines[start + 1..end]
+            .iter()
+            .any(|line| line.split('=').next().is_some_and(|name| name.trim() == key)),
+        _ => false,
+    };
+
+    let mut missing = Vec::new();
+    for (key, value) in [("name", package_name), ("version", "0.1.0"), ("edition", SCRIPT_EDITION)] {
+        if !has_key(key) {
+            missing.push(format!("{} = \"{}\"", key, value));
+        }
+    }
+    match package_start {
+        Some(start) => {
+            lines.splice(start + 1..start + 1, missing);
+        }
+        None => {
+            missing.insert(0, "[package]".to_string());
+            if !lines.is_empty() {
+                missing.push(String::new());
+            }
+            lines.splice(0..0, missing);
+        }
+    }
+    format!("{}\n", lines.join("\n"))
+}
+
+// Function to turn the file name of a script into a package name cargo accepts
+pub fn package_name(file_stem: &str) -> String {
+    let name: String = file_stem
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        name
+    } else {
+        format!("script-{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headers_are_split_off_keeping_the_line_numbers() {
+        let script = "#!/usr/bin/env cargo\n\n---cargo\n[dependencies]\nrand = \"0.8\"\n---\nfn main() {}\n";
+        let (header, code) = split_header(script);
+        assert!(header.shebang);
+        assert_eq!(header.manifest.as_deref(), Some("[dependencies]\nrand = \"0.8\"\n"));
+        assert_eq!(header.text, "#!/usr/bin/env cargo\n\n---cargo\n[dependencies]\nrand = \"0.8\"\n---\n");
+        assert_eq!(code, "\n\n\n\n\n\nfn main() {}\n");
+        assert_eq!(edition(&header, None), 2024);
+
+        // An inner attribute is no shebang
+        let (header, code) = split_header("#![allow(dead_code)]\nfn main() {}\n");
+        assert!(!header.shebang && header.text.is_empty());
+        assert_eq!(code, "#![allow(dead_code)]\nfn main() {}\n");
+        assert_eq!(edition(&header, Some("[package]\nname = \"a\"\n")), 2015);
+    }
+
+    #[test]
+    fn script_manifests_get_the_package_keys_they_leave_out() {
+        assert_eq!(manifest_edition("[package]\nedition = \"2021\"\n[dependencies]\nedition = \"1\"\n"), Some(2021));
+        assert_eq!(manifest_edition("[package]\nedition.workspace = true\n"), None);
+        assert_eq!(
+            package_manifest(Some("[package]\nedition = \"2021\"\n\n[dependencies]\nrand = \"0.8\"\n"), "dice"),
+            "[package]\nname = \"dice\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\nrand = \"0.8\"\n"
+        );
+        assert_eq!(package_manifest(None, "dice"), "[package]\nname = \"dice\"\nversion = \"0.1.0\"\nedition = \"2024\"\n");
+        assert_eq!(package_name("2048 game"), "script-2048-game");
+    }
+}
@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::ops::Range;
+use syn::Item;
+
+use crate::item_source_ranges;
+use crate::plan::PlanEntry;
+
+// Where a planned function is in the source and where it goes, for editors to highlight. The
+// path and the group are borrowed from the plan, so no item's code is copied
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "schema", rename = "refactor/plan-span/v1"))]
+pub struct PlanSpan<'a> {
+    pub item_path: &'a [String],
+    // Bytes of the source the function takes up, its docs and comments included
+    pub range: Range<usize>,
+    pub group: &'a str,
+    pub rationale: Option<&'a str>,
+}
+
+// Function to find the span of every planned function among `items`, following inline modules,
+// where `items_start` is the offset in `source` at which the list of items begins
+pub fn plan_spans<'a>(source: &'a str, items: &[Item], items_start: usize, entries: &'a [PlanEntry]) -> Vec<PlanSpan<'a>> {
+    let by_path: HashMap<&[String], &PlanEntry> = entries.iter().map(|entry| (entry.item_path.as_slice(), entry)).collect();
+    let mut spans = Vec::new();
+    collect_spans(source, items, items_start, &mut Vec::new(), &by_path, &mut spans);
+    spans
+}
+
+fn collect_spans<'a>(
+    source: &'a str,
+    items: &[Item],
+    items_start: usize,
+    module_path: &mut Vec<String>,
+    by_path: &HashMap<&[String], &'a PlanEntry>,
+    spans: &mut Vec<PlanSpan<'a>>,
+) {
+    for (item, range) in items.iter().zip(item_source_ranges(source, items, items_start)) {
+        match item {
+            Item::Fn(func) => {
+                module_path.push(func.sig.ident.to_string());
+                if let Some(entry) = by_path.get(module_path.as_slice()) {
+                    spans.push(PlanSpan {
+                        item_path: &entry.item_path,
+                        range,
+                        group: &entry.group,
+                        rationale: entry.rationale.as_deref(),
+                    });
+                }
+                module_path.pop();
+            }
+            Item::Mod(item_mod) => {
+                if let Some((brace, items)) = &item_mod.content {
+                    module_path.push(item_mod.ident.to_string());
+                    collect_spans(source, items, brace.span.open().byte_range().end, module_path, by_path, spans);
+                    module_path.pop();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Function to map offsets into the decoded source to offsets into the file it was read from,
+// which may start with a byte order mark and a script header and end its lines with `\r\n`
+pub fn file_offsets(original: &[u8], source_start: usize) -> impl Fn(usize) -> usize {
+    let bom = if original.starts_with(b"\xef\xbb\xbf") { 3 } else { 0 };
+    // Where the decoded text has a `\n` that was a `\r\n`
+    let mut dropped = Vec::new();
+    for (index, window) in original[bom..].windows(2).enumerate() {
+        if window == b"\r\n" {
+            dropped.push(index - dropped.len());
+        }
+    }
+    move |offset| {
+        let decoded = source_start + offset;
+        bom + decoded + dropped.partition_point(|newline| *newline < decoded)
+    }
+}
+
+// Function to print the spans one per line, as `start..end group item_path`, with the rationale
+// of placements the grouping alone didn't choose
+pub fn print_spans(spans: &[PlanSpan], file_offset: impl Fn(usize) -> usize) {
+    for span in spans {
+        let range = file_offset(span.range.start)..file_offset(span.range.end);
+        match span.rationale {
+            Some(rationale) => println!("{}..{} {} {}  # {}", range.start, range.end, span.group, span.item_path.join("::"), rationale),
+            None => println!("{}..{} {} {}", range.start, range.end, span.group, span.item_path.join("::")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(item_path: &[&str], group: &str) -> PlanEntry {
+        PlanEntry {
+            item_path: item_path.iter().map(|name| name.to_string()).collect(),
+            fingerprint: String::new(),
+            proposed: group.to_string(),
+            group: group.to_string(),
+            rationale: None,
+        }
+    }
+
+    #[test]
+    fn spans_cover_the_planned_functions_of_inline_modules_too() {
+        let source = "use std::fs;\n\n// Reads a file\nfn read_file() {}\n\nmod engine {\n    fn parse() {}\n}\n\nfn main() {}\n";
+        let syntax_tree = syn::parse_file(source).unwrap();
+        let entries = [entry(&["read_file"], "file_io"), entry(&["engine", "parse"], "util")];
+        let spans = plan_spans(source, &syntax_tree.items, 0, &entries);
+        let found: Vec<(&str, &str)> = spans.iter().map(|span| (span.group, source[span.range.clone()].trim())).collect();
+        assert_eq!(found, [("file_io", "// Reads a file\nfn read_file() {}"), ("util", "fn parse() {}")]);
+        assert_eq!(spans[1].item_path, ["engine", "parse"]);
+    }
+
+    #[test]
+    fn offsets_account_for_the_byte_order_mark_and_crlf_line_endings() {
+        let original = b"\xef\xbb\xbffn a() {}\r\nfn b() {}\r\n";
+        let offset = file_offsets(original, 0);
+        // `fn b` starts at 10 in the decoded text and at 14 in the file
+        assert_eq!(offset(0), 3);
+        assert_eq!(offset(10), 14);
+        assert_eq!(&original[offset(10)..offset(19)], b"fn b() {}");
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::{HashMap, HashSet};
+use syn::visit::Visit;
+use syn::{FnArg, ItemFn, Type};
+
+// A state type is only worth its own module when at least this many functions drive it
+const STATE_MACHINE_MIN_FUNCTIONS: usize = 2;
+
+// A function naming at least this many variants or associated consts of a type outside of
+// patterns, such as `Status::Failed` or `Config::DEFAULT_PORT`, drives it as well
+const STATE_MACHINE_MIN_NAMED: usize = 3;
+
+// Finds the state types a function drives: enums it matches on, structs it takes by `&mut`, and
+// types whose variants and associated consts it names often
+struct StateUsageVisitor<'a> {
+    // Types defined next to the function, mapped to whether they are enums
+    state_types: &'a HashMap<String, bool>,
+    in_pattern: bool,
+    used: HashSet<String>,
+    // How often each type's variants and associated consts are named outside of patterns
+    named: HashMap<String, usize>,
+}
+
+impl Visit<'_> for StateUsageVisitor<'_> {
+    // Patterns of `match` arms, `if let`, `let` and parameters alike
+    fn visit_pat(&mut self, node: &syn::Pat) {
+        let outer = std::mem::replace(&mut self.in_pattern, true);
+        syn::visit::visit_pat(self, node);
+        self.in_pattern = outer;
+    }
+
+    // Expressions inside patterns, such as the bounds of a range, aren't patterns themselves
+    fn visit_expr(&mut self, node: &syn::Expr) {
+        let outer = std::mem::replace(&mut self.in_pattern, false);
+        syn::visit::visit_expr(self, node);
+        self.in_pattern = outer;
+    }
+
+    fn visit_path(&mut self, node: &syn::Path) {
+        // The type may be written with a path in front, as in `crate::Status::Failed`
+        let segments: Vec<String> = node.segments.iter().map(|segment| segment.ident.to_string()).collect();
+        let found = segments.windows(2).find_map(|pair| Some((&pair[0], &pair[1], *self.state_types.get(&pair[0])?)));
+        if let Some((name, member, is_enum)) = found {
+            let is_const = member.chars().all(|c| c.is_uppercase() || c.is_ascii_digit() || c == '_');
+            let is_variant = is_enum && member.starts_with(|c: char| c.is_uppercase());
+            if self.in_pattern && is_enum {
+                self.used.insert(name.clone());
+            } else if !self.in_pattern && (is_variant || is_const) {
+                *self.named.entry(name.clone()).or_default() += 1;
+            }
+        }
+        syn::visit::visit_path(self, node);
+    }
+
+    fn visit_fn_arg(&mut self, node: &FnArg) {
+        if let FnArg::Typed(pat_type) = node {
+            if let Type::Reference(reference) = &*pat_type.ty {
+                if let (Some(_), Type::Path(type_path)) = (reference.mutability, &*reference.elem) {
+                    if let Some(ident) = type_path.path.get_ident() {
+                        if self.state_types.get(&ident.to_string()) == Some(&false) {
+                            self.used.insert(ident.to_string());
+                        }
+                    }
+                }
+            }
+        }
+        syn::visit::visit_fn_arg(self, node);
+    }
+}
+
+// Function to find the functions that together drive a state type defined next to them, mapped
+// to that type. A function driving several types goes with the one most functions drive
+pub fn find_state_machines(func_asts: &HashMap<String, ItemFn>, state_types: &HashMap<String, bool>) -> HashMap<String, String> {
+    let mut drivers: HashMap<String, Vec<String>> = HashMap::new();
+    let mut used_by_function = HashMap::new();
+    for (func_name, func_ast) in func_asts {
+        let mut visitor = StateUsageVisitor {
+            state_types,
+            in_pattern: false,
+            used: HashSet::new(),
+            named: HashMap::new(),
+        };
+        visitor.visit_item_fn(func_ast);
+        for (state_type, count) in &visitor.named {
+            if *count >= STATE_MACHINE_MIN_NAMED {
+                visitor.used.insert(state_type.clone());
+            }
+        }
+        for state_type in &visitor.used {
+            drivers.entry(state_type.clone()).or_default().push(func_name.clone());
+        }
+        used_by_function.insert(func_name.clone(), visitor.used);
+    }
+
+    let mut state_machines = HashMap::new();
+    for (func_name, used) in used_by_function {
+        let state_type = used
+            .into_iter()
+            .filter(|state_type| drivers[state_type].len() >= STATE_MACHINE_MIN_FUNCTIONS)
+            .max_by(|a, b| drivers[a].len().cmp(&drivers[b].len()).then_with(|| b.cmp(a)));
+        if let Some(state_type) = state_type {
+            state_machines.insert(func_name, state_type);
+        }
+    }
+    state_machines
+}
+
+// Function to name the module of a state type, such as `connection_state` for `ConnectionState`
+pub fn module_name(state_type: &str) -> String {
+    let mut name = String::new();
+    for (index, c) in state_type.chars().enumerate() {
+        if c.is_uppercase() && index > 0 && !name.ends_with('_') {
+            name.push('_');
+        }
+        name.extend(c.to_lowercase());
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn functions_matching_on_or_mutating_a_type_drive_it() {
+        let functions = [
+            "fn step(state: Status) -> Status { match state { Status::Idle => Status::Running, other => other } }",
+            "fn is_done(state: &Status) -> bool { matches!(state, Status::Done) || if let Status::Done = state { true } else { false } }",
+            "fn reset(config: &mut Config) { config.port = 0; }",
+            "fn tune(config: &mut Config) { config.port += 1; }",
+            "fn defaults() -> (u16, u16, u16) { (Config::DEFAULT_PORT, Config::MIN_PORT, Config::MAX_PORT) }",
+            "fn report(config: &Config) -> Status { Status::Idle }",
+        ];
+        let func_asts: HashMap<String, ItemFn> = functions
+            .iter()
+            .map(|code| syn::parse_str::<ItemFn>(code).unwrap())
+            .map(|func| (func.sig.ident.to_string(), func))
+            .collect();
+        let state_types = HashMap::from([("Status".to_string(), true), ("Config".to_string(), false)]);
+        let mut found: Vec<(String, String)> = find_state_machines(&func_asts, &state_types).into_iter().collect();
+        found.sort();
+        // Naming a variant once, as `report` does, isn't driving the type
+        let expected = [("defaults", "Config"), ("is_done", "Status"), ("reset", "Config"), ("step", "Status"), ("tune", "Config")];
+        assert_eq!(found, expected.map(|(func_name, state_type)| (func_name.to_string(), state_type.to_string())));
+    }
+
+    #[test]
+    fn modules_are_named_in_snake_case() {
+        assert_eq!(module_name("ConnectionState"), "connection_state");
+        assert_eq!(module_name("Status"), "status");
+    }
+}
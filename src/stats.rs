@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::Path;
+use syn::Item;
+
+use crate::output::json_escape;
+use crate::{dry_run, GeneratedFile};
+
+// How large one module came out of the split
+struct ModuleStats {
+    module: String,
+    file: String,
+    lines: usize,
+    functions: usize,
+    // Lines of its functions on average, and of the longest one
+    average_function_lines: f64,
+    longest_function_lines: usize,
+    imports: usize,
+}
+
+// Function to find how many lines each top-level function of a file spans, from `fn` to its
+// closing brace as a gate counts them
+fn function_lengths(file: &syn::File) -> Vec<usize> {
+    file.items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Fn(func) => Some(func.block.brace_token.span.close().end().line + 1 - func.sig.fn_token.span.start().line),
+            _ => None,
+        })
+        .collect()
+}
+
+// Function to measure the Rust files a run writes, the generated modules and the files left
+// to hold the rest
+fn measure<'a>(files: impl IntoIterator<Item = &'a GeneratedFile>) -> Vec<ModuleStats> {
+    files
+        .into_iter()
+        .filter(|file| file.path.extension().is_some_and(|extension| extension == "rs"))
+        .map(|file| {
+            // The functions counted are those the summary lists, the module's own top-level ones,
+            // and not the ones nested in them or in its tests
+            let lengths = syn::parse_file(&file.code).map_or_else(|_| Vec::new(), |parsed| function_lengths(&parsed));
+            let total: usize = lengths.iter().sum();
+            let (imports, _, _) = dry_run::contents(&file.code);
+            ModuleStats {
+                module: match file.module_path.as_slice() {
+                    [] => "crate".to_string(),
+                    path => format!("crate::{}", path.join("::")),
+                },
+                file: file.path.display().to_string(),
+                lines: file.code.lines().count(),
+                functions: lengths.len(),
+                average_function_lines: if lengths.is_empty() { 0.0 } else { total as f64 / lengths.len() as f64 },
+                longest_function_lines: lengths.iter().copied().max().unwrap_or(0),
+                imports: imports.len(),
+            }
+        })
+        .collect()
+}
+
+// Function to write the stats as a JSON object, with a `schema` like the run report's
+fn render_json(stats: &[ModuleStats]) -> String {
+    let modules: Vec<String> = stats
+        .iter()
+        .map(|module| {
+            format!(
+                "    {{\"module\": \"{}\", \"file\": \"{}\", \"lines\": {}, \"functions\": {}, \"average_function_lines\": {:.1}, \"longest_function_lines\": {}, \"imports\": {}}}",
+                json_escape(&module.module),
+                json_escape(&module.file),
+                module.lines,
+                module.functions,
+                module.average_function_lines,
+                module.longest_function_lines,
+                module.imports
+            )
+        })
+        .collect();
+    let modules = match modules.as_slice() {
+        [] => "[]".to_string(),
+        _ => format!("[\n{}\n  ]", modules.join(",\n")),
+    };
+    format!("{{\n  \"schema\": \"refactor/stats/v1\",\n  \"modules\": {}\n}}\n", modules)
+}
+
+// Function to write the stats as a table, one module to a line
+fn render_text(stats: &[ModuleStats]) -> String {
+    let mut rows = vec![["module", "file", "lines", "functions", "avg fn lines", "longest fn", "imports"].map(String::from)];
+    for module in stats {
+        rows.push([
+            module.module.clone(),
+            module.file.clone(),
+            module.lines.to_string(),
+            module.functions.to_string(),
+            format!("{:.1}", module.average_function_lines),
+            module.longest_function_lines.to_string(),
+            module.imports.to_string(),
+        ]);
+    }
+    let widths: Vec<usize> = (0..7).map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0)).collect();
+    let mut text = String::new();
+    for row in &rows {
+        // Names are aligned on the left and counts on the right
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(column, (cell, width))| if column < 2 { format!("{:<width$}", cell, width = width) } else { format!("{:>width$}", cell, width = width) })
+            .collect();
+        text.push_str(cells.join("  ").trim_end());
+        text.push('\n');
+    }
+    text
+}
+
+// Function to write the size of every module a run writes to `path`, as JSON when its name ends
+// in `.json` and as a table otherwise, so that a split can be checked for units of a sensible size
+pub fn write<'a>(path: &Path, files: impl IntoIterator<Item = &'a GeneratedFile>) -> Result<(), String> {
+    let stats = measure(files);
+    let text = match path.extension().is_some_and(|extension| extension == "json") {
+        true => render_json(&stats),
+        false => render_text(&stats),
+    };
+    fs::write(path, text).map_err(|e| format!("Failed to write {:?}: {}", path, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn files() -> Vec<GeneratedFile> {
+        vec![
+            GeneratedFile {
+                path: PathBuf::from("src/util_mod.rs"),
+                code: "use std::fs;\n\npub fn short() {}\n\npub fn long() {\n    fn nested() {}\n    nested();\n}\n".to_string(),
+                module_path: vec!["util_mod".to_string()],
+                written_in: Vec::new(),
+            },
+            GeneratedFile { path: PathBuf::from("src/tmp_main.rs"), code: "mod util_mod;\n".to_string(), module_path: Vec::new(), written_in: Vec::new() },
+            GeneratedFile { path: PathBuf::from(".refactor/refactor.plan"), code: "util short\n".to_string(), module_path: Vec::new(), written_in: Vec::new() },
+        ]
+    }
+
+    #[test]
+    fn modules_are_measured_by_their_own_functions() {
+        let stats = measure(&files());
+        assert_eq!(stats.len(), 2);
+        let util = &stats[0];
+        assert_eq!((util.module.as_str(), util.lines, util.functions, util.longest_function_lines, util.imports), ("crate::util_mod", 8, 2, 4, 1));
+        assert_eq!(util.average_function_lines, 2.5);
+        assert_eq!((stats[1].module.as_str(), stats[1].functions, stats[1].average_function_lines), ("crate", 0, 0.0));
+    }
+
+    #[test]
+    fn stats_are_written_as_a_table_or_json() {
+        let stats = measure(&files());
+        assert_eq!(
+            render_text(&stats),
+            "module           file             lines  functions  avg fn lines  longest fn  imports\ncrate::util_mod  src/util_mod.rs      8          2           2.5           4        1\ncrate            src/tmp_main.rs      1          0           0.0           0        0\n"
+        );
+        let json: serde_json::Value = serde_json::from_str(&render_json(&stats)).expect("The stats aren't JSON");
+        assert_eq!(json["schema"], "refactor/stats/v1");
+        assert_eq!(json["modules"][0]["average_function_lines"], 2.5);
+        assert_eq!(render_json(&[]), "{\n  \"schema\": \"refactor/stats/v1\",\n  \"modules\": []\n}\n");
+    }
+}
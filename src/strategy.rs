@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use syn::visit::{visit_item_fn, Visit};
+use syn::ItemFn;
+
+use crate::{plan, ReferenceVisitor};
+
+// A function called by at least this share of the others is a hub, such as a logging helper,
+// whose calls say nothing about which functions belong together
+const HUB_CALLER_SHARE: f64 = 0.5;
+const HUB_MIN_CALLERS: usize = 3;
+
+// A function with at least this many type parameters, or with one bounded by at least this
+// many traits, is generic enough to serve any domain when it names none of the crate's types
+const GENERIC_MIN_TYPE_PARAMS: usize = 2;
+const GENERIC_MIN_BOUNDS: usize = 3;
+
+// How functions are grouped
+#[derive(Clone, Copy, PartialEq)]
+pub enum FunctionStrategy {
+    // By the keywords in their names
+    Keywords,
+    // By the parts of the call graph they form, falling back to keywords for loners
+    Calls,
+}
+
+// Where the structs and enums that no state machine takes along go
+#[derive(Clone, Copy, PartialEq)]
+pub enum TypeStrategy {
+    // They stay where they are declared
+    Stay,
+    // Types referring to each other move together, next to the only group using them if any
+    Clusters,
+}
+
+// Where the impls of a moving type go
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImplStrategy {
+    // Along with the type
+    WithType,
+    // Inherent impls go along with the type, trait impls stay where they are
+    Stay,
+}
+
+// Where the domain-neutral generic helpers go, such as `fn merge_by<K: Ord, V, F>(..) where F: Fn(&V) -> K`
+#[derive(Clone, Copy, PartialEq)]
+pub enum GenericStrategy {
+    // Into a group like any other function
+    Group,
+    // Together into a `generic_support` module
+    Support,
+    // They stay where they are declared
+    Stay,
+}
+
+// The strategy chosen for each kind of item. Macros are always pinned where they are declared,
+// since `macro_rules!` are only in scope below their definition
+#[derive(Clone, Copy)]
+pub struct Strategies {
+    pub functions: FunctionStrategy,
+    pub types: TypeStrategy,
+    pub impls: ImplStrategy,
+    pub generics: GenericStrategy,
+}
+
+impl Default for Strategies {
+    fn default() -> Self {
+        Strategies {
+            functions: FunctionStrategy::Keywords,
+            types: TypeStrategy::Stay,
+            impls: ImplStrategy::WithType,
+            generics: GenericStrategy::Group,
+        }
+    }
+}
+
+// Function to order candidates that are otherwise just as good: by name with seed 0, and in an
+// order given by any other seed, the same on every run
+pub fn tie_break(seed: u64, name: &str) -> u64 {
+    if seed == 0 {
+        0
+    } else {
+        plan::stable_hash(&format!("{}|{}", seed, name))
+    }
+}
+
+// Function to write the strategies back the way `parse` reads them
+pub fn spec(strategies: &Strategies) -> String {
+    let functions = match strategies.functions {
+        FunctionStrategy::Keywords => "keywords",
+        FunctionStrategy::Calls => "calls",
+    };
+    let types = match strategies.types {
+        TypeStrategy::Stay => "stay",
+        TypeStrategy::Clusters => "clusters",
+    };
+    let impls = match strategies.impls {
+        ImplStrategy::WithType => "with-type",
+        ImplStrategy::Stay => "stay",
+    };
+    let generics = match strategies.generics {
+        GenericStrategy::Group => "group",
+        GenericStrategy::Support => "support",
+        GenericStrategy::Stay => "stay",
+    };
+    format!("fn={},type={},impl={},generic={},macro=pinned", functions, types, impls, generics)
+}
+
+// Function to read strategies written like `fn=calls,type=clusters`, leaving the kinds not
+// mentioned as they are in `base`
+pub fn parse(spec: &str, base: Strategies) -> Result<Strategies, String> {
+    let mut strategies = base;
+    for part in spec.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        match part.split_once('=') {
+            Some(("fn", "keywords")) => strategies.functions = FunctionStrategy::Keywords,
+            Some(("fn", "calls")) => strategies.functions = FunctionStrategy::Calls,
+            Some(("type", "stay")) => strategies.types = TypeStrategy::Stay,
+            Some(("type", "clusters")) => strategies.types = TypeStrategy::Clusters,
+            Some(("impl", "with-type")) => strategies.impls = ImplStrategy::WithType,
+            Some(("impl", "stay")) => strategies.impls = ImplStrategy::Stay,
+            Some(("generic", "group")) => strategies.generics = GenericStrategy::Group,
+            Some(("generic", "support")) => strategies.generics = GenericStrategy::Support,
+            Some(("generic", "stay")) => strategies.generics = GenericStrategy::Stay,
+            Some(("macro", "pinned")) => {}
+            _ => {
+                return Err(format!(
+                    "`{}` is not a strategy; the strategies are fn=keywords|calls, type=stay|clusters, impl=with-type|stay, generic=group|support|stay and macro=pinned",
+                    part
+                ))
+            }
+        }
+    }
+    Ok(strategies)
+}
+
+// Collects the names of every path segment, such as `Vec` and `Config` in `Vec<Config>`
+#[derive(Default)]
+struct PathNameVisitor {
+    names: HashSet<String>,
+}
+
+impl Visit<'_> for PathNameVisitor {
+    fn visit_path_segment(&mut self, node: &syn::PathSegment) {
+        self.names.insert(node.ident.to_string());
+        syn::visit::visit_path_segment(self, node);
+    }
+}
+
+// Function to tell whether a function is a domain-neutral generic helper: generic over several
+// types, or over one with many bounds, while its signature names none of `domain_types`. Returns
+// its type parameters when it is
+pub fn generic_helper_params(func: &ItemFn, domain_types: &HashSet<String>) -> Option<Vec<String>> {
+    let generics = &func.sig.generics;
+    let params: Vec<String> = generics.type_params().map(|param| param.ident.to_string()).collect();
+    let bounds = generics.type_params().map(|param| param.bounds.len()).sum::<usize>()
+        + generics.where_clause.iter().flat_map(|clause| &clause.predicates).map(|predicate| match predicate {
+            syn::WherePredicate::Type(predicate) => predicate.bounds.len(),
+            _ => 0,
+        }).sum::<usize>();
+    if params.len() < GENERIC_MIN_TYPE_PARAMS && (params.is_empty() || bounds < GENERIC_MIN_BOUNDS) {
+        return None;
+    }
+    let mut visitor = PathNameVisitor::default();
+    visitor.visit_signature(&func.sig);
+    (!visitor.names.iter().any(|name| domain_types.contains(name))).then_some(params)
+}
+
+// Function to split `candidates` into the connected parts of the graph of calls between them,
+// mapped to the function naming each part: the one the others call the least, then the one
+// calling the most, with `seed` breaking ties. Loners and calls through hubs are left out
+pub fn call_clusters(func_asts: &HashMap<String, ItemFn>, candidates: &HashSet<String>, seed: u64) -> BTreeMap<String, Vec<String>> {
+    let mut calls: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    let mut callers: HashMap<&str, usize> = HashMap::new();
+    for caller in candidates {
+        let mut visitor = ReferenceVisitor {
+            referenced_names: HashSet::new(),
+            through_super: false,
+        };
+        visit_item_fn(&mut visitor, &func_asts[caller]);
+        let callees: BTreeSet<&str> = candidates
+            .iter()
+            .filter(|callee| *callee != caller && visitor.referenced_names.contains(*callee))
+            .map(String::as_str)
+            .collect();
+        for callee in &callees {
+            *callers.entry(callee).or_default() += 1;
+        }
+        calls.insert(caller, callees);
+    }
+
+    let hub_callers = (candidates.len().saturating_sub(1) as f64 * HUB_CALLER_SHARE).max(HUB_MIN_CALLERS as f64);
+    let is_hub = |name: &str| callers.get(name).is_some_and(|&count| count as f64 >= hub_callers);
+    let mut edges: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for (caller, callees) in &calls {
+        for callee in callees.iter().filter(|callee| !is_hub(callee) && !is_hub(caller)) {
+            edges.entry(caller).or_default().insert(callee);
+            edges.entry(callee).or_default().insert(caller);
+        }
+    }
+
+    let mut clusters = BTreeMap::new();
+    for component in components(&edges) {
+        let driver = component
+            .iter()
+            .min_by_key(|name| {
+                let called_by = component.iter().filter(|caller| calls[*caller].contains(*name)).count();
+                let calling = calls[*name].iter().filter(|callee| component.contains(*callee)).count();
+                (called_by, usize::MAX - calling, tie_break(seed, name))
+            })
+            .expect("A call cluster is never empty");
+        clusters.insert(driver.to_string(), component.iter().map(|name| name.to_string()).collect());
+    }
+    clusters
+}
+
+// Function to split the types into the connected parts of the graph of their references to each
+// other, each sorted with the type the others refer to the most first and `seed` breaking ties.
+// Loners are left out
+pub fn type_clusters(references: &HashMap<String, HashSet<String>>, seed: u64) -> Vec<Vec<String>> {
+    let mut edges: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    let mut referrers: HashMap<&str, usize> = HashMap::new();
+    for (name, referenced) in references {
+        for other in referenced.iter().filter(|other| *other != name && references.contains_key(*other)) {
+            edges.entry(name).or_default().insert(other);
+            edges.entry(other).or_default().insert(name);
+            *referrers.entry(other).or_default() += 1;
+        }
+    }
+    components(&edges)
+        .into_iter()
+        .map(|component| {
+            let mut cluster: Vec<&str> = component.into_iter().collect();
+            cluster.sort_by_key(|name| (usize::MAX - referrers.get(name).copied().unwrap_or(0), tie_break(seed, name)));
+            cluster.into_iter().map(String::from).collect()
+        })
+        .collect()
+}
+
+// Function to find the connected components of an undirected graph, in a stable order
+fn components<'a>(edges: &BTreeMap<&'a str, BTreeSet<&'a str>>) -> Vec<BTreeSet<&'a str>> {
+    let mut seen = HashSet::new();
+    let mut components = Vec::new();
+    for &start in edges.keys() {
+        if !seen.insert(start) {
+            continue;
+        }
+        let mut component = BTreeSet::from([start]);
+        let mut pending = vec![start];
+        while let Some(node) = pending.pop() {
+            for &next in &edges[node] {
+                if seen.insert(next) {
+                    component.insert(next);
+                    pending.push(next);
+                }
+            }
+        }
+        components.push(component);
+    }
+    components
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ties_are_broken_the_same_way_on_every_run() {
+        assert_eq!(tie_break(0, "parse"), tie_break(0, "read"));
+        assert_eq!(tie_break(7, "parse"), tie_break(7, "parse"));
+        assert_ne!(tie_break(7, "parse"), tie_break(8, "parse"));
+    }
+
+    #[test]
+    fn type_clusters_put_the_most_referred_type_first() {
+        let references = |names: &[(&str, &[&str])]| -> HashMap<String, HashSet<String>> {
+            names.iter().map(|(name, referenced)| (name.to_string(), referenced.iter().map(|other| other.to_string()).collect())).collect()
+        };
+        let types = references(&[("Config", &["Engine"]), ("Engine", &["Config", "Job"]), ("Job", &["Engine"]), ("Lone", &[])]);
+        assert_eq!(type_clusters(&types, 0), [["Engine", "Config", "Job"]]);
+
+        // `Config` and `Job` are referred to as often, which seed 0 orders by name and any other
+        // seed in an order of its own that runs with that seed agree on
+        let seeded = type_clusters(&types, 7);
+        let mut expected = vec!["Config", "Job"];
+        expected.sort_by_key(|name| tie_break(7, name));
+        assert_eq!(seeded, [[vec!["Engine"], expected].concat()]);
+        for _ in 0..8 {
+            let types = references(&[("Lone", &[]), ("Job", &["Engine"]), ("Engine", &["Job", "Config"]), ("Config", &["Engine"])]);
+            assert_eq!(type_clusters(&types, 7), seeded);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::io::IsTerminal;
+use std::path::Path;
+
+use crate::dry_run;
+use crate::teach::Decision;
+use crate::GeneratedFile;
+
+// Colors of the summary, as ANSI escape codes
+const BOLD: &str = "1";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const CYAN: &str = "36";
+const DIM: &str = "2";
+
+// Function to check whether the summary is colored: only on a terminal, and never when
+// NO_COLOR is set, as https://no-color.org asks
+fn colored() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+}
+
+// Function to color text when the summary is colored
+fn paint(text: &str, color: &str) -> String {
+    if colored() {
+        format!("\x1b[{}m{}\x1b[0m", color, text)
+    } else {
+        text.to_string()
+    }
+}
+
+// Function to write what a run wrote: a headline, then a table of the generated modules with
+// the functions, lines and imports each got, the imports the root gained, and the items that
+// were kept where they were
+pub fn render(headline: &str, files: &[GeneratedFile], root_path: &Path, original: &str, decisions: &[Decision]) -> String {
+    let mut lines = vec![paint(headline, &format!("{};{}", BOLD, GREEN))];
+
+    let mut rows = vec![["module".to_string(), "file".to_string(), "functions".to_string(), "lines".to_string(), "imports".to_string()]];
+    for file in files.iter().filter(|file| !file.module_path.is_empty()) {
+        let (imports, functions, _) = dry_run::contents(&file.code);
+        rows.push([
+            format!("crate::{}", file.module_path.join("::")),
+            file.path.display().to_string(),
+            functions.len().to_string(),
+            file.code.lines().count().to_string(),
+            imports.len().to_string(),
+        ]);
+    }
+    if let Some(root) = files.iter().find(|file| file.path == root_path) {
+        let (imports, functions, _) = dry_run::contents(&root.code);
+        rows.push([
+            "crate".to_string(),
+            root.path.display().to_string(),
+            functions.len().to_string(),
+            root.code.lines().count().to_string(),
+            imports.len().to_string(),
+        ]);
+    }
+    if rows.len() > 1 {
+        lines.push(String::new());
+        // Columns are padded before they are colored, since escape codes take no room on screen
+        let widths: Vec<usize> = (0..5).map(|column| rows.iter().map(|row| row[column].chars().count()).max().unwrap_or(0)).collect();
+        for (index, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(column, (cell, width))| {
+                    // Names are aligned on the left and counts on the right
+                    let padded = if column < 2 { format!("{:<width$}", cell, width = width) } else { format!("{:>width$}", cell, width = width) };
+                    match (index, column) {
+                        (0, _) => paint(&padded, BOLD),
+                        (_, 0) => paint(&padded, CYAN),
+                        (_, 1) => paint(&padded, DIM),
+                        _ => padded,
+                    }
+                })
+                .collect();
+            lines.push(format!("  {}", cells.join("  ").trim_end()));
+        }
+    }
+
+    if let Some(root) = files.iter().find(|file| file.path == root_path) {
+        let (before, _, _) = dry_run::contents(original);
+        let before: Vec<String> = before.iter().map(|import| dry_run::normalized(import)).collect();
+        let (after, _, _) = dry_run::contents(&root.code);
+        let added: Vec<&String> = after.iter().filter(|import| !before.contains(&dry_run::normalized(import))).collect();
+        if !added.is_empty() {
+            lines.push(String::new());
+            lines.push(paint("Imports added to the root:", BOLD));
+            for import in added {
+                lines.push(format!("  {}", import.replace('\n', " ")));
+            }
+        }
+    }
+
+    let kept: Vec<&Decision> = decisions.iter().filter(|decision| decision.change.starts_with("stays")).collect();
+    if !kept.is_empty() {
+        lines.push(String::new());
+        lines.push(paint("Kept in place:", &format!("{};{}", BOLD, YELLOW)));
+        for decision in kept {
+            lines.push(format!("  {} {}", paint(&decision.subject, YELLOW), decision.change));
+        }
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::teach::Concept;
+
+    #[test]
+    fn summaries_tabulate_the_modules_and_what_the_root_gained() {
+        let files = vec![
+            GeneratedFile {
+                path: PathBuf::from("src/util_mod.rs"),
+                code: "use std::fs;\n\npub fn load() {}\n\npub fn save() {}\n".to_string(),
+                module_path: vec!["util_mod".to_string()],
+                written_in: Vec::new(),
+            },
+            GeneratedFile { path: PathBuf::from("src/tmp_main.rs"), code: "mod util_mod;\nuse std::fs;\nuse util_mod::*;\n\nfn main() {}\n".to_string(), module_path: Vec::new(), written_in: Vec::new() },
+        ];
+        let decisions = vec![
+            Decision { subject: "main".to_string(), change: "stays in the root, as the entry point".to_string(), concept: Concept::Placement },
+            Decision { subject: "load".to_string(), change: "moves to `util_mod`".to_string(), concept: Concept::Placement },
+        ];
+        let summary = render("Refactoring complete.", &files, Path::new("src/tmp_main.rs"), "use std::fs;\n\nfn main() {}\n", &decisions);
+        assert_eq!(
+            summary,
+            "Refactoring complete.\n\n  module           file             functions  lines  imports\n  crate::util_mod  src/util_mod.rs          2      5        1\n  crate            src/tmp_main.rs          1      5        2\n\nImports added to the root:\n  use util_mod::*;\n\nKept in place:\n  main stays in the root, as the entry point\n"
+        );
+    }
+
+    #[test]
+    fn summaries_of_nothing_are_the_headline() {
+        assert_eq!(render("Nothing to split.", &[], Path::new("src/tmp_main.rs"), "", &[]), "Nothing to split.\n");
+    }
+}
@@ -0,0 +1,114 @@
+// The Rust concept behind a structural decision
+#[derive(Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Concept {
+    Placement,
+    Privacy,
+    TraitScope,
+    RelativePaths,
+    ReExports,
+    RootOnly,
+    Exports,
+    LowLevel,
+}
+
+impl Concept {
+    fn explanation(self) -> &'static str {
+        match self {
+            Concept::Placement => {
+                "Modules group code that changes together. A file per concern keeps each one small enough \
+                 to read in one go, and `mod name;` tells the compiler to look for it in `name.rs`."
+            }
+            Concept::Privacy => {
+                "Privacy is per module: a private item is visible in its own module and that module's \
+                 children. An item moved one module down needs `pub(super)` (or one more `super`) to stay \
+                 visible in the modules that could use it before, and no further."
+            }
+            Concept::TraitScope => {
+                "A trait method can only be called while the trait is in scope, so the module calling \
+                 it needs its own `use` of the trait even though the type is defined elsewhere."
+            }
+            Concept::RelativePaths => {
+                "`self::` and `super::` are relative to the module the code is written in. Code moved \
+                 one module down needs one more `super::` to keep naming the same items."
+            }
+            Concept::ReExports => {
+                "`pub use module::*;` makes the module's items available under the parent's path as \
+                 well, so the code that called them before the move keeps compiling unchanged."
+            }
+            Concept::RootOnly => {
+                "Some items are only valid at the crate root, like `main` or the exported macros of a \
+                 proc-macro crate, so they stay there and only their contents can move."
+            }
+            Concept::Exports => {
+                "`#[no_mangle]` and `#[export_name]` functions are symbols other code links against by \
+                 name, so they stay where they are declared, with their visibility and attributes as written."
+            }
+            Concept::LowLevel => {
+                "Assembly, `#[link_section]` and `#[used]` depend on symbols, sections and the order the \
+                 linker sees them in, none of which the compiler checks, so these items are kept where \
+                 they are (or together in an `asm` module with `--asm group`)."
+            }
+        }
+    }
+}
+
+// A structural decision made while refactoring, explained in teaching mode
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "schema", rename = "refactor/decision/v1"))]
+pub struct Decision {
+    pub subject: String,
+    pub change: String,
+    pub concept: Concept,
+}
+
+// Function to print every decision with the concept behind it, explaining each concept once
+pub fn print_report(decisions: &[Decision]) {
+    print!("{}", report(decisions));
+}
+
+// Function to write the report of the decisions, those about the same item or module together
+fn report(decisions: &[Decision]) -> String {
+    let mut decisions: Vec<&Decision> = decisions.iter().collect();
+    decisions.sort_by(|a, b| a.subject.cmp(&b.subject));
+
+    let mut explained = Vec::new();
+    let mut report = String::from("What changed and why:\n");
+    for decision in decisions {
+        report.push_str(&format!("  {}: {}\n", decision.subject, decision.change));
+        let concept = decision.concept as usize;
+        if !explained.contains(&concept) {
+            explained.push(concept);
+            report.push_str(&format!("      why: {}\n", decision.concept.explanation()));
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(subject: &str, change: &str, concept: Concept) -> Decision {
+        Decision { subject: subject.to_string(), change: change.to_string(), concept }
+    }
+
+    #[test]
+    fn each_concept_is_explained_at_its_first_decision() {
+        let decisions = vec![
+            decision("write", "moves to `io_mod`", Concept::Placement),
+            decision("read", "`private` becomes `pub(super)`", Concept::Privacy),
+            decision("read", "moves to `io_mod`", Concept::Placement),
+            decision("main", "stays in the root", Concept::RootOnly),
+        ];
+        let report = report(&decisions);
+        let lines: Vec<&str> = report.lines().filter(|line| !line.starts_with("      why:")).collect();
+        assert_eq!(lines, vec!["What changed and why:", "  main: stays in the root", "  read: `private` becomes `pub(super)`", "  read: moves to `io_mod`", "  write: moves to `io_mod`"]);
+        for concept in [Concept::Placement, Concept::Privacy, Concept::RootOnly] {
+            assert_eq!(report.matches(concept.explanation()).count(), 1);
+        }
+        assert!(report.contains("  read: moves to `io_mod`\n      why: Modules group code"));
+        assert!(report.ends_with("  write: moves to `io_mod`\n"));
+    }
+}
@@ -0,0 +1,258 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use syn::ext::IdentExt;
+use syn::{Item, ItemMod, Visibility};
+
+// A file of the crate together with the module path it defines and where its children live
+pub struct ModuleFile {
+    pub path: PathBuf,
+    pub module_path: Vec<String>,
+    pub module_dir: PathBuf,
+}
+
+// A module together with the number of items it holds, in the spirit of `cargo modules`
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "schema", rename = "refactor/module-tree/v1"))]
+pub struct ModuleTree {
+    pub name: String,
+    pub visibility: String,
+    pub item_count: usize,
+    pub children: Vec<ModuleTree>,
+}
+
+impl ModuleTree {
+    // Function to render the tree as ASCII lines, children sorted by name
+    fn render(&self) -> Vec<String> {
+        let mut lines = vec![self.label()];
+        self.render_children("", &mut lines);
+        lines
+    }
+
+    fn render_children(&self, prefix: &str, lines: &mut Vec<String>) {
+        let mut children: Vec<&ModuleTree> = self.children.iter().collect();
+        children.sort_by(|a, b| a.name.cmp(&b.name));
+
+        for (index, child) in children.iter().enumerate() {
+            let is_last = index + 1 == children.len();
+            let (branch, indent) = if is_last { ("└── ", "    ") } else { ("├── ", "│   ") };
+            lines.push(format!("{}{}{}", prefix, branch, child.label()));
+            child.render_children(&format!("{}{}", prefix, indent), lines);
+        }
+    }
+
+    fn label(&self) -> String {
+        let items = if self.item_count == 1 { "item" } else { "items" };
+        if self.visibility.is_empty() {
+            format!("{} ({} {})", self.name, self.item_count, items)
+        } else {
+            format!("mod {}: {} ({} {})", self.name, self.visibility, self.item_count, items)
+        }
+    }
+}
+
+// Function to build the module tree of a file as it exists today, following `mod foo;`
+// declarations into the files they refer to
+pub fn current_tree(name: &str, items: &[Item], module_dir: &Path) -> ModuleTree {
+    let mut tree = ModuleTree {
+        name: name.to_string(),
+        visibility: String::new(),
+        item_count: count_items(items),
+        children: Vec::new(),
+    };
+
+    for item in items {
+        if let Item::Mod(item_mod) = item {
+            tree.children.push(module_tree(item_mod, module_dir));
+        }
+    }
+    tree
+}
+
+// Function to build the tree of a module item, whether inline or declared in a separate file
+pub fn module_tree(item_mod: &ItemMod, module_dir: &Path) -> ModuleTree {
+    let name = item_mod.ident.to_string();
+    // Files and directories are named without the `r#` of a raw identifier
+    let file_name = item_mod.ident.unraw().to_string();
+    let child_dir = module_dir.join(&file_name);
+
+    let mut tree = match &item_mod.content {
+        Some((_, items)) => current_tree(&name, items, &child_dir),
+        None => match declared_module_file(item_mod, &file_name, module_dir).and_then(|path| {
+            let file = syn::parse_file(&fs::read_to_string(&path).ok()?).ok()?;
+            Some((path, file))
+        }) {
+            // A file loaded through `#[path]` keeps its own children next to it, like a `mod.rs`
+            Some((path, file)) if item_mod.attrs.iter().any(|attr| attr.path().is_ident("path")) => {
+                current_tree(&name, &file.items, path.parent().unwrap_or(module_dir))
+            }
+            Some((_, file)) => current_tree(&name, &file.items, &child_dir),
+            None => ModuleTree {
+                name: format!("{} (unresolved)", name),
+                visibility: String::new(),
+                item_count: 0,
+                children: Vec::new(),
+            },
+        },
+    };
+    tree.visibility = visibility_label(&item_mod.vis);
+    tree
+}
+
+// Function to count the items of a module, leaving out imports and submodules
+pub fn count_items(items: &[Item]) -> usize {
+    items
+        .iter()
+        .filter(|item| !matches!(item, Item::Use(_) | Item::Mod(_)))
+        .count()
+}
+
+// Function to describe a visibility the way `cargo modules` does
+pub fn visibility_label(vis: &Visibility) -> String {
+    match vis {
+        Visibility::Public(_) => "pub".to_string(),
+        Visibility::Restricted(restricted) => {
+            let path = quote::ToTokens::to_token_stream(&restricted.path).to_string().replace(' ', "");
+            if restricted.in_token.is_some() {
+                format!("pub(in {})", path)
+            } else {
+                format!("pub({})", path)
+            }
+        }
+        Visibility::Inherited => "priv".to_string(),
+    }
+}
+
+// Function to print the current and proposed trees next to each other
+pub fn print_side_by_side(current: &ModuleTree, proposed: &ModuleTree) {
+    let left = current.render();
+    let right = proposed.render();
+    let width = left
+        .iter()
+        .map(|line| line.chars().count())
+        .chain(std::iter::once("current".len()))
+        .max()
+        .unwrap_or(0);
+
+    println!("{:<width$}    proposed", "current", width = width);
+    for index in 0..left.len().max(right.len()) {
+        let left_line = left.get(index).map_or("", String::as_str);
+        let right_line = right.get(index).map_or("", String::as_str);
+        let padding = width - left_line.chars().count();
+        println!("{}{}    {}", left_line, " ".repeat(padding), right_line);
+    }
+}
+
+// Function to find the file backing `mod name;` declared in a module living in `module_dir`
+pub fn resolve_module_file(name: &str, module_dir: &Path) -> Option<PathBuf> {
+    [module_dir.join(format!("{}.rs", name)), module_dir.join(name).join("mod.rs")]
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+// Function to find the file backing a `mod name;` item, following its `#[path]` attribute if any
+pub fn declared_module_file(item_mod: &ItemMod, file_name: &str, module_dir: &Path) -> Option<PathBuf> {
+    let path_attr = item_mod.attrs.iter().find(|attr| attr.path().is_ident("path"));
+    match path_attr.map(|attr| &attr.meta) {
+        Some(syn::Meta::NameValue(syn::MetaNameValue {
+            value: syn::Expr::Lit(syn::ExprLit { lit: syn::Lit::Str(path), .. }),
+            ..
+        })) => Some(module_dir.join(path.value())).filter(|path| path.is_file()),
+        _ => resolve_module_file(file_name, module_dir),
+    }
+}
+
+// Function to find the crate root when a package or source directory is given instead of a file
+pub fn resolve_crate_root(path: &Path) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    ["src/lib.rs", "src/main.rs", "lib.rs", "main.rs"]
+        .iter()
+        .map(|candidate| path.join(candidate))
+        .find(|candidate| candidate.is_file())
+}
+
+// Function to list every file of the crate reachable through `mod foo;` declarations
+pub fn crate_module_files(root: &Path) -> Vec<ModuleFile> {
+    let mut module_files = Vec::new();
+    let root_dir = root.parent().unwrap_or(Path::new(".")).to_path_buf();
+    collect_module_files(root.to_path_buf(), Vec::new(), root_dir, &mut module_files);
+    module_files
+}
+
+fn collect_module_files(path: PathBuf, module_path: Vec<String>, module_dir: PathBuf, module_files: &mut Vec<ModuleFile>) {
+    let Some(syntax_tree) = fs::read_to_string(&path).ok().and_then(|content| syn::parse_file(&content).ok()) else {
+        return;
+    };
+    collect_declared_modules(&syntax_tree.items, &module_path, &module_dir, module_files);
+    module_files.push(ModuleFile {
+        path,
+        module_path,
+        module_dir,
+    });
+}
+
+fn collect_declared_modules(items: &[Item], module_path: &[String], module_dir: &Path, module_files: &mut Vec<ModuleFile>) {
+    for item in items {
+        if let Item::Mod(item_mod) = item {
+            let name = item_mod.ident.to_string();
+            let mut child_path = module_path.to_vec();
+            child_path.push(name.clone());
+            let file_name = item_mod.ident.unraw().to_string();
+            let child_dir = module_dir.join(&file_name);
+
+            match &item_mod.content {
+                Some((_, items)) => collect_declared_modules(items, &child_path, &child_dir, module_files),
+                None => {
+                    if let Some(file) = declared_module_file(item_mod, &file_name, module_dir) {
+                        collect_module_files(file, child_path, child_dir, module_files);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trees_render_their_modules_sorted_with_visibility_and_item_counts() {
+        let file = syn::parse_file("use std::fs;\nconst A: u8 = 0;\npub mod net { fn send() {} pub(crate) mod tcp { fn connect() {} fn close() {} } }\nmod gone;\npub(in crate::net) mod io {}\n").unwrap();
+        let tree = current_tree("crate", &file.items, Path::new("/nonexistent"));
+        assert_eq!(
+            tree.render(),
+            [
+                "crate (1 item)",
+                "├── mod gone (unresolved): priv (0 items)",
+                "├── mod io: pub(in crate::net) (0 items)",
+                "└── mod net: pub (1 item)",
+                "    └── mod tcp: pub(crate) (2 items)",
+            ]
+        );
+    }
+
+    #[test]
+    fn module_files_are_found_through_their_declarations() {
+        let dir = std::env::temp_dir().join(format!("refactor-tree-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src/engine")).unwrap();
+        fs::create_dir_all(dir.join("src/other")).unwrap();
+        fs::write(dir.join("src/main.rs"), "mod engine;\n#[path = \"other/odd.rs\"]\nmod odd;\nfn main() {}\n").unwrap();
+        fs::write(dir.join("src/engine/mod.rs"), "mod parse;\n").unwrap();
+        fs::write(dir.join("src/engine/parse.rs"), "fn parse() {}\n").unwrap();
+        fs::write(dir.join("src/other/odd.rs"), "fn odd() {}\n").unwrap();
+
+        let root = resolve_crate_root(&dir).unwrap();
+        assert_eq!(root, dir.join("src/main.rs"));
+        let mut found: Vec<(String, PathBuf)> = crate_module_files(&root)
+            .into_iter()
+            .map(|file| (file.module_path.join("::"), file.path.strip_prefix(&dir).unwrap().to_path_buf()))
+            .collect();
+        found.sort();
+        let expected = [("", "src/main.rs"), ("engine", "src/engine/mod.rs"), ("engine::parse", "src/engine/parse.rs"), ("odd", "src/other/odd.rs")];
+        assert_eq!(found, expected.map(|(module_path, path)| (module_path.to_string(), PathBuf::from(path))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// A file about to be written, with what it held before so the write can be rolled back
+pub struct Backup {
+    pub path: PathBuf,
+    pub previous: Option<Vec<u8>>,
+}
+
+// Function to find the package the input belongs to, by looking for a Cargo.toml upwards
+pub fn find_package_root(path: &Path) -> Option<PathBuf> {
+    let start = fs::canonicalize(path).ok()?;
+    start
+        .ancestors()
+        .find(|dir| dir.join("Cargo.toml").is_file())
+        .map(Path::to_path_buf)
+}
+
+// Function to run `cargo check` on the package and collect its warnings, without the locations
+// since those are expected to change when code moves between files
+pub fn cargo_warnings(package_root: &Path) -> Result<Vec<String>, String> {
+    let output = Command::new("cargo")
+        .args(["check", "--all-targets", "--message-format", "short"])
+        .current_dir(package_root)
+        .output()
+        .map_err(|e| format!("Failed to run cargo check: {}", e))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(format!("cargo check failed:\n{}", stderr.trim_end()));
+    }
+
+    Ok(stderr
+        .lines()
+        .filter_map(|line| line.find("warning: ").map(|start| line[start..].to_string()))
+        // The closing "`crate` (bin "crate") generated N warnings" summaries name no warning
+        .filter(|warning| !warning.contains(") generated "))
+        .collect())
+}
+
+// Function to run `cargo check` on the package and collect its errors, each as the one line of
+// the short message format, such as `src/io_mod.rs:12:5: error[E0433]: failed to resolve: ...`
+pub fn cargo_errors(package_root: &Path) -> Result<Vec<String>, String> {
+    let output = Command::new("cargo")
+        .args(["check", "--all-targets", "--message-format", "short"])
+        .current_dir(package_root)
+        .output()
+        .map_err(|e| format!("Failed to run cargo check: {}", e))?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Ok(stderr
+        .lines()
+        // Summaries such as "error: could not compile" come without a location
+        .filter(|line| !line.starts_with("error") && (line.contains(": error[") || line.contains(": error: ")))
+        .map(str::to_string)
+        .collect())
+}
+
+// Function to find the warnings in `after` that `before` didn't have, as many times as they were added
+pub fn new_warnings(before: &[String], after: &[String]) -> Vec<String> {
+    let mut remaining: HashMap<&str, usize> = HashMap::new();
+    for warning in before {
+        *remaining.entry(warning).or_default() += 1;
+    }
+    after
+        .iter()
+        .filter(|warning| match remaining.get_mut(warning.as_str()) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .cloned()
+        .collect()
+}
+
+// Function to remember what `path` holds before it is overwritten
+pub fn backup(path: &Path) -> Backup {
+    Backup {
+        path: path.to_path_buf(),
+        previous: fs::read(path).ok(),
+    }
+}
+
+// Function to put back what the backed up files held, removing the ones that didn't exist
+pub fn rollback(backups: &[Backup]) {
+    for backup in backups.iter().rev() {
+        let result = match &backup.previous {
+            Some(previous) => fs::write(&backup.path, previous),
+            None => fs::remove_file(&backup.path),
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to roll back {:?}: {}", backup.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_added_warnings_are_new() {
+        let warning = |text: &str| text.to_string();
+        let before = [warning("warning: unused import: `fs`"), warning("warning: function `old` is never used")];
+        let after = [
+            warning("warning: unused import: `fs`"),
+            warning("warning: unused import: `fs`"),
+            warning("warning: function `old` is never used"),
+            warning("warning: unused variable: `count`"),
+        ];
+        assert_eq!(new_warnings(&before, &after), ["warning: unused import: `fs`", "warning: unused variable: `count`"]);
+        assert!(new_warnings(&after, &before).is_empty());
+    }
+
+    #[test]
+    fn rolled_back_files_hold_what_they_held_before() {
+        let dir = std::env::temp_dir().join(format!("refactor-verify-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (kept, created) = (dir.join("main.rs"), dir.join("util_mod.rs"));
+        fs::write(&kept, "fn main() {}\n").unwrap();
+        let backups = [backup(&kept), backup(&created), backup(&kept)];
+        fs::write(&kept, "mod util_mod;\n").unwrap();
+        fs::write(&created, "fn helper() {}\n").unwrap();
+        fs::write(&kept, "mod util_mod;\nfn main() {}\n").unwrap();
+
+        rollback(&backups);
+        assert_eq!(fs::read_to_string(&kept).unwrap(), "fn main() {}\n");
+        assert!(!created.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
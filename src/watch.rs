@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::tree;
+
+// How often the watched files are looked at
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Function to take down when each file of the crate at `root` last changed. The root is always
+// in it, since a root with a syntax error halfway through an edit lists no modules
+fn snapshot(root: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut paths: Vec<PathBuf> = tree::crate_module_files(root).into_iter().map(|module_file| module_file.path).collect();
+    if !paths.iter().any(|path| path == root) {
+        paths.push(root.to_path_buf());
+    }
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let modified = fs::metadata(&path).and_then(|metadata| metadata.modified()).ok();
+            (path, modified)
+        })
+        .collect()
+}
+
+// Function to plan the split of `path` again and show it in place of the last plan, by running a
+// dry run with the flags the watch was given
+fn show_plan(path: &Path, flags: &[String]) -> Result<(), String> {
+    let program = std::env::current_exe().map_err(|e| format!("Failed to find the refactor executable: {}", e))?;
+    // Clear the terminal and go back to its top left corner
+    print!("\x1b[2J\x1b[H");
+    println!("Watching {}; the plan below follows every change until Ctrl-C.", path.display());
+    println!();
+    Command::new(program)
+        .args(flags)
+        .arg("--dry-run")
+        .arg(path)
+        .status()
+        .map(|_| ())
+        .map_err(|e| format!("Failed to plan the split of {:?}: {}", path, e))
+}
+
+// Function to watch a file or crate and show the split it would get each time one of its files
+// changes, without ever writing anything
+pub fn watch(path: &Path, flags: &[String]) -> Result<(), String> {
+    let root = tree::resolve_crate_root(path).ok_or_else(|| format!("There is no Rust file or crate at {:?}", path))?;
+    let mut last = snapshot(&root);
+    show_plan(path, flags)?;
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let current = snapshot(&root);
+        if current != last {
+            last = current;
+            show_plan(path, flags)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_change_with_any_file_of_the_crate() {
+        let dir = std::env::temp_dir().join(format!("refactor-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (root, module) = (dir.join("main.rs"), dir.join("util.rs"));
+        fs::write(&root, "mod util;\nfn main() {}\n").unwrap();
+        fs::write(&module, "pub fn helper() {}\n").unwrap();
+        let first = snapshot(&root);
+        assert_eq!(first.iter().map(|(path, _)| path.clone()).collect::<Vec<_>>(), vec![root.clone(), module.clone()]);
+        fs::File::options().append(true).open(&module).unwrap().set_modified(SystemTime::now() + Duration::from_secs(60)).unwrap();
+        assert_ne!(snapshot(&root), first);
+        // A root halfway through an edit is still watched
+        fs::write(&root, "mod util;\nfn main( {}\n").unwrap();
+        assert!(snapshot(&root).iter().any(|(path, _)| *path == root));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
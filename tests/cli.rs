@@ -0,0 +1,972 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// Function to copy the crate of `tests/fixtures/<name>` into a directory of the test's own, where
+// the run can write without disturbing the other tests
+fn fixture(name: &str, test: &str) -> PathBuf {
+    fn copy_dir(from: &Path, to: &Path) {
+        fs::create_dir_all(to).expect("Failed to create the fixture directory");
+        for entry in fs::read_dir(from).expect("Failed to read the fixture").flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                copy_dir(&path, &to.join(entry.file_name()));
+            } else {
+                fs::copy(&path, to.join(entry.file_name())).expect("Failed to copy the fixture");
+            }
+        }
+    }
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(test);
+    if dir.exists() {
+        fs::remove_dir_all(&dir).expect("Failed to clear the fixture directory");
+    }
+    copy_dir(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name), &dir);
+    dir
+}
+
+// Function to run the tool in `dir`, without the refactor.toml of anything around it
+fn refactor(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_refactor"))
+        .args(args)
+        .arg("--no-config")
+        .current_dir(dir)
+        .output()
+        .expect("Failed to run refactor")
+}
+
+// Function to run the tool and check that it succeeded, returning what it printed
+fn refactor_ok(dir: &Path, args: &[&str]) -> String {
+    let output = refactor(dir, args);
+    assert!(output.status.success(), "refactor {:?} failed:\n{}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+// Function to run cargo on the crate in `dir` and check that it succeeded without warnings
+fn cargo_ok(dir: &Path, command: &str) {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let output = Command::new(cargo)
+        .args([command, "--quiet", "--offline"])
+        .current_dir(dir)
+        .env_remove("CARGO_TARGET_DIR")
+        .output()
+        .expect("Failed to run cargo");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "cargo {} failed in {:?}:\n{}", command, dir, stderr);
+    assert!(!stderr.contains("warning"), "cargo {} warned in {:?}:\n{}", command, dir, stderr);
+}
+
+// Function to put the generated root in place of the input, as the user does after a run
+fn swap_root(dir: &Path) {
+    fs::rename(dir.join("src/tmp_main.rs"), dir.join("src/main.rs")).expect("The run wrote no root");
+}
+
+fn read(path: PathBuf) -> String {
+    fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed to read {:?}: {}", path, e))
+}
+
+#[test]
+fn split_file_compiles() {
+    let dir = fixture("split", "split_file_compiles");
+    refactor_ok(&dir, &["src/main.rs"]);
+    assert!(dir.join("src/file_io_mod.rs").is_file());
+    assert!(dir.join("src/util_mod.rs").is_file());
+    assert!(read(dir.join("src/file_io_mod.rs")).contains("fn read_words"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn merged_imports_share_one_tree_per_crate() {
+    let dir = fixture("split", "merged_imports_share_one_tree_per_crate");
+    refactor_ok(&dir, &["--import-style", "merged", "src/main.rs"]);
+    assert!(read(dir.join("src/file_io_mod.rs")).starts_with("use std::{collections::HashMap, fs, io};\n"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn explicit_imports_spell_out_the_glob_of_an_opaque_parent() {
+    let dir = fixture("split", "explicit_imports_spell_out_the_glob_of_an_opaque_parent");
+    // The items a macro declares hide the parent's names, which takes a glob import otherwise
+    let macro_items = "use std::io;\n\nmacro_rules! separator {\n    () => {\n        const SEPARATOR: char = ' ';\n    };\n}\n\nseparator!();\n";
+    let source = read(dir.join("src/main.rs")).replacen("use std::io;\n", macro_items, 1).replace("split_once(' ')", "split_once(SEPARATOR)");
+    fs::write(dir.join("src/main.rs"), source).expect("Failed to write the input");
+    refactor_ok(&dir, &["src/main.rs"]);
+    assert!(read(dir.join("src/util_mod.rs")).starts_with("use crate::*;\n"));
+    fs::remove_file(dir.join("src/tmp_main.rs")).expect("Failed to remove the root");
+    refactor_ok(&dir, &["--explicit-imports", "src/main.rs"]);
+    assert!(read(dir.join("src/util_mod.rs")).starts_with("use crate::{HashMap, SEPARATOR};\n"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn unformatted_runs_leave_the_glue_as_generated() {
+    let dir = fixture("split", "unformatted_runs_leave_the_glue_as_generated");
+    fs::write(dir.join("rustfmt.toml"), "max_width = 30\n").expect("Failed to write the rustfmt config");
+    refactor_ok(&dir, &["--import-style", "merged", "src/main.rs"]);
+    assert!(read(dir.join("src/file_io_mod.rs")).starts_with("use std::{\n    collections::HashMap, fs,\n    io,\n};\n"));
+    fs::remove_file(dir.join("src/tmp_main.rs")).expect("Failed to remove the root");
+    refactor_ok(&dir, &["--import-style", "merged", "--no-format", "src/main.rs"]);
+    assert!(read(dir.join("src/file_io_mod.rs")).starts_with("use std::{collections::HashMap, fs, io};\n"));
+}
+
+#[test]
+fn editions_and_rustfmt_configs_can_be_given() {
+    let dir = fixture("split", "editions_and_rustfmt_configs_can_be_given");
+    let source = read(dir.join("src/main.rs"))
+        .replacen("use std::io;\n", "use std::io;\nuse std::convert::TryFrom;\n", 1)
+        .replace("count.parse().ok()?", "usize::try_from(count.parse::<u64>().ok()?).ok()?");
+    fs::write(dir.join("src/main.rs"), source).expect("Failed to write the input");
+    // `TryFrom` is in the prelude of the manifest's 2021 edition only
+    refactor_ok(&dir, &["src/main.rs"]);
+    assert!(read(dir.join("src/util_mod.rs")).starts_with("use std::collections::HashMap;\n"));
+    fs::remove_file(dir.join("src/tmp_main.rs")).expect("Failed to remove the root");
+    refactor_ok(&dir, &["--edition", "2018", "src/main.rs"]);
+    assert!(read(dir.join("src/util_mod.rs")).starts_with("use crate::*;\n"));
+
+    fs::remove_file(dir.join("src/tmp_main.rs")).expect("Failed to remove the root");
+    fs::write(dir.join("narrow.toml"), "max_width = 30\n").expect("Failed to write the rustfmt config");
+    refactor_ok(&dir, &["--import-style", "merged", "--rustfmt-config", "narrow.toml", "src/main.rs"]);
+    assert!(read(dir.join("src/file_io_mod.rs")).starts_with("use std::{\n"));
+    let output = refactor(&dir, &["--rustfmt-config", "missing.toml", "src/main.rs"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("The rustfmt config \"missing.toml\" doesn't exist"));
+}
+
+#[test]
+fn smoke_tests_pass() {
+    let dir = fixture("split", "smoke_tests_pass");
+    refactor_ok(&dir, &["--smoke-tests", "src/main.rs"]);
+    swap_root(&dir);
+    assert!(read(dir.join("src/main.rs")).contains("#[test]"));
+    cargo_ok(&dir, "test");
+}
+
+#[test]
+fn in_place_run_is_reverted() {
+    let dir = fixture("split", "in_place_run_is_reverted");
+    let original = read(dir.join("src/main.rs"));
+    refactor_ok(&dir, &["--in-place", "src/main.rs"]);
+    assert_ne!(read(dir.join("src/main.rs")), original);
+    assert!(!dir.join("src/main.rs.orig").exists());
+    cargo_ok(&dir, "check");
+
+    refactor_ok(&dir, &["revert"]);
+    assert_eq!(read(dir.join("src/main.rs")), original);
+    assert!(!dir.join("src/file_io_mod.rs").exists());
+    assert!(!dir.join("src/util_mod.rs").exists());
+}
+
+#[test]
+fn undo_reverts_the_last_run() {
+    let dir = fixture("split", "undo_reverts_the_last_run");
+    let original = read(dir.join("src/main.rs"));
+    refactor_ok(&dir, &["--in-place", "src/main.rs"]);
+    let manifest = read(dir.join(".refactor/revert/manifest"));
+    assert!(manifest.contains("moved crate::read_words crate::file_io_mod::read_words"), "{}", manifest);
+
+    let stdout = refactor_ok(&dir, &["undo"]);
+    assert!(stdout.contains("moved items are back where they were"), "{}", stdout);
+    assert_eq!(read(dir.join("src/main.rs")), original);
+    assert!(!dir.join(".refactor/revert").exists());
+}
+
+#[test]
+fn verified_runs_that_add_no_warning_are_kept() {
+    let dir = fixture("split", "verified_runs_that_add_no_warning_are_kept");
+    refactor_ok(&dir, &["--verify", "warnings", "--in-place", "src/main.rs"]);
+    assert!(read(dir.join("src/main.rs")).contains("mod util_mod;"));
+    assert!(dir.join("src/util_mod.rs").is_file());
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn runs_wait_for_the_lock_of_a_live_run() {
+    let dir = fixture("split", "runs_wait_for_the_lock_of_a_live_run");
+    fs::create_dir_all(dir.join(".refactor")).expect("Failed to create .refactor");
+    // The test itself is the live run holding the lock
+    fs::write(dir.join(".refactor/lock"), format!("{}\n0\n", std::process::id())).expect("Failed to write the lock");
+    let output = refactor(&dir, &["src/main.rs"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("pass --wait to queue behind it"));
+    assert!(!dir.join("src/tmp_main.rs").exists());
+
+    fs::remove_file(dir.join(".refactor/lock")).expect("Failed to remove the lock");
+    refactor_ok(&dir, &["--wait", "src/main.rs"]);
+    assert!(!dir.join(".refactor/lock").exists());
+}
+
+#[test]
+fn reexports_are_narrowed_to_the_names_in_use() {
+    let dir = fixture("split", "reexports_are_narrowed_to_the_names_in_use");
+    refactor_ok(&dir, &["--in-place", "src/main.rs"]);
+    // The root only calls `parse_counts` of the util module
+    let stdout = refactor_ok(&dir, &["audit-reexports", "."]);
+    assert!(stdout.contains("`use util_mod::*;`: only part of it is used, narrow it to `use util_mod::parse_counts;`"), "{}", stdout);
+    assert!(!stdout.contains("file_io_mod"), "{}", stdout);
+    assert!(read(dir.join("src/main.rs")).contains("use util_mod::*;"));
+
+    refactor_ok(&dir, &["audit-reexports", "--fix", "."]);
+    let root = read(dir.join("src/main.rs"));
+    assert!(root.contains("use file_io_mod::*;\nuse util_mod::parse_counts;\n"), "{}", root);
+    cargo_ok(&dir, "check");
+    assert!(refactor_ok(&dir, &["audit-reexports", "."]).contains("Every generated re-export is still used."));
+}
+
+#[test]
+fn crate_is_split_in_place() {
+    let dir = fixture("crate", "crate_is_split_in_place");
+    let stdout = refactor_ok(&dir, &["--min-lines", "10", "."]);
+    assert!(stdout.contains("Split 1 of the 1 files"), "{}", stdout);
+    assert!(dir.join("src/engine/file_io_mod.rs").is_file());
+    assert!(read(dir.join("src/engine.rs")).contains("mod file_io_mod;"));
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn crate_dry_runs_list_the_files_and_honor_min_fns() {
+    let dir = fixture("crate", "crate_dry_runs_list_the_files_and_honor_min_fns");
+    let stdout = refactor_ok(&dir, &["--min-lines", "10", "--dry-run", "."]);
+    assert!(stdout.contains("./src/engine.rs\n  ./src/engine/file_io_mod.rs (crate::engine::file_io_mod)\n"), "{}", stdout);
+    let stdout = refactor_ok(&dir, &["--min-lines", "10", "--min-fns", "20", "."]);
+    assert!(stdout.contains("No file of the crate is longer than 10 lines with 20 or more functions."), "{}", stdout);
+    assert!(!dir.join("src/engine").exists());
+}
+
+#[test]
+fn split_modules_are_renamed_across_the_crate() {
+    let dir = fixture("crate", "split_modules_are_renamed_across_the_crate");
+    refactor_ok(&dir, &["--min-lines", "10", "."]);
+    refactor_ok(&dir, &["rename-mod", "engine", "lexer", "."]);
+    refactor_ok(&dir, &["rename-mod", "lexer::file_io_mod", "storage", "."]);
+    assert!(read(dir.join("src/main.rs")).contains("lexer::load_file"));
+    assert!(read(dir.join("src/lexer.rs")).contains("pub use storage::*;"));
+    assert!(dir.join("src/lexer/storage.rs").is_file() && !dir.join("src/engine").exists());
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn plan_is_applied() {
+    let dir = fixture("split", "plan_is_applied");
+    let stdout = refactor_ok(&dir, &["plan", "src/main.rs"]);
+    assert!(stdout.contains("Plan written to"), "{}", stdout);
+    // Moving a function by hand in the plan moves it in the split
+    let plan_path = dir.join(".refactor/refactor.plan");
+    let plan = read(plan_path.clone());
+    let line = plan.lines().find(|line| line.contains("parse_count")).expect("The plan lacks parse_count");
+    let edited = line.replace("util", "file_io");
+    fs::write(&plan_path, plan.replace(line, &edited)).expect("Failed to edit the plan");
+    // A run given the plan follows it as well
+    let summary = refactor_ok(&dir, &["--plan", ".refactor/refactor.plan", "--dry-run", "src/main.rs"]);
+    assert!(summary.contains("functions: read_words, write_counts, parse_count\n"), "{}", summary);
+    refactor_ok(&dir, &["apply", ".refactor/refactor.plan"]);
+    assert!(read(dir.join("src/file_io_mod.rs")).contains("fn parse_count"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn applied_plans_are_remembered_next_to_the_plan() {
+    let dir = fixture("split", "applied_plans_are_remembered_next_to_the_plan");
+    refactor_ok(&dir, &["plan", "src/main.rs"]);
+    let plan_path = dir.join(".refactor/refactor.plan");
+    let edit = |from: &str, to: &str| fs::write(&plan_path, read(plan_path.clone()).replace(from, to)).expect("Failed to edit the plan");
+
+    // Moving the only caller of `parse_count` away from it is worth a suggestion
+    edit("parse_counts = util", "parse_counts = file_io");
+    let stdout = refactor_ok(&dir, &["apply", ".refactor/refactor.plan"]);
+    assert!(stdout.contains("`parse_count` is only called by `parse_counts` in the `file_io` group"), "{}", stdout);
+    assert!(!stdout.contains(".."), "{}", stdout);
+
+    // Once it follows its caller there is nothing left to suggest
+    edit("parse_count = util", "parse_count = file_io");
+    let stdout = refactor_ok(&dir, &["apply", ".refactor/refactor.plan"]);
+    assert!(!stdout.contains("is only called by"), "{}", stdout);
+    assert!(read(dir.join(".refactor/overrides")).contains("= file_io  # parse_count\n"));
+    assert!(!dir.join("src/.refactor-overrides").exists() && !dir.join("src/.refactor").exists());
+
+    // A later run without the plan places them the same way
+    refactor_ok(&dir, &["src/main.rs"]);
+    let file_io = read(dir.join("src/file_io_mod.rs"));
+    assert!(file_io.contains("fn parse_count(") && file_io.contains("fn parse_counts("), "{}", file_io);
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn seeded_runs_are_reproduced() {
+    let run = |test: &str| {
+        let dir = fixture("split", test);
+        let stdout = refactor_ok(&dir, &["--seed", "7", "--strategy", "fn=calls", "src/main.rs"]);
+        assert!(stdout.contains(", seed 7, config ") && stdout.contains("--seed 7 reproduce it"), "{}", stdout);
+        ["src/tmp_main.rs", "src/file_io_mod.rs", "src/util_mod.rs", ".refactor/refactor.plan"]
+            .iter()
+            .filter(|path| dir.join(path).exists())
+            .map(|path| (path.to_string(), read(dir.join(path))))
+            .collect::<Vec<_>>()
+    };
+    let first = run("seeded_runs_are_reproduced");
+    assert_eq!(run("seeded_runs_are_reproduced_again"), first);
+    let plan = &first.iter().find(|(path, _)| path == ".refactor/refactor.plan").expect("The run wrote no plan").1;
+    assert!(plan.contains("# Made by refactor ") && plan.contains(", seed 7, "), "{}", plan);
+}
+
+#[test]
+fn errors_after_an_apply_are_explained_by_the_plan() {
+    let dir = fixture("split", "errors_after_an_apply_are_explained_by_the_plan");
+    refactor_ok(&dir, &["plan", "src/main.rs"]);
+    refactor_ok(&dir, &["apply", ".refactor/refactor.plan"]);
+    swap_root(&dir);
+    // An import the analysis missed makes the moved function fail to compile
+    let module = read(dir.join("src/file_io_mod.rs"));
+    fs::write(dir.join("src/file_io_mod.rs"), module.replace("use std::collections::HashMap;\n", "")).expect("Failed to edit the module");
+
+    let output = refactor(&dir, &["explain-error", "."]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("missing import for `HashMap` — signature analysis gap for fn `write_counts`, which the plan put in `file_io`"), "{}", stdout);
+    assert!(stdout.contains("fix: add `use std::collections::HashMap;` to src/file_io_mod.rs"), "{}", stdout);
+    assert!(stdout.contains("set `write_counts = self` in"), "{}", stdout);
+    assert!(stdout.contains("1 error, 1 explained by decisions of the tool."), "{}", stdout);
+}
+
+#[test]
+fn teaching_mode_explains_each_concept_once() {
+    let dir = fixture("split", "teaching_mode_explains_each_concept_once");
+    let stdout = refactor_ok(&dir, &["--teach", "src/main.rs"]);
+    let report = &stdout[stdout.find("What changed and why:").expect("No teaching report")..];
+    assert!(report.contains("  read_words: moves to `file_io_mod` because its name contains `read`\n"), "{}", report);
+    assert!(report.contains("  read_words: `private` becomes `pub(super)`\n"), "{}", report);
+    assert_eq!(report.matches("why: Privacy is per module").count(), 1, "{}", report);
+    assert_eq!(report.matches("why: Modules group code that changes together").count(), 1, "{}", report);
+}
+
+#[test]
+fn recipes_name_the_modules_of_a_split() {
+    let dir = fixture("split", "recipes_name_the_modules_of_a_split");
+    let listing = refactor_ok(&dir, &["recipes"]);
+    assert!(listing.contains("  flags: --strategy fn=keywords,type=clusters --min-module-items 2 --misc-module domain\n"), "{}", listing);
+    refactor_ok(&dir, &["--recipe", "cli-monolith", "src/main.rs"]);
+    assert!(read(dir.join("src/io_mod.rs")).contains("fn read_words"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn modules_are_laid_out_like_the_model_crate() {
+    let dir = fixture("split", "modules_are_laid_out_like_the_model_crate");
+    let model = dir.join("model/src");
+    fs::create_dir_all(&model).expect("Failed to create the model crate");
+    fs::write(model.join("lib.rs"), "mod storage;\nmod text;\n").expect("Failed to write the model crate");
+    fs::write(model.join("storage.rs"), "pub fn read_file() {}\npub fn write_file() {}\n").expect("Failed to write the model crate");
+    fs::write(model.join("text.rs"), "pub fn parse_word() {}\n").expect("Failed to write the model crate");
+
+    refactor_ok(&dir, &["--like", "model", "src/main.rs"]);
+    assert!(read(dir.join("src/storage.rs")).contains("fn read_words"));
+    assert!(read(dir.join("src/text.rs")).contains("fn parse_count"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn config_files_set_defaults_the_command_line_overrides() {
+    let dir = fixture("split", "config_files_set_defaults_the_command_line_overrides");
+    fs::write(dir.join("refactor.toml"), "[naming]\nmodule_prefix = \"part_\"\n").expect("Failed to write the config");
+    let run = |args: &[&str]| Command::new(env!("CARGO_BIN_EXE_refactor")).args(args).current_dir(&dir).output().expect("Failed to run refactor");
+    let stdout = String::from_utf8_lossy(&run(&["--dry-run", "src/main.rs"]).stdout).into_owned();
+    assert!(stdout.contains("src/part_util.rs"), "{}", stdout);
+    let stdout = String::from_utf8_lossy(&run(&["--dry-run", "--module-prefix", "m_", "src/main.rs"]).stdout).into_owned();
+    assert!(stdout.contains("src/m_util.rs"), "{}", stdout);
+    fs::write(dir.join("refactor.toml"), "module-prefix = \"part_\"\n").expect("Failed to write the config");
+    let output = run(&["src/main.rs"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("refactor.toml:1: unknown key `module-prefix`"));
+}
+
+#[test]
+fn watch_plans_the_split_again_after_each_change() {
+    use std::io::Read;
+    use std::time::{Duration, Instant};
+
+    let dir = fixture("split", "watch_plans_the_split_again_after_each_change");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_refactor"))
+        .args(["watch", "src/main.rs", "--no-config"])
+        .current_dir(&dir)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to run refactor");
+    let mut stdout = child.stdout.take().expect("No stdout");
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buffer = [0; 4096];
+        while let Ok(read) = stdout.read(&mut buffer) {
+            if read == 0 || sender.send(String::from_utf8_lossy(&buffer[..read]).into_owned()).is_err() {
+                break;
+            }
+        }
+    });
+    // Function to read what the watch printed until the last plan it shows contains `text`
+    let mut printed = String::new();
+    let mut wait_for = |text: &str| {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while !printed.rsplit("Watching").next().is_some_and(|plan| plan.contains(text)) && Instant::now() < deadline {
+            if let Ok(chunk) = receiver.recv_timeout(Duration::from_millis(100)) {
+                printed.push_str(&chunk);
+            }
+        }
+        printed.rsplit("Watching").next().unwrap_or_default().to_string()
+    };
+    let first = wait_for("functions: parse_count, parse_counts");
+    assert!(!first.contains("write_report"), "{}", first);
+    let mut source = read(dir.join("src/main.rs"));
+    source.push_str("\nfn write_report(path: &str) -> std::io::Result<()> {\n    std::fs::write(path, \"\")\n}\n");
+    fs::write(dir.join("src/main.rs"), source).expect("Failed to edit the input");
+    let second = wait_for("write_report");
+    child.kill().expect("Failed to stop the watch");
+    child.wait().expect("The watch didn't stop");
+    assert!(second.contains("functions: read_words, write_counts, write_report"), "{}", second);
+    assert!(!dir.join("src/tmp_main.rs").exists());
+}
+
+#[test]
+fn interactive_runs_write_the_modules_as_reviewed() {
+    use std::io::Write;
+
+    let dir = fixture("split", "interactive_runs_write_the_modules_as_reviewed");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_refactor"))
+        .args(["--interactive", "src/main.rs", "--no-config"])
+        .current_dir(&dir)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("Failed to run refactor");
+    child.stdin.take().expect("No stdin").write_all(b"s\nr numbers\n").expect("Failed to answer");
+    let output = child.wait_with_output().expect("Failed to run refactor");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("[1/2] crate::file_io_mod (src/file_io_mod.rs)\n  moves crate::read_words\n"));
+    assert!(!dir.join("src/file_io_mod.rs").exists());
+    assert!(read(dir.join("src/numbers.rs")).contains("fn parse_counts"));
+    assert!(read(dir.join("src/tmp_main.rs")).contains("fn read_words"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn stdin_inputs_print_the_split_under_their_name() {
+    use std::io::Write;
+
+    let dir = fixture("split", "stdin_inputs_print_the_split_under_their_name");
+    let source = read(dir.join("src/main.rs"));
+    let run = |args: &[&str]| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_refactor"))
+            .args(args)
+            .arg("--no-config")
+            .current_dir(&dir)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .expect("Failed to run refactor");
+        child.stdin.take().expect("No stdin").write_all(source.as_bytes()).expect("Failed to write the input");
+        let output = child.wait_with_output().expect("Failed to run refactor");
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    let unnamed = run(&["-"]);
+    assert!(unnamed.starts_with("==> file_io_mod.rs <==\n") && unnamed.contains("\n==> main.rs <==\n"), "{}", unnamed);
+    let named = run(&["-", "--stdin-name", "src/main.rs"]);
+    assert!(named.starts_with("==> src/file_io_mod.rs <==\n") && named.contains("\n==> src/main.rs <==\n"), "{}", named);
+    assert!(!dir.join("src/file_io_mod.rs").exists() && !dir.join("src/tmp_main.rs").exists());
+}
+
+#[test]
+fn log_levels_decide_what_goes_to_stderr() {
+    let dir = fixture("split", "log_levels_decide_what_goes_to_stderr");
+    let stderr = |args: &[&str]| String::from_utf8_lossy(&refactor(&dir, args).stderr).into_owned();
+    let verbose = stderr(&["-vv", "--dry-run", "src/main.rs"]);
+    assert!(verbose.contains(" INFO  parse] src/main.rs has 8 items in 32 lines\n"), "{}", verbose);
+    assert!(verbose.contains(" DEBUG group] `read_words` goes to `file_io` because its name contains `read`\n"), "{}", verbose);
+    assert!(!stderr(&["--log-level", "info", "--dry-run", "src/main.rs"]).contains("DEBUG"));
+    fs::write(dir.join("src/main.rs"), format!("mod missing;\n{}", read(dir.join("src/main.rs")))).expect("Failed to edit the input");
+    assert!(stderr(&["--dry-run", "src/main.rs"]).contains("Warning: the file of `mod missing` was not found"));
+    assert_eq!(stderr(&["--log-level", "error", "--dry-run", "src/main.rs"]), "");
+}
+
+#[test]
+fn batches_refactor_every_file_a_pattern_matches() {
+    let one = fixture("split", "batches_refactor_every_file_a_pattern_matches/one");
+    fixture("split", "batches_refactor_every_file_a_pattern_matches/two");
+    let dir = one.parent().expect("No batch directory").to_path_buf();
+    let output = refactor(&dir, &["--check", "--max-file-lines", "10", "*/src/main.rs"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Checked 2 files:\n  one/src/main.rs: would be split\n  two/src/main.rs: would be split\n"), "{}", stdout);
+    // Two files of one directory would write their roots over each other
+    fs::copy(one.join("src/main.rs"), one.join("src/other.rs")).expect("Failed to copy the input");
+    let output = refactor(&dir, &["one/src/*.rs"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("would write their roots to the same directory"));
+    fs::remove_file(one.join("src/other.rs")).expect("Failed to remove the copy");
+    let stdout = refactor_ok(&dir, &["*/src/main.rs"]);
+    assert!(stdout.contains("Refactored 2 files:\n  one/src/main.rs: ok\n  two/src/main.rs: ok\n"), "{}", stdout);
+    assert!(dir.join("two/src/util_mod.rs").is_file());
+}
+
+#[test]
+fn mistyped_flags_name_the_flag_they_meant() {
+    let dir = fixture("split", "mistyped_flags_name_the_flag_they_meant");
+    let output = refactor(&dir, &["--dryrun", "src/main.rs"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown flag `--dryrun`, did you mean `--dry-run`?"));
+    let help = refactor_ok(&dir, &["--help"]);
+    assert!(help.contains("\nGrouping:\n") && help.contains("  --dry-run "), "{}", help);
+    let script = refactor_ok(&dir, &["completions", "bash"]);
+    assert!(script.contains("--import-style) COMPREPLY=($(compgen -W \"original grouped per-line merged\" -- \"$cur\")); return ;;"), "{}", script);
+    assert!(!dir.join("src/tmp_main.rs").exists());
+}
+
+#[test]
+fn stats_measure_each_module_of_the_split() {
+    let dir = fixture("split", "stats_measure_each_module_of_the_split");
+    refactor_ok(&dir, &["--dry-run", "--stats", "stats.json", "src/main.rs"]);
+    let stats: serde_json::Value = serde_json::from_str(&read(dir.join("stats.json"))).expect("The stats aren't JSON");
+    let modules = stats["modules"].as_array().expect("No modules");
+    let util = modules.iter().find(|module| module["module"] == "crate::util_mod").expect("util_mod isn't measured");
+    assert_eq!((util["functions"].as_u64(), util["imports"].as_u64()), (Some(2), Some(1)));
+    refactor_ok(&dir, &["--dry-run", "--stats", "stats.txt", "src/main.rs"]);
+    assert!(read(dir.join("stats.txt")).starts_with("module "));
+    assert!(!dir.join("src/util_mod.rs").exists());
+}
+
+#[test]
+fn inline_modules_are_split_and_promoted_to_files() {
+    let dir = fixture("inline_mods", "inline_modules_are_split_and_promoted_to_files");
+    refactor_ok(&dir, &["--min-inline-fns", "5", "src/main.rs"]);
+    assert!(!dir.join("src/text").exists());
+    refactor_ok(&dir, &["--promote-inline-mods", "src/main.rs"]);
+    assert!(read(dir.join("src/text.rs")).contains("pub use util_mod::*;"));
+    assert!(read(dir.join("src/text/file_io_mod.rs")).contains("fn read_words"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn inputs_keep_their_newlines_and_invalid_utf8_needs_lossy() {
+    let dir = fixture("split", "inputs_keep_their_newlines_and_invalid_utf8_needs_lossy");
+    let source = read(dir.join("src/main.rs"));
+    fs::write(dir.join("src/main.rs"), format!("\u{FEFF}{}", source.replace('\n', "\r\n"))).expect("Failed to write the input");
+    refactor_ok(&dir, &["src/main.rs"]);
+    let file_io = read(dir.join("src/file_io_mod.rs"));
+    assert!(file_io.contains("one per line\r\n") && !file_io.replace("\r\n", "").contains('\n'), "{:?}", file_io);
+    // The byte order mark stays at the top of the root, and only there
+    assert!(read(dir.join("src/tmp_main.rs")).starts_with('\u{FEFF}') && !file_io.starts_with('\u{FEFF}'));
+
+    let mut bytes = source.into_bytes();
+    let at = bytes.iter().position(|byte| *byte == b'R').expect("No comment to break");
+    bytes.insert(at, 0xff);
+    fs::write(dir.join("src/main.rs"), bytes).expect("Failed to write the input");
+    let output = refactor(&dir, &["src/main.rs"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("(pass --lossy to replace it)"));
+    refactor_ok(&dir, &["--lossy", "src/main.rs"]);
+    assert!(read(dir.join("src/file_io_mod.rs")).contains("// \u{FFFD}Reads the words"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn single_use_helpers_follow_their_caller_when_asked() {
+    let dir = fixture("split", "single_use_helpers_follow_their_caller_when_asked");
+    let source = read(dir.join("src/main.rs")).replace(".lines().map(String::from)", ".lines().map(parse_word)");
+    fs::write(dir.join("src/main.rs"), format!("{}\nfn parse_word(line: &str) -> String {{\n    line.trim().to_string()\n}}\n", source)).expect("Failed to write the input");
+    let stdout = refactor_ok(&dir, &["src/main.rs"]);
+    assert!(stdout.contains("`parse_word` is only called by `read_words` in the `file_io` group; inline it there or move it along with --inline-single-use"), "{}", stdout);
+    assert!(read(dir.join("src/util_mod.rs")).contains("fn parse_word"));
+    refactor_ok(&dir, &["--inline-single-use", "src/main.rs"]);
+    assert!(read(dir.join("src/file_io_mod.rs")).contains("fn parse_word"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn linker_placed_items_stay_or_move_into_an_asm_module() {
+    let dir = fixture("split", "linker_placed_items_stay_or_move_into_an_asm_module");
+    let source = read(dir.join("src/main.rs"));
+    let extra = "\n#[used]\n#[link_section = \".init_array\"]\nstatic INIT: extern \"C\" fn() = init_counts;\n\nextern \"C\" fn init_counts() {}\n";
+    fs::write(dir.join("src/main.rs"), format!("{}{}", source, extra)).expect("Failed to write the input");
+    refactor_ok(&dir, &["src/main.rs"]);
+    assert!(read(dir.join("src/tmp_main.rs")).contains("static INIT"));
+    assert!(!dir.join("src/asm.rs").exists());
+    // A root that is already there would only get its regions updated
+    fs::remove_file(dir.join("src/tmp_main.rs")).expect("Failed to remove the root");
+    refactor_ok(&dir, &["--asm", "group", "src/main.rs"]);
+    assert!(read(dir.join("src/asm.rs")).contains("#[link_section = \".init_array\"]\npub(super) static INIT"));
+    assert!(!read(dir.join("src/tmp_main.rs")).contains("static INIT"));
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn public_statics_of_an_asm_module_stay_in_a_library_api() {
+    let dir = fixture("split", "public_statics_of_an_asm_module_stay_in_a_library_api");
+    let main = read(dir.join("src/main.rs"));
+    let items = main[..main.find("fn main()").expect("The fixture has a main")].replace("\nfn ", "\npub fn ");
+    let extra = "\n#[used]\n#[link_section = \".init_array\"]\npub static INIT: extern \"C\" fn() = init_counts;\n\nextern \"C\" fn init_counts() {}\n";
+    fs::remove_file(dir.join("src/main.rs")).expect("Failed to remove the binary root");
+    fs::write(dir.join("src/lib.rs"), items + extra).expect("Failed to write the library root");
+    refactor_ok(&dir, &["--asm", "group", "src/lib.rs"]);
+    assert!(read(dir.join("src/tmp_lib.rs")).contains("\npub use asm::*;\n"));
+    fs::rename(dir.join("src/tmp_lib.rs"), dir.join("src/lib.rs")).expect("The run wrote no root");
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn god_objects_are_proposed_as_components() {
+    let dir = fixture("split", "god_objects_are_proposed_as_components");
+    // Ten fields, half of them read by the `query_` methods and half by the `lookup_` ones
+    let fields: Vec<String> = (0..5).map(|i| format!("db_{}", i)).chain((0..5).map(|i| format!("cache_{}", i))).collect();
+    let mut source = format!("pub struct Server {{\n{}}}\n\nimpl Server {{\n", fields.iter().map(|field| format!("    {}: u32,\n", field)).collect::<String>());
+    for i in 0..10 {
+        source.push_str(&format!("    pub fn query_{}(&self) -> u32 {{\n        self.db_0 + self.db_1 + self.db_2 + self.db_3 + self.db_4\n    }}\n\n", i));
+        source.push_str(&format!("    pub fn lookup_{}(&self) -> u32 {{\n        self.cache_0 + self.cache_1 + self.cache_2 + self.cache_3 + self.cache_4\n    }}\n\n", i));
+    }
+    source.push_str("}\n\nfn main() {}\n");
+    fs::write(dir.join("src/main.rs"), source).expect("Failed to write the input");
+    let stdout = refactor_ok(&dir, &["god-objects", "."]);
+    assert!(stdout.contains("`Server` in ./src/main.rs: 10 fields, 20 methods, used in 1 module\n"), "{}", stdout);
+    assert!(stdout.contains("  ServerDb (./src/server/db.rs)\n    fields: db_0, db_1, db_2, db_3, db_4\n"), "{}", stdout);
+    assert!(stdout.contains("  ServerCache (./src/server/cache.rs)\n"), "{}", stdout);
+    assert!(!dir.join("src/server").exists());
+}
+
+#[test]
+fn small_groups_merge_into_the_misc_module() {
+    let dir = fixture("split", "small_groups_merge_into_the_misc_module");
+    let summary = refactor_ok(&dir, &["--dry-run", "--min-module-items", "3", "--misc-module", "self", "src/main.rs"]);
+    assert!(!summary.contains("(crate::"), "{}", summary);
+    refactor_ok(&dir, &["--min-module-items", "3", "--misc-module", "shared", "src/main.rs"]);
+    assert!(read(dir.join("src/shared.rs")).contains("fn read_words"));
+    assert!(!dir.join("src/util_mod.rs").exists() && !dir.join("src/file_io_mod.rs").exists());
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn check_exits_with_1_when_the_input_would_be_split() {
+    let dir = fixture("split", "check_exits_with_1_when_the_input_would_be_split");
+    refactor_ok(&dir, &["--check", "src/main.rs"]);
+    let output = refactor(&dir, &["--check", "--max-file-lines", "10", "src/main.rs"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(!dir.join("src/tmp_main.rs").exists());
+}
+
+#[test]
+fn dry_run_and_diff_write_nothing() {
+    let dir = fixture("split", "dry_run_and_diff_write_nothing");
+    let summary = refactor_ok(&dir, &["--dry-run", "src/main.rs"]);
+    assert!(summary.contains("functions: read_words, write_counts"), "{}", summary);
+    assert!(summary.contains("  drops use std::fs;\n"), "{}", summary);
+    assert!(summary.contains("  adds use util_mod::*;\n"), "{}", summary);
+    let diff = refactor_ok(&dir, &["--diff", "src/main.rs"]);
+    assert!(diff.contains("+++ b/src/util_mod.rs"), "{}", diff);
+    assert!(!dir.join("src/tmp_main.rs").exists());
+    assert!(!dir.join("src/util_mod.rs").exists());
+}
+
+#[test]
+fn json_reports_describe_the_run() {
+    let dir = fixture("split", "json_reports_describe_the_run");
+    let report: serde_json::Value = serde_json::from_str(&refactor_ok(&dir, &["--dry-run", "--format", "json", "src/main.rs"])).expect("The report isn't JSON");
+    assert_eq!(report["written"], false);
+    let items = report["items"].as_array().expect("No items");
+    let read_words = items.iter().find(|item| item["item"] == "crate::read_words").expect("read_words isn't planned");
+    assert_eq!(read_words["to"], "crate::file_io_mod::read_words");
+    assert_eq!(read_words["lines"], serde_json::json!([5, 8]));
+    let report: serde_json::Value = serde_json::from_str(&refactor_ok(&dir, &["--format", "json", "src/main.rs"])).expect("The report isn't JSON");
+    assert_eq!(report["written"], true);
+    assert!(dir.join("src/util_mod.rs").is_file());
+}
+
+#[test]
+fn diffs_apply_to_a_compiling_split() {
+    let dir = fixture("split", "diffs_apply_to_a_compiling_split");
+    let diff = refactor_ok(&dir, &["--diff", "src/main.rs"]);
+    assert!(diff.contains("--- a/src/main.rs\n+++ b/src/main.rs\n"), "{}", diff);
+    fs::write(dir.join("refactor.patch"), diff).expect("Failed to write the patch");
+    let output = Command::new("git").args(["apply", "refactor.patch"]).current_dir(&dir).output().expect("Failed to run git");
+    assert!(output.status.success(), "git apply failed:\n{}", String::from_utf8_lossy(&output.stderr));
+    assert!(read(dir.join("src/main.rs")).contains("mod util_mod;"));
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn emit_stdout_and_json_print_the_files() {
+    let dir = fixture("split", "emit_stdout_and_json_print_the_files");
+    let listing = refactor_ok(&dir, &["--emit", "stdout", "src/main.rs"]);
+    assert!(listing.contains("==> src/util_mod.rs <=="), "{}", listing);
+    let json = refactor_ok(&dir, &["--emit", "json", "src/main.rs"]);
+    assert!(json.contains("\"path\": \"src/util_mod.rs\""), "{}", json);
+    assert!(!dir.join("src/util_mod.rs").exists());
+}
+
+#[test]
+fn emit_git_commits_to_a_new_branch() {
+    let dir = fixture("split", "emit_git_commits_to_a_new_branch");
+    let git = |args: &[&str]| {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(&dir)
+            .env("GIT_AUTHOR_NAME", "test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .output()
+            .expect("Failed to run git");
+        assert!(output.status.success(), "git {:?} failed:\n{}", args, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    };
+    git(&["init", "--quiet", "--initial-branch", "main"]);
+    git(&["add", "-A"]);
+    git(&["commit", "--quiet", "-m", "Add the fixture"]);
+    let original = read(dir.join("src/main.rs"));
+
+    let run = || {
+        Command::new(env!("CARGO_BIN_EXE_refactor"))
+            .args(["--emit", "git", "src/main.rs", "--no-config"])
+            .current_dir(&dir)
+            .env("GIT_AUTHOR_NAME", "test")
+            .env("GIT_AUTHOR_EMAIL", "test@example.com")
+            .env("GIT_COMMITTER_NAME", "test")
+            .env("GIT_COMMITTER_EMAIL", "test@example.com")
+            .output()
+            .expect("Failed to run refactor")
+    };
+    assert!(run().status.success());
+    // A second run leaves the first one's branch as it is
+    assert!(run().status.success());
+    let branches = git(&["branch", "--format", "%(refname:short)"]);
+    assert_eq!(branches.lines().collect::<Vec<_>>(), ["main", "refactor", "refactor-2"]);
+
+    // The commit has the new root in place of the input, and no plan
+    let files = git(&["ls-tree", "-r", "--name-only", "refactor"]);
+    assert_eq!(files.lines().collect::<Vec<_>>(), ["Cargo.toml", "src/file_io_mod.rs", "src/main.rs", "src/util_mod.rs"]);
+    assert!(git(&["show", "refactor:src/main.rs"]).contains("mod util_mod;"));
+    assert_eq!(read(dir.join("src/main.rs")), original);
+    assert!(git(&["status", "--porcelain"]).is_empty());
+}
+
+#[test]
+fn preview_shows_the_input_next_to_the_split() {
+    let dir = fixture("split", "preview_shows_the_input_next_to_the_split");
+    let stdout = refactor_ok(&dir, &["preview", "src/main.rs"]);
+    let page_path = stdout.trim().strip_prefix("Preview written to ").expect("No preview path");
+    let page = read(PathBuf::from(page_path));
+    assert!(page.contains("<span class=\"planned\" title=\"read_words goes to file_io\""), "{}", page);
+    assert!(page.contains("<h2>src/util_mod.rs</h2>"), "{}", page);
+    assert!(!dir.join("src/util_mod.rs").exists());
+}
+
+#[test]
+fn tree_shows_the_proposed_modules() {
+    let dir = fixture("split", "tree_shows_the_proposed_modules");
+    let stdout = refactor_ok(&dir, &["tree", "src/main.rs"]);
+    assert!(stdout.contains("mod util_mod"), "{}", stdout);
+}
+
+#[test]
+fn graph_exports_the_paths_between_modules() {
+    let dir = fixture("crate", "graph_exports_the_paths_between_modules");
+    refactor_ok(&dir, &["--min-lines", "10", "."]);
+    let graph: serde_json::Value = serde_json::from_str(&refactor_ok(&dir, &["graph", "--format", "json", "."])).expect("The graph isn't JSON");
+    assert_eq!(graph["schema"], "refactor/import-graph/v1");
+    assert_eq!(graph["nodes"].as_array().map(Vec::len), Some(5));
+    assert_eq!(graph["adjacency"]["crate"], serde_json::json!([{"target": "crate::engine", "weight": 4}]));
+    assert_eq!(graph["adjacency"]["crate::engine::util_mod"][0]["target"], "crate::engine");
+
+    refactor_ok(&dir, &["graph", "--format", "structurizr", "--output", "modules.dsl", "."]);
+    let dsl = read(dir.join("modules.dsl"));
+    assert!(dsl.contains(r#"m0 -> m1 "Uses" "4 paths""#), "{}", dsl);
+}
+
+#[test]
+fn gate_fails_on_long_files() {
+    let dir = fixture("crate", "gate_fails_on_long_files");
+    refactor_ok(&dir, &["gate", "."]);
+    let output = refactor(&dir, &["gate", "--max-file-lines", "10", "."]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("src/engine.rs"));
+    let output = refactor(&dir, &["gate", "--max-fn-lines", "4", "."]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("./src/main.rs:3:1: function `main` has 5 lines, more than the limit of 4\n") && stdout.ends_with("1 violation.\n"), "{}", stdout);
+}
+
+#[test]
+fn spans_locate_the_planned_functions() {
+    let dir = fixture("split", "spans_locate_the_planned_functions");
+    let stdout = refactor_ok(&dir, &["spans", "src/main.rs"]);
+    let line = stdout.lines().find(|line| line.ends_with(" file_io read_words")).unwrap_or_else(|| panic!("No span of read_words:\n{}", stdout));
+    // The range is of bytes of the file, the function's docs included
+    let (start, end) = line.split(' ').next().and_then(|range| range.split_once("..")).expect("The span has no range");
+    let source = read(dir.join("src/main.rs"));
+    let code = &source[start.parse::<usize>().unwrap()..end.parse::<usize>().unwrap()];
+    assert!(code.trim_start().starts_with("// Reads the words of a file") && code.trim_end().ends_with('}'), "{}", code);
+}
+
+#[test]
+fn excluded_functions_stay_in_the_root() {
+    let dir = fixture("split", "excluded_functions_stay_in_the_root");
+    refactor_ok(&dir, &["--exclude", "write_counts,parse_*", "src/main.rs"]);
+    let root = read(dir.join("src/tmp_main.rs"));
+    assert!(["fn write_counts", "fn parse_count(", "fn parse_counts"].iter().all(|name| root.contains(name)), "{}", root);
+    assert!(read(dir.join("src/file_io_mod.rs")).contains("fn read_words"));
+    assert!(!dir.join("src/util_mod.rs").exists());
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn output_dirs_take_the_modules_and_root() {
+    let dir = fixture("split", "output_dirs_take_the_modules_and_root");
+    let stdout = refactor_ok(&dir, &["--output-dir", "out", "src/main.rs"]);
+    assert!(stdout.contains("  crate::util_mod     out/util_mod.rs "), "{}", stdout);
+    assert!(!dir.join("src/tmp_main.rs").exists() && !dir.join("src/util_mod.rs").exists());
+    for name in ["file_io_mod.rs", "util_mod.rs"] {
+        fs::rename(dir.join("out").join(name), dir.join("src").join(name)).expect("The run wrote no module");
+    }
+    fs::rename(dir.join("out/tmp_main.rs"), dir.join("src/main.rs")).expect("The run wrote no root");
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn package_is_written_with_a_manifest() {
+    let dir = fixture("split", "package_is_written_with_a_manifest");
+    refactor_ok(&dir, &["--package", "words", "src/main.rs"]);
+    assert!(dir.join("words/src/main.rs").is_file());
+    fs::write(dir.join("words/Cargo.toml"), read(dir.join("words/Cargo.toml")) + "\n[workspace]\n").expect("Failed to write the manifest");
+    cargo_ok(&dir.join("words"), "check");
+}
+
+#[test]
+fn public_api_changes_need_allow_api_changes() {
+    let dir = fixture("split", "public_api_changes_need_allow_api_changes");
+    // A library whose `net::parse_count` is shadowed by its own, which a second glob makes ambiguous
+    let main = read(dir.join("src/main.rs"));
+    let items = main[..main.find("fn main()").expect("The fixture has a main")].replace("\nfn ", "\npub fn ");
+    let net = "\npub mod net {\n    pub fn parse_count(port: u16) -> bool {\n        port > 0\n    }\n}\n\npub use net::*;\n";
+    fs::remove_file(dir.join("src/main.rs")).expect("Failed to remove the binary root");
+    fs::write(dir.join("src/lib.rs"), items + net).expect("Failed to write the library root");
+
+    let output = refactor(&dir, &["src/lib.rs"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("  - crate::parse_count: fn parse_count (line : & str) -> Option < (String , usize) >\n"), "{}", stderr);
+    assert!(stderr.contains("pass --allow-api-changes"), "{}", stderr);
+    assert!(!dir.join("src/tmp_lib.rs").exists() && !dir.join("src/util_mod.rs").exists());
+
+    let output = refactor(&dir, &["--allow-api-changes", "src/lib.rs"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("the public API of the library changes"));
+    assert!(read(dir.join("src/util_mod.rs")).contains("pub fn parse_count"));
+}
+
+#[test]
+fn no_std_modules_import_from_core_and_alloc() {
+    let dir = fixture("no_std", "no_std_modules_import_from_core_and_alloc");
+    refactor_ok(&dir, &["--smoke-tests", "src/lib.rs"]);
+    let root = read(dir.join("src/tmp_lib.rs"));
+    assert!(root.starts_with("#![no_std]"), "{}", root);
+    let modules: Vec<PathBuf> = fs::read_dir(dir.join("src"))
+        .expect("Failed to list the generated files")
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().ends_with("_mod.rs")))
+        .collect();
+    assert_eq!(modules.len(), 2, "{:?}", modules);
+    for path in &modules {
+        let code = read(path.clone());
+        assert!(code.contains("use alloc::") || code.contains("use core::"), "{:?}:\n{}", path, code);
+        assert!(!code.contains("std::"), "{:?}:\n{}", path, code);
+    }
+    assert!(!root.contains("std::"), "{}", root);
+    fs::rename(dir.join("src/tmp_lib.rs"), dir.join("src/lib.rs")).expect("The run wrote no root");
+    cargo_ok(&dir, "test");
+}
+
+// Function to read the files an `--emit memory` run lists after its summary, by their paths
+fn memory_listing(stdout: &str) -> Vec<(String, String)> {
+    let mut files: Vec<(String, String)> = Vec::new();
+    for line in stdout.lines() {
+        match line.strip_prefix("==> ").and_then(|line| line.strip_suffix(" <==")) {
+            Some(path) => files.push((path.to_string(), String::new())),
+            None => {
+                if let Some((_, code)) = files.last_mut() {
+                    code.push_str(line);
+                    code.push('\n');
+                }
+            }
+        }
+    }
+    files
+}
+
+#[test]
+fn unit_tests_follow_the_functions_they_test() {
+    let dir = fixture("unit_tests", "unit_tests_follow_the_functions_they_test");
+    let files = memory_listing(&refactor_ok(&dir, &["--emit", "memory", "src/main.rs"]));
+    let code = |path: &str| files.iter().find(|(file, _)| file == path).map(|(_, code)| code.as_str()).unwrap_or_else(|| panic!("{} wasn't listed", path));
+    assert!(code("src/file_io_mod.rs").contains("mod tests {"));
+    assert!(code("src/file_io_mod.rs").contains("fn reads()") && code("src/file_io_mod.rs").contains("fn round_trip()"));
+    assert!(code("src/util_mod.rs").contains("fn parses()"));
+    // A test of functions in several modules stays behind, with the helper it uses
+    assert!(code("src/tmp_main.rs").contains("fn mixed()") && code("src/tmp_main.rs").contains("struct Fixture;"));
+    assert!(!code("src/tmp_main.rs").contains("fn reads()"));
+
+    // Nothing was written, and what would have been passes its tests
+    assert_eq!(fs::read_dir(dir.join("src")).expect("Failed to list the sources").count(), 1);
+    assert!(!dir.join(".refactor").exists());
+    for (path, code) in files.iter().filter(|(path, _)| path.starts_with("src/")) {
+        fs::write(dir.join(path), code).expect("Failed to write a listed file");
+    }
+    swap_root(&dir);
+    cargo_ok(&dir, "test");
+}
+
+#[test]
+fn traits_sharing_a_method_leave_no_unused_import() {
+    // `write!` works with both the `fmt::Write` and the `io::Write` the fixture imports
+    let dir = fixture("traits", "traits_sharing_a_method_leave_no_unused_import");
+    refactor_ok(&dir, &["src/main.rs"]);
+    let file_io = read(dir.join("src/file_io_mod.rs"));
+    assert!(file_io.starts_with("use std::io::{self, Write};\n"), "{}", file_io);
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn generic_helpers_keep_their_support_module_to_themselves() {
+    // The calls of `generic_support` make a cluster that would take the name of the support group
+    let dir = fixture("generics", "generic_helpers_keep_their_support_module_to_themselves");
+    refactor_ok(&dir, &["--strategy", "fn=calls,generic=support", "src/main.rs"]);
+    let support = read(dir.join("src/generic_support.rs"));
+    assert!(support.contains("fn group_by") && support.contains("fn largest_by"), "{}", support);
+    assert!(!support.contains("fn order_summary"), "{}", support);
+    swap_root(&dir);
+    cargo_ok(&dir, "check");
+}
+
+#[test]
+fn cfg_twins_move_together_without_gating_their_module() {
+    let dir = fixture("cfg_twins", "cfg_twins_move_together_without_gating_their_module");
+    refactor_ok(&dir, &["--smoke-tests", "src/main.rs"]);
+    let crypto = read(dir.join("src/crypto_mod.rs"));
+    assert!(crypto.contains("#[cfg(unix)]\npub(super) fn crypto_hash_key"), "{}", crypto);
+    assert!(crypto.contains("#[cfg(not(unix))]\npub(super) fn crypto_hash_key"), "{}", crypto);
+    assert!(!crypto.contains("#!["), "{}", crypto);
+    // A module only one configuration has is declared and re-exported in that configuration only
+    assert!(read(dir.join("src/network_mod.rs")).starts_with("#![cfg(windows)]\n"));
+    let root = read(dir.join("src/tmp_main.rs"));
+    assert!(root.contains("#[cfg(windows)]\npub mod network_mod;") && root.contains("#[cfg(windows)]\nuse network_mod::*;"), "{}", root);
+    swap_root(&dir);
+    cargo_ok(&dir, "test");
+}
@@ -0,0 +1,40 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+
+#[cfg(unix)]
+fn crypto_hash_key(key: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn read_config(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+#[cfg(not(unix))]
+fn crypto_hash_key(key: &str) -> u64 {
+    key.bytes().map(u64::from).sum()
+}
+
+fn crypto_seal(key: &str) -> u64 {
+    crypto_hash_key(key) ^ 0x5a5a
+}
+
+#[cfg(windows)]
+fn connect_pipe(name: &str) -> String {
+    format!("\\\\.\\pipe\\{}", name)
+}
+
+#[cfg(windows)]
+fn send_message(name: &str, message: &str) -> String {
+    format!("{} <- {}", connect_pipe(name), message)
+}
+
+fn main() {
+    #[cfg(windows)]
+    println!("{}", send_message("refactor", "hello"));
+    let config = read_config("config.txt");
+    println!("{}", crypto_seal(&config));
+}
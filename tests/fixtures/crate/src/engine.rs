@@ -0,0 +1,31 @@
+//! The engine.
+
+use std::fs;
+use std::io::Write;
+
+/// A token.
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub text: String,
+}
+
+pub fn load_file(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_default()
+}
+
+pub fn save_file(path: &str, text: &str) {
+    let mut file = fs::File::create(path).expect("create");
+    file.write_all(text.as_bytes()).expect("write");
+}
+
+pub fn parse_line(line: &str) -> Vec<Token> {
+    line.split_whitespace().map(|word| parse_token(word)).collect()
+}
+
+fn parse_token(word: &str) -> Token {
+    Token { text: word.to_string() }
+}
+
+pub fn format_tokens(tokens: &[Token]) -> String {
+    tokens.iter().map(|token| token.text.clone()).collect::<Vec<_>>().join(" ")
+}
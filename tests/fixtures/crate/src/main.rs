@@ -0,0 +1,7 @@
+mod engine;
+
+fn main() {
+    let text = engine::load_file("words.txt");
+    let tokens = engine::parse_line(&text);
+    engine::save_file("tokens.txt", &engine::format_tokens(&tokens));
+}
@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+
+// Groups values by the key `key_of` gives them
+fn group_by<K: Ord, V: Clone, F>(values: &[V], key_of: F) -> BTreeMap<K, Vec<V>>
+where
+    F: Fn(&V) -> K,
+{
+    let mut groups: BTreeMap<K, Vec<V>> = BTreeMap::new();
+    for value in values {
+        groups.entry(key_of(value)).or_default().push(value.clone());
+    }
+    groups
+}
+
+fn largest_by<T, K: Ord, F>(values: &[T], key_of: F) -> Option<&T>
+where
+    F: Fn(&T) -> K,
+{
+    values.iter().max_by_key(|value| key_of(value))
+}
+
+struct Order {
+    customer: String,
+    total: u32,
+}
+
+fn generic_support(orders: &[Order]) -> String {
+    let summary = order_summary(orders);
+    format!("{} ({})", summary, order_count(orders))
+}
+
+fn order_summary(orders: &[Order]) -> String {
+    orders.iter().map(|order| format!("{}: {}", order.customer, order.total)).collect::<Vec<_>>().join(", ")
+}
+
+fn order_count(orders: &[Order]) -> usize {
+    orders.len()
+}
+
+fn main() {
+    let orders = vec![Order { customer: "ada".to_string(), total: 3 }, Order { customer: "bob".to_string(), total: 5 }];
+    let by_customer = group_by(&orders.iter().map(|order| order.total).collect::<Vec<_>>(), |total| total % 2);
+    let largest = largest_by(&orders, |order| order.total).map(|order| order.customer.clone());
+    println!("{} {:?} {:?}", generic_support(&orders), by_customer, largest);
+}
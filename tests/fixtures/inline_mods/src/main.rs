@@ -0,0 +1,23 @@
+mod text {
+    pub fn read_words(path: &str) -> Vec<String> {
+        std::fs::read_to_string(path).unwrap_or_default().lines().map(String::from).collect()
+    }
+
+    pub fn write_words(path: &str, words: &[String]) {
+        std::fs::write(path, words.join("\n")).unwrap();
+    }
+
+    pub fn parse_count(line: &str) -> Option<usize> {
+        line.parse().ok()
+    }
+
+    pub fn parse_counts(text: &str) -> Vec<usize> {
+        text.lines().filter_map(parse_count).collect()
+    }
+}
+
+fn main() {
+    let words = text::read_words("words.txt");
+    text::write_words("out.txt", &words);
+    println!("{:?}", text::parse_counts(""));
+}
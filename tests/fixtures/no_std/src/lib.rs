@@ -0,0 +1,27 @@
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+pub fn parse_words(text: &str) -> Vec<String> {
+    text.split_whitespace().map(|word| word.to_string()).collect()
+}
+
+pub fn parse_number(text: &str) -> Option<u32> {
+    text.trim().parse().ok()
+}
+
+pub fn format_words(words: &[String]) -> String {
+    let mut out = String::new();
+    for word in words {
+        write!(out, "{} ", word).unwrap();
+    }
+    out
+}
+
+pub fn format_count(count: usize) -> String {
+    count.to_string()
+}
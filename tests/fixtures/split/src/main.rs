@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+// Reads the words of a file, one per line
+fn read_words(path: &str) -> io::Result<Vec<String>> {
+    Ok(fs::read_to_string(path)?.lines().map(String::from).collect())
+}
+
+fn write_counts(path: &str, counts: &HashMap<String, usize>) -> io::Result<()> {
+    let mut lines: Vec<String> = counts.iter().map(|(word, count)| format!("{} {}", word, count)).collect();
+    lines.sort();
+    fs::write(path, lines.join("\n"))
+}
+
+fn parse_count(line: &str) -> Option<(String, usize)> {
+    let (word, count) = line.split_once(' ')?;
+    Some((word.to_string(), count.parse().ok()?))
+}
+
+fn parse_counts(text: &str) -> HashMap<String, usize> {
+    text.lines().filter_map(parse_count).collect()
+}
+
+fn main() {
+    let words = read_words("words.txt").unwrap_or_default();
+    let mut counts = parse_counts("");
+    for word in words {
+        *counts.entry(word).or_insert(0) += 1;
+    }
+    write_counts("counts.txt", &counts).unwrap();
+}
@@ -0,0 +1,34 @@
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+
+fn format_report(items: &[u32]) -> String {
+    let mut out = String::new();
+    for item in items {
+        write!(out, "{} ", item).unwrap();
+    }
+    out
+}
+
+fn format_header(title: &str) -> String {
+    let mut out = String::new();
+    writeln!(out, "== {} ==", title).unwrap();
+    out
+}
+
+fn write_output(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    writeln!(stdout, "{}", text)?;
+    stdout.flush()
+}
+
+fn write_error(text: &str) {
+    let mut stderr = std::io::stderr();
+    writeln!(stderr, "{}", text).unwrap();
+}
+
+fn main() {
+    let report = format_report(&[1, 2, 3]);
+    write_output(&format_header("r")).unwrap();
+    write_output(&report).unwrap();
+    write_error("done");
+}
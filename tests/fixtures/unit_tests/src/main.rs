@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+
+fn read_file() -> String { "a".to_string() }
+fn write_file(s: &str) -> usize { s.len() }
+fn parse_value(s: &str) -> HashMap<String, usize> { let mut m = HashMap::new(); m.insert(s.to_string(), 1); m }
+
+fn main() {
+    println!("{} {:?}", write_file(&read_file()), parse_value("x"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    struct Fixture;
+
+    // Reads the file
+    #[test]
+    fn reads() {
+        assert_eq!(read_file(), "a");
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(super::write_file(&read_file()), 1);
+    }
+
+    #[test]
+    fn parses() {
+        let expected: HashMap<String, usize> = [("x".to_string(), 1)].into_iter().collect();
+        assert_eq!(parse_value("x"), expected);
+    }
+
+    #[test]
+    fn mixed() {
+        let _f = Fixture;
+        assert_eq!(write_file(&read_file()), parse_value("a").len());
+    }
+}